@@ -4,4 +4,10 @@ REPO=/home/williamsandst/repos/rust/magnificence-oxidized-chess
 ENGINE1=$1
 ENGINE2=$2
 OPENING_BOOK="-openings file=$REPO/benchmark/books/noob_4moves.pgn format=pgn start=1"
-cutechess-cli -debug -rounds 3 -games 2 -engine cmd=$ENGINE1 -engine cmd=$ENGINE2 -each restart=on proto=uci tc=inf/40+0.4 -pgnout result.pgn -recover $OPENING_BOOK
\ No newline at end of file
+# An optional Polyglot book is played from by the engines themselves instead of the PGN openings
+ENGINE_OPTIONS=""
+if [ -n "$3" ]; then
+    ENGINE_OPTIONS="option.OwnBook=true option.BookFile=$3"
+    OPENING_BOOK=""
+fi
+cutechess-cli -debug -rounds 3 -games 2 -engine cmd=$ENGINE1 -engine cmd=$ENGINE2 -each restart=on proto=uci tc=inf/40+0.4 $ENGINE_OPTIONS -pgnout result.pgn -recover $OPENING_BOOK
\ No newline at end of file
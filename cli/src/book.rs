@@ -0,0 +1,36 @@
+use std::fs;
+use std::sync::Arc;
+
+use clap::ArgMatches;
+use engine_core::book::BookBuilder;
+use engine_core::core::bitboard::constants::BitboardRuntimeConstants;
+use engine_core::pgn;
+
+use crate::{exit_with_error, parse_number};
+
+/// Create a Polyglot opening book from the games of one or more PGN files
+pub fn run_make_book(matches: &ArgMatches) {
+    let output_path = matches.value_of("output").unwrap();
+    let max_ply = parse_number::<usize>(matches.value_of("max-ply").unwrap(), "maximum ply");
+    let min_games = parse_number::<u32>(matches.value_of("min-games").unwrap(), "minimum game count");
+
+    let runtime_constants = Arc::new(BitboardRuntimeConstants::create());
+    let mut builder = BookBuilder::new(max_ply);
+    let mut game_count = 0;
+    for path in matches.values_of("pgn").unwrap() {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| exit_with_error(&format!("Could not read '{}': {}", path, e)));
+        let games = pgn::parse_pgn(&text, Arc::clone(&runtime_constants))
+            .unwrap_or_else(|e| exit_with_error(&format!("Could not parse '{}': {}", path, e)));
+        for game in games.iter() {
+            builder.add_game(game);
+        }
+        game_count += games.len();
+        println!("Read {} games from {}", games.len(), path);
+    }
+
+    let book = builder.build(min_games);
+    if let Err(e) = fs::write(output_path, book.to_bytes()) {
+        exit_with_error(&format!("Could not write '{}': {}", output_path, e));
+    }
+    println!("Wrote {} entries from {} games to {}", book.len(), game_count, output_path);
+}
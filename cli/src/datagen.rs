@@ -0,0 +1,88 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Instant;
+
+use clap::ArgMatches;
+use engine_core::core::bitboard::constants::BitboardRuntimeConstants;
+use engine_core::datagen::{DatagenOptions, DatagenProgress, SelfPlay};
+use engine_core::engine::SearchLimits;
+
+use crate::{exit_with_error, parse_number};
+
+/// Play self-play games and append their quiet positions to the output file. After every game the
+/// number of games and the length of the output are saved next to it, so an interrupted run can be
+/// resumed without losing or duplicating positions.
+pub fn run_datagen(matches: &ArgMatches) {
+    let output_path = matches.value_of("output").unwrap();
+    let progress_path = format!("{}.progress", output_path);
+    let games = parse_number::<u64>(matches.value_of("games").unwrap(), "game count");
+    let limits = SearchLimits {
+        depth: matches.value_of("depth").map(|depth| parse_number(depth, "depth")),
+        nodes: matches.value_of("nodes").map(|nodes| parse_number(nodes, "node count")),
+        ..SearchLimits::default()
+    };
+    if limits.depth.is_none() && limits.nodes.is_none() {
+        exit_with_error("Either --nodes or --depth is required");
+    }
+    let seed = matches.value_of("seed").map(|seed| parse_number::<u64>(seed, "seed"));
+
+    let mut progress = match matches.is_present("resume") {
+        true => {
+            let text = fs::read_to_string(&progress_path)
+                .unwrap_or_else(|e| exit_with_error(&format!("Could not read '{}': {}", progress_path, e)));
+            let progress = DatagenProgress::parse(&text)
+                .unwrap_or_else(|| exit_with_error(&format!("Invalid progress file '{}'", progress_path)));
+            if seed.is_some_and(|seed| seed != progress.seed) {
+                exit_with_error(&format!("The run being resumed uses seed {}", progress.seed));
+            }
+            progress
+        },
+        false => {
+            if fs::metadata(output_path).is_ok() {
+                exit_with_error(&format!("'{}' already exists, use --resume to continue generating into it", output_path));
+            }
+            DatagenProgress { seed: seed.unwrap_or(0), games: 0, bytes: 0 }
+        }
+    };
+
+    let mut output = OpenOptions::new().create(true).append(true).open(output_path)
+        .unwrap_or_else(|e| exit_with_error(&format!("Could not open '{}': {}", output_path, e)));
+    // Drop the positions of a game which was being written when the run was interrupted
+    if let Err(e) = output.set_len(progress.bytes) {
+        exit_with_error(&format!("Could not truncate '{}': {}", output_path, e));
+    }
+
+    let options = DatagenOptions {
+        limits,
+        random_plies: parse_number(matches.value_of("random-plies").unwrap(), "random ply count"),
+        max_plies: parse_number(matches.value_of("max-plies").unwrap(), "maximum ply count"),
+        seed: progress.seed
+    };
+    let mut self_play = SelfPlay::new(Arc::new(BitboardRuntimeConstants::create()), options);
+    if let Some(hash) = matches.value_of("hash") {
+        self_play.set_option("Hash", hash).unwrap_or_else(|e| exit_with_error(&e));
+    }
+
+    println!("Generating games {} to {} with seed {}", progress.games + 1, games, progress.seed);
+    let start = Instant::now();
+    let mut positions = 0;
+    while progress.games < games {
+        let (entries, result) = self_play.play_game(progress.games);
+        let text: String = entries.iter().map(|entry| entry.to_line() + "\n").collect();
+        if let Err(e) = output.write_all(text.as_bytes()).and_then(|_| output.flush()) {
+            exit_with_error(&format!("Could not write '{}': {}", output_path, e));
+        }
+        progress.games += 1;
+        progress.bytes += text.len() as u64;
+        positions += entries.len();
+        // Replace the progress file in one step, so it is never left half written
+        let temporary_path = format!("{}.tmp", progress_path);
+        if let Err(e) = fs::write(&temporary_path, progress.to_text()).and_then(|_| fs::rename(&temporary_path, &progress_path)) {
+            exit_with_error(&format!("Could not write '{}': {}", progress_path, e));
+        }
+        println!("Game {}: {}, {} positions ({} in total, {:.1} s)",
+            progress.games, result.as_str(), entries.len(), positions, start.elapsed().as_secs_f64());
+    }
+    println!("Wrote {} positions to {}", positions, output_path);
+}
@@ -1,5 +1,9 @@
 mod uci;
+mod book;
+mod datagen;
+mod tablebase;
 use clap::Arg;
+use engine_core::datagen::{DEFAULT_MAX_PLIES, DEFAULT_RANDOM_PLIES};
 
 const USE_FANCY_SPLASH: bool = true;
 const FANCY_SPLASH: &str = 
@@ -52,8 +56,122 @@ fn main() {
         .long("nosplash")
         .value_name("NOSPLASH")
         .takes_value(false))
+    .subcommand(clap::App::new("datagen")
+        .about("Generates training positions for tuning from self-play games.")
+        .arg(Arg::new("output")
+            .help("File to append the positions to, one '<fen> | <score> | <result>' line per position.")
+            .short('o')
+            .long("output")
+            .value_name("FILE")
+            .required(true)
+            .takes_value(true))
+        .arg(Arg::new("games")
+            .help("Total number of games to play, including the games of a resumed run.")
+            .short('g')
+            .long("games")
+            .value_name("COUNT")
+            .default_value("1000")
+            .takes_value(true))
+        .arg(Arg::new("nodes")
+            .help("Nodes to search for every move.")
+            .short('n')
+            .long("nodes")
+            .value_name("NODES")
+            .takes_value(true))
+        .arg(Arg::new("depth")
+            .help("Depth to search to for every move.")
+            .short('d')
+            .long("depth")
+            .value_name("DEPTH")
+            .takes_value(true))
+        .arg(Arg::new("random-plies")
+            .help("Random moves played at the start of every game.")
+            .long("random-plies")
+            .value_name("PLIES")
+            .default_value(&DEFAULT_RANDOM_PLIES.to_string())
+            .takes_value(true))
+        .arg(Arg::new("max-plies")
+            .help("Games longer than this are adjudicated as draws.")
+            .long("max-plies")
+            .value_name("PLIES")
+            .default_value(&DEFAULT_MAX_PLIES.to_string())
+            .takes_value(true))
+        .arg(Arg::new("seed")
+            .help("Seed of the random openings, the same seed gives the same games.")
+            .short('s')
+            .long("seed")
+            .value_name("SEED")
+            .takes_value(true))
+        .arg(Arg::new("hash")
+            .help("Transposition table size in MB.")
+            .long("hash")
+            .value_name("MB")
+            .takes_value(true))
+        .arg(Arg::new("resume")
+            .help("Continue an interrupted run into the same output file.")
+            .short('r')
+            .long("resume")
+            .takes_value(false)))
+    .subcommand(clap::App::new("makebook")
+        .about("Creates a Polyglot opening book from PGN games, weighting moves by how well they scored.")
+        .arg(Arg::new("pgn")
+            .help("PGN files to read the games from.")
+            .value_name("PGN")
+            .required(true)
+            .multiple_values(true))
+        .arg(Arg::new("output")
+            .help("Book file to write.")
+            .short('o')
+            .long("output")
+            .value_name("FILE")
+            .default_value("book.bin")
+            .takes_value(true))
+        .arg(Arg::new("max-ply")
+            .help("Only moves of the first plies of each game are added.")
+            .long("max-ply")
+            .value_name("PLIES")
+            .default_value("20")
+            .takes_value(true))
+        .arg(Arg::new("min-games")
+            .help("Moves played in fewer games are left out.")
+            .long("min-games")
+            .value_name("COUNT")
+            .default_value("3")
+            .takes_value(true)))
+    .subcommand(clap::App::new("tbgen")
+        .about("Generates distance-to-mate endgame tables, such as KQvK or KBNvK, and the tables they depend on.")
+        .arg(Arg::new("material")
+            .help("Materials of the tables to generate, the stronger side first.")
+            .value_name("MATERIAL")
+            .required(true)
+            .multiple_values(true))
+        .arg(Arg::new("output")
+            .help("Directory to write the tables to. Tables already in it are not generated again.")
+            .short('o')
+            .long("output")
+            .value_name("DIRECTORY")
+            .default_value(".")
+            .takes_value(true))
+        .arg(Arg::new("kpk-bitbase")
+            .help("Also write the KPvK bitbase compiled into the engine to this file.")
+            .long("kpk-bitbase")
+            .value_name("FILE")
+            .takes_value(true)))
     .get_matches();
 
+    if let Some(datagen_matches) = matches.subcommand_matches("datagen") {
+        datagen::run_datagen(datagen_matches);
+        return;
+    }
+    if let Some(book_matches) = matches.subcommand_matches("makebook") {
+        book::run_make_book(book_matches);
+        return;
+    }
+    if let Some(tablebase_matches) = matches.subcommand_matches("tbgen") {
+        tablebase::run_generate_tables(tablebase_matches);
+        return;
+    }
+
     let player = matches.value_of("player").unwrap();
 
     if let Some(values) = matches.values_of("command") {
@@ -76,3 +194,12 @@ fn main() {
         uci::start_uci_protocol(player);
     }
 }
+
+fn parse_number<T: std::str::FromStr>(text: &str, name: &str) -> T {
+    return text.parse().unwrap_or_else(|_| exit_with_error(&format!("Invalid {}: {}", name, text)));
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
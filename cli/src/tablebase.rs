@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use clap::ArgMatches;
+use engine_core::core::bitboard::constants::BitboardRuntimeConstants;
+use engine_core::tablebase::generator::generate_with_dependencies;
+use engine_core::tablebase::{kpk, Dtm, DtmTable, DtmTables, Material, EXTENSION};
+
+use crate::exit_with_error;
+
+/// Generate distance-to-mate tables and the tables they depend on into the output directory. Tables which are
+/// already in the directory are not generated again.
+pub fn run_generate_tables(matches: &ArgMatches) {
+    let output_directory = matches.value_of("output").unwrap();
+    let mut materials: Vec<Material> = matches.values_of("material").unwrap()
+        .map(|name| Material::from_name(name).unwrap_or_else(|e| exit_with_error(&e.to_string())))
+        .collect();
+    let bitbase_path = matches.value_of("kpk-bitbase");
+    let kpk = Material::from_name("KPvK").unwrap();
+    if bitbase_path.is_some() && !materials.contains(&kpk) {
+        materials.push(kpk.clone());
+    }
+
+    if let Err(e) = fs::create_dir_all(output_directory) {
+        exit_with_error(&format!("Could not create '{}': {}", output_directory, e));
+    }
+    let mut tables = DtmTables::load(output_directory).unwrap_or_else(|e| exit_with_error(&e.to_string()));
+    let runtime_constants = Arc::new(BitboardRuntimeConstants::create());
+    let mut start = Instant::now();
+    let result = generate_with_dependencies(&materials, &mut tables, runtime_constants, &mut |table| {
+        let path = Path::new(output_directory).join(format!("{}.{}", table.material(), EXTENSION));
+        if let Err(e) = fs::write(&path, table.to_bytes()) {
+            exit_with_error(&format!("Could not write '{}': {}", path.display(), e));
+        }
+        println!("{} in {:.1}s: {}", path.display(), start.elapsed().as_secs_f64(), table_summary(table));
+        start = Instant::now();
+    });
+    if let Err(e) = result {
+        exit_with_error(&e.to_string());
+    }
+
+    if let Some(bitbase_path) = bitbase_path {
+        if let Err(e) = fs::write(bitbase_path, kpk::create_bitbase(tables.get(&kpk).unwrap())) {
+            exit_with_error(&format!("Could not write '{}': {}", bitbase_path, e));
+        }
+        println!("Wrote the KPvK bitbase to {}", bitbase_path);
+    }
+}
+
+/// Counts of the results of a table and its longest mate
+fn table_summary(table: &DtmTable) -> String {
+    let (mut wins, mut draws, mut losses, mut longest_mate) = (0, 0, 0, 0);
+    for dtm in table.iter() {
+        match dtm {
+            Some(Dtm::Win(plies)) => {
+                wins += 1;
+                longest_mate = longest_mate.max(plies);
+            }
+            Some(Dtm::Draw) => draws += 1,
+            Some(Dtm::Loss(_)) => losses += 1,
+            None => ()
+        }
+    }
+    return format!("{} wins, {} draws, {} losses, longest mate in {} plies", wins, draws, losses, longest_mate);
+}
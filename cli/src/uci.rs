@@ -1,6 +1,8 @@
 use constants::BitboardRuntimeConstants;
-use engine_core::engine::ab_engine::StandardAlphaBetaEngine;
-use engine_core::engine::{self, Engine, SearchMetadata, ShouldAbortSearchCallback};
+use engine_core::engine::ab_engine::{StandardAlphaBetaEngine, MAX_SEARCH_DEPTH, MAX_THREADS};
+use engine_core::engine::search_features::FEATURE_OPTION_NAMES;
+use engine_core::engine::{self, Engine, Score, SearchLimits, SearchMetadata, ShouldAbortSearchCallback};
+use engine_core::engine::transposition_table::{DEFAULT_HASH_SIZE_MB, MAX_HASH_SIZE_MB, MIN_HASH_SIZE_MB};
 use move_list::{MoveList, MoveListCollection};
 /// Functionality for running the Universal Chess Protocol
 /// 
@@ -14,15 +16,22 @@ use rustyline::Editor;
 use rustyline::error::ReadlineError;
 use std::sync::{Arc, Mutex};
 use std::{io, thread};
+use std::fs;
 use std::io::BufRead;
-use std::rc::Rc;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 
 use engine_core::core::*;
+use engine_core::core::variant::Variant;
 use engine_core::core::bitboard::*;
+use engine_core::core::bitboard::eval_params::EvalParams;
+use engine_core::core::bitboard::nnue::Network;
+use engine_core::book::Book;
 use engine_core::commands;
+use engine_core::pgn::{self, GameResult, PgnGame};
+use engine_core::epd;
+use engine_core::tablebase::{kpk, Dtm, DtmTables};
 
 const ENGINE_NAME: &str = "Magnificence Oxidized";
 const ENGINE_AUTHORS: &str = "William Sandstrom and Harald Bjurulf";
@@ -43,14 +52,47 @@ struct GoState {
     search_moves: Option<Vec<String>>,
     // Restrict the search to a certain amount of time
     move_time: Option<usize>,
+    // Moves left until the next time control
+    moves_to_go: Option<usize>,
+    // Search until the stop command is received
+    infinite: bool,
+}
+
+impl GoState {
+    fn to_search_limits(&self) -> SearchLimits {
+        let millis = |time: Option<usize>| time.map(|ms| Duration::from_millis(ms as u64));
+        return SearchLimits {
+            depth: self.depth,
+            nodes: self.nodes.map(|nodes| nodes as u64),
+            move_time: millis(self.move_time),
+            white_time: millis(self.white_time),
+            white_increment: millis(self.white_time_increment),
+            black_time: millis(self.black_time),
+            black_increment: millis(self.black_time_increment),
+            moves_to_go: self.moves_to_go,
+            infinite: self.infinite
+        };
+    }
 }
 
 struct WorkerState {
-    board_constant_state: Rc<BitboardRuntimeConstants>,
+    board_constant_state: Arc<BitboardRuntimeConstants>,
     board: Board,
     engine: Box<dyn Engine>,
     move_history: Vec<Move>,
     strict_uci_mode: bool,
+    /// Game loaded with `pgn load`, which can be stepped through
+    loaded_game: Option<PgnGame>,
+    /// Engine options which were set successfully, applied to engines created for test suites
+    engine_options: Vec<(String, String)>,
+    /// Opening book loaded with the `BookFile` option, used for `go` when `OwnBook` is set
+    book: Option<Book>,
+    own_book: bool,
+    /// Castling moves of positions set afterwards are written as the king capturing its own rook,
+    /// set with the `UCI_Chess960` option
+    chess960: bool,
+    /// Rules of positions set afterwards, set with the `UCI_Variant` option
+    variant: Variant,
 }
 
 struct SharedState {
@@ -76,14 +118,19 @@ enum CommandType {
     Stop,
     Quit,
     // Non-UCI commands
-    Perft(usize),
-    Divide(usize),
-    PerftTests,
+    Perft(usize, Option<usize>),
+    Divide(usize, Option<usize>),
+    PerftTests(Option<String>, usize),
+    EpdSuite(String, GoState),
     Move(String),
     Undo,
+    PgnLoad(String, usize),
+    PgnStep(isize),
+    PgnSave(Option<String>),
     DisplayBoard,
     EvaluateBoard,
     LegalMoves,
+    TablebaseProbe,
     Help,
     Unknown,
     Error(String),
@@ -118,11 +165,11 @@ pub fn start_uci_protocol(player_type: &str) {
         });
 
         // The worker thread listens for commands
-        let board_constant_state_rc = Rc::new(board_constant_state);
-        let board = Board::from_fen(STARTING_POS_FEN, Rc::clone(&board_constant_state_rc));
+        let board_constant_state = Arc::new(board_constant_state);
+        let board = Board::new(Arc::clone(&board_constant_state));
         let mut worker_state = WorkerState {
             board: board.clone(),
-            board_constant_state: board_constant_state_rc,
+            board_constant_state,
             engine: engine::from_name(
                 &player_type_copy, 
                 &board, 
@@ -133,6 +180,12 @@ pub fn start_uci_protocol(player_type: &str) {
             ),
             move_history: Vec::new(),
             strict_uci_mode: false,
+            loaded_game: None,
+            engine_options: Vec::new(),
+            book: None,
+            own_book: false,
+            chess960: false,
+            variant: Variant::Standard,
         };
 
         while let Ok(command) = rx.recv() {
@@ -154,6 +207,14 @@ pub fn start_uci_protocol(player_type: &str) {
         let command = parse_command(&line);
 
         let mut state = shared_state.lock().unwrap();
+        let is_infinite_search = matches!(&command, CommandType::Go(go_state) if go_state.infinite);
+        if matches!(command, CommandType::Go(_)) {
+            state.stop_search = false;
+        }
+        if command == CommandType::Quit {
+            // Abort any running search so the worker can exit
+            state.stop_search = true;
+        }
         if tx.send(command.clone()).is_err() || command == CommandType::Quit {
             break;
         }
@@ -165,7 +226,8 @@ pub fn start_uci_protocol(player_type: &str) {
         }
 
         state.is_worker_complete = false;
-        if !state.strict_uci_mode {
+        // Infinite searches can only be ended with stop, so keep reading input
+        if !state.strict_uci_mode && !is_infinite_search {
             drop(state);
             while !shared_state.lock().unwrap().is_worker_complete {
                 std::thread::sleep(std::time::Duration::from_millis(50));
@@ -183,9 +245,9 @@ pub fn start_uci_protocol(player_type: &str) {
 }
 
 pub fn run_single_uci_command(command_line: &str, player_type: &str) {
-    let board_constant_state = Rc::new(BitboardRuntimeConstants::create());
+    let board_constant_state = Arc::new(BitboardRuntimeConstants::create());
 
-    let board = Board::new(Rc::clone(&board_constant_state));
+    let board = Board::new(Arc::clone(&board_constant_state));
     let mut state = WorkerState {
         board: board.clone(),
         board_constant_state,
@@ -199,6 +261,12 @@ pub fn run_single_uci_command(command_line: &str, player_type: &str) {
         ),
         move_history: Vec::new(),
         strict_uci_mode: false,
+        loaded_game: None,
+        engine_options: Vec::new(),
+        book: None,
+        own_book: false,
+        chess960: false,
+        variant: Variant::Standard,
     };
 
     let shared_state = Arc::new(Mutex::new(SharedState {
@@ -214,9 +282,31 @@ pub fn run_single_uci_command(command_line: &str, player_type: &str) {
 }
 
 fn search(state: &mut WorkerState, go_state: &GoState, shared_state: &Arc<Mutex<SharedState>>) {
-    let pv = state.engine.search(&state.board);
-    let mv = pv.first().unwrap();
-    println!("bestmove {}", mv);
+    // Book moves are played without searching, except for infinite searches which are used for analysis
+    if state.own_book && !go_state.infinite {
+        if let Some(mv) = state.book.as_ref().and_then(|book| book.pick_move(&state.board)) {
+            println!("info string book move {}", mv);
+            println!("bestmove {}", mv);
+            return;
+        }
+    }
+    let pv = state.engine.search(&state.board, &go_state.to_search_limits());
+    // The UCI protocol does not allow sending bestmove before stop during infinite searches
+    while go_state.infinite && !shared_state.lock().unwrap().stop_search {
+        thread::sleep(Duration::from_millis(10));
+    }
+    match pv.first() {
+        Some(mv) => println!("bestmove {}", mv),
+        None => println!("bestmove 0000")
+    }
+}
+
+/// Report invalid input. GUIs are informed through an info string in UCI mode.
+fn report_error(state: &WorkerState, message: &str) {
+    match state.strict_uci_mode {
+        true => println!("info string {}", message),
+        false => println!("Error: {}", message)
+    }
 }
 
 fn log_callback(text: &str) {
@@ -246,25 +336,96 @@ fn handle_command(command : &CommandType, state: &mut WorkerState, shared_state:
             uci_start();
         }
         CommandType::UCINewGame => {
-            // Maybe do something here?
-            
+            state.engine.new_game();
+        }
+        CommandType::SetOption(name, value) if name.eq_ignore_ascii_case("EvalFile") => {
+            load_network_file(value, state);
+        }
+        CommandType::SetOption(name, value) if name.eq_ignore_ascii_case("ParamFile") => {
+            load_param_file(value, state);
+        }
+        CommandType::SetOption(name, value) if name.eq_ignore_ascii_case("BookFile") => {
+            load_book_file(value, state);
+        }
+        CommandType::SetOption(name, value) if name.eq_ignore_ascii_case("OwnBook") => {
+            match value.to_lowercase().as_str() {
+                "true" => state.own_book = true,
+                "false" => state.own_book = false,
+                _ => report_error(state, &format!("Invalid value for OwnBook: {}", value))
+            }
+        }
+        CommandType::SetOption(name, value) if name.eq_ignore_ascii_case("UCI_Chess960") => {
+            match value.to_lowercase().as_str() {
+                "true" => state.chess960 = true,
+                "false" => state.chess960 = false,
+                _ => report_error(state, &format!("Invalid value for UCI_Chess960: {}", value))
+            }
+        }
+        CommandType::SetOption(name, value) if name.eq_ignore_ascii_case("UCI_Variant") => {
+            match Variant::from_uci_name(value) {
+                Some(variant) => state.variant = variant,
+                None => report_error(state, &format!("Invalid value for UCI_Variant: {}", value))
+            }
+        }
+        CommandType::SetOption(name, value) => {
+            match state.engine.set_option(name, value) {
+                Ok(()) => {
+                    state.engine_options.retain(|(option_name, _)| !option_name.eq_ignore_ascii_case(name));
+                    state.engine_options.push((name.clone(), value.clone()));
+                }
+                Err(e) if !state.strict_uci_mode => println!("Error: {}", e),
+                Err(_) => ()
+            }
         }
         CommandType::Go(go_state) => {
             search(state, go_state, shared_state)
         },
         CommandType::Position(fen, moves) => {
-            if let Some(fen_pos) = fen {
-                state.board = Board::from_fen(&fen_pos, Rc::clone(&state.board_constant_state));
+            // The previous position is kept if the FEN or any of the moves is invalid
+            let (mut board, mut move_history) = match fen {
+                Some(fen_pos) => match Board::from_fen_variant(&fen_pos, state.variant, Arc::clone(&state.board_constant_state)) {
+                    Ok(mut board) => {
+                        board.set_chess960(state.chess960);
+                        (board, Vec::new())
+                    },
+                    Err(e) => {
+                        report_error(state, &format!("Invalid FEN '{}': {}", fen_pos, e));
+                        return;
+                    }
+                },
+                None => (state.board.clone(), state.move_history.clone())
+            };
+            for mv_algebraic in moves {
+                match Move::try_from_algebraic(&board, mv_algebraic) {
+                    Ok(mv) => {
+                        board.make_move(&mv);
+                        move_history.push(mv);
+                    }
+                    Err(e) => {
+                        report_error(state, &format!("Invalid position: {}", e));
+                        return;
+                    }
+                }
             }
-            state.board = commands::board_from_moves(&state.board, moves);
+            state.board = board;
+            state.move_history = move_history;
         },
-        CommandType::Perft(depth) => {
-            perft(depth, state);
+        CommandType::Perft(depth, expected) => {
+            perft(depth, *expected, state);
         },
         CommandType::Move(mv_algebraic) => {
-            let mv = Move::from_algebraic(&state.board, mv_algebraic);
-            state.board.make_move(&mv);
-            state.move_history.push(mv);
+            // Accept both UCI notation (e2e4) and SAN (e4)
+            let mv = match Move::try_from_algebraic(&state.board, mv_algebraic) {
+                Err(MoveParseError::InvalidFormat(_)) => Move::try_from_san(&state.board, mv_algebraic),
+                result => result
+            };
+            match mv {
+                Ok(mv) => {
+                    state.board.make_move(&mv);
+                    state.move_history.push(mv);
+                }
+                Err(e) => report_error(state, &e.to_string())
+            }
         },
         CommandType::Undo => {
             let possible_mv = state.move_history.pop();
@@ -276,11 +437,33 @@ fn handle_command(command : &CommandType, state: &mut WorkerState, shared_state:
                 println!("No moves have been made, cannot undo.");
             }
         }
+        CommandType::PgnLoad(path, game_number) => {
+            pgn_load(path, *game_number, state);
+        }
+        CommandType::PgnStep(plies) => {
+            pgn_step(*plies, state);
+        }
+        CommandType::PgnSave(path) => {
+            pgn_save(path, state);
+        }
         CommandType::DisplayBoard => {
             println!("{}", state.board.to_string());
         },
-        CommandType::Divide(depth) => {
-            divide(depth, state);
+        CommandType::EvaluateBoard => {
+            println!("{}", format_eval_trace(&state.board.eval_trace()));
+            if state.board.network().is_some() {
+                let eval = match state.board.get_current_player() {
+                    Color::White => state.board.eval(),
+                    Color::Black => -state.board.eval()
+                };
+                println!("NNUE evaluation: {:+.2} (white side)", eval as f64 / 100.0);
+            }
+        },
+        CommandType::TablebaseProbe => {
+            tablebase_probe(state);
+        }
+        CommandType::Divide(depth, expected) => {
+            divide(depth, *expected, state);
         }
         CommandType::LegalMoves => {
             let mut move_vector = MoveList::empty();
@@ -289,16 +472,157 @@ fn handle_command(command : &CommandType, state: &mut WorkerState, shared_state:
             moves.sort();
             println!("Legal moves ({}): {}", state.board.get_current_player().to_char(), moves.join(" "));
         }
-        CommandType::PerftTests => {
-            commands::perft_tests(Rc::clone(&state.board_constant_state), 20_000_000_000);
+        CommandType::EpdSuite(path, go_state) => {
+            run_epd_suite(path, go_state, state, shared_state);
+        }
+        CommandType::PerftTests(path, node_limit) => {
+            perft_suite(path, *node_limit, state);
         }
         _ => {}
     };
     shared_state.lock().unwrap().is_worker_complete = true;
 }
 
+// Print the distance to mate of the current position from the tables of the DtmPath option, or the KPvK bitbase
+fn tablebase_probe(state: &WorkerState) {
+    let path = state.engine_options.iter()
+        .find(|(name, value)| name.eq_ignore_ascii_case("DtmPath") && !matches!(value.as_str(), "" | "<empty>"))
+        .map(|(_, value)| value.as_str());
+    let tables = match path.map(DtmTables::load) {
+        Some(Ok(tables)) => tables,
+        Some(Err(e)) => {
+            report_error(state, &e.to_string());
+            return;
+        }
+        None => DtmTables::default()
+    };
+    let dtm = if tables.is_empty() { None } else { tables.probe(&state.board) };
+    match dtm {
+        Some(Dtm::Win(plies)) => println!("Mate in {} ({} plies)", (plies + 1) / 2, plies),
+        Some(Dtm::Loss(0)) => println!("Checkmated"),
+        Some(Dtm::Loss(plies)) => println!("Mated in {} ({} plies)", plies / 2, plies),
+        Some(Dtm::Draw) => println!("Draw"),
+        None => match kpk::probe(&state.board) {
+            Some(true) => println!("Win for the side with the pawn (KPvK bitbase)"),
+            Some(false) => println!("Draw (KPvK bitbase)"),
+            None => println!("The position is not in the tables, set DtmPath to a directory of generated tables")
+        }
+    }
+}
+
+// Load evaluation parameters written by the tuner, used by all boards from now on. An empty path restores the defaults.
+fn load_param_file(path: &str, state: &mut WorkerState) {
+    let params = match path {
+        "" | "<empty>" => EvalParams::default(),
+        _ => {
+            let parsed = fs::read_to_string(path).map_err(|e| e.to_string())
+                .and_then(|text| EvalParams::parse(&text).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(params) => params,
+                Err(e) => {
+                    report_error(state, &format!("Could not load evaluation parameters from '{}': {}", path, e));
+                    return;
+                }
+            }
+        }
+    };
+    update_board_constants(state, |constants| constants.eval_params = Arc::new(params));
+    if !state.strict_uci_mode {
+        println!("Loaded evaluation parameters from '{}'", path);
+    }
+}
+
+// Load a network and evaluate all boards with it from now on. An empty path switches back to the classical evaluation.
+fn load_network_file(path: &str, state: &mut WorkerState) {
+    let network = match path {
+        "" | "<empty>" => None,
+        _ => {
+            let parsed = fs::read(path).map_err(|e| e.to_string())
+                .and_then(|bytes| Network::from_bytes(&bytes).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(network) => Some(Arc::new(network)),
+                Err(e) => {
+                    report_error(state, &format!("Could not load network from '{}': {}", path, e));
+                    return;
+                }
+            }
+        }
+    };
+    if !state.strict_uci_mode {
+        match &network {
+            Some(network) => println!("Loaded network with {} hidden neurons from '{}'", network.hidden_size(), path),
+            None => println!("Using the classical evaluation")
+        }
+    }
+    update_board_constants(state, |constants| constants.network = network);
+}
+
+// Load a Polyglot opening book. An empty path unloads the book.
+fn load_book_file(path: &str, state: &mut WorkerState) {
+    state.book = match path {
+        "" | "<empty>" => None,
+        _ => {
+            let parsed = fs::read(path).map_err(|e| e.to_string())
+                .and_then(|bytes| Book::from_bytes(&bytes).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(book) => Some(book),
+                Err(e) => {
+                    report_error(state, &format!("Could not load book from '{}': {}", path, e));
+                    return;
+                }
+            }
+        }
+    };
+    if !state.strict_uci_mode {
+        match &state.book {
+            Some(book) => println!("Loaded book with {} entries from '{}', it is used when OwnBook is true", book.len(), path),
+            None => println!("Unloaded the opening book")
+        }
+    }
+}
+
+// Change the constants used for new boards and apply the evaluation settings to the current board
+fn update_board_constants(state: &mut WorkerState, update: impl FnOnce(&mut BitboardRuntimeConstants)) {
+    let mut board_constant_state = (*state.board_constant_state).clone();
+    update(&mut board_constant_state);
+    state.board_constant_state = Arc::new(board_constant_state);
+    state.board.set_eval_params(Arc::clone(&state.board_constant_state.eval_params));
+    state.board.set_network(state.board_constant_state.network.clone());
+}
+
+// Print the search metadata as a UCI info line
 fn handle_search_metadata(metadata: SearchMetadata) {
-    //println!("Go status: {:?}", metadata);
+    println!("{}", format_uci_info(&metadata));
+}
+
+fn format_uci_info(metadata: &SearchMetadata) -> String {
+    let score = match metadata.score {
+        Score::Mate(moves) => format!("mate {}", moves),
+        Score::Centipawns(centipawns) => format!("cp {}", centipawns)
+    };
+    let pv = metadata.pv.iter().map(|mv| mv.to_algebraic()).collect::<Vec<String>>().join(" ");
+    return format!("info depth {} seldepth {} score {} nodes {} nps {} time {} hashfull {} tbhits {} pv {}",
+        metadata.depth, metadata.selective_depth, score, metadata.nodes, metadata.nodes_per_second,
+        metadata.time.as_millis(), metadata.hashfull, metadata.tb_hits, pv
+    ).trim_end().to_string();
+}
+
+// Print the evaluation terms as a table, with the middlegame and endgame score of each side
+fn format_eval_trace(trace: &evaluation::EvalTrace) -> String {
+    let format_score = |score: evaluation::TaperedScore| format!("{:>6} {:>6}", score.mg, score.eg);
+    let mut lines = vec![
+        format!("{:<20} | {:^13} | {:^13} | {:^13}", "Term", "White", "Black", "Total"),
+        format!("{:<20} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", "", "MG", "EG", "MG", "EG", "MG", "EG"),
+        "-".repeat(66)
+    ];
+    for term in trace.terms.iter() {
+        lines.push(format!("{:<20} | {} | {} | {}", term.name, format_score(term.white), format_score(term.black), format_score(term.total())));
+    }
+    lines.push("-".repeat(66));
+    lines.push(format!("{:<20} | {:>13} | {:>13} | {}", "Total", "", "", format_score(trace.total())));
+    lines.push(format!("Phase: {}/{}", trace.phase, eval_params::TOTAL_PHASE));
+    lines.push(format!("Tapered evaluation: {:+.2} (white side)", trace.tapered() as f64 / 100.0));
+    return lines.join("\n");
 }
 
 fn get_system_time() -> Duration {
@@ -309,25 +633,203 @@ fn get_system_time() -> Duration {
 fn uci_start() {
     println!("id name {}", ENGINE_NAME);
     println!("id author {}", ENGINE_AUTHORS);
+    println!("option name Hash type spin default {} min {} max {}", DEFAULT_HASH_SIZE_MB, MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB);
+    println!("option name Clear Hash type button");
+    println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+    println!("option name EvalFile type string default <empty>");
+    println!("option name ParamFile type string default <empty>");
+    println!("option name OwnBook type check default false");
+    println!("option name BookFile type string default <empty>");
+    println!("option name SyzygyPath type string default <empty>");
+    println!("option name DtmPath type string default <empty>");
+    println!("option name UCI_Chess960 type check default false");
+    println!("option name UCI_Variant type combo default chess{}",
+        Variant::ALL.iter().map(|variant| format!(" var {}", variant)).collect::<String>());
+    println!("option name MaxDepth type spin default {} min 1 max {}", MAX_SEARCH_DEPTH, MAX_SEARCH_DEPTH);
+    for name in FEATURE_OPTION_NAMES {
+        println!("option name {} type check default true", name);
+    }
     println!("uciok");
 }
 
-fn perft(depth: &usize, state: &mut WorkerState) {
+fn perft(depth: &usize, expected: Option<usize>, state: &mut WorkerState) {
     println!("Performing perft of depth {}", depth);
     let mut reserved_moves = MoveListCollection::new();
     let (perft_count, duration) = timeit(|| commands::perft(*depth, &mut state.board, &mut reserved_moves));
     let million_moves_per_second = (perft_count / 1_000_000) as f64 / duration;
     println!("Perft completed in {:.3} seconds ({:.2}M moves per second)", duration, million_moves_per_second);
     println!("Result: {}", perft_count);
+    check_perft_result(*depth, perft_count, expected, state);
 }
 
-fn divide(depth: &usize, state: &mut WorkerState) {
+fn divide(depth: &usize, expected: Option<usize>, state: &mut WorkerState) {
     println!("Performing perft of depth {}", depth);
     let mut reserved_moves = MoveListCollection::new();
     let (perft_count, duration) = timeit(|| commands::divide(*depth, &mut state.board, &mut reserved_moves));
     let million_moves_per_second = (perft_count / 1_000_000) as f64 / duration;
     println!("Perft completed in {:.3} seconds ({:.2}M moves per second)", duration, million_moves_per_second);
     println!("Result: {}", perft_count);
+    check_perft_result(*depth, perft_count, expected, state);
+}
+
+// Compare a perft result with the expected node count, if one was given, and bisect on a mismatch
+fn check_perft_result(depth: usize, perft_count: usize, expected: Option<usize>, state: &WorkerState) {
+    match expected {
+        Some(expected) if expected == perft_count => println!("Result matches the expected node count"),
+        Some(expected) => {
+            println!("Error: expected {} nodes, found {}", expected, perft_count);
+            commands::report_perft_error(&state.board, depth);
+        }
+        None => ()
+    }
+}
+
+/// Run a perft EPD suite with `D<depth>` operations, or the built in standard, Chess960 and variant perft
+/// positions if no file is given
+fn perft_suite(path: &Option<String>, node_limit: usize, state: &mut WorkerState) {
+    let positions = match path {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(text) => epd::parse_epd(&text, Arc::clone(&state.board_constant_state)),
+            Err(e) => return report_error(state, &format!("Could not read {}: {}", path, e))
+        },
+        None => epd::parse_epd(&format!("{}{}{}", commands::DEFAULT_PERFT_SUITE, commands::CHESS960_PERFT_SUITE,
+            commands::VARIANT_PERFT_SUITE),
+            Arc::clone(&state.board_constant_state))
+    };
+    match positions {
+        Ok(positions) if commands::perft_suite(&positions, node_limit) => println!("All perft tests passed"),
+        Ok(_) => println!("Perft tests failed"),
+        Err(e) => report_error(state, &format!("Could not parse perft suite: {}", e))
+    }
+}
+
+fn pgn_load(path: &str, game_number: usize, state: &mut WorkerState) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return report_error(state, &format!("Could not read {}: {}", path, e))
+    };
+    let mut games = match pgn::parse_pgn(&text, Arc::clone(&state.board_constant_state)) {
+        Ok(games) => games,
+        Err(e) => return report_error(state, &format!("Could not parse {}: {}", path, e))
+    };
+    if game_number == 0 || game_number > games.len() {
+        return report_error(state, &format!("Game {} does not exist, {} has {} games", game_number, path, games.len()));
+    }
+    let game = games.swap_remove(game_number - 1);
+    println!("Loaded game {} of {}: {} - {}, {} ({} plies)", game_number, games.len() + 1,
+        game.get_tag("White").unwrap_or("?"), game.get_tag("Black").unwrap_or("?"), game.result.as_str(), game.moves.len());
+    state.board = game.start_board.clone();
+    state.move_history.clear();
+    state.loaded_game = Some(game);
+}
+
+/// Step forwards or backwards through the loaded game
+fn pgn_step(plies: isize, state: &mut WorkerState) {
+    let game_moves: Vec<Move> = match &state.loaded_game {
+        Some(game) => game.moves.iter().map(|pgn_move| pgn_move.mv).collect(),
+        None => return report_error(state, "No game is loaded, use 'pgn load <file>'")
+    };
+    let ply = state.move_history.len();
+    if ply > game_moves.len() || state.move_history[..] != game_moves[..ply] {
+        return report_error(state, "The current position is not part of the loaded game");
+    }
+    let target_ply = (ply as isize + plies).clamp(0, game_moves.len() as isize) as usize;
+    while state.move_history.len() < target_ply {
+        let mv = game_moves[state.move_history.len()];
+        println!("{}", mv.to_san(&state.board));
+        state.board.make_move(&mv);
+        state.move_history.push(mv);
+    }
+    while state.move_history.len() > target_ply {
+        let mv = state.move_history.pop().unwrap();
+        state.board.unmake_move(&mv);
+    }
+    println!("Ply {} of {}", target_ply, game_moves.len());
+}
+
+/// Save the moves played from the last set position as PGN, or print it if no path is given
+fn pgn_save(path: &Option<String>, state: &mut WorkerState) {
+    let mut start_board = state.board.clone();
+    for mv in state.move_history.iter().rev() {
+        start_board.unmake_move(mv);
+    }
+    let result = GameResult::from_status(state.board.get_game_status());
+    let mut game = PgnGame::from_moves(&start_board, &state.move_history, result);
+    game.set_tag("Event", "Magnificence Oxidized CLI game");
+    match path {
+        Some(path) => match fs::write(path, game.to_pgn()) {
+            Ok(_) => println!("Saved game to {}", path),
+            Err(e) => report_error(state, &format!("Could not write {}: {}", path, e))
+        },
+        None => print!("{}", game.to_pgn())
+    }
+}
+
+/// Search every position of an EPD test suite and report which positions are solved.
+/// Every output line starts with `epd` followed by name value pairs, so results of different builds can be compared.
+fn run_epd_suite(path: &str, go_state: &GoState, state: &mut WorkerState, shared_state: &Arc<Mutex<SharedState>>) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return report_error(state, &format!("Could not read {}: {}", path, e))
+    };
+    let positions = match epd::parse_epd(&text, Arc::clone(&state.board_constant_state)) {
+        Ok(positions) => positions,
+        Err(e) => return report_error(state, &format!("Could not parse {}: {}", path, e))
+    };
+
+    // A separate engine is used to collect the search metadata without printing info lines
+    let last_metadata: Arc<Mutex<Option<SearchMetadata>>> = Arc::new(Mutex::new(None));
+    let last_metadata_clone = Arc::clone(&last_metadata);
+    let shared_state_clone = Arc::clone(shared_state);
+    let mut engine = engine::from_name(
+        state.engine.get_name(),
+        &state.board,
+        Box::new(move |metadata| *last_metadata_clone.lock().unwrap() = Some(metadata)),
+        Box::new(|_| ()),
+        Box::new(move || shared_state_clone.lock().unwrap().stop_search),
+        Box::new(get_system_time)
+    );
+    for (name, value) in state.engine_options.iter() {
+        let _ = engine.set_option(name, value);
+    }
+
+    let limits = go_state.to_search_limits();
+    let san_list = |board: &Board, moves: &Vec<Move>| match moves.is_empty() {
+        true => "-".to_string(),
+        false => moves.iter().map(|mv| mv.to_san(board)).collect::<Vec<String>>().join(",")
+    };
+    let start_time = get_system_time();
+    let mut solved = 0;
+    for (i, position) in positions.iter().enumerate() {
+        // The analysis depth of the position is used when no limits are given
+        let limits = match position.depth {
+            Some(depth) if limits == SearchLimits::default() => SearchLimits::from_depth(depth),
+            _ => limits.clone()
+        };
+        engine.new_game();
+        *last_metadata.lock().unwrap() = None;
+        let pv = engine.search(&position.board, &limits);
+        let metadata = last_metadata.lock().unwrap().take();
+
+        let is_solved = pv.first().map_or(false, |mv| position.is_solved_by(mv));
+        if is_solved {
+            solved += 1;
+        }
+        println!("epd position {} id \"{}\" result {} move {} bm {} am {} depth {} nodes {} time {}",
+            i + 1,
+            position.id.as_deref().unwrap_or("-"),
+            if is_solved { "solved" } else { "failed" },
+            pv.first().map_or("0000".to_string(), |mv| mv.to_san(&position.board)),
+            san_list(&position.board, &position.best_moves),
+            san_list(&position.board, &position.avoid_moves),
+            metadata.as_ref().map_or(0, |metadata| metadata.depth),
+            metadata.as_ref().map_or(0, |metadata| metadata.nodes),
+            metadata.as_ref().map_or(0, |metadata| metadata.time.as_millis())
+        );
+    }
+    let score = if positions.is_empty() { 0.0 } else { 100.0 * solved as f64 / positions.len() as f64 };
+    println!("epd total solved {} failed {} positions {} score {:.1} time {}",
+        solved, positions.len() - solved, positions.len(), score, (get_system_time() - start_time).as_millis());
 }
 
 // =============== Input parsing ===================
@@ -367,15 +869,17 @@ fn parse_command(line: &str) -> CommandType {
         "stop" => CommandType::Stop,
         "uci" => CommandType::UCI,
         "ucinewgame" => CommandType::UCINewGame,
+        "setoption" => parse_uci_setoption_cmd(&words[1..]),
         "isready" => CommandType::IsReady,
         "help" | "h" => CommandType::Help,
         "display" | "disp" | "d" | "board" | "show" => CommandType::DisplayBoard,
         "eval" | "evaluate" | "score" => CommandType::EvaluateBoard,
         "divide" | "div" => {
             if words.len() > 1 {
-                match words[1].parse::<usize>() {
-                    Ok(n) => CommandType::Divide(n),
-                    Err(_) => CommandType::Error("Invalid divide perft depth".to_string())
+                match (words[1].parse::<usize>(), words.get(2).map(|n| n.parse::<usize>()).transpose()) {
+                    (Ok(n), Ok(expected)) => CommandType::Divide(n, expected),
+                    (Err(_), _) => CommandType::Error("Invalid divide perft depth".to_string()),
+                    (_, Err(_)) => CommandType::Error("Invalid expected node count".to_string())
                 }
             }
             else {
@@ -398,9 +902,10 @@ fn parse_command(line: &str) -> CommandType {
         }
         "perft" => {
             if words.len() > 1 {
-                match words[1].parse::<usize>() {
-                    Ok(n) => CommandType::Perft(n),
-                    Err(_) => CommandType::Error("Invalid perft depth".to_string())
+                match (words[1].parse::<usize>(), words.get(2).map(|n| n.parse::<usize>()).transpose()) {
+                    (Ok(n), Ok(expected)) => CommandType::Perft(n, expected),
+                    (Err(_), _) => CommandType::Error("Invalid perft depth".to_string()),
+                    (_, Err(_)) => CommandType::Error("Invalid expected node count".to_string())
                 }
             }
             else {
@@ -408,13 +913,51 @@ fn parse_command(line: &str) -> CommandType {
             }
         }
         "moves" | "getmoves" | "legalmoves" | "mvs" => CommandType::LegalMoves,
+        "tbprobe" | "probe" => CommandType::TablebaseProbe,
         "undo" | "unmake" => CommandType::Undo,
-        "perfttests" | "perftest" | "testperft" => CommandType::PerftTests,
+        "pgn" => parse_pgn_cmd(&words[1..]),
+        "perfttests" | "perftest" | "testperft" | "perftsuite" => {
+            // Optional perft EPD file and node limit, in any order
+            let path = words[1..].iter().find(|word| word.parse::<usize>().is_err()).map(|path| path.to_string());
+            let node_limit = words[1..].iter().find_map(|word| word.parse::<usize>().ok()).unwrap_or(20_000_000_000);
+            CommandType::PerftTests(path, node_limit)
+        }
+        "epd" | "testsuite" => {
+            if words.len() > 1 {
+                match parse_uci_command_go(&words[2..]) {
+                    CommandType::Go(go_state) if go_state.infinite => CommandType::Error("Test suites can not be run with infinite searches".to_string()),
+                    CommandType::Go(go_state) => CommandType::EpdSuite(words[1].to_string(), go_state),
+                    command => command
+                }
+            }
+            else {
+                CommandType::Error("Please specify an EPD file, optionally followed by go limits".to_string())
+            }
+        }
         _ => CommandType::Unknown
     };
     return command;
 }
 
+// Parse the PGN subcommands: load, next, prev and save
+fn parse_pgn_cmd(words: &[&str]) -> CommandType {
+    // Optional number after the subcommand or file name, defaults to 1
+    let number_argument = |index: usize| words.get(index).map_or(Ok(1), |n| n.parse::<usize>());
+    return match (words.first(), words.len()) {
+        (Some(&"load"), 2..=3) => match number_argument(2) {
+            Ok(game_number) => CommandType::PgnLoad(words[1].to_string(), game_number),
+            Err(_) => CommandType::Error("Invalid game number".to_string())
+        },
+        (Some(&"next") | Some(&"prev") | Some(&"back"), 1..=2) => match number_argument(1) {
+            Ok(plies) if words[0] == "next" => CommandType::PgnStep(plies as isize),
+            Ok(plies) => CommandType::PgnStep(-(plies as isize)),
+            Err(_) => CommandType::Error("Invalid number of plies".to_string())
+        },
+        (Some(&"save"), 1..=2) => CommandType::PgnSave(words.get(1).map(|path| path.to_string())),
+        _ => CommandType::Error("Usage: pgn load <file> [game number] | pgn next [plies] | pgn prev [plies] | pgn save [file]".to_string())
+    };
+}
+
 // Get a named argument value from a list of words, as an integer.
 // Returns None if no arguments found
 fn get_named_argument_as_num(words : &[&str], name: &str) -> Option<usize> {
@@ -429,6 +972,13 @@ fn get_named_argument_as_num(words : &[&str], name: &str) -> Option<usize> {
     }
 }
 
+// Get a named clock time in milliseconds. GUIs send negative times when the clock has run out, which count as 0.
+fn get_named_argument_as_clock(words : &[&str], name: &str) -> Option<usize> {
+    return get_named_argument(words, name)
+        .and_then(|n| n.parse::<i64>().ok())
+        .map(|n| n.max(0) as usize);
+}
+
 // Get a named arguments value from a list of words
 // Returns None if no arguments found
 fn get_named_argument(words : &[&str], name: &str) -> Option<String> {
@@ -494,6 +1044,21 @@ fn parse_uci_position_cmd(words_slice : &[&str]) -> CommandType {
     }
 }
 
+// Parse the UCI 'setoption name <id> [value <x>]' command into a CommandType::SetOption(NAME, VALUE)
+// Returns a CommandType::Error if the command is not well formed
+fn parse_uci_setoption_cmd(words : &[&str]) -> CommandType {
+    if words.len() < 2 || words[0] != "name" {
+        return CommandType::Error("Please specify an option name".to_string());
+    }
+    let value_index = words.iter().position(|x| *x == "value").unwrap_or(words.len());
+    if value_index <= 1 {
+        return CommandType::Error("Please specify an option name".to_string());
+    }
+    let name = words[1..value_index].join(" ");
+    let value = words.get(value_index + 1..).map(|value| value.join(" ")).unwrap_or_default();
+    return CommandType::SetOption(name, value);
+}
+
 // Parse the UCI 'go' command into a CommandType::Go
 // Returns a CommandType::Error if the command is not well formed
 fn parse_uci_command_go(words : &[&str]) -> CommandType {
@@ -505,20 +1070,22 @@ fn parse_uci_command_go(words : &[&str]) -> CommandType {
         black_time: None,
         black_time_increment: None,
         move_time: None,
+        moves_to_go: None,
+        infinite: false,
         search_moves: None
     };
 
     go_state.depth = get_named_argument_as_num(&words, "depth");
     go_state.nodes = get_named_argument_as_num(&words, "nodes");
-    go_state.white_time = get_named_argument_as_num(&words, "wtime");
+    go_state.white_time = get_named_argument_as_clock(&words, "wtime");
     go_state.white_time_increment = get_named_argument_as_num(&words, "winc");
-    go_state.black_time = get_named_argument_as_num(&words, "btime");
+    go_state.black_time = get_named_argument_as_clock(&words, "btime");
     go_state.black_time_increment = get_named_argument_as_num(&words, "binc");
     go_state.move_time = get_named_argument_as_num(&words, "movetime");
+    go_state.moves_to_go = get_named_argument_as_num(&words, "movestogo");
 
-    // Use an infinite depth if infinite is stated
-    if get_named_argument(&words, "infinite") != None || words.len() == 0 {
-        go_state.depth = Some(1000);
+    if get_named_argument(&words, "infinite") != None {
+        go_state.infinite = true;
     }
     // If only one argument is specified, treat it as depth
     else if words.len() == 1 {
@@ -0,0 +1,351 @@
+//! Opening books in the Polyglot format.
+//!
+//! A book file is a list of 16 byte entries sorted by position key: the key (`u64`), the move (`u16`), the weight
+//! (`u16`) and a learn value (`u32`), all big-endian. Keys are Polyglot Zobrist keys, which use their own random
+//! numbers and differ from the hash keys of `Board`. Moves store the destination square in bits 0 to 5, the origin
+//! square in bits 6 to 11 and the promotion piece in bits 12 to 14, with squares starting at A1. Castling is
+//! stored as the king capturing its own rook, `e1h1` instead of `e1g1`.
+
+mod polyglot_keys;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rand::Rng;
+
+use crate::core::bitboard::Board;
+use crate::core::move_list::MoveList;
+use crate::core::{Color, Move, Piece};
+use crate::core::variant::Variant;
+use crate::pgn::{GameResult, PgnGame};
+use polyglot_keys::POLYGLOT_KEYS;
+
+const ENTRY_SIZE: usize = 16;
+const CASTLING_OFFSET: usize = 768;
+const EP_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+/// Polyglot piece type of each piece type of `Piece`: pawn, knight, bishop, rook, queen, king
+const POLYGLOT_PIECE_TYPE: [usize; 6] = [0, 2, 1, 3, 4, 5];
+/// Promotion pieces in the order of their Polyglot codes, starting from 1
+const PROMOTION_CHARS: [char; 4] = ['n', 'b', 'r', 'q'];
+
+/// Polyglot square of a board square, board squares start at A8 and Polyglot squares at A1
+fn polyglot_square(pos: u8) -> u16 {
+    return (pos ^ 56) as u16;
+}
+
+/// Polyglot Zobrist key of the position
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for pos in 0..64 {
+        let piece = board.get_piece(pos);
+        if piece == Piece::Empty {
+            continue;
+        }
+        let piece_index = piece.to_u8() as usize;
+        // Black pieces come before white pieces of the same type
+        let kind = 2 * POLYGLOT_PIECE_TYPE[piece_index % 6] + piece.is_white() as usize;
+        key ^= POLYGLOT_KEYS[64 * kind + polyglot_square(pos) as usize];
+    }
+
+    // Castling rights are stored in the same order, white kingside, white queenside, black kingside, black queenside
+    let castling = board.get_castling_u8();
+    for index in 0..4 {
+        if castling & (1 << index) != 0 {
+            key ^= POLYGLOT_KEYS[CASTLING_OFFSET + index];
+        }
+    }
+
+    // The en passant file is only included if a pawn of the side to move stands next to the pawn which moved
+    if board.get_ep() > 0 {
+        let file = board.get_ep() - 1;
+        let (pawn_row_start, pawn) = match board.get_current_player() {
+            Color::White => (24, Piece::WhitePawn),
+            Color::Black => (32, Piece::BlackPawn)
+        };
+        let has_capturer = (file > 0 && board.get_piece(pawn_row_start + file - 1) == pawn)
+            || (file < 7 && board.get_piece(pawn_row_start + file + 1) == pawn);
+        if has_capturer {
+            key ^= POLYGLOT_KEYS[EP_OFFSET + file as usize];
+        }
+    }
+
+    if board.get_current_player() == Color::White {
+        key ^= POLYGLOT_KEYS[TURN_OFFSET];
+    }
+    return key;
+}
+
+/// Polyglot encoding of a move in `board`
+pub fn encode_move(board: &Board, mv: &Move) -> u16 {
+    // Castling is encoded as the king capturing its own rook
+    let to = match board.castling_index(mv) {
+        Some(index) => board.get_castling_rook(index),
+        None => mv.to
+    };
+    let promotion = match mv.promotion {
+        Piece::Empty => 0,
+        promotion => PROMOTION_CHARS.iter().position(|c| *c == promotion.as_char().to_ascii_lowercase()).unwrap() as u16 + 1
+    };
+    return polyglot_square(to) | polyglot_square(mv.from) << 6 | promotion << 12;
+}
+
+/// The legal move of `board` with the Polyglot encoding `encoded`, or `None` if there is no such move
+pub fn decode_move(board: &Board, encoded: u16) -> Option<Move> {
+    let mut moves = MoveList::empty();
+    board.get_moves(&mut moves, false);
+    return moves.iter().find(|mv| encode_move(board, mv) == encoded).copied();
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BookEntry {
+    pub key: u64,
+    /// Move in the Polyglot encoding, see `encode_move`
+    pub mv: u16,
+    /// Relative probability of playing the move
+    pub weight: u16,
+    /// Not used by the engine, kept when a book is written again
+    pub learn: u32
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BookError {
+    /// The file size is not a multiple of the entry size
+    WrongSize(usize)
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::WrongSize(size) => write!(f, "{} bytes is not a whole number of {} byte book entries", size, ENTRY_SIZE)
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Book {
+    /// Sorted by key
+    entries: Vec<BookEntry>
+}
+
+impl Book {
+    /// Create a book from entries in any order
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by_key(|entry| entry.key);
+        return Book { entries };
+    }
+
+    /// Read a Polyglot book file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, BookError> {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(BookError::WrongSize(bytes.len()));
+        }
+        let entries = bytes.chunks_exact(ENTRY_SIZE).map(|entry| BookEntry {
+            key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(entry[12..16].try_into().unwrap())
+        }).collect();
+        return Ok(Book::from_entries(entries));
+    }
+
+    /// Polyglot book file of this book, which can be read with `from_bytes`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        return self.entries.iter().flat_map(|entry| {
+            let mut bytes = Vec::with_capacity(ENTRY_SIZE);
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.mv.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+            bytes
+        }).collect();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    /// Legal book moves of the position with their weights. Entries whose move is not legal, which
+    /// can happen when two positions have the same key, are skipped. Books are for standard chess only.
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        if board.get_variant() != Variant::Standard {
+            return Vec::new();
+        }
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        return self.entries[start..].iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| decode_move(board, entry.mv).map(|mv| (mv, entry.weight)))
+            .collect();
+    }
+
+    /// Pick a book move at random with probabilities proportional to the weights,
+    /// or `None` if the position is not in the book
+    pub fn pick_move(&self, board: &Board) -> Option<Move> {
+        return self.pick_move_with(board, &mut rand::thread_rng());
+    }
+
+    pub fn pick_move_with(&self, board: &Board, rng: &mut impl Rng) -> Option<Move> {
+        let moves = self.moves(board);
+        let total_weight: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+        if total_weight == 0 {
+            return None;
+        }
+        let mut target = rng.gen_range(0..total_weight);
+        for (mv, weight) in moves {
+            if target < weight as u32 {
+                return Some(mv);
+            }
+            target -= weight as u32;
+        }
+        return None;
+    }
+}
+
+/// Creates a book from the opening moves of games. A move is weighted by the score of its side in
+/// the games it was played in, two points for a win and one for a draw.
+pub struct BookBuilder {
+    /// Moves after this many plies are not added
+    max_ply: usize,
+    /// Score and number of games of each move, by position key and encoded move
+    moves: BTreeMap<(u64, u16), (u64, u32)>
+}
+
+impl BookBuilder {
+    pub fn new(max_ply: usize) -> BookBuilder {
+        return BookBuilder { max_ply, moves: BTreeMap::new() };
+    }
+
+    pub fn add_game(&mut self, game: &PgnGame) {
+        let mut board = game.start_board.clone();
+        for pgn_move in game.moves.iter().take(self.max_ply) {
+            let score = match (game.result, board.get_current_player()) {
+                (GameResult::WhiteWon, Color::White) | (GameResult::BlackWon, Color::Black) => 2,
+                (GameResult::WhiteWon, Color::Black) | (GameResult::BlackWon, Color::White) => 0,
+                _ => 1
+            };
+            let stats = self.moves.entry((polyglot_key(&board), encode_move(&board, &pgn_move.mv))).or_insert((0, 0));
+            stats.0 += score;
+            stats.1 += 1;
+            board.make_move(&pgn_move.mv);
+        }
+    }
+
+    /// Create the book from the moves played in at least `min_games` games. Moves which never scored are left
+    /// out and the weights are scaled down if the largest one does not fit.
+    pub fn build(&self, min_games: u32) -> Book {
+        let included: Vec<_> = self.moves.iter()
+            .filter(|(_, (score, games))| *games >= min_games && *score > 0)
+            .collect();
+        let max_score = included.iter().map(|(_, (score, _))| *score).max().unwrap_or(0);
+        let scale = |score: u64| match max_score > u16::MAX as u64 {
+            true => (score * u16::MAX as u64 / max_score).max(1) as u16,
+            false => score as u16
+        };
+        let mut entries: Vec<BookEntry> = included.iter()
+            .map(|((key, mv), (score, _))| BookEntry { key: *key, mv: *mv, weight: scale(*score), learn: 0 })
+            .collect();
+        // Moves of a position are ordered by weight, the best move first
+        entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight));
+        return Book { entries };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::Move;
+    use crate::pgn::parse_pgn;
+    use super::{decode_move, encode_move, polyglot_key, Book, BookBuilder, BookEntry, BookError};
+
+    #[test]
+    fn test_polyglot_keys() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        // Keys from the Polyglot specification, of the positions after the moves from the starting position
+        let positions = [
+            ("", 0x463B96181691FC9C),
+            ("e2e4", 0x823C9B50FD114196),
+            ("e2e4 d7d5", 0x0756B94461C50FB0),
+            ("e2e4 d7d5 e4e5", 0x662FAFB965DB29D4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22A48B5A8E47FF78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652A607CA3F242C1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00FDD303C946BDD9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3C8123EA7B067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5C3F9B829B279560)
+        ];
+        for (moves, key) in positions {
+            let mut board = Board::new(Arc::clone(&constant_state));
+            for mv in moves.split_whitespace() {
+                board.make_move(&Move::try_from_algebraic(&board, mv).unwrap());
+            }
+            assert_eq!(polyglot_key(&board), key, "after '{}'", moves);
+        }
+    }
+
+    #[test]
+    fn test_move_encoding() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1", constant_state).unwrap();
+        let encode = |algebraic| encode_move(&board, &Move::try_from_algebraic(&board, algebraic).unwrap());
+        // e1 is square 4 and h1 is square 7
+        assert_eq!(encode("e1g1"), 4 << 6 | 7);
+        assert_eq!(encode("e1c1"), 4 << 6);
+        assert_eq!(encode("b7a8q"), 49 << 6 | 56 | 4 << 12);
+        assert_eq!(encode("b7b8n"), 49 << 6 | 57 | 1 << 12);
+        for algebraic in ["e1g1", "e1c1", "b7a8q", "b7b8n", "a1a8", "e1d2"] {
+            assert_eq!(decode_move(&board, encode(algebraic)).unwrap().to_algebraic(), algebraic);
+        }
+        // Not a legal move
+        assert_eq!(decode_move(&board, 0 << 6 | 63), None);
+    }
+
+    #[test]
+    fn test_book() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let pgn = "1. e4 e5 2. Nf3 1-0\n\n1. e4 c5 0-1\n\n1. d4 d5 1/2-1/2\n\n1. e4 e5 2. Bc4 1/2-1/2\n";
+        let games = parse_pgn(pgn, Arc::clone(&constant_state)).unwrap();
+        let mut builder = BookBuilder::new(2);
+        for game in games.iter() {
+            builder.add_game(game);
+        }
+        let book = builder.build(1);
+        let board = Board::new(Arc::clone(&constant_state));
+        let weighted_moves = |book: &Book, board: &Board| book.moves(board).iter()
+            .map(|(mv, weight)| (mv.to_algebraic(), *weight)).collect::<Vec<(String, u16)>>();
+        // e4 won once, lost once and drew once, d4 drew once
+        assert_eq!(weighted_moves(&book, &board), [("e2e4".to_string(), 3), ("d2d4".to_string(), 1)]);
+        let mut after_e4 = board.clone();
+        after_e4.make_move(&Move::try_from_algebraic(&board, "e2e4").unwrap());
+        assert_eq!(weighted_moves(&book, &after_e4), [("c7c5".to_string(), 2), ("e7e5".to_string(), 1)]);
+        // Moves after the maximum ply are not added
+        let mut after_e5 = after_e4.clone();
+        after_e5.make_move(&Move::try_from_algebraic(&after_e4, "e7e5").unwrap());
+        assert_eq!(book.pick_move(&after_e5), None);
+        // Moves played in fewer games are left out
+        assert_eq!(weighted_moves(&builder.build(2), &after_e4), [("e7e5".to_string(), 1)]);
+
+        let read_book = Book::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(read_book, book);
+        assert_eq!(Book::from_bytes(&[0; 20]), Err(BookError::WrongSize(20)));
+
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(1);
+        let picks: Vec<String> = (0..400).map(|_| book.pick_move_with(&board, &mut rng).unwrap().to_algebraic()).collect();
+        let e4_count = picks.iter().filter(|mv| *mv == "e2e4").count();
+        assert!(e4_count > 250 && e4_count < 350 && e4_count + picks.iter().filter(|mv| *mv == "d2d4").count() == 400);
+
+        // Entries which are not legal in the position are skipped
+        let illegal = Book::from_entries(vec![BookEntry { key: polyglot_key(&board), mv: 4 << 6 | 36, weight: 1, learn: 0 }]);
+        assert_eq!(illegal.pick_move(&board), None);
+    }
+}
@@ -1,6 +1,11 @@
+mod reference_movegen;
+
+use std::fmt;
+
 use constants::BitboardRuntimeConstants;
 
-use crate::core::{move_list::{MoveList, MoveListCollection}, Move};
+use crate::core::{move_list::{MoveList, MoveListCollection}, Move, MoveParseError};
+use crate::epd::{self, EpdPosition};
 use super::core::bitboard::*;
 
 const USE_LEAF_NODE_OPTIMIZATION : bool = true;
@@ -45,44 +50,181 @@ pub fn divide(depth: usize, board: &mut Board, reserved_moves: &mut MoveListColl
     return results.iter().fold(0, |l, r| l + r.1);
 }
 
-pub fn board_from_moves(board: &Board, moves: &Vec<String>) -> Board {
+/// Play `moves` in UCI notation from `board`. Fails on the first move which is not legal.
+pub fn board_from_moves(board: &Board, moves: &Vec<String>) -> Result<Board, MoveParseError> {
     let mut board_copy = board.clone();
     for mv_algebraic in moves {
-        let mv = Move::from_algebraic(&board_copy, mv_algebraic);
+        let mv = Move::try_from_algebraic(&board_copy, mv_algebraic)?;
         board_copy.make_move(&mv);
     }
-    return board_copy;
+    return Ok(board_copy);
+}
+
+/// Standard perft positions from chessprogrammingwiki.com, with expected node counts as `D<depth>` operations
+pub const DEFAULT_PERFT_SUITE: &str = "\
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;D5 4865609
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ;D1 48 ;D2 2039 ;D3 97862 ;D4 4085603 ;D5 193690690 ;D6 8031647685
+8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191 ;D3 2812 ;D4 43238 ;D5 674624 ;D6 11030083 ;D7 178633661 ;D8 3009794393
+r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1 ;D1 6 ;D2 264 ;D3 9467 ;D4 422333 ;D5 15833292 ;D6 706045033
+rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8 ;D1 44 ;D2 1486 ;D3 62379 ;D4 2103487 ;D5 89941194
+r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10 ;D1 46 ;D2 2079 ;D3 89890 ;D4 3894594 ;D5 164075551 ;D6 6923051137
+# Manually added extra case (promotions + ep)
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P2P/2N2Q2/PPPBBPp1/1R2K2R b Kkq h3 0 4 ;D1 50 ;D2 2069 ;D3 99997 ;D4 4235277
+";
+
+/// Chess960 perft positions from chessprogrammingwiki.com, with castling rights in Shredder-FEN
+pub const CHESS960_PERFT_SUITE: &str = "\
+bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 ;D1 21 ;D2 528 ;D3 12189 ;D4 326672 ;D5 8146062 ;D6 227689589
+2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9 ;D1 21 ;D2 807 ;D3 18002 ;D4 667366 ;D5 16253601 ;D6 590751109
+b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9 ;D1 20 ;D2 479 ;D3 10471 ;D4 273318 ;D5 6417013 ;D6 177654692
+qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9 ;D1 22 ;D2 593 ;D3 13440 ;D4 382958 ;D5 9183776 ;D6 274103539
+1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9 ;D1 28 ;D2 1120 ;D3 31058 ;D4 1171749 ;D5 34030312 ;D6 1250970898
+qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9 ;D1 29 ;D2 899 ;D3 26578 ;D4 824055 ;D5 24851983 ;D6 775718317
+q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9 ;D1 30 ;D2 860 ;D3 24566 ;D4 732757 ;D5 21093346 ;D6 649209803
+qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9 ;D1 25 ;D2 635 ;D3 17054 ;D4 465806 ;D5 13203304 ;D6 377184252
+qnnbbrkr/1p2ppp1/2pp3p/p7/1P5P/2NP4/P1P1PPP1/Q1NBBRKR w HFhf - 0 9 ;D1 24 ;D2 572 ;D3 15243 ;D4 384260 ;D5 11110203 ;D6 293989890
+qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9 ;D1 28 ;D2 811 ;D3 23175 ;D4 679699 ;D5 19836606 ;D6 594527992
+";
+
+/// Perft positions of King of the Hill, Three-check, Antichess and Crazyhouse, with the rules given by the `variant` operation
+pub const VARIANT_PERFT_SUITE: &str = "\
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;D5 4865609 ;variant kingofthehill
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 ;D1 48 ;D2 2039 ;D3 97862 ;D4 4085603 ;variant kingofthehill
+8/8/8/2k5/8/4K3/8/8 w - - 0 1 ;D1 7 ;D2 46 ;D3 272 ;D4 1916 ;D5 12341 ;variant kingofthehill
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;variant 3check
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1 ;D1 48 ;D2 2039 ;D3 97848 ;D4 4081798 ;variant 3check
+r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 1+3 0 1 ;D1 42 ;D2 1231 ;D3 49106 ;D4 1447873 ;variant 3check
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1 ;D1 20 ;D2 400 ;D3 8067 ;D4 153299 ;D5 2732672 ;variant antichess
+8/1p6/8/8/8/8/P7/8 w - - 0 1 ;D1 2 ;D2 4 ;D3 4 ;D4 3 ;D5 1 ;D6 0 ;variant antichess
+8/2p5/8/8/8/8/P7/8 w - - 0 1 ;D1 2 ;D2 4 ;D3 4 ;D4 4 ;D5 4 ;D6 4 ;D7 4 ;D8 4 ;D9 12 ;D10 36 ;D11 312 ;D12 2557 ;variant antichess
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;D5 4888832 ;variant crazyhouse
+2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1 ;D1 301 ;D2 75353 ;D3 15634852 ;variant crazyhouse
+r1b1kb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1qPP/RNBQK2R[Pp] w KQkq - 0 5 ;D1 1 ;D2 58 ;D3 5411 ;D4 229479 ;variant crazyhouse
+";
+
+/// Run the standard, the Chess960 and the variant perft suites
+pub fn perft_tests(runtime_constants: std::sync::Arc<BitboardRuntimeConstants>, node_limit: usize) -> bool {
+    let mut positions = epd::parse_epd(DEFAULT_PERFT_SUITE, std::sync::Arc::clone(&runtime_constants)).unwrap();
+    positions.extend(epd::parse_epd(CHESS960_PERFT_SUITE, std::sync::Arc::clone(&runtime_constants)).unwrap());
+    positions.extend(epd::parse_epd(VARIANT_PERFT_SUITE, runtime_constants).unwrap());
+    return perft_suite(&positions, node_limit);
 }
 
-pub fn perft_tests(runtime_constants: std::rc::Rc<BitboardRuntimeConstants>, node_limit: usize) -> bool {
-    let tests: Vec<(&str, Vec<u64>)> = vec![
-            // These are from chessprogrammingwiki.com
-            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", vec![20, 400, 8902, 197_281, 4_865_609]),
-            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", vec![48, 2039, 97_862, 4_085_603, 193_690_690, 8_031_647_685]),
-            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ", vec![14, 191, 2812, 43_238, 674_624, 11_030_083, 178_633_661, 3_009_794_393]),
-            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", vec![6, 264, 9_467, 422_333, 15_833_292, 706_045_033]),
-            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", vec![44, 1_486, 62_379, 2_103_487, 89_941_194]),
-            ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", vec![46, 2_079, 89_890, 3_894_594, 164_075_551, 6_923_051_137]), 
-            // Manually added extra case (promotions + ep)
-            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P2P/2N2Q2/PPPBBPp1/1R2K2R b Kkq h3 0 4", vec![50, 2069, 99_997, 42_35_277])
-        ];
+/// Run perft on every position of a perft EPD suite and compare with the expected node counts.
+/// Depths where the expected node count exceeds `node_limit` are skipped.
+/// On a mismatch the position is bisected down to a minimal failing position, which is printed.
+pub fn perft_suite(positions: &[EpdPosition], node_limit: usize) -> bool {
     let mut reserved_moves = MoveListCollection::new();
     let mut success = true;
-    for (fen, results) in tests {
-        let mut board = Board::from_fen(fen, runtime_constants.clone());
-        println!("Running fen test on position {fen}");
-        for (i, result) in results.iter().enumerate() {
-            if *result as usize >= node_limit {
+    for position in positions {
+        let mut board = position.board.clone();
+        println!("Running fen test on position {}", board.to_fen());
+        for (depth, expected) in position.perft_counts.iter() {
+            if *expected >= node_limit {
                 // Stop searching for positions if the expected node count exceeds the node limit
                 break;
             }
-            let found = perft(i + 1, &mut board, &mut reserved_moves);
-            if found != (*result) as usize {
-                println!("Error on depth: {}, expected: {},  found: {}", i + 1, result, found);
+            let found = perft(*depth, &mut board, &mut reserved_moves);
+            if found != *expected {
+                println!("Error on depth: {}, expected: {},  found: {}", depth, expected, found);
+                report_perft_error(&board, *depth);
                 success = false;
                 break;
             }
         }
     }
     return success;
-}
\ No newline at end of file
+}
+
+/// Bisect a position where `perft(depth)` is wrong and print the minimal failing position
+pub fn report_perft_error(board: &Board, depth: usize) {
+    println!("Bisecting with the reference move generator...");
+    match find_perft_error(board, depth) {
+        Some(error) => println!("{}", error),
+        None => println!("The reference move generator agrees on all moves, the error is in make_move or in the expected node count")
+    }
+}
+
+/// A minimal position where the move generator disagrees with the reference move generator
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PerftError {
+    /// Moves leading from the bisected position to the failing position
+    pub path: Vec<Move>,
+    /// FEN of the failing position
+    pub fen: String,
+    /// Legal moves which were not generated
+    pub missing_moves: Vec<Move>,
+    /// Generated moves which are illegal or were generated more than once
+    pub extra_moves: Vec<Move>,
+    /// Moves after which `unmake_move` does not restore the position
+    pub unmake_errors: Vec<Move>
+}
+
+impl fmt::Display for PerftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let moves_str = |moves: &Vec<Move>| if moves.is_empty() {
+            "-".to_string()
+        } else {
+            moves.iter().map(|mv| mv.to_algebraic()).collect::<Vec<String>>().join(" ")
+        };
+        writeln!(f, "Minimal failing position: {}", self.fen)?;
+        writeln!(f, "Reached by: {}", moves_str(&self.path))?;
+        writeln!(f, "Missing moves: {}", moves_str(&self.missing_moves))?;
+        writeln!(f, "Extra moves: {}", moves_str(&self.extra_moves))?;
+        write!(f, "Moves not restored by unmake: {}", moves_str(&self.unmake_errors))
+    }
+}
+
+/// Bisect a position where `perft(depth)` is wrong. At every level the generated moves are compared
+/// with a slow reference move generator. If they agree, the first move whose subtree has a different
+/// node count than the reference is followed, until a position with wrong moves is found.
+/// Returns None if the reference move generator agrees on the whole tree.
+pub fn find_perft_error(board: &Board, depth: usize) -> Option<PerftError> {
+    let mut board = board.clone();
+    let mut reserved_moves = MoveListCollection::new();
+    let mut path = Vec::new();
+    let same_move = |a: &Move, b: &Move| a.from == b.from && a.to == b.to && a.promotion == b.promotion;
+    for depth in (1..=depth).rev() {
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let reference_moves = reference_movegen::reference_moves(&board);
+
+        let missing_moves: Vec<Move> = reference_moves.iter()
+            .filter(|mv| !moves.iter().any(|generated| same_move(mv, generated)))
+            .copied().collect();
+        let extra_moves: Vec<Move> = moves.iter().enumerate()
+            .filter(|(i, mv)| !reference_moves.iter().any(|reference| same_move(mv, reference))
+                || moves.iter().take(*i).any(|earlier| same_move(mv, earlier)))
+            .map(|(_, mv)| *mv).collect();
+        let board_before = board.clone();
+        let unmake_errors: Vec<Move> = moves.iter().filter(|mv| {
+            board.make_move(mv);
+            board.unmake_move(mv);
+            let restored = board.to_fen() == board_before.to_fen() && board.get_hashkey() == board_before.get_hashkey();
+            // Continue checking from the correct position
+            board = board_before.clone();
+            return !restored;
+        }).copied().collect();
+        if !missing_moves.is_empty() || !extra_moves.is_empty() || !unmake_errors.is_empty() {
+            return Some(PerftError { path, fen: board.to_fen(), missing_moves, extra_moves, unmake_errors });
+        }
+
+        if depth == 1 {
+            break;
+        }
+        let diverging_move = moves.iter().find(|mv| {
+            board.make_move(mv);
+            let diverges = perft(depth - 1, &mut board, &mut reserved_moves) != reference_movegen::reference_perft(&board, depth - 1);
+            board.unmake_move(mv);
+            return diverges;
+        }).copied();
+        match diverging_move {
+            Some(mv) => {
+                board.make_move(&mv);
+                path.push(mv);
+            }
+            None => return None
+        }
+    }
+    return None;
+}
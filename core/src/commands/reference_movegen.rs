@@ -0,0 +1,260 @@
+//! Slow and simple move generator, only used as a reference when looking for bugs in the bitboard move generator.
+//!
+//! Moves are found by walking the board square by square. Pseudo-legal moves are made on a copy of the
+//! board and are removed if the king can be captured afterwards. In Antichess all pseudo-legal moves
+//! are legal, unless a capture is possible. Drops of Crazyhouse are tried on every empty square.
+
+use crate::core::{bitboard::Board, variant::Variant, Color, Move, Piece};
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_OFFSETS: [(i8, i8); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+/// Pieces of one color in the order pawn, knight, bishop, rook, queen, king
+fn pieces_of(color: Color) -> [Piece; 6] {
+    return match color {
+        Color::White => [Piece::WhitePawn, Piece::WhiteKnight, Piece::WhiteBishop, Piece::WhiteRook, Piece::WhiteQueen, Piece::WhiteKing],
+        Color::Black => [Piece::BlackPawn, Piece::BlackKnight, Piece::BlackBishop, Piece::BlackRook, Piece::BlackQueen, Piece::BlackKing]
+    };
+}
+
+fn color_of(piece: Piece) -> Option<Color> {
+    return match piece {
+        Piece::Empty => None,
+        piece if piece.is_white() => Some(Color::White),
+        _ => Some(Color::Black)
+    };
+}
+
+fn square(x: i8, y: i8) -> Option<u8> {
+    if (0..8).contains(&x) && (0..8).contains(&y) {
+        return Some((y * 8 + x) as u8);
+    }
+    return None;
+}
+
+/// Is `pos` attacked by any piece of `attacker`
+fn is_attacked(board: &Board, pos: u8, attacker: Color) -> bool {
+    let [pawn, knight, bishop, rook, queen, king] = pieces_of(attacker);
+    let (x, y) = ((pos % 8) as i8, (pos / 8) as i8);
+    let attacks_from = |offsets: &[(i8, i8)], piece: Piece| offsets.iter()
+        .filter_map(|(dx, dy)| square(x + dx, y + dy))
+        .any(|from| board.get_piece(from) == piece);
+    let slides_from = |directions: &[(i8, i8)], piece: Piece| directions.iter().any(|(dx, dy)| {
+        let (mut to_x, mut to_y) = (x + dx, y + dy);
+        while let Some(from) = square(to_x, to_y) {
+            match board.get_piece(from) {
+                Piece::Empty => (),
+                found => return found == piece || found == queen
+            }
+            to_x += dx;
+            to_y += dy;
+        }
+        return false;
+    });
+    // White pawns move towards lower rows, so they attack from the row below
+    let pawn_row_offset = if attacker == Color::White { 1 } else { -1 };
+    return attacks_from(&[(-1, pawn_row_offset), (1, pawn_row_offset)], pawn)
+        || attacks_from(&KNIGHT_OFFSETS, knight)
+        || attacks_from(&KING_OFFSETS, king)
+        || slides_from(&ROOK_DIRECTIONS, rook)
+        || slides_from(&BISHOP_DIRECTIONS, bishop);
+}
+
+/// Add a move to `to` unless it is occupied by a piece of the player to move
+fn add_move(board: &Board, from: u8, to: u8, moves: &mut Vec<Move>) {
+    let captured = board.get_piece(to);
+    if color_of(captured) != Some(board.get_current_player()) {
+        moves.push(Move::new(board, from, to, Piece::Empty, captured));
+    }
+}
+
+fn add_pawn_moves(board: &Board, from: u8, moves: &mut Vec<Move>) {
+    let player = board.get_current_player();
+    let [_, knight, bishop, rook, queen, king] = pieces_of(player);
+    let mut promotions = vec![queen, rook, bishop, knight];
+    if board.get_variant() == Variant::Antichess {
+        promotions.push(king);
+    }
+    let (x, y) = ((from % 8) as i8, (from / 8) as i8);
+    let (direction, start_row, promotion_row, ep_row) = match player {
+        Color::White => (-1, 6, 0, 2),
+        Color::Black => (1, 1, 7, 5)
+    };
+    let mut add_pawn_move = |to: u8, captured: Piece| {
+        if (to / 8) as i8 == promotion_row {
+            for promotion in promotions.iter().copied() {
+                moves.push(Move::new(board, from, to, promotion, captured));
+            }
+        }
+        else {
+            moves.push(Move::new(board, from, to, Piece::Empty, captured));
+        }
+    };
+
+    if let Some(to) = square(x, y + direction) {
+        if board.get_piece(to) == Piece::Empty {
+            add_pawn_move(to, Piece::Empty);
+            if let Some(double_to) = square(x, y + 2 * direction) {
+                if y == start_row && board.get_piece(double_to) == Piece::Empty {
+                    add_pawn_move(double_to, Piece::Empty);
+                }
+            }
+        }
+    }
+    for dx in [-1, 1] {
+        if let Some(to) = square(x + dx, y + direction) {
+            let captured = board.get_piece(to);
+            if color_of(captured) == Some(player.next_player()) {
+                add_pawn_move(to, captured);
+            }
+            // En passant moves store an empty captured piece, like the bitboard move generator
+            else if captured == Piece::Empty && board.get_ep() as i8 == x + dx + 1 && y + direction == ep_row {
+                add_pawn_move(to, Piece::Empty);
+            }
+        }
+    }
+}
+
+/// Castling moves, where the king ends on the g- or c-file and the rook next to it. The squares between
+/// the king and the rook and their destinations must be empty, and the king may not pass through an attacked
+/// square. That the king does not land in check is checked with the other moves.
+fn add_castling_moves(board: &Board, king_pos: u8, moves: &mut Vec<Move>) {
+    let player = board.get_current_player();
+    let opponent = player.next_player();
+    let first_bit = if player == Color::White { 0 } else { 2 };
+    let back_rank = king_pos - king_pos % 8;
+    let squares = |a: u8, b: u8| a.min(b)..=a.max(b);
+    for bit in first_bit..first_bit + 2 {
+        if board.get_castling_u8() & (1 << bit) == 0 {
+            continue;
+        }
+        let rook_pos = board.get_castling_rook(bit);
+        let (king_to, rook_to) = match bit % 2 {
+            0 => (back_rank + 6, back_rank + 5),
+            _ => (back_rank + 2, back_rank + 3)
+        };
+        let empty = squares(king_pos, king_to).chain(squares(rook_pos, rook_to))
+            .all(|pos| pos == king_pos || pos == rook_pos || board.get_piece(pos) == Piece::Empty);
+        let safe = squares(king_pos, king_to).all(|pos| !is_attacked(board, pos, opponent));
+        if empty && safe {
+            // Chess960 castling is encoded as the king capturing its own rook
+            let to = if board.is_chess960() { rook_pos } else { king_to };
+            moves.push(Move::new(board, king_pos, to, Piece::Empty, Piece::Empty));
+        }
+    }
+}
+
+/// Add drops of the pieces in the pocket of the player to move on the empty squares, pawns not on the back ranks
+fn add_drops(board: &Board, moves: &mut Vec<Move>) {
+    let pieces = pieces_of(board.get_current_player());
+    for piece in pieces[..5].iter().copied() {
+        if board.get_pocket_count(piece) == 0 {
+            continue;
+        }
+        for to in 0..64u8 {
+            let back_rank = !(8..56).contains(&to);
+            if board.get_piece(to) == Piece::Empty && !(piece == pieces[0] && back_rank) {
+                moves.push(Move::new_drop(board, piece, to));
+            }
+        }
+    }
+}
+
+/// Returns true if the king of the opponent is on the hill, the opponent has given three checks or the player
+/// to move has no pieces left, in the variants with these rules
+fn is_variant_game_end(board: &Board) -> bool {
+    let player = board.get_current_player();
+    let opponent = player.next_player();
+    return match board.get_variant() {
+        Variant::Standard | Variant::Crazyhouse => false,
+        Variant::KingOfTheHill => [27, 28, 35, 36].iter().any(|pos| board.get_piece(*pos) == pieces_of(opponent)[5]),
+        Variant::ThreeCheck => board.get_checks_given(opponent) >= 3,
+        Variant::Antichess => (0..64).all(|pos| color_of(board.get_piece(pos)) != Some(player))
+    };
+}
+
+/// Generate all legal moves of the player to move
+pub fn reference_moves(board: &Board) -> Vec<Move> {
+    let player = board.get_current_player();
+    let [pawn, knight, bishop, rook, queen, king] = pieces_of(player);
+    let antichess = board.get_variant() == Variant::Antichess;
+    let mut moves = Vec::new();
+    if is_variant_game_end(board) {
+        return moves;
+    }
+    for from in 0..64u8 {
+        let piece = board.get_piece(from);
+        let (x, y) = ((from % 8) as i8, (from / 8) as i8);
+        let add_steps = |offsets: &[(i8, i8)], moves: &mut Vec<Move>| {
+            for to in offsets.iter().filter_map(|(dx, dy)| square(x + dx, y + dy)) {
+                add_move(board, from, to, moves);
+            }
+        };
+        let slide = |directions: &[(i8, i8)], moves: &mut Vec<Move>| {
+            for (dx, dy) in directions {
+                let (mut to_x, mut to_y) = (x + dx, y + dy);
+                while let Some(to) = square(to_x, to_y) {
+                    add_move(board, from, to, moves);
+                    if board.get_piece(to) != Piece::Empty {
+                        break;
+                    }
+                    to_x += dx;
+                    to_y += dy;
+                }
+            }
+        };
+        if piece == pawn {
+            add_pawn_moves(board, from, &mut moves);
+        }
+        else if piece == knight {
+            add_steps(&KNIGHT_OFFSETS, &mut moves);
+        }
+        else if piece == bishop || piece == queen {
+            slide(&BISHOP_DIRECTIONS, &mut moves);
+        }
+        if piece == rook || piece == queen {
+            slide(&ROOK_DIRECTIONS, &mut moves);
+        }
+        else if piece == king {
+            add_steps(&KING_OFFSETS, &mut moves);
+            if !antichess {
+                add_castling_moves(board, from, &mut moves);
+            }
+        }
+    }
+    if board.get_variant() == Variant::Crazyhouse {
+        add_drops(board, &mut moves);
+    }
+    if antichess {
+        let is_capture = |mv: &Move| mv.captured != Piece::Empty || (board.get_piece(mv.from) == pawn && mv.from % 8 != mv.to % 8);
+        if moves.iter().any(is_capture) {
+            moves.retain(is_capture);
+        }
+        return moves;
+    }
+    moves.retain(|mv| {
+        let mut board_copy = board.clone();
+        board_copy.make_move(mv);
+        let king_pos = (0..64u8).find(|pos| board_copy.get_piece(*pos) == king);
+        return king_pos.is_none_or(|pos| !is_attacked(&board_copy, pos, player.next_player()));
+    });
+    return moves;
+}
+
+/// Count the leaf nodes at `depth` using the reference move generator
+pub fn reference_perft(board: &Board, depth: usize) -> usize {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = reference_moves(board);
+    if depth == 1 {
+        return moves.len();
+    }
+    return moves.iter().map(|mv| {
+        let mut board_copy = board.clone();
+        board_copy.make_move(mv);
+        return reference_perft(&board_copy, depth - 1);
+    }).sum();
+}
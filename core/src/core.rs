@@ -1,5 +1,7 @@
 pub mod bitboard;
 pub mod move_list;
+pub mod variant;
+mod san;
 
 #[cfg(test)]
 pub(crate) mod tests;
@@ -10,11 +12,12 @@ use bitboard::{constants::{BISHOP_VALUE, KING_VALUE, KNIGHT_VALUE, PAWN_VALUE, Q
 use strum_macros::EnumIter;
 use num;
 use num_derive::{FromPrimitive, ToPrimitive};
+use move_list::MoveList;
 
 pub static STARTING_POS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumIter, FromPrimitive, ToPrimitive)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, FromPrimitive, ToPrimitive)]
 /// Represents a piece or square on the board
 /// 
 /// #### NOTE
@@ -43,6 +46,8 @@ pub enum Color {
     Black,
 }
 
+/// A move of the piece on `from` to `to`. Drops of Crazyhouse have the same `from` and `to` square,
+/// and the dropped piece as `promotion`.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Move {
     pub from : u8,
@@ -198,6 +203,9 @@ impl Move {
     pub fn to_algebraic(&self) -> String {
         let from = pos_to_algebraic_pos(self.from % 8, self.from / 8);
         let to = pos_to_algebraic_pos(self.to % 8, self.to / 8);
+        if self.is_drop() {
+            return format!("{}@{}", self.promotion.as_char().to_ascii_uppercase(), to);
+        }
         let mut algebraic_move = format!("{}{}", from, to);
         if self.promotion != Piece::Empty {
             let promotion_piece_offset = if self.promotion.is_white() {6} else {0};
@@ -218,17 +226,38 @@ impl Move {
         }
     }
 
+    /// Create a drop of `piece` from the pocket on `to`, in Crazyhouse
+    pub fn new_drop(board: &Board, piece: Piece, to: u8) -> Move {
+        return Move::new(board, to, to, piece, Piece::Empty);
+    }
+
+    /// Returns true if the move drops a piece from the pocket, see `Move`
+    pub fn is_drop(&self) -> bool {
+        return self.from == self.to;
+    }
+
     pub fn from_pos(board: &Board, from_x: usize, from_y: usize, to_x: usize, to_y: usize) -> Move {
         return Move::new(
             board, 
             (from_y * 8 + from_x) as u8,
             (to_y * 8 + to_x) as u8,
-            board.get_piece_pos(to_x, to_y),
-            Piece::Empty
+            Piece::Empty,
+            board.get_piece_pos(to_x, to_y)
         );
     }
 
+    /// Create a move from UCI notation, for example `e2e4`, `e7e8q` or `N@f3`, without checking that it is legal.
+    /// Panics if the string is malformed. Use `try_from_algebraic` for untrusted input.
     pub fn from_algebraic(board: &Board, algebraic: &str) -> Move {
+        if algebraic.chars().nth(1) == Some('@') {
+            let (to_x, to_y) = algebraic_pos_to_pos(&algebraic[2..4]);
+            let piece = algebraic.chars().nth(0).unwrap().to_ascii_uppercase();
+            let piece = match board.get_current_player() {
+                Color::White => Piece::from_char(piece),
+                Color::Black => Piece::from_char(piece.to_ascii_lowercase())
+            };
+            return Move::new_drop(board, piece, to_y * 8 + to_x);
+        }
         let from_x = algebraic.chars().nth(0).unwrap() as usize - 'a' as usize;
         let from_y = 7 - (algebraic.chars().nth(1).unwrap() as usize - '1' as usize);
         let to_x = algebraic.chars().nth(2).unwrap() as usize - 'a' as usize;
@@ -246,10 +275,55 @@ impl Move {
         };
         let mut mv = Move::from_pos(board, from_x, from_y, to_x, to_y);
         mv.promotion = promotion;
-        mv.captured = board.get_piece_pos(to_x, to_y);
         return mv;
     }
 
+    /// Resolve a move in UCI notation, for example `e2e4`, `e7e8q` or the Crazyhouse drop `N@f3`, to one
+    /// of the legal moves in `board`
+    pub fn try_from_algebraic(board: &Board, algebraic: &str) -> Result<Move, MoveParseError> {
+        let chars: Vec<char> = algebraic.chars().collect();
+        let is_column = |c: char| ('a'..='h').contains(&c);
+        let is_row = |c: char| ('1'..='8').contains(&c);
+        if chars.len() == 4 && chars[1] == '@' {
+            if !matches!(chars[0].to_ascii_uppercase(), 'P' | 'N' | 'B' | 'R' | 'Q') || !is_column(chars[2]) || !is_row(chars[3]) {
+                return Err(MoveParseError::InvalidFormat(algebraic.to_string()));
+            }
+            let (to_x, to_y) = algebraic_pos_to_pos(&algebraic[2..4]);
+            let mut moves = MoveList::empty();
+            board.get_moves(&mut moves, false);
+            let legal_drop = moves.iter().find(|mv| mv.is_drop() && mv.to == to_y * 8 + to_x
+                && mv.promotion.as_char().eq_ignore_ascii_case(&chars[0]));
+            return legal_drop.copied().ok_or_else(|| MoveParseError::IllegalMove(algebraic.to_string()));
+        }
+        if !(chars.len() == 4 || chars.len() == 5) || !is_column(chars[0]) || !is_row(chars[1])
+                || !is_column(chars[2]) || !is_row(chars[3]) {
+            return Err(MoveParseError::InvalidFormat(algebraic.to_string()));
+        }
+        let promotion = match chars.get(4).map(|c| c.to_ascii_lowercase()) {
+            None => None,
+            Some(c @ ('q' | 'r' | 'b' | 'n' | 'k')) => Some(c),
+            Some(_) => return Err(MoveParseError::InvalidFormat(algebraic.to_string()))
+        };
+        let (from_x, from_y) = algebraic_pos_to_pos(&algebraic[0..2]);
+        let (to_x, to_y) = algebraic_pos_to_pos(&algebraic[2..4]);
+        let from = from_y * 8 + from_x;
+        let to = to_y * 8 + to_x;
+
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let legal_move = moves.iter().find(|mv| {
+            let mv_promotion = match mv.promotion {
+                Piece::Empty => None,
+                piece => Some(piece.as_char().to_ascii_lowercase())
+            };
+            return mv.from == from && mv.to == to && mv_promotion == promotion && !mv.is_drop();
+        });
+        return match legal_move {
+            Some(mv) => Ok(*mv),
+            None => Err(MoveParseError::IllegalMove(algebraic.to_string()))
+        };
+    }
+
     pub fn is_quiet(&self) -> bool {
         return self.captured == Piece::Empty;
     }
@@ -259,6 +333,32 @@ impl Move {
     }
 }
 
+/// Reasons for rejecting a move in UCI notation
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MoveParseError {
+    /// The move is not of the form `e2e4`, `e7e8q` or `N@f3`
+    InvalidFormat(String),
+    /// The move is not valid Standard Algebraic Notation
+    InvalidSan(String),
+    /// The move is not among the legal moves of the position
+    IllegalMove(String),
+    /// The move matches more than one legal move of the position
+    AmbiguousMove(String)
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveParseError::InvalidFormat(mv) => write!(f, "invalid move '{}', expected a move like e2e4, e7e8q or N@f3", mv),
+            MoveParseError::InvalidSan(mv) => write!(f, "invalid move '{}', expected a move like Nf3, exd5, O-O, e8=Q or N@f3", mv),
+            MoveParseError::IllegalMove(mv) => write!(f, "illegal move '{}'", mv),
+            MoveParseError::AmbiguousMove(mv) => write!(f, "ambiguous move '{}'", mv)
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
 impl fmt::Display for Move {
     /// Return a string representation of the board. Used for debugging.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -271,5 +371,15 @@ pub enum GameStatus {
     InProgress,
     WhiteWon,
     BlackWon,
-    Stalemate
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial
+}
+
+impl GameStatus {
+    pub fn is_draw(&self) -> bool {
+        return matches!(self, GameStatus::Stalemate | GameStatus::ThreefoldRepetition | 
+            GameStatus::FiftyMoveRule | GameStatus::InsufficientMaterial);
+    }
 }
\ No newline at end of file
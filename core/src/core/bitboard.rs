@@ -1,18 +1,27 @@
 use crate::core::*;
 
 mod formatting;
+pub use formatting::FenError;
 pub mod constants;
 mod helpers;
 mod move_gen;
 mod see;
-mod evaluation;
+mod variants;
+mod crazyhouse;
+pub mod evaluation;
+pub mod eval_params;
+pub mod nnue;
 use constants::*;
+use eval_params::EvalParams;
+use evaluation::TaperedScore;
+use nnue::Accumulator;
 use move_list::MoveList;
 use move_list::SearchResult;
+use variant::Variant;
 
 #[cfg(target_feature = "bmi2")]
 use std::arch::x86_64::{_pdep_u64, _pext_u64};
-use std::rc::Rc;
+use std::sync::Arc;
 // Use count_ones() for popcnt
 
 /// Represents a chess board.
@@ -27,24 +36,56 @@ pub struct Board {
     hash_key: u64,
     ep: u8,
     castling: u8,
+    /// Squares of the rooks of the castling rights, indexed like the bits of `castling`
+    castling_rooks: [u8; 4],
+    /// Castling moves are encoded as the king capturing its own rook, as in Chess960 UCI notation.
+    /// Otherwise the king moves two squares.
+    chess960: bool,
+    variant: Variant,
+    /// Checks given by white and black in Three-check
+    checks_given: [u8; 2],
+    /// Number of pieces in the pockets of Crazyhouse, indexed by `Piece`. White drops the white pieces.
+    pockets: [u8; 12],
+    /// Squares of the pieces which were pawns before promoting, they go back to the pocket as pawns when captured
+    promoted: u64,
+    /// `promoted` before each move of the game, restored by `unmake_move` in Crazyhouse
+    promoted_history: Vec<u64>,
     current_player: Color,
     quiet: u8,
-    half_moves: u8,
+    half_moves: u16,
     mailboard: [Piece; 64],
-    runtime_constants: Rc<BitboardRuntimeConstants>
+    /// Material and piece-square score, updated incrementally in `set_piece`
+    psqt: TaperedScore,
+    eval_params: Arc<EvalParams>,
+    /// Network accumulator if the board is evaluated with a network, updated incrementally in `set_piece`
+    nnue: Option<Accumulator>,
+    /// Hash keys of earlier positions in the game, used for repetition detection
+    position_history: Vec<u64>,
+    runtime_constants: Arc<BitboardRuntimeConstants>
 }
 
 impl Board {
-    pub fn empty(runtime_constants: Rc<BitboardRuntimeConstants>) -> Board {
+    pub fn empty(runtime_constants: Arc<BitboardRuntimeConstants>) -> Board {
         let mut board = Board {
             piece_sets: [0; 13],
             hash_key: 0,
             ep: 0,
             castling: 0,
+            castling_rooks: STANDARD_CASTLING_ROOKS,
+            chess960: false,
+            variant: Variant::Standard,
+            checks_given: [0; 2],
+            pockets: [0; 12],
+            promoted: 0,
+            promoted_history: Vec::new(),
             current_player: Color::White,
             quiet: 0,
             half_moves: 1,
             mailboard: [Piece::Empty; 64],
+            psqt: TaperedScore::default(),
+            eval_params: Arc::clone(&runtime_constants.eval_params),
+            nnue: runtime_constants.network.clone().map(Accumulator::new),
+            position_history: Vec::with_capacity(256),
             runtime_constants
         };
         board.piece_sets[Piece::Empty.to_u8() as usize] = !(0u64);
@@ -52,19 +93,30 @@ impl Board {
         return board;
     }
 
-    pub fn new(runtime_constants: Rc<BitboardRuntimeConstants>) -> Board {
-        return Board::from_fen(STARTING_POS_FEN, runtime_constants);
+    pub fn new(runtime_constants: Arc<BitboardRuntimeConstants>) -> Board {
+        return Board::from_fen(STARTING_POS_FEN, runtime_constants).unwrap();
     }
 
     pub fn make_move(&mut self, mv: &Move) {
-        self.flip_player();
+        self.position_history.push(self.hash_key);
+        if self.variant == Variant::Crazyhouse {
+            if mv.is_drop() {
+                return self.make_drop(mv);
+            }
+            self.capture_to_pocket(mv);
+        }
         let mut piece_to_move = self.get_piece(mv.from);
+        let castling_index = match piece_to_move {
+            Piece::WhiteKing | Piece::BlackKing => self.castling_index_of(self.current_player, mv.from, mv.to, self.castling),
+            _ => None
+        };
+        self.flip_player();
         let mut ep = 0;
 
         // Quiet moves
         if piece_to_move != Piece::WhitePawn && piece_to_move != Piece::BlackPawn 
                 && mv.captured == Piece::Empty {
-            self.quiet += 1;
+            self.quiet = self.quiet.saturating_add(1);
         }
         else {
             self.quiet = 0;
@@ -93,56 +145,54 @@ impl Board {
                 piece_to_move = mv.promotion;
             }
         }
-        // Castling
-        match mv.from {
-            0 => self.set_one_castling_right::<BLACK, true, false>(),
-            7 => self.set_one_castling_right::<BLACK, false, false>(),
-            56 => self.set_one_castling_right::<WHITE, true, false>(),
-            63 => self.set_one_castling_right::<WHITE, false, false>(),
-            _ => (),
-        }
-        match mv.to {
-            0 => self.set_one_castling_right::<BLACK, true, false>(),
-            7 => self.set_one_castling_right::<BLACK, false, false>(),
-            56 => self.set_one_castling_right::<WHITE, true, false>(),
-            63 => self.set_one_castling_right::<WHITE, false, false>(),
-            _ => (),
-        }
-
-        if piece_to_move == Piece::WhiteKing || piece_to_move == Piece::BlackKing {
-            if piece_to_move == Piece::WhiteKing {
-                self.set_castling(self.castling & !(0b11))
-            } else {
-                self.set_castling(self.castling & !(0b1100));
-            }
-            if mv.from == 4 && mv.to == 2 {
-                self.set_piece(0, Piece::Empty);
-                self.set_piece(3, Piece::BlackRook);
-            }
-            // Black king side castling
-            else if mv.from == 4 && mv.to == 6 {
-                self.set_piece(5, Piece::BlackRook);
-                self.set_piece(7, Piece::Empty);
-            }
-            // White king side castling
-            else if mv.from == 60 && mv.to == 62 {
-                self.set_piece(61, Piece::WhiteRook);
-                self.set_piece(63, Piece::Empty);
-            }
-            // White queen side castling
-            else if mv.from == 60 && mv.to == 58 {
-                self.set_piece(56, Piece::Empty);
-                self.set_piece(59, Piece::WhiteRook);
+        // Castling rights are lost when the king or the rook moves, or when the rook is captured
+        let mut castling = self.castling;
+        for (index, rook_square) in self.castling_rooks.iter().enumerate() {
+            if mv.from == *rook_square || mv.to == *rook_square {
+                castling &= !(1 << index);
             }
         }
-    
-        self.set_piece(mv.to, piece_to_move);
-        self.set_piece(mv.from, Piece::Empty);
+        if piece_to_move == Piece::WhiteKing {
+            castling &= !0b11;
+        }
+        else if piece_to_move == Piece::BlackKing {
+            castling &= !0b1100;
+        }
+        self.set_castling(castling);
+
+        if let Some(index) = castling_index {
+            // The king and the rook may land on each other's squares, so both are removed first
+            let (king_to, rook_to) = Board::castling_destinations(index);
+            let rook = if index < 2 { Piece::WhiteRook } else { Piece::BlackRook };
+            self.set_piece(mv.from, Piece::Empty);
+            self.set_piece(self.castling_rooks[index], Piece::Empty);
+            self.set_piece(rook_to, rook);
+            self.set_piece(king_to, piece_to_move);
+        }
+        else {
+            self.set_piece(mv.to, piece_to_move);
+            self.set_piece(mv.from, Piece::Empty);
+        }
         self.set_ep(ep);
         self.half_moves += 1;
+        if self.variant == Variant::ThreeCheck && self.is_in_check() {
+            let player = self.current_player.next_player();
+            self.set_checks_given(player, self.checks_given[player as usize] + 1);
+        }
     }
 
     pub fn unmake_move(&mut self, mv: &Move) {
+        if self.variant == Variant::ThreeCheck && self.is_in_check() {
+            let player = self.current_player.next_player();
+            self.set_checks_given(player, self.checks_given[player as usize] - 1);
+        }
+        if self.variant == Variant::Crazyhouse {
+            if mv.is_drop() {
+                return self.unmake_drop(mv);
+            }
+            self.uncapture_from_pocket(mv);
+        }
+        self.position_history.pop();
         self.half_moves -= 1;
         let moved_piece = self.get_piece(mv.to);
         self.flip_player();
@@ -150,6 +200,21 @@ impl Board {
         self.set_ep(mv.ep);
         self.quiet = mv.quiet;
 
+        if mv.captured == Piece::Empty && mv.promotion == Piece::Empty {
+            if let Some(index) = self.castling_index_of(self.current_player, mv.from, mv.to, mv.castling) {
+                let (king_to, rook_to) = Board::castling_destinations(index);
+                let (king, rook) = match self.current_player {
+                    Color::White => (Piece::WhiteKing, Piece::WhiteRook),
+                    Color::Black => (Piece::BlackKing, Piece::BlackRook)
+                };
+                self.set_piece(king_to, Piece::Empty);
+                self.set_piece(rook_to, Piece::Empty);
+                self.set_piece(self.castling_rooks[index], rook);
+                self.set_piece(mv.from, king);
+                return;
+            }
+        }
+
         if mv.promotion != Piece::Empty {
             // Undo promotion
             self.set_piece(mv.to, mv.captured);
@@ -162,28 +227,6 @@ impl Board {
             }
             return;
         }
-        else if moved_piece == Piece::WhiteKing || moved_piece == Piece::BlackKing {
-            // Black left side castling
-            if mv.from == 4 && mv.to == 2 {
-                self.set_piece(0, Piece::BlackRook);
-                self.set_piece(3, Piece::Empty);
-            }
-            // Black right side castling
-            else if mv.from == 4 && mv.to == 6 {
-                self.set_piece(5, Piece::Empty);
-                self.set_piece(7, Piece::BlackRook);
-            }
-            // White left side castling
-            else if mv.from == 60 && mv.to == 62 {
-                self.set_piece(61, Piece::Empty);
-                self.set_piece(63, Piece::WhiteRook);
-            }
-            // White right side castling
-            else if mv.from == 60 && mv.to == 58 {
-                self.set_piece(56, Piece::WhiteRook);
-                self.set_piece(59, Piece::Empty);
-            }
-        }
         else if moved_piece == Piece::WhitePawn && self.ep > 0 && ((mv.from - mv.to) % 8 != 0) && mv.captured == Piece::Empty {
             // Restore removed pawn from en passant
             self.set_piece((self.ep as usize + 24 - 1) as u8, Piece::BlackPawn);
@@ -195,6 +238,40 @@ impl Board {
         self.set_piece(mv.from, moved_piece);
     }
 
+    /// Pass the turn without moving, used by null move pruning. The en passant square is cleared and
+    /// the halfmove clock is reset, so repetitions are not detected across the null move.
+    /// Returns the en passant square and halfmove clock to pass to `unmake_null_move`.
+    pub fn make_null_move(&mut self) -> (u8, u8) {
+        let state = (self.ep, self.quiet);
+        self.position_history.push(self.hash_key);
+        self.flip_player();
+        self.set_ep(0);
+        self.quiet = 0;
+        return state;
+    }
+
+    pub fn unmake_null_move(&mut self, (ep, quiet): (u8, u8)) {
+        self.position_history.pop();
+        self.flip_player();
+        self.set_ep(ep);
+        self.quiet = quiet;
+    }
+
+    /// Returns true if `color` has any pieces other than pawns and the king. Positions without
+    /// such pieces are the typical zugzwang positions where passing the turn would be an advantage.
+    pub fn has_non_pawn_material(&self, color: Color) -> bool {
+        let pieces = match color {
+            Color::White => [Piece::WhiteKnight, Piece::WhiteBishop, Piece::WhiteRook, Piece::WhiteQueen],
+            Color::Black => [Piece::BlackKnight, Piece::BlackBishop, Piece::BlackRook, Piece::BlackQueen]
+        };
+        return pieces.iter().any(|piece| self.get_piece_set(*piece) != 0);
+    }
+
+    /// Number of pieces on the board, including the kings
+    pub fn piece_count(&self) -> usize {
+        return (!self.get_piece_set(Piece::Empty)).count_ones() as usize;
+    }
+
     pub fn set_piece_pos(&mut self, x: usize, y: usize, piece: &Piece) {
         self.set_piece((y * 8 + x) as u8, *piece)
     }
@@ -209,6 +286,13 @@ impl Board {
         Board::set_bit(&mut self.piece_sets[piecenum], pos);
         self.flip_zoobrist_piece(pos, piece);
 
+        let piece_square_scores = &self.eval_params.piece_square_scores;
+        self.psqt += piece_square_scores[piecenum][pos as usize] - piece_square_scores[old_piece.to_u8() as usize][pos as usize];
+        if let Some(accumulator) = &mut self.nnue {
+            accumulator.remove_piece(old_piece, pos);
+            accumulator.add_piece(piece, pos);
+        }
+
         unsafe { *self.mailboard.get_unchecked_mut(pos as usize) = piece };
     }
 
@@ -232,10 +316,65 @@ impl Board {
         self.flip_player();
     }
 
+    /// Returns how many times the current position has occurred earlier in the game.
+    /// Only positions since the last capture or pawn move are considered, as earlier positions cannot repeat.
+    pub fn repetition_count(&self) -> usize {
+        let history_length = self.position_history.len();
+        let reversible_plies = (self.quiet as usize).min(history_length);
+        // Skip every other position, as those have the other player to move
+        return self.position_history[history_length - reversible_plies..].iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash_key| hash_key == self.hash_key)
+            .count();
+    }
+
+    /// Returns true if the current position has occurred at least once before in the game
+    pub fn is_repetition(&self) -> bool {
+        return self.repetition_count() > 0;
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        return self.repetition_count() >= 2;
+    }
+
+    /// Returns true if 50 moves have been made by each player without captures or pawn moves
+    pub fn is_fifty_move_rule_draw(&self) -> bool {
+        return self.quiet >= 100;
+    }
+
+    /// Returns true if neither player can possibly checkmate. This is the case with only kings, 
+    /// a single minor piece, or only bishops which are all on the same square color.
+    /// In Three-check only bare kings can not give check, the kings can always walk to the hill
+    /// in King of the Hill, and Antichess and Crazyhouse are never drawn by material.
+    pub fn has_insufficient_material(&self) -> bool {
+        match self.variant {
+            Variant::Standard => (),
+            Variant::ThreeCheck => return self.piece_count() == 2,
+            Variant::KingOfTheHill | Variant::Antichess | Variant::Crazyhouse => return false
+        }
+        let mating_material = self.get_piece_set(Piece::WhitePawn) | self.get_piece_set(Piece::BlackPawn) |
+            self.get_piece_set(Piece::WhiteRook) | self.get_piece_set(Piece::BlackRook) |
+            self.get_piece_set(Piece::WhiteQueen) | self.get_piece_set(Piece::BlackQueen);
+        if mating_material != 0 {
+            return false;
+        }
+        let knights = self.get_piece_set(Piece::WhiteKnight) | self.get_piece_set(Piece::BlackKnight);
+        let bishops = self.get_piece_set(Piece::WhiteBishop) | self.get_piece_set(Piece::BlackBishop);
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+        return knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0);
+    }
+
     pub fn get_game_status(&mut self) -> GameStatus {
         let mut legal_moves = MoveList::empty();
         self.get_moves(&mut legal_moves, false);
         return match legal_moves.result() {
+            SearchResult::InProgress if self.is_threefold_repetition() => GameStatus::ThreefoldRepetition,
+            SearchResult::InProgress if self.is_fifty_move_rule_draw() => GameStatus::FiftyMoveRule,
+            SearchResult::InProgress if self.has_insufficient_material() => GameStatus::InsufficientMaterial,
             SearchResult::InProgress => GameStatus::InProgress,
             SearchResult::Stalemate => GameStatus::Stalemate,
             SearchResult::Loss => {
@@ -244,6 +383,12 @@ impl Board {
                     Color::Black => GameStatus::WhiteWon
                 }
             }
+            SearchResult::Win => {
+                match self.get_current_player() {
+                    Color::White => GameStatus::WhiteWon,
+                    Color::Black => GameStatus::BlackWon
+                }
+            }
         }
     }
 }
\ No newline at end of file
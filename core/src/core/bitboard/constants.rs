@@ -1,5 +1,8 @@
 use rand::Rng;
+use std::sync::Arc;
 use super::super::*;
+use super::eval_params::EvalParams;
+use super::nnue::Network;
 
 pub const WHITE: bool = true;
 pub const BLACK: bool = false;
@@ -18,6 +21,21 @@ pub const PIECE_VALUES: [i32; 6] = [PAWN_VALUE, BISHOP_VALUE, KNIGHT_VALUE,
 pub const CASTLING_RIGHTS_INDEX: usize = 13*64;
 pub const EP_INDEX: usize = 13 * 64 + 4;
 pub const PLAYER_INDEX: usize = 13 * 64 + 4 + 9;
+/// Keys of the checks given in Three-check, 4 per player for 0 to 3 checks
+pub const CHECKS_INDEX: usize = PLAYER_INDEX + 1;
+/// Keys of the variants in the order of `Variant`
+pub const VARIANT_INDEX: usize = CHECKS_INDEX + 2 * 4;
+/// Most pieces of one type a pocket can hold in Crazyhouse, all 16 pawns
+pub const MAX_POCKET_COUNT: usize = 16;
+/// Keys of the pockets of Crazyhouse, one per piece in the order of `Piece` and count from 0 to `MAX_POCKET_COUNT`
+pub const POCKET_INDEX: usize = VARIANT_INDEX + 5;
+/// Keys of the squares of promoted pieces in Crazyhouse
+pub const PROMOTED_INDEX: usize = POCKET_INDEX + 12 * (MAX_POCKET_COUNT + 1);
+const ZOOBRIST_KEY_COUNT: usize = PROMOTED_INDEX + 64;
+/// The four center squares d5, e5, d4 and e4, the hill of King of the Hill
+pub const CENTER_SQUARES: u64 = (0b11 << 27) | (0b11 << 35);
+/// Rook squares of the castling rights in standard chess: h1, a1, h8 and a8
+pub const STANDARD_CASTLING_ROOKS: [u8; 4] = [63, 56, 7, 0];
 #[cfg(any(test,debug_assertions))]
 const NUMBER_OF_MAGIC_TABLE_TRIES: usize = 10;
 #[cfg(not(any(test,debug_assertions)))]
@@ -61,6 +79,19 @@ pub const ROWS: [u64; 8] = {
     masks
 };
 
+/// Light squares of the board. A8 (bit 0) is a light square.
+pub const LIGHT_SQUARES: u64 = {
+    let mut mask = 0u64;
+    let mut i = 0;
+    while i < 64 {
+        if (i % 8 + i / 8) % 2 == 0 {
+            mask |= 1u64 << i;
+        }
+        i += 1;
+    }
+    mask
+};
+
 pub const LEFT_RIGHT_DIAGONALS: [u64; 64] = {
     let mut masks = [0u64; 64];
     let mut i = 0;
@@ -252,7 +283,11 @@ pub struct BitboardRuntimeConstants {
     #[cfg(not(target_feature = "bmi2"))]
     rook_magic_magic_table: ([u8;64], [u64; 64], [Vec<u64>; 64]),
 
-    pub zoobrist_keys: [u64;13*64 + 4 + 9 + 1]
+    pub zoobrist_keys: [u64; ZOOBRIST_KEY_COUNT],
+    /// Evaluation parameters of boards created with these constants
+    pub eval_params: Arc<EvalParams>,
+    /// Network of boards created with these constants, which are evaluated with the classical evaluation if there is none
+    pub network: Option<Arc<Network>>
 }
 
 impl BitboardRuntimeConstants{
@@ -266,7 +301,9 @@ impl BitboardRuntimeConstants{
             bishop_magic_magic_table: Self::generate_bishop_magic_numbers(),
             #[cfg(not(target_feature = "bmi2"))]
             rook_magic_magic_table: Self::generate_rook_magic_numbers(),
-            zoobrist_keys: Self::create_zoobrist_keys()
+            zoobrist_keys: Self::create_zoobrist_keys(),
+            eval_params: Arc::new(EvalParams::default()),
+            network: None
         };
         #[cfg(not(target_feature = "bmi2"))]
         println!("Magic magic table size: {} KiB (optimal = {})", (constants.get_magic_bitboard_size() * 8) / 1024, constants.is_magic_optimal());
@@ -437,8 +474,8 @@ impl BitboardRuntimeConstants{
         magic
     }
 
-    fn create_zoobrist_keys() -> [u64;13*64 + 4 + 9 + 1] {
-        let mut keys = [0u64; 13*64 + 4 + 9 + 1];
+    fn create_zoobrist_keys() -> [u64; ZOOBRIST_KEY_COUNT] {
+        let mut keys = [0u64; ZOOBRIST_KEY_COUNT];
         let mut rng = rand_pcg::Pcg64::new(0xcafef00dd15ea5e5, 0xa02bdbf7bb3c0a7ac28fa16a64abf96);
         for i in 0..keys.len() {
             keys[i] = rng.gen::<u64>();
@@ -446,6 +483,13 @@ impl BitboardRuntimeConstants{
         for i in 0..64 {
             keys[(Piece::Empty.to_u8() as usize) * 64 + i] = 0;
         }
+        // Standard chess, players without checks given and empty pockets do not change the hash key
+        keys[CHECKS_INDEX] = 0;
+        keys[CHECKS_INDEX + 4] = 0;
+        keys[VARIANT_INDEX] = 0;
+        for piece in 0..12 {
+            keys[POCKET_INDEX + piece * (MAX_POCKET_COUNT + 1)] = 0;
+        }
         return keys;
     }
 
@@ -0,0 +1,174 @@
+use crate::core::*;
+use bitboard::constants::*;
+
+use super::Board;
+
+/// Piece of `color` of the same type as `piece`
+pub(super) fn piece_of_color(piece: Piece, color: Color) -> Piece {
+    return Piece::from_u8(piece.to_u8() % 6 + 6 * color as u8);
+}
+
+/// Piece going to the pocket of `player` when making `mv`, where `moved` is the piece making the move
+/// and `promoted` are the promoted pieces before the move. Captured promoted pieces turn back into pawns.
+fn pocket_piece_of_capture(mv: &Move, moved: Piece, promoted: u64, player: Color) -> Option<Piece> {
+    let captured = match mv.captured {
+        // En passant
+        Piece::Empty if matches!(moved, Piece::WhitePawn | Piece::BlackPawn) && mv.from % 8 != mv.to % 8 => Piece::WhitePawn,
+        Piece::Empty => return None,
+        _ if promoted & (1u64 << mv.to) != 0 => Piece::WhitePawn,
+        captured => captured
+    };
+    return Some(piece_of_color(captured, player));
+}
+
+impl Board {
+    /// Number of `piece` in the pocket of the player with the color of the piece, only used in Crazyhouse
+    pub fn get_pocket_count(&self, piece: Piece) -> u8 {
+        return self.pockets[piece.to_u8() as usize];
+    }
+
+    /// Set the number of `piece` in the pocket of its color and update the hash key
+    pub(super) fn set_pocket_count(&mut self, piece: Piece, count: u8) {
+        let index = piece.to_u8() as usize;
+        self.hash_key ^= self.pocket_key(piece, self.pockets[index]) ^ self.pocket_key(piece, count);
+        self.pockets[index] = count;
+    }
+
+    fn pocket_key(&self, piece: Piece, count: u8) -> u64 {
+        let count = (count as usize).min(MAX_POCKET_COUNT);
+        return self.runtime_constants.zoobrist_keys[POCKET_INDEX + piece.to_u8() as usize * (MAX_POCKET_COUNT + 1) + count];
+    }
+
+    /// Returns true if the piece on `pos` was a pawn before promoting
+    pub fn is_promoted(&self, pos: u8) -> bool {
+        return self.promoted & (1u64 << pos) != 0;
+    }
+
+    /// Mark the pieces on the squares of `promoted` as promoted and update the hash key
+    pub(super) fn set_promoted(&mut self, promoted: u64) {
+        let mut difference = self.promoted ^ promoted;
+        while difference > 0 {
+            self.hash_key ^= self.runtime_constants.zoobrist_keys[PROMOTED_INDEX + difference.trailing_zeros() as usize];
+            difference &= difference - 1;
+        }
+        self.promoted = promoted;
+    }
+
+    /// Hash key of the pockets and the promoted pieces, part of `variant_hash`
+    pub(super) fn pocket_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (piece, count) in self.pockets.iter().enumerate() {
+            hash ^= self.pocket_key(Piece::from_u8(piece as u8), *count);
+        }
+        let mut promoted = self.promoted;
+        while promoted > 0 {
+            hash ^= self.runtime_constants.zoobrist_keys[PROMOTED_INDEX + promoted.trailing_zeros() as usize];
+            promoted &= promoted - 1;
+        }
+        return hash;
+    }
+
+    /// Piece making `mv`, which is the dropped piece for drops
+    pub fn moving_piece(&self, mv: &Move) -> Piece {
+        return match mv.is_drop() {
+            true => mv.promotion,
+            false => self.get_piece(mv.from)
+        };
+    }
+
+    /// Put the piece captured by `mv` into the pocket of the player to move and move the promoted
+    /// marker along with the moving piece. Called by `make_move` before the pieces are moved.
+    pub(super) fn capture_to_pocket(&mut self, mv: &Move) {
+        self.promoted_history.push(self.promoted);
+        let moved = self.get_piece(mv.from);
+        if let Some(piece) = pocket_piece_of_capture(mv, moved, self.promoted, self.current_player) {
+            self.set_pocket_count(piece, self.get_pocket_count(piece) + 1);
+        }
+        let (from, to) = (1u64 << mv.from, 1u64 << mv.to);
+        let mut promoted = self.promoted & !to;
+        if self.promoted & from != 0 || mv.promotion != Piece::Empty {
+            promoted = (promoted & !from) | to;
+        }
+        self.set_promoted(promoted);
+    }
+
+    /// Undo `capture_to_pocket`. Called by `unmake_move` before the pieces are moved back.
+    pub(super) fn uncapture_from_pocket(&mut self, mv: &Move) {
+        let promoted = self.promoted_history.pop().unwrap_or(0);
+        let moved = self.get_piece(mv.to);
+        if let Some(piece) = pocket_piece_of_capture(mv, moved, promoted, self.current_player.next_player()) {
+            self.set_pocket_count(piece, self.get_pocket_count(piece) - 1);
+        }
+        self.set_promoted(promoted);
+    }
+
+    /// `make_move` for drops, which take the piece from the pocket. Pawn drops reset the halfmove clock
+    /// like other pawn moves.
+    pub(super) fn make_drop(&mut self, mv: &Move) {
+        let piece = mv.promotion;
+        self.set_pocket_count(piece, self.get_pocket_count(piece) - 1);
+        self.set_piece(mv.to, piece);
+        self.flip_player();
+        self.quiet = match piece {
+            Piece::WhitePawn | Piece::BlackPawn => 0,
+            _ => self.quiet.saturating_add(1)
+        };
+        self.set_ep(0);
+        self.half_moves += 1;
+    }
+
+    pub(super) fn unmake_drop(&mut self, mv: &Move) {
+        self.position_history.pop();
+        self.half_moves -= 1;
+        self.flip_player();
+        self.set_ep(mv.ep);
+        self.quiet = mv.quiet;
+        self.set_piece(mv.to, Piece::Empty);
+        self.set_pocket_count(mv.promotion, self.get_pocket_count(mv.promotion) + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::variant::Variant;
+    use crate::core::Move;
+    use super::Board;
+
+    #[test]
+    fn test_crazyhouse_pockets() {
+        let constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let fen = "r3k3/1P6/8/3pP3/8/8/8/4K3[Nqr] w q d6 0 1";
+        let mut board = Board::from_fen_variant(fen, Variant::Crazyhouse, Arc::clone(&constants)).unwrap();
+        assert_eq!(board.to_fen(), fen);
+        let original = board.clone();
+
+        // Captured pieces, also by en passant, go to the pocket of the capturing player and a captured
+        // promoted piece goes back as a pawn
+        let mut played = Vec::new();
+        for (algebraic, fen) in [
+            ("e5d6", "r3k3/1P6/3P4/8/8/8/8/4K3[NPqr] b q - 0 2"),
+            ("Q@e2", "r3k3/1P6/3P4/8/8/8/4q3/4K3[NPr] w q - 1 3"),
+            ("e1e2", "r3k3/1P6/3P4/8/8/8/4K3/8[QNPr] b q - 0 4"),
+            ("e8d8", "r2k4/1P6/3P4/8/8/8/4K3/8[QNPr] w - - 1 5"),
+            ("b7a8q", "Q~2k4/8/3P4/8/8/8/4K3/8[QRNPr] b - - 0 6"),
+            ("R@c8", "Q~1rk4/8/3P4/8/8/8/4K3/8[QRNP] w - - 1 7"),
+            ("a8c8", "2Q~k4/8/3P4/8/8/8/4K3/8[QRRNP] b - - 0 8"),
+            ("d8c8", "2k5/8/3P4/8/8/8/4K3/8[QRRNPp] w - - 0 9")
+        ] {
+            let mv = Move::try_from_algebraic(&board, algebraic).unwrap();
+            assert_eq!(mv.to_algebraic(), algebraic);
+            board.make_move(&mv);
+            played.push(mv);
+            assert_eq!(board.to_fen(), fen);
+            assert_eq!(board.get_hashkey(), board.calculate_hash());
+        }
+        for mv in played.iter().rev() {
+            board.unmake_move(mv);
+        }
+        assert_eq!(board, original);
+        assert_eq!(board.get_hashkey(), original.get_hashkey());
+    }
+}
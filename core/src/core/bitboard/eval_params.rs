@@ -0,0 +1,334 @@
+//! Parameters of the evaluation. Every parameter has a middlegame and an endgame value, which are
+//! blended by the game phase. Piece arrays are in the order of `Piece`: pawn, bishop, knight, rook, queen, king.
+//! Piece-square tables are from the view of white, with A8 as the first square.
+//!
+//! The constants are the default parameters. `EvalParams` holds a parameter set which can be loaded at
+//! runtime from a parameter file with one `name value` pair per line, as written by the tuner.
+
+use std::fmt;
+
+use super::evaluation::{s, TaperedScore};
+
+pub const MATERIAL: [TaperedScore; 6] = [s(82, 94), s(365, 297), s(337, 281), s(477, 512), s(1025, 936), s(0, 0)];
+
+/// Contribution of each piece to the game phase, a full board has `TOTAL_PHASE`
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const TOTAL_PHASE: i32 = 24;
+
+pub const PIECE_SQUARE_TABLES: [[TaperedScore; 64]; 6] = [
+    // Pawn
+    [
+        s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0),
+        s( 98, 178), s(134, 173), s( 61, 158), s( 95, 134), s( 68, 147), s(126, 132), s( 34, 165), s(-11, 187),
+        s( -6,  94), s(  7, 100), s( 26,  85), s( 31,  67), s( 65,  56), s( 56,  53), s( 25,  82), s(-20,  84),
+        s(-14,  32), s( 13,  24), s(  6,  13), s( 21,   5), s( 23,  -2), s( 12,   4), s( 17,  17), s(-23,  17),
+        s(-27,  13), s( -2,   9), s( -5,  -3), s( 12,  -7), s( 17,  -7), s(  6,  -8), s( 10,   3), s(-25,  -1),
+        s(-26,   4), s( -4,   7), s( -4,  -6), s(-10,   1), s(  3,   0), s(  3,  -5), s( 33,  -1), s(-12,  -8),
+        s(-35,  13), s( -1,   8), s(-20,   8), s(-23,  10), s(-15,  13), s( 24,   0), s( 38,   2), s(-22,  -7),
+        s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0), s(  0,   0),
+    ],
+    // Bishop
+    [
+        s(-29, -14), s(  4, -21), s(-82, -11), s(-37,  -8), s(-25,  -7), s(-42,  -9), s(  7, -17), s( -8, -24),
+        s(-26,  -8), s( 16,  -4), s(-18,   7), s(-13, -12), s( 30,  -3), s( 59, -13), s( 18,  -4), s(-47, -14),
+        s(-16,   2), s( 37,  -8), s( 43,   0), s( 40,  -1), s( 35,  -2), s( 50,   6), s( 37,   0), s( -2,   4),
+        s( -4,  -3), s(  5,   9), s( 19,  12), s( 50,   9), s( 37,  14), s( 37,  10), s(  7,   3), s( -2,   2),
+        s( -6,  -6), s( 13,   3), s( 13,  13), s( 26,  19), s( 34,   7), s( 12,  10), s( 10,  -3), s(  4,  -9),
+        s(  0, -12), s( 15,  -3), s( 15,   8), s( 15,  10), s( 14,  13), s( 27,   3), s( 18,  -7), s( 10, -15),
+        s(  4, -14), s( 15, -18), s( 16,  -7), s(  0,  -1), s(  7,   4), s( 21,  -9), s( 33, -15), s(  1, -27),
+        s(-33, -23), s( -3,  -9), s(-14, -23), s(-21,  -5), s(-13,  -9), s(-12, -16), s(-39,  -5), s(-21, -17),
+    ],
+    // Knight
+    [
+        s(-167, -58), s(-89, -38), s(-34, -13), s(-49, -28), s( 61, -31), s(-97, -27), s(-15, -63), s(-107, -99),
+        s( -73, -25), s(-41,  -8), s( 72, -25), s( 36,  -2), s( 23,  -9), s( 62, -25), s(  7, -24), s( -17, -52),
+        s( -47, -24), s( 60, -20), s( 37,  10), s( 65,   9), s( 84,  -1), s(129,  -9), s( 73, -19), s(  44, -41),
+        s(  -9, -17), s( 17,   3), s( 19,  22), s( 53,  22), s( 37,  22), s( 69,  11), s( 18,   8), s(  22, -18),
+        s( -13, -18), s(  4,  -6), s( 16,  16), s( 13,  25), s( 28,  16), s( 19,  17), s( 21,   4), s(  -8, -18),
+        s( -23, -23), s( -9,  -3), s( 12,  -1), s( 10,  15), s( 19,  10), s( 17,  -3), s( 25, -20), s( -16, -22),
+        s( -29, -42), s(-53, -20), s(-12, -10), s( -3,  -5), s( -1,  -2), s( 18, -20), s(-14, -23), s( -19, -44),
+        s(-105, -29), s(-21, -51), s(-58, -23), s(-33, -15), s(-17, -22), s(-28, -18), s(-19, -50), s( -23, -64),
+    ],
+    // Rook
+    [
+        s( 32,  13), s( 42,  10), s( 32,  18), s( 51,  15), s( 63,  12), s(  9,  12), s( 31,   8), s( 43,   5),
+        s( 27,  11), s( 32,  13), s( 58,  13), s( 62,  11), s( 80,  -3), s( 67,   3), s( 26,   8), s( 44,   3),
+        s( -5,   7), s( 19,   7), s( 26,   7), s( 36,   5), s( 17,   4), s( 45,  -3), s( 61,  -5), s( 16,  -3),
+        s(-24,   4), s(-11,   3), s(  7,  13), s( 26,   1), s( 24,   2), s( 35,   1), s( -8,  -1), s(-20,   2),
+        s(-36,   3), s(-26,   5), s(-12,   8), s( -1,   4), s(  9,  -5), s( -7,  -6), s(  6,  -8), s(-23, -11),
+        s(-45,  -4), s(-25,   0), s(-16,  -5), s(-17,  -1), s(  3,  -7), s(  0, -12), s( -5,  -8), s(-33, -16),
+        s(-44,  -6), s(-16,  -6), s(-20,   0), s( -9,   2), s( -1,  -9), s( 11,  -9), s( -6, -11), s(-71,  -3),
+        s(-19,  -9), s(-13,   2), s(  1,   3), s( 17,  -1), s( 16,  -5), s(  7, -13), s(-37,   4), s(-26, -20),
+    ],
+    // Queen
+    [
+        s(-28,  -9), s(  0,  22), s( 29,  22), s( 12,  27), s( 59,  27), s( 44,  19), s( 43,  10), s( 45,  20),
+        s(-24, -17), s(-39,  20), s( -5,  32), s(  1,  41), s(-16,  58), s( 57,  25), s( 28,  30), s( 54,   0),
+        s(-13, -20), s(-17,   6), s(  7,   9), s(  8,  49), s( 29,  47), s( 56,  35), s( 47,  19), s( 57,   9),
+        s(-27,   3), s(-27,  22), s(-16,  24), s(-16,  45), s( -1,  57), s( 17,  40), s( -2,  57), s(  1,  36),
+        s( -9, -18), s(-26,  28), s( -9,  19), s(-10,  47), s( -2,  31), s( -4,  34), s(  3,  39), s( -3,  23),
+        s(-14, -16), s(  2, -27), s(-11,  15), s( -2,   6), s( -5,   9), s(  2,  17), s( 14,  10), s(  5,   5),
+        s(-35, -22), s( -8, -23), s( 11, -30), s(  2, -16), s(  8, -16), s( 15, -23), s( -3, -36), s(  1, -32),
+        s( -1, -33), s(-18, -28), s( -9, -22), s( 10, -43), s(-15,  -5), s(-25, -32), s(-31, -20), s(-50, -41),
+    ],
+    // King
+    [
+        s(-65, -74), s( 23, -35), s( 16, -18), s(-15, -18), s(-56, -11), s(-34,  15), s(  2,   4), s( 13, -17),
+        s( 29, -12), s( -1,  17), s(-20,  14), s( -7,  17), s( -8,  17), s( -4,  38), s(-38,  23), s(-29,  11),
+        s( -9,  10), s( 24,  17), s(  2,  23), s(-16,  15), s(-20,  20), s(  6,  45), s( 22,  44), s(-22,  13),
+        s(-17,  -8), s(-20,  22), s(-12,  24), s(-27,  27), s(-30,  26), s(-25,  33), s(-14,  26), s(-36,   3),
+        s(-49, -18), s( -1,  -4), s(-27,  21), s(-39,  24), s(-46,  27), s(-44,  23), s(-33,   9), s(-51, -11),
+        s(-14, -19), s(-14,  -3), s(-22,  11), s(-46,  21), s(-44,  23), s(-30,  16), s(-15,   7), s(-27,  -9),
+        s(  1, -27), s(  7, -11), s( -8,   4), s(-64,  13), s(-43,  14), s(-16,   4), s(  9,  -5), s(  8, -17),
+        s(-15, -53), s( 36, -34), s( 12, -21), s(-54, -11), s(  8, -28), s(-28, -14), s( 24, -24), s( 14, -43),
+    ],
+];
+
+/// Bonus per safe square a piece attacks above `MOBILITY_BASELINE`. Safe squares are not occupied by
+/// own pieces and not attacked by enemy pawns.
+pub const MOBILITY: [TaperedScore; 6] = [s(0, 0), s(5, 5), s(4, 4), s(2, 4), s(1, 2), s(0, 0)];
+pub const MOBILITY_BASELINE: [i32; 6] = [0, 6, 4, 7, 13, 0];
+
+/// Bonus for a passed pawn, indexed by its rank from the view of its owner
+pub const PASSED_PAWN: [TaperedScore; 8] = [s(0, 0), s(0, 5), s(2, 10), s(5, 20), s(15, 40), s(30, 70), s(50, 110), s(0, 0)];
+pub const ISOLATED_PAWN: TaperedScore = s(-10, -12);
+/// Penalty for every pawn on a file after the first
+pub const DOUBLED_PAWN: TaperedScore = s(-8, -20);
+
+pub const BISHOP_PAIR: TaperedScore = s(30, 50);
+/// Rook on a file without pawns
+pub const ROOK_OPEN_FILE: TaperedScore = s(25, 10);
+/// Rook on a file without own pawns
+pub const ROOK_SEMI_OPEN_FILE: TaperedScore = s(12, 8);
+
+/// Attack units for every square next to the enemy king a piece attacks
+pub const KING_ATTACK_WEIGHTS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+/// Middlegame penalty for the king, indexed by the attack units of the opponent
+pub const KING_SAFETY: [i32; 32] = [
+      0,   0,   1,   2,   4,   6,   9,  12,  16,  20,  25,  30,  36,  42,  49,  56,
+     64,  72,  81,  90, 100, 110, 121, 132, 144, 156, 169, 182, 196, 210, 225, 240,
+];
+
+/// Bonus for the king in King of the Hill, indexed by the number of king moves to the nearest center square
+pub const HILL_DISTANCE_BONUS: [i32; 4] = [400, 250, 100, 30];
+/// Bonus in Three-check, indexed by the number of checks given
+pub const CHECKS_GIVEN_BONUS: [i32; 4] = [0, 150, 400, 1000];
+/// Value of every piece in Antichess, where the player with fewer pieces is better
+pub const ANTICHESS_PIECE_VALUE: i32 = 100;
+/// Value of the pieces in the pocket in Crazyhouse in the order of `Piece`, more than on the board for pawns
+/// and minor pieces, which can be dropped on any empty square
+pub const POCKET_PIECE_VALUE: [i32; 5] = [120, 380, 360, 500, 1000];
+/// Bonus in Crazyhouse for every attack unit of a piece in the pocket and empty square next to the enemy king
+pub const POCKET_KING_ATTACK: i32 = 3;
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "bishop", "knight", "rook", "queen", "king"];
+
+/// Evaluation parameters used by a board, see the constants of this module for their meaning
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EvalParams {
+    pub(crate) material: [TaperedScore; 6],
+    pub(crate) piece_square_tables: [[TaperedScore; 64]; 6],
+    pub(crate) mobility: [TaperedScore; 6],
+    pub(crate) passed_pawn: [TaperedScore; 8],
+    pub(crate) isolated_pawn: TaperedScore,
+    pub(crate) doubled_pawn: TaperedScore,
+    pub(crate) bishop_pair: TaperedScore,
+    pub(crate) rook_open_file: TaperedScore,
+    pub(crate) rook_semi_open_file: TaperedScore,
+    pub(crate) king_safety: [i32; 32],
+    /// Material and piece-square score of every piece on every square, positive for white and negative for black.
+    /// Derived from `material` and `piece_square_tables`.
+    pub(crate) piece_square_scores: [[TaperedScore; 64]; 13]
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EvalParamsErrorKind {
+    UnknownParameter(String),
+    InvalidValue(String),
+    /// The line has a name but no value, or more than one value
+    WrongFieldCount
+}
+
+/// Error while parsing a parameter file, `line` is the line number starting from 1
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EvalParamsError {
+    pub line: usize,
+    pub kind: EvalParamsErrorKind
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            EvalParamsErrorKind::UnknownParameter(name) => write!(f, "unknown parameter '{}'", name),
+            EvalParamsErrorKind::InvalidValue(value) => write!(f, "invalid value '{}'", value),
+            EvalParamsErrorKind::WrongFieldCount => write!(f, "expected a parameter name and a value")
+        }
+    }
+}
+
+impl std::error::Error for EvalParamsError {}
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        let mut params = EvalParams {
+            material: MATERIAL,
+            piece_square_tables: PIECE_SQUARE_TABLES,
+            mobility: MOBILITY,
+            passed_pawn: PASSED_PAWN,
+            isolated_pawn: ISOLATED_PAWN,
+            doubled_pawn: DOUBLED_PAWN,
+            bishop_pair: BISHOP_PAIR,
+            rook_open_file: ROOK_OPEN_FILE,
+            rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+            king_safety: KING_SAFETY,
+            piece_square_scores: [[s(0, 0); 64]; 13]
+        };
+        params.update_piece_square_scores();
+        return params;
+    }
+}
+
+fn square_name(pos: usize) -> String {
+    return format!("{}{}", (b'a' + (pos % 8) as u8) as char, 8 - pos / 8);
+}
+
+fn visit_score(name: String, score: &mut TaperedScore, visitor: &mut dyn FnMut(String, &mut i32)) {
+    visitor(format!("{}.mg", name), &mut score.mg);
+    visitor(format!("{}.eg", name), &mut score.eg);
+}
+
+impl EvalParams {
+    fn update_piece_square_scores(&mut self) {
+        for piece in 0..6 {
+            for pos in 0..64 {
+                let white = self.piece_square_tables[piece][pos];
+                // Black uses the table of white mirrored vertically
+                let black = self.piece_square_tables[piece][pos ^ 56];
+                self.piece_square_scores[piece][pos] = self.material[piece] + white;
+                self.piece_square_scores[piece + 6][pos] = -(self.material[piece] + black);
+            }
+        }
+    }
+
+    /// Call `visitor` with the name and value of every parameter, always in the same order.
+    /// Parameters without effect, such as the material value of the king, are skipped.
+    fn visit(&mut self, visitor: &mut dyn FnMut(String, &mut i32)) {
+        for piece in 0..5 {
+            visit_score(format!("material.{}", PIECE_NAMES[piece]), &mut self.material[piece], visitor);
+        }
+        for piece in 0..6 {
+            for pos in 0..64 {
+                // Pawns are never on the first or last rank
+                if piece == 0 && !(8..56).contains(&pos) {
+                    continue;
+                }
+                visit_score(format!("psqt.{}.{}", PIECE_NAMES[piece], square_name(pos)), &mut self.piece_square_tables[piece][pos], visitor);
+            }
+        }
+        for piece in 1..5 {
+            visit_score(format!("mobility.{}", PIECE_NAMES[piece]), &mut self.mobility[piece], visitor);
+        }
+        for rank in 1..7 {
+            visit_score(format!("passed_pawn.{}", rank), &mut self.passed_pawn[rank], visitor);
+        }
+        visit_score("isolated_pawn".to_string(), &mut self.isolated_pawn, visitor);
+        visit_score("doubled_pawn".to_string(), &mut self.doubled_pawn, visitor);
+        visit_score("bishop_pair".to_string(), &mut self.bishop_pair, visitor);
+        visit_score("rook_open_file".to_string(), &mut self.rook_open_file, visitor);
+        visit_score("rook_semi_open_file".to_string(), &mut self.rook_semi_open_file, visitor);
+        for (units, penalty) in self.king_safety.iter_mut().enumerate() {
+            visitor(format!("king_safety.{}", units), penalty);
+        }
+    }
+
+    /// Names of all parameters, in the order of `values`
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.clone().visit(&mut |name, _| names.push(name));
+        return names;
+    }
+
+    /// Values of all parameters
+    pub fn values(&self) -> Vec<i32> {
+        let mut values = Vec::new();
+        self.clone().visit(&mut |_, value| values.push(*value));
+        return values;
+    }
+
+    /// Parameters with the values of `values`, which must be in the order of `values`
+    pub fn with_values(&self, values: &[i32]) -> EvalParams {
+        let mut params = self.clone();
+        let mut index = 0;
+        params.visit(&mut |_, value| {
+            *value = values[index];
+            index += 1;
+        });
+        assert_eq!(index, values.len(), "Wrong number of evaluation parameters");
+        params.update_piece_square_scores();
+        return params;
+    }
+
+    /// Parse a parameter file. Parameters missing from the file keep their default value.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Result<EvalParams, EvalParamsError> {
+        let mut params = EvalParams::default();
+        let names = params.names();
+        let mut values = params.values();
+        for (index, line) in text.lines().enumerate() {
+            let error = |kind| EvalParamsError { line: index + 1, kind };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 2 {
+                return Err(error(EvalParamsErrorKind::WrongFieldCount));
+            }
+            let param_index = names.iter().position(|name| name == fields[0])
+                .ok_or_else(|| error(EvalParamsErrorKind::UnknownParameter(fields[0].to_string())))?;
+            values[param_index] = fields[1].parse()
+                .map_err(|_| error(EvalParamsErrorKind::InvalidValue(fields[1].to_string())))?;
+        }
+        params = params.with_values(&values);
+        return Ok(params);
+    }
+
+    /// Parameter file with all parameters, which can be read with `parse`
+    pub fn to_param_file(&self) -> String {
+        return self.names().iter().zip(self.values())
+            .map(|(name, value)| format!("{} {}\n", name, value))
+            .collect();
+    }
+
+    /// Rust source of the constants in this module with the values of these parameters
+    pub fn to_rust_constants(&self) -> String {
+        let score = |score: &TaperedScore| format!("s({:4}, {:4})", score.mg, score.eg);
+        let scores = |scores: &[TaperedScore]| scores.iter().map(score).collect::<Vec<String>>().join(", ");
+        let mut text = String::new();
+        text += &format!("pub const MATERIAL: [TaperedScore; 6] = [{}];\n\n", scores(&self.material));
+        text += "pub const PIECE_SQUARE_TABLES: [[TaperedScore; 64]; 6] = [\n";
+        for piece in 0..6 {
+            text += &format!("    // {}{}\n    [\n", PIECE_NAMES[piece][..1].to_uppercase(), &PIECE_NAMES[piece][1..]);
+            for row in self.piece_square_tables[piece].chunks(8) {
+                text += &format!("        {},\n", scores(row));
+            }
+            text += "    ],\n";
+        }
+        text += "];\n\n";
+        text += &format!("pub const MOBILITY: [TaperedScore; 6] = [{}];\n", scores(&self.mobility));
+        text += &format!("pub const PASSED_PAWN: [TaperedScore; 8] = [{}];\n", scores(&self.passed_pawn));
+        for (name, value) in [
+            ("ISOLATED_PAWN", &self.isolated_pawn), ("DOUBLED_PAWN", &self.doubled_pawn), ("BISHOP_PAIR", &self.bishop_pair),
+            ("ROOK_OPEN_FILE", &self.rook_open_file), ("ROOK_SEMI_OPEN_FILE", &self.rook_semi_open_file)
+        ] {
+            text += &format!("pub const {}: TaperedScore = {};\n", name, score(value));
+        }
+        text += "pub const KING_SAFETY: [i32; 32] = [\n";
+        for row in self.king_safety.chunks(16) {
+            text += &format!("    {},\n", row.iter().map(|penalty| format!("{:3}", penalty)).collect::<Vec<String>>().join(", "));
+        }
+        text += "];\n";
+        return text;
+    }
+}
@@ -1,24 +1,399 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::sync::Arc;
 
-use std::iter::zip;
+use super::{constants::*, eval_params::*, nnue::{Accumulator, Network}, Board, Color, Piece};
+use crate::core::variant::Variant;
 
-use super::{constants::*, Board, Color, Piece};
+/// A middlegame and an endgame score, blended by the game phase when evaluating
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TaperedScore {
+    pub mg: i32,
+    pub eg: i32
+}
+
+pub const fn s(mg: i32, eg: i32) -> TaperedScore {
+    return TaperedScore { mg, eg };
+}
+
+impl TaperedScore {
+    /// Blend the middlegame and endgame score, `phase` is `TOTAL_PHASE` for the opening and 0 for a bare endgame
+    pub fn taper(&self, phase: i32) -> i32 {
+        return (self.mg * phase + self.eg * (TOTAL_PHASE - phase)) / TOTAL_PHASE;
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = TaperedScore;
+    fn add(self, rhs: TaperedScore) -> TaperedScore {
+        return s(self.mg + rhs.mg, self.eg + rhs.eg);
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = TaperedScore;
+    fn sub(self, rhs: TaperedScore) -> TaperedScore {
+        return s(self.mg - rhs.mg, self.eg - rhs.eg);
+    }
+}
+
+impl AddAssign for TaperedScore {
+    fn add_assign(&mut self, rhs: TaperedScore) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for TaperedScore {
+    fn sub_assign(&mut self, rhs: TaperedScore) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = TaperedScore;
+    fn neg(self) -> TaperedScore {
+        return s(-self.mg, -self.eg);
+    }
+}
+
+impl Mul<i32> for TaperedScore {
+    type Output = TaperedScore;
+    fn mul(self, rhs: i32) -> TaperedScore {
+        return s(self.mg * rhs, self.eg * rhs);
+    }
+}
+
+/// One term of the evaluation, with the score of each side from its own view
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EvalTerm {
+    pub name: &'static str,
+    pub white: TaperedScore,
+    pub black: TaperedScore
+}
+
+impl EvalTerm {
+    /// Score of the term from the view of white
+    pub fn total(&self) -> TaperedScore {
+        return self.white - self.black;
+    }
+}
+
+/// Breakdown of the evaluation of a position into its terms, see `Board::eval_trace`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EvalTrace {
+    pub terms: Vec<EvalTerm>,
+    pub phase: i32
+}
+
+impl EvalTrace {
+    /// Sum of all terms from the view of white
+    pub fn total(&self) -> TaperedScore {
+        return self.terms.iter().fold(s(0, 0), |total, term| total + term.total());
+    }
+
+    /// Sum of all terms blended by the game phase, from the view of white
+    pub fn tapered(&self) -> i32 {
+        return self.total().taper(self.phase);
+    }
+}
+
+/// Pieces of `color` in the order of `Piece`
+fn pieces_of(color: Color) -> [Piece; 6] {
+    return match color {
+        Color::White => [Piece::WhitePawn, Piece::WhiteBishop, Piece::WhiteKnight, Piece::WhiteRook, Piece::WhiteQueen, Piece::WhiteKing],
+        Color::Black => [Piece::BlackPawn, Piece::BlackBishop, Piece::BlackKnight, Piece::BlackRook, Piece::BlackQueen, Piece::BlackKing]
+    };
+}
+
+/// Squares attacked by the pawns in `pawns` of `color`
+fn pawn_attacks(pawns: u64, color: Color) -> u64 {
+    // White pawns move towards bit 0
+    return match color {
+        Color::White => ((pawns >> 7) & !COLUMNS[0]) | ((pawns >> 9) & !COLUMNS[7]),
+        Color::Black => ((pawns << 7) & !COLUMNS[7]) | ((pawns << 9) & !COLUMNS[0])
+    };
+}
+
+/// Squares in front of `pos` from the view of `color`, on the same file and the files next to it
+fn front_span(pos: usize, color: Color) -> u64 {
+    let (x, y) = (pos % 8, pos / 8);
+    let files = adjacent_files(x) | COLUMNS[x];
+    let rows = match color {
+        Color::White => (1u64 << (y * 8)) - 1,
+        Color::Black => u64::MAX.checked_shl((y as u32 + 1) * 8).unwrap_or(0)
+    };
+    return files & rows;
+}
+
+fn adjacent_files(x: usize) -> u64 {
+    let left = if x > 0 { COLUMNS[x - 1] } else { 0 };
+    let right = if x < 7 { COLUMNS[x + 1] } else { 0 };
+    return left | right;
+}
+
+/// Number of king moves from `pos` to the nearest of the four center squares
+fn hill_distance(pos: usize) -> usize {
+    let distance = |coordinate: usize| if coordinate < 3 { 3 - coordinate } else { coordinate.saturating_sub(4) };
+    return distance(pos % 8).max(distance(pos / 8));
+}
+
+/// Rank of `pos` from the view of `color`, 0 is the first rank
+fn relative_rank(pos: usize, color: Color) -> usize {
+    return match color {
+        Color::White => 7 - pos / 8,
+        Color::Black => pos / 8
+    };
+}
 
 impl Board {
-    //Evaluates total piece value for ```COLOR```.
-    fn piece_values_for<const COLOR: bool>(&self) -> i32 {
-        let pieces = match COLOR {
-            WHITE => Piece::white_pieces(),
-            BLACK => Piece::black_pieces()
+    /// Game phase from `TOTAL_PHASE` at the start of the game to 0 when only kings and pawns are left
+    pub fn phase(&self) -> i32 {
+        let phase: i32 = (0..6).map(|piece| {
+            let count = self.piece_sets[piece].count_ones() + self.piece_sets[piece + 6].count_ones();
+            PHASE_WEIGHTS[piece] * count as i32
+        }).sum();
+        // Promotions can give more than the starting material
+        return phase.min(TOTAL_PHASE);
+    }
+
+    /// Material and piece-square score, kept up to date in `set_piece`. Positive scores are good for white.
+    pub fn piece_square_score(&self) -> TaperedScore {
+        return self.psqt;
+    }
+
+    pub fn eval_params(&self) -> &Arc<EvalParams> {
+        return &self.eval_params;
+    }
+
+    /// Network the board is evaluated with, `None` for the classical evaluation
+    pub fn network(&self) -> Option<&Arc<Network>> {
+        return self.nnue.as_ref().map(|accumulator| accumulator.network());
+    }
+
+    /// Evaluate with `network` instead of the network of the runtime constants, or with the classical evaluation if it is `None`
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(|network| self.calculate_accumulator(&network));
+    }
+
+    pub(in crate::core) fn calculate_accumulator(&self, network: &Arc<Network>) -> Accumulator {
+        let mut accumulator = Accumulator::new(Arc::clone(network));
+        for (pos, piece) in self.mailboard.iter().enumerate() {
+            accumulator.add_piece(*piece, pos as u8);
+        }
+        return accumulator;
+    }
+
+    /// Evaluate with `eval_params` instead of the parameters of the runtime constants
+    pub fn set_eval_params(&mut self, eval_params: Arc<EvalParams>) {
+        self.eval_params = eval_params;
+        self.psqt = self.calculate_piece_square_score();
+    }
+
+    pub(in crate::core) fn calculate_piece_square_score(&self) -> TaperedScore {
+        return self.mailboard.iter().enumerate()
+            .fold(s(0, 0), |score, (pos, piece)| score + self.eval_params.piece_square_scores[piece.to_u8() as usize][pos]);
+    }
+
+    /// Mobility of the pieces of `color`, counting safe squares each piece attacks
+    fn mobility(&self, color: Color, occupancy: u64) -> TaperedScore {
+        let own_pieces = pieces_of(color).iter().fold(0, |set, piece| set | self.get_piece_set(*piece));
+        let enemy_pawns = self.get_piece_set(pieces_of(color.next_player())[0]);
+        let safe_squares = !own_pieces & !pawn_attacks(enemy_pawns, color.next_player());
+        let mut score = s(0, 0);
+        for piece in 1..5 {
+            let mut pieces = self.get_piece_set(pieces_of(color)[piece]);
+            while pieces > 0 {
+                let pos = pieces.trailing_zeros() as usize;
+                let moves = (self.piece_attacks(piece, pos, occupancy) & safe_squares).count_ones() as i32;
+                score += self.eval_params.mobility[piece] * (moves - MOBILITY_BASELINE[piece]);
+                pieces &= pieces - 1;
+            }
+        }
+        return score;
+    }
+
+    /// Squares attacked by the piece of type `piece` (index in the order of `Piece`) on `pos`
+    pub(super) fn piece_attacks(&self, piece: usize, pos: usize, occupancy: u64) -> u64 {
+        return match piece {
+            1 => self.runtime_constants.bishop_magic(pos, occupancy),
+            2 => KNIGHT_MOVE_MASKS[pos],
+            3 => self.runtime_constants.rook_magic(pos, occupancy),
+            4 => self.runtime_constants.bishop_magic(pos, occupancy) | self.runtime_constants.rook_magic(pos, occupancy),
+            5 => KING_MOVE_MASKS[pos],
+            _ => 0
         };
-        zip(PIECE_VALUES, pieces)
-            .map(|(value, piece)|value * (self.piece_sets[piece.to_u8() as usize].count_ones() as i32)).sum()
     }
 
+    /// Passed, isolated and doubled pawns of `color`
+    fn pawn_structure(&self, color: Color) -> TaperedScore {
+        let own_pawns = self.get_piece_set(pieces_of(color)[0]);
+        let enemy_pawns = self.get_piece_set(pieces_of(color.next_player())[0]);
+        let mut score = s(0, 0);
+        let mut pawns = own_pawns;
+        while pawns > 0 {
+            let pos = pawns.trailing_zeros() as usize;
+            if front_span(pos, color) & enemy_pawns == 0 && front_span(pos, color) & COLUMNS[pos % 8] & own_pawns == 0 {
+                score += self.eval_params.passed_pawn[relative_rank(pos, color)];
+            }
+            if adjacent_files(pos % 8) & own_pawns == 0 {
+                score += self.eval_params.isolated_pawn;
+            }
+            pawns &= pawns - 1;
+        }
+        for column in COLUMNS {
+            let count = (column & own_pawns).count_ones() as i32;
+            if count > 1 {
+                score += self.eval_params.doubled_pawn * (count - 1);
+            }
+        }
+        return score;
+    }
+
+    /// Bishop pair and rooks on open files of `color`
+    fn piece_bonuses(&self, color: Color) -> TaperedScore {
+        let [own_pawn, bishop, _, rook, _, _] = pieces_of(color);
+        let own_pawns = self.get_piece_set(own_pawn);
+        let all_pawns = own_pawns | self.get_piece_set(pieces_of(color.next_player())[0]);
+        let mut score = s(0, 0);
+        if self.get_piece_set(bishop).count_ones() >= 2 {
+            score += self.eval_params.bishop_pair;
+        }
+        let mut rooks = self.get_piece_set(rook);
+        while rooks > 0 {
+            let column = COLUMNS[rooks.trailing_zeros() as usize % 8];
+            if column & all_pawns == 0 {
+                score += self.eval_params.rook_open_file;
+            }
+            else if column & own_pawns == 0 {
+                score += self.eval_params.rook_semi_open_file;
+            }
+            rooks &= rooks - 1;
+        }
+        return score;
+    }
+
+    /// Penalty for the king of `color` from the attack units of the enemy pieces on the squares around it
+    fn king_safety(&self, color: Color, occupancy: u64) -> TaperedScore {
+        let king = self.get_piece_set(pieces_of(color)[5]);
+        if king == 0 {
+            return s(0, 0);
+        }
+        let king_pos = king.trailing_zeros() as usize;
+        let king_zone = KING_MOVE_MASKS[king_pos] | king;
+        let enemy_pieces = pieces_of(color.next_player());
+        let mut units = 0;
+        for piece in 1..5 {
+            let mut pieces = self.get_piece_set(enemy_pieces[piece]);
+            while pieces > 0 {
+                let pos = pieces.trailing_zeros() as usize;
+                units += KING_ATTACK_WEIGHTS[piece] * (self.piece_attacks(piece, pos, occupancy) & king_zone).count_ones() as i32;
+                pieces &= pieces - 1;
+            }
+        }
+        let king_safety = &self.eval_params.king_safety;
+        return s(-king_safety[(units as usize).min(king_safety.len() - 1)], 0);
+    }
+
+    /// Positional score of `color`, excluding material and piece-square tables
+    fn positional_score(&self, color: Color, occupancy: u64) -> TaperedScore {
+        return self.mobility(color, occupancy) + self.pawn_structure(color) + self.piece_bonuses(color) + self.king_safety(color, occupancy);
+    }
+
+    /// Material and piece-square table scores of `color`, from its own view
+    fn material_and_piece_squares(&self, color: Color) -> (TaperedScore, TaperedScore) {
+        let (mut material, mut piece_squares) = (s(0, 0), s(0, 0));
+        for (piece, piece_type) in pieces_of(color).iter().enumerate() {
+            let mut pieces = self.get_piece_set(*piece_type);
+            while pieces > 0 {
+                let pos = pieces.trailing_zeros() as usize;
+                // Tables are from the view of white
+                let table_pos = match color {
+                    Color::White => pos,
+                    Color::Black => pos ^ 56
+                };
+                material += self.eval_params.material[piece];
+                piece_squares += self.eval_params.piece_square_tables[piece][table_pos];
+                pieces &= pieces - 1;
+            }
+        }
+        return (material, piece_squares);
+    }
+
+    /// Evaluation split into its terms, with the score of each side. Used to find out why a position
+    /// is evaluated the way it is, the tapered total matches `classical_eval` from the view of white.
+    pub fn eval_trace(&self) -> EvalTrace {
+        let occupancy = !self.get_piece_set(Piece::Empty);
+        let (white_material, white_piece_squares) = self.material_and_piece_squares(Color::White);
+        let (black_material, black_piece_squares) = self.material_and_piece_squares(Color::Black);
+        let term = |name, score: &dyn Fn(Color) -> TaperedScore| EvalTerm { name, white: score(Color::White), black: score(Color::Black) };
+        let terms = vec![
+            EvalTerm { name: "Material", white: white_material, black: black_material },
+            EvalTerm { name: "Piece-square tables", white: white_piece_squares, black: black_piece_squares },
+            term("Mobility", &|color| self.mobility(color, occupancy)),
+            term("Pawn structure", &|color| self.pawn_structure(color)),
+            term("Bishops and rooks", &|color| self.piece_bonuses(color)),
+            term("King safety", &|color| self.king_safety(color, occupancy))
+        ];
+        return EvalTrace { terms, phase: self.phase() };
+    }
+
+    /// Evaluate the position from the view of the player to move, with the network if the board has one.
+    /// King of the Hill and Three-check add terms for their goals and Crazyhouse for the pockets, Antichess
+    /// only has terms of its own.
     pub fn eval(&self) -> i32 {
-        let result = self.piece_values_for::<WHITE>() - self.piece_values_for::<BLACK>();
+        if self.variant == Variant::Antichess {
+            return self.antichess_eval();
+        }
+        let eval = match &self.nnue {
+            Some(accumulator) => accumulator.evaluate(self.current_player),
+            None => self.classical_eval()
+        };
+        return eval + self.variant_eval();
+    }
+
+    /// Progress towards the goal of King of the Hill or Three-check, or the pockets of Crazyhouse, from the view
+    /// of the player to move
+    fn variant_eval(&self) -> i32 {
+        let score = |color: Color| match self.variant {
+            Variant::KingOfTheHill => match self.get_piece_set(pieces_of(color)[5]) {
+                0 => 0,
+                king => HILL_DISTANCE_BONUS[hill_distance(king.trailing_zeros() as usize)]
+            }
+            Variant::ThreeCheck => CHECKS_GIVEN_BONUS[self.checks_given[color as usize].min(3) as usize],
+            Variant::Crazyhouse => self.pocket_score(color),
+            Variant::Standard | Variant::Antichess => 0
+        };
+        return score(self.current_player) - score(self.current_player.next_player());
+    }
+
+    /// Pieces in the pocket of `color` in Crazyhouse, which are worth more when they can be dropped next
+    /// to the enemy king
+    fn pocket_score(&self, color: Color) -> i32 {
+        let drop_squares = match self.get_piece_set(pieces_of(color.next_player())[5]) {
+            0 => 0,
+            king => (KING_MOVE_MASKS[king.trailing_zeros() as usize] & self.get_piece_set(Piece::Empty)).count_ones() as i32
+        };
+        let mut score = 0;
+        for (piece, value) in POCKET_PIECE_VALUE.iter().enumerate() {
+            let count = self.get_pocket_count(pieces_of(color)[piece]) as i32;
+            score += count * (value + POCKET_KING_ATTACK * KING_ATTACK_WEIGHTS[piece] * drop_squares);
+        }
+        return score;
+    }
+
+    /// Evaluate an Antichess position from the view of the player to move, who is better with fewer pieces
+    fn antichess_eval(&self) -> i32 {
+        let piece_count = |color: Color| self.player_occupancy(color).count_ones() as i32;
+        return ANTICHESS_PIECE_VALUE * (piece_count(self.current_player.next_player()) - piece_count(self.current_player));
+    }
+
+    /// Evaluate the position with the hand-written evaluation terms, from the view of the player to move
+    pub fn classical_eval(&self) -> i32 {
+        let occupancy = !self.get_piece_set(Piece::Empty);
+        let score = self.psqt + self.positional_score(Color::White, occupancy) - self.positional_score(Color::Black, occupancy);
+        let result = score.taper(self.phase());
         match self.current_player {
             Color::White => result,
             Color::Black => -result
         }
     }
-}
\ No newline at end of file
+}
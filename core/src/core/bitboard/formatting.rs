@@ -1,71 +1,298 @@
 use bitboard::BitboardRuntimeConstants;
+use bitboard::constants::{MAX_POCKET_COUNT, ROWS};
 
 use crate::core::*;
-use std::{fmt, rc::Rc};
+use crate::core::variant::Variant;
+use std::{fmt, sync::Arc};
 use super::Board;
 
+/// Reasons for rejecting a FEN string
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FenError {
+    /// The FEN string is empty
+    Empty,
+    /// The FEN string has more than six fields
+    TooManyFields(usize),
+    /// The piece placement does not have exactly eight rows
+    WrongRowCount(usize),
+    /// The row for the given rank does not describe exactly eight squares
+    WrongRowLength(u8),
+    /// The piece placement contains a character which is neither a piece nor a digit from 1 to 8
+    InvalidPiece(char),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    /// The remaining checks of Three-check are not of the form `3+3`
+    InvalidChecks(String),
+    /// The pockets of Crazyhouse are not of the form `[Qn]`
+    InvalidPocket(String),
+    /// A player does not have exactly one king
+    WrongKingCount(Color, u32),
+    /// There is a pawn on the given square on the first or last rank
+    PawnOnBackRank(String),
+    /// The player who is not to move is in check
+    OpponentInCheck,
+    /// The castling right does not match the king and rook placement
+    CastlingWithoutKingOrRook(char),
+    /// No pawn can have double pushed past the en passant square
+    ImplausibleEnPassant(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "FEN string is empty"),
+            FenError::TooManyFields(count) => write!(f, "expected at most 6 fields, found {}", count),
+            FenError::WrongRowCount(count) => write!(f, "expected 8 rows of pieces, found {}", count),
+            FenError::WrongRowLength(rank) => write!(f, "rank {} does not have exactly 8 squares", rank),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move '{}', expected 'w' or 'b'", side),
+            FenError::InvalidCastling(castling) => write!(f, "invalid castling rights '{}'", castling),
+            FenError::InvalidEnPassant(ep) => write!(f, "invalid en passant square '{}'", ep),
+            FenError::InvalidHalfmoveClock(clock) => write!(f, "invalid halfmove clock '{}'", clock),
+            FenError::InvalidFullmoveNumber(number) => write!(f, "invalid fullmove number '{}'", number),
+            FenError::InvalidChecks(checks) => write!(f, "invalid remaining checks '{}', expected checks like 3+3", checks),
+            FenError::InvalidPocket(pocket) => write!(f, "invalid pockets '{}', expected pieces in brackets like [Qn]", pocket),
+            FenError::WrongKingCount(color, count) => write!(f, "{:?} has {} kings, expected 1", color, count),
+            FenError::PawnOnBackRank(square) => write!(f, "pawn on back rank square {}", square),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            FenError::CastlingWithoutKingOrRook(right) => write!(f, "castling right '{}' without king and rook on their starting squares", right),
+            FenError::ImplausibleEnPassant(ep) => write!(f, "no pawn can have double pushed past en passant square {}", ep),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
 impl Board {
-    // Create a new board from a FEN string
-    pub fn from_fen(fen: &str, runtime_constants: Rc<BitboardRuntimeConstants>) -> Board {
+    /// Create a new board from a FEN string. Fails if the FEN string is malformed or
+    /// if the position is not legal.
+    pub fn from_fen(fen: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Board, FenError> {
+        return Board::from_fen_variant(fen, Variant::Standard, runtime_constants);
+    }
+
+    /// Create a new board played with the rules of `variant` from a FEN string. Fails if the FEN string
+    /// is malformed or if the position is not legal in the variant.
+    pub fn from_fen_variant(fen: &str, variant: Variant, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Board, FenError> {
+        let mut board = Board::from_fen_unchecked(fen, runtime_constants)?;
+        board.set_variant(variant);
+        board.check_legality()?;
+        return Ok(board);
+    }
+
+    /// Create a new board from a FEN string without checking that the position is legal.
+    /// Useful for test positions, for example positions without kings.
+    ///
+    /// All fields except the piece placement are optional. The remaining checks of Three-check can be
+    /// given after the en passant square, as in `3+3`. The pockets of Crazyhouse follow the piece placement,
+    /// as in `[Qn]`, and promoted pieces are marked with a `~`, as in `Q~`.
+    pub fn from_fen_unchecked(fen: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Board, FenError> {
         let mut board = Board::empty(runtime_constants);
-        let parts: Vec<&str> = fen.trim().split(" ").collect();
+        let mut parts: Vec<&str> = fen.split_whitespace().collect();
+        if parts.is_empty() {
+            return Err(FenError::Empty);
+        }
+        if parts.get(4).is_some_and(|checks| checks.contains('+')) {
+            let checks = parts.remove(4);
+            let remaining: Vec<u8> = checks.split('+').filter_map(|remaining| remaining.parse().ok()).collect();
+            match remaining[..] {
+                [white, black] if white <= 3 && black <= 3 => board.checks_given = [3 - white, 3 - black],
+                _ => return Err(FenError::InvalidChecks(checks.to_string()))
+            }
+        }
+        if parts.len() > 6 {
+            return Err(FenError::TooManyFields(parts.len()));
+        }
+
+        // Pockets
+        let mut placement = parts[0];
+        if let Some((pieces, pockets)) = placement.split_once('[') {
+            let invalid = || FenError::InvalidPocket(format!("[{}", pockets));
+            for c in pockets.strip_suffix(']').ok_or_else(invalid)?.chars() {
+                let index = match c {
+                    'P' | 'N' | 'B' | 'R' | 'Q' | 'p' | 'n' | 'b' | 'r' | 'q' => Piece::from_char(c).to_u8() as usize,
+                    _ => return Err(invalid())
+                };
+                if board.pockets[index] as usize >= MAX_POCKET_COUNT {
+                    return Err(invalid());
+                }
+                board.pockets[index] += 1;
+            }
+            placement = pieces;
+        }
 
-        let pieces = parts[0];
-        let mut y: usize = 0;
         // Place pieces
-        for row in pieces.split("/") {
+        let rows: Vec<&str> = placement.split('/').collect();
+        if rows.len() != 8 {
+            return Err(FenError::WrongRowCount(rows.len()));
+        }
+        for (y, row) in rows.iter().enumerate() {
+            let rank = 8 - y as u8;
             let mut x: usize = 0;
+            let mut previous = ' ';
             for c in row.chars() {
-                if c.is_digit(10) {
+                match c {
+                    // Promoted piece in Crazyhouse
+                    '~' if previous.is_ascii_alphabetic() => board.promoted |= 1 << (y * 8 + x - 1),
                     // Digit means empty spaces
-                    let num = c.to_digit(10).unwrap() as usize;
-                    x += num;
-                }
-                else {
-                    // Map the character to the correct piece
-                    let piece = Piece::from_char(c);
-                    board.set_piece_pos(x, y, &piece);
-                    x += 1;
-                }
-                if x >= 8 {
-                    continue;
+                    '1'..='8' => x += c.to_digit(10).unwrap() as usize,
+                    'P' | 'B' | 'N' | 'R' | 'Q' | 'K' | 'p' | 'b' | 'n' | 'r' | 'q' | 'k' => {
+                        if x >= 8 {
+                            return Err(FenError::WrongRowLength(rank));
+                        }
+                        board.set_piece_pos(x, y, &Piece::from_char(c));
+                        x += 1;
+                    }
+                    _ => return Err(FenError::InvalidPiece(c))
                 }
+                previous = c;
+            }
+            if x != 8 {
+                return Err(FenError::WrongRowLength(rank));
             }
-            y += 1;
         }
         // Extract current player color
-        if parts.len() > 1 {
-            board.current_player = Color::from_char(parts[1].chars().nth(0).unwrap());
+        if let Some(&side) = parts.get(1) {
+            board.current_player = match side {
+                "w" => Color::White,
+                "b" => Color::Black,
+                _ => return Err(FenError::InvalidSideToMove(side.to_string()))
+            };
         }
-        // Castling
-        if parts.len() > 2 {
-            // let castling = parts[2];
-            let white_kingside = parts[2].contains('K');
-            let white_queenside = parts[2].contains('Q');
-            let black_kingside = parts[2].contains('k');
-            let black_queenside = parts[2].contains('q');
-            board.set_castling_bools(white_kingside, white_queenside, black_kingside, black_queenside);
+        // Castling. KQkq refer to the outermost rook on that side of the king, as in X-FEN, and
+        // the file of the rook can be given instead, as in Shredder-FEN
+        if let Some(&castling) = parts.get(2) {
+            if castling != "-" {
+                for c in castling.chars() {
+                    let index = match board.parse_castling_right(c) {
+                        Some(index) if board.castling & (1 << index) == 0 => index,
+                        _ => return Err(FenError::InvalidCastling(castling.to_string()))
+                    };
+                    board.castling |= 1 << index;
+                }
+                board.chess960 = !board.has_standard_castling();
+            }
         }
-        // EP
-        if parts.len() > 3 && parts[3] != "-" {
-            let (x, _) = algebraic_pos_to_pos(parts[3]);
-            board.ep = x + 1;
+        // EP. The rank is implied by the player to move, only the column is stored
+        if let Some(&ep) = parts.get(3) {
+            if ep != "-" {
+                let expected_rank = match board.current_player {
+                    Color::White => '6',
+                    Color::Black => '3'
+                };
+                let chars: Vec<char> = ep.chars().collect();
+                if chars.len() != 2 || !('a'..='h').contains(&chars[0]) || chars[1] != expected_rank {
+                    return Err(FenError::InvalidEnPassant(ep.to_string()));
+                }
+                let (x, _) = algebraic_pos_to_pos(ep);
+                board.ep = x + 1;
+            }
         }
-        // Quiet move number
-        if parts.len() > 4 {
-            board.quiet = str::parse(parts[4]).unwrap();
+        // Quiet move number. The clock saturates, as anything from 100 on already allows the fifty-move rule
+        if let Some(&quiet) = parts.get(4) {
+            let quiet: u32 = str::parse(quiet).map_err(|_| FenError::InvalidHalfmoveClock(quiet.to_string()))?;
+            board.quiet = quiet.min(u8::MAX as u32) as u8;
         }
         // Half moves
-        if parts.len() > 5 {
-            // Half moves
-            board.half_moves = str::parse(parts[5]).unwrap();
+        if let Some(&half_moves) = parts.get(5) {
+            board.half_moves = str::parse(half_moves).map_err(|_| FenError::InvalidFullmoveNumber(half_moves.to_string()))?;
         }
         board.hash_key = board.calculate_hash();
-        return board;
+        return Ok(board);
     }
 
-    /// Returns the current board as a FEN string
+    /// Set the rook square of the castling right given by a character of the castling field, and
+    /// return the index of the right. The rook does not have to be on the square.
+    fn parse_castling_right(&mut self, c: char) -> Option<usize> {
+        let (color_offset, back_rank, king, rook) = match c.is_ascii_uppercase() {
+            true => (0, 56, Piece::WhiteKing, Piece::WhiteRook),
+            false => (2, 0, Piece::BlackKing, Piece::BlackRook)
+        };
+        let king_file = (0..8).find(|file| self.get_piece(back_rank + file) == king);
+        let mut rook_files = (0..8).filter(|file| self.get_piece(back_rank + file) == rook);
+        let (queenside, rook_file) = match c.to_ascii_lowercase() {
+            'k' => (false, king_file.and_then(|king_file| rook_files.rev().find(|file| *file > king_file)).unwrap_or(7)),
+            'q' => (true, king_file.and_then(|king_file| rook_files.find(|file| *file < king_file)).unwrap_or(0)),
+            file @ 'a'..='h' => {
+                let rook_file = file as u8 - b'a';
+                (king_file.map_or(rook_file < 4, |king_file| rook_file < king_file), rook_file)
+            }
+            _ => return None
+        };
+        let index = color_offset + queenside as usize;
+        self.castling_rooks[index] = back_rank + rook_file;
+        return Some(index);
+    }
+
+    /// Check that the position could have been reached in a legal game. In Antichess, where the king is an
+    /// ordinary piece, any number of kings is allowed.
+    pub fn check_legality(&self) -> Result<(), FenError> {
+        // Exactly one king per player
+        for (color, king) in [(Color::White, Piece::WhiteKing), (Color::Black, Piece::BlackKing)] {
+            let count = self.get_piece_set(king).count_ones();
+            if count != 1 && self.variant != Variant::Antichess {
+                return Err(FenError::WrongKingCount(color, count));
+            }
+        }
+        // Pawns can never stand on the first or last rank
+        let pawns = self.get_piece_set(Piece::WhitePawn) | self.get_piece_set(Piece::BlackPawn);
+        let back_rank_pawns = pawns & (ROWS[0] | ROWS[7]);
+        if back_rank_pawns != 0 {
+            let pos = back_rank_pawns.trailing_zeros() as u8;
+            return Err(FenError::PawnOnBackRank(pos_to_algebraic_pos(pos % 8, pos / 8)));
+        }
+        // The player who just moved can not have left their king in check
+        let mut opponent_to_move = self.clone();
+        opponent_to_move.flip_player();
+        if opponent_to_move.is_in_check() {
+            return Err(FenError::OpponentInCheck);
+        }
+        // Castling rights require the king on the back rank and the rook on the side of the castling
+        for index in 0..4 {
+            if self.castling & (1 << index) == 0 {
+                continue;
+            }
+            let (king, rook) = match index < 2 {
+                true => (Piece::WhiteKing, Piece::WhiteRook),
+                false => (Piece::BlackKing, Piece::BlackRook)
+            };
+            let king_square = self.get_piece_set(king).trailing_zeros() as u8;
+            let rook_square = self.castling_rooks[index];
+            let queenside = index % 2 == 1;
+            if king_square / 8 != rook_square / 8 || self.get_piece(rook_square) != rook || (rook_square < king_square) != queenside {
+                return Err(FenError::CastlingWithoutKingOrRook("KQkq".as_bytes()[index] as char));
+            }
+        }
+        // The en passant square and the square the pawn came from must be empty,
+        // and the pawn which double pushed must be in front of them
+        if self.ep > 0 {
+            let x = self.ep - 1;
+            let (ep_y, from_y, pawn_y, pawn) = match self.current_player {
+                Color::White => (2, 1, 3, Piece::BlackPawn),
+                Color::Black => (5, 6, 4, Piece::WhitePawn)
+            };
+            if self.get_piece(ep_y * 8 + x) != Piece::Empty || self.get_piece(from_y * 8 + x) != Piece::Empty
+                || self.get_piece(pawn_y * 8 + x) != pawn {
+                return Err(FenError::ImplausibleEnPassant(self.get_ep_str()));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Returns the current board as a FEN string. Castling rights of Chess960 positions are written in X-FEN,
+    /// which is the same as standard FEN unless a rook with castling rights is not the outermost one.
     pub fn to_fen(&self) -> String {
+        return self.fen_with_castling(&self.get_castling_str());
+    }
+
+    /// Returns the current board as a FEN string with the castling rights as rook files, for example `HAha`
+    pub fn to_shredder_fen(&self) -> String {
+        return self.fen_with_castling(&self.get_shredder_castling_str());
+    }
+
+    fn fen_with_castling(&self, castling: &str) -> String {
         let mut fen_string = String::with_capacity(64);
         // Pieces
         let mut run_of_empty = 0;
@@ -79,6 +306,9 @@ impl Board {
             }
             if *piece != Piece::Empty {
                 fen_string.push(piece.as_char());
+                if self.is_promoted(i as u8) {
+                    fen_string.push('~');
+                }
             }
             if *piece == Piece::Empty {
                 run_of_empty += 1;
@@ -87,12 +317,26 @@ impl Board {
         if run_of_empty > 0 {
             fen_string.push_str(&format!("{}", run_of_empty));
         }
+        // Pockets
+        if self.variant == Variant::Crazyhouse {
+            fen_string.push('[');
+            for piece in "QRBNPqrbnp".chars().map(Piece::from_char) {
+                for _ in 0..self.get_pocket_count(piece) {
+                    fen_string.push(piece.as_char());
+                }
+            }
+            fen_string.push(']');
+        }
         // Current player
         fen_string.push_str(&format!(" {}", self.current_player.to_char()));
         // Castling
-        fen_string.push_str(&format!(" {}", &self.get_castling_str()));
+        fen_string.push_str(&format!(" {}", castling));
         // EP
         fen_string.push_str(&format!(" {}", &self.get_ep_str()));
+        // Remaining checks
+        if self.variant == Variant::ThreeCheck {
+            fen_string.push_str(&format!(" {}+{}", 3 - self.checks_given[0].min(3), 3 - self.checks_given[1].min(3)));
+        }
         // Quiet move number
         fen_string.push_str(&format!(" {}", self.quiet));
         // Full move number
@@ -109,19 +353,36 @@ impl Board {
         println!("");
     }
 
+    /// Castling rights in X-FEN, see `to_fen`
     pub fn get_castling_str(&self) -> String {
+        return self.castling_str(false);
+    }
+
+    /// Castling rights in Shredder-FEN, see `to_shredder_fen`
+    pub fn get_shredder_castling_str(&self) -> String {
+        return self.castling_str(true);
+    }
+
+    fn castling_str(&self, shredder: bool) -> String {
         let mut castling_str = "".to_string();
-        if self.get_castling(Color::White, false) {
-            castling_str.push('K');
-        }
-        if self.get_castling(Color::White, true) {
-            castling_str.push('Q');
-        }
-        if self.get_castling(Color::Black, false) {
-            castling_str.push('k');
-        }
-        if self.get_castling(Color::Black, true) {
-            castling_str.push('q');
+        for index in 0..4 {
+            if self.castling & (1 << index) == 0 {
+                continue;
+            }
+            let rook_square = self.castling_rooks[index];
+            let rook = self.get_piece(rook_square);
+            let (back_rank, rook_file) = (rook_square - rook_square % 8, rook_square % 8);
+            let mut outer_files = match index % 2 {
+                0 => rook_file + 1..8,
+                _ => 0..rook_file
+            };
+            let outermost = outer_files.all(|file| self.get_piece(back_rank + file) != rook);
+            let c = match (shredder || !outermost, index % 2) {
+                (true, _) => (b'a' + rook_file) as char,
+                (false, 0) => 'k',
+                (false, _) => 'q'
+            };
+            castling_str.push(if index < 2 { c.to_ascii_uppercase() } else { c });
         }
         if castling_str.len() == 0 {
             return "-".to_string();
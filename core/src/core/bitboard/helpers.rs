@@ -53,20 +53,10 @@ impl Board {
         if self.current_player == Color::White {
             result ^= self.runtime_constants.zoobrist_keys[PLAYER_INDEX];
         }
+        result ^= self.variant_hash();
         return result;
     }
 
-    /// Gets the castling rights for given player. Returns queenside castling rights if 
-    /// `queenside` is `true`, kingside otherwise.
-    pub(super) fn get_castling(&self, player: Color, queenside: bool) -> bool {
-        let color_offset = match player {
-            Color::White => 0,
-            Color::Black => 2
-        };
-        let index = color_offset + queenside as u8;
-        return (self.castling & (1u8 << index)) > 0;
-    }
-
     /// Set castling rights.
     pub(super) fn set_castling(&mut self, new_val: u8) {
         let old_val = self.castling;
@@ -83,7 +73,88 @@ impl Board {
         return self.castling;
     }
 
+    /// Squares the king and the rook move to when castling with the right `index`, in the order of
+    /// the castling bits. These are the same as in standard chess also in Chess960.
+    pub(in crate::core) fn castling_destinations(index: usize) -> (u8, u8) {
+        let back_rank = if index < 2 { 56 } else { 0 };
+        return match index % 2 {
+            0 => (back_rank + 6, back_rank + 5),
+            _ => (back_rank + 2, back_rank + 3)
+        };
+    }
+
+    /// Index of the castling right which a king move of `color` from `from` to `to` uses, given
+    /// the castling rights `rights` before the move. `None` if the move is not castling.
+    pub(super) fn castling_index_of(&self, color: Color, from: u8, to: u8, rights: u8) -> Option<usize> {
+        let first_index = match color {
+            Color::White => 0,
+            Color::Black => 2
+        };
+        for index in first_index..first_index + 2 {
+            if rights & (1 << index) == 0 {
+                continue;
+            }
+            let (king_to, _) = Board::castling_destinations(index);
+            let is_castling = match self.chess960 {
+                true => to == self.castling_rooks[index],
+                // With castling rights the king is on the e-file in standard chess
+                false => from == (king_to / 8) * 8 + 4 && to == king_to
+            };
+            if is_castling {
+                return Some(index);
+            }
+        }
+        return None;
+    }
+
+    /// Index of the castling right used by `mv` in the order of the castling bits: white kingside, white
+    /// queenside, black kingside and black queenside. `None` if the move is not castling.
+    pub fn castling_index(&self, mv: &Move) -> Option<usize> {
+        let king = match self.current_player {
+            Color::White => Piece::WhiteKing,
+            Color::Black => Piece::BlackKing
+        };
+        if self.get_piece(mv.from) != king {
+            return None;
+        }
+        return self.castling_index_of(self.current_player, mv.from, mv.to, self.castling);
+    }
+
+    /// Returns true if `mv` is castling
+    pub fn is_castling_move(&self, mv: &Move) -> bool {
+        return self.castling_index(mv).is_some();
+    }
+
+    /// Square of the rook of the castling right `index`, in the order of the castling bits
+    pub fn get_castling_rook(&self, index: usize) -> u8 {
+        return self.castling_rooks[index];
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        return self.chess960;
+    }
+
+    /// Encode castling moves as the king capturing its own rook, as in the UCI notation of Chess960.
+    /// Positions where the king or a rook with castling rights is not on its standard square always
+    /// use this encoding.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960 || !self.has_standard_castling();
+    }
+
+    /// Returns true if the king and rooks of all castling rights are on their standard chess squares
+    pub(super) fn has_standard_castling(&self) -> bool {
+        return (0..4).all(|index| {
+            let (king_square, king) = match index < 2 {
+                true => (60, Piece::WhiteKing),
+                false => (4, Piece::BlackKing)
+            };
+            return self.castling & (1 << index) == 0
+                || (self.castling_rooks[index] == STANDARD_CASTLING_ROOKS[index] && self.get_piece(king_square) == king);
+        });
+    }
+
     /// Generate a u8 representing castling rights from named booleans.
+    #[cfg(test)]
     pub(super) fn generate_castling_u8(white_kingside: bool, white_queenside: bool, black_kingside: bool, 
             black_queenside: bool) -> u8 {
         return (white_kingside as u8) | ((white_queenside as u8) << 1) | 
@@ -91,6 +162,7 @@ impl Board {
     }
 
     /// Set castling rights by named booleans.
+    #[cfg(test)]
     pub(super) fn set_castling_bools(&mut self, white_kingside: bool, white_queenside: bool, black_kingside: bool, 
             black_queenside: bool) {
         self.set_castling(Board::generate_castling_u8(
@@ -114,20 +186,6 @@ impl Board {
         self.hash_key ^= self.runtime_constants.zoobrist_keys[PLAYER_INDEX];
     } 
 
-    pub(super) fn set_one_castling_right<const COLOR: bool, const QUEENSIDE: bool, const ALLOWED: bool>(&mut self) {
-        let color_offset = match COLOR {
-            WHITE => 0,
-            BLACK => 2
-        };
-        let queenside = QUEENSIDE as u32;
-        let index = color_offset + queenside;
-        let new = match ALLOWED {
-            true => self.castling | 1u8 << index,
-            false => self.castling & (!1u8).rotate_left(index),
-        };
-        self.set_castling(new);
-    }
-
     pub fn get_ep(&self) -> u8 {
         return self.ep;
     }
@@ -136,7 +194,7 @@ impl Board {
         return self.quiet;
     }
 
-    pub fn get_half_moves(&self) -> u8 {
+    pub fn get_half_moves(&self) -> u16 {
         return self.half_moves;
     }
     
@@ -164,5 +222,16 @@ impl Board {
             panic!("Invalid board state. Stored hash_key {}, calculated hashkey {} in boardstate \"{}\"", 
                 self.hash_key, self.calculate_hash(), self.to_fen())
         }
+        // Validate that the incrementally updated network accumulator is correct
+        if let Some(accumulator) = &self.nnue {
+            if *accumulator != self.calculate_accumulator(accumulator.network()) {
+                panic!("Invalid board state. Network accumulator differs from the calculated one in boardstate \"{}\"", self.to_fen())
+            }
+        }
+        // Validate that the incrementally updated piece-square score is correct
+        if self.calculate_piece_square_score() != self.psqt {
+            panic!("Invalid board state. Stored piece-square score {:?}, calculated score {:?} in boardstate \"{}\"",
+                self.psqt, self.calculate_piece_square_score(), self.to_fen())
+        }
     }
 }
\ No newline at end of file
@@ -8,9 +8,12 @@ mod knights;
 mod kings;
 mod bishops;
 mod rooks;
+mod antichess;
+mod drops;
 
 use bitboard::constants::*;
 use move_list::{MoveList, SearchResult};
+use variant::Variant;
 
 use crate::core::*;
 use super::Board;
@@ -168,8 +171,15 @@ impl MovegenState {
 impl Board {
     /// Get all valid moves for this position. Pushes the moves to the mutable vector `moves` which is passed in.
     pub fn get_moves(&self, moves: &mut MoveList, only_captures: bool) {
-        let mut state = MovegenState::new(&self, only_captures);
         moves.clear();
+        if let Some(result) = self.variant_result() {
+            moves.set_result(result);
+            return;
+        }
+        if self.variant == Variant::Antichess {
+            return self.generate_antichess_moves(moves, only_captures);
+        }
+        let mut state = MovegenState::new(&self, only_captures);
         match self.current_player {
             Color::White => self.generate_moves_white(moves, &mut state),
             Color::Black => self.generate_moves_black(moves, &mut state)
@@ -182,6 +192,14 @@ impl Board {
         }
     }
 
+    /// Returns true if the player to move is in check. The king is not royal in Antichess, so there are no checks.
+    pub fn is_in_check(&self) -> bool {
+        if self.variant == Variant::Antichess {
+            return false;
+        }
+        return MovegenState::new(self, false).in_check();
+    }
+
     /// Generate valid moves for white
     pub(in crate::core) fn generate_moves_white(&self, moves : &mut MoveList, state: &mut MovegenState) {
         if state.checks < 2 {
@@ -190,6 +208,7 @@ impl Board {
             self.generate_white_bishop_like_moves(moves, state);
             self.generate_white_rook_like_moves(moves, state);
             self.generate_white_castling_moves(moves, state);
+            self.generate_drop_moves(moves, state);
         }
         self.generate_white_king_moves(moves, state);
     }
@@ -202,6 +221,7 @@ impl Board {
             self.generate_black_bishop_like_moves(moves, state);
             self.generate_black_rook_like_moves(moves, state);
             self.generate_black_castling_moves(moves, state);
+            self.generate_drop_moves(moves, state);
         }
         self.generate_black_king_moves(moves, state);
     }
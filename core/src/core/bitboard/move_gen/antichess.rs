@@ -0,0 +1,172 @@
+use move_list::{MoveList, SearchResult};
+use variant::Variant;
+
+use super::Board;
+use super::bitboard::constants::*;
+use crate::core::*;
+
+const PROMOTION_PIECES_WHITE : [Piece; 5] = [Piece::WhiteQueen, Piece::WhiteRook, Piece::WhiteBishop, Piece::WhiteKnight, Piece::WhiteKing];
+const PROMOTION_PIECES_BLACK : [Piece; 5] = [Piece::BlackQueen, Piece::BlackRook, Piece::BlackBishop, Piece::BlackKnight, Piece::BlackKing];
+
+/// Move the squares of `set` by `offset` towards a8, or towards h1 if `offset` is negative
+fn shift(set: u64, offset: i8) -> u64 {
+    return match offset > 0 {
+        true => set >> offset,
+        false => set << -offset
+    };
+}
+
+impl Board {
+    /// Moves of Antichess. The king is an ordinary piece, so there are no checks, pins or castling, and pawns
+    /// can also promote to a king. Capturing is compulsory: when a capture is possible, only captures are legal.
+    /// The player to move wins if there is no legal move.
+    pub(in crate::core) fn generate_antichess_moves(&self, moves: &mut MoveList, only_captures: bool) {
+        self.extract_antichess_moves(moves, true);
+        if moves.len() > 0 {
+            return;
+        }
+        self.extract_antichess_moves(moves, false);
+        if moves.len() == 0 {
+            moves.set_result(SearchResult::Win);
+        }
+        else if only_captures {
+            moves.clear();
+        }
+    }
+
+    /// Returns true if the player to move has to capture, which is the case in Antichess when any capture is possible
+    pub fn has_forced_capture(&self) -> bool {
+        if self.variant != Variant::Antichess {
+            return false;
+        }
+        let (pawn_attacks, piece_attacks) = self.antichess_attacks();
+        let enemy_occupancy = self.player_occupancy(self.current_player.next_player());
+        return piece_attacks & enemy_occupancy != 0 || pawn_attacks & (enemy_occupancy | self.antichess_ep_square()) != 0;
+    }
+
+    /// Squares attacked by the pawns and by the other pieces of the player to move
+    fn antichess_attacks(&self) -> (u64, u64) {
+        let occupancy = !self.get_piece_set(Piece::Empty);
+        let (offset, forward) = self.antichess_player_offsets();
+        let pawns = self.piece_sets[offset];
+        let pawn_attacks = shift(pawns & !COLUMNS[0], forward + 1) | shift(pawns & !COLUMNS[7], forward - 1);
+        let mut piece_attacks = 0;
+        for piece in 1..6 {
+            let mut pieces = self.piece_sets[offset + piece];
+            while pieces > 0 {
+                piece_attacks |= self.piece_attacks(piece, pieces.trailing_zeros() as usize, occupancy);
+                pieces &= pieces - 1;
+            }
+        }
+        return (pawn_attacks, piece_attacks);
+    }
+
+    /// Offset of the pieces of the player to move in `piece_sets`, and the difference between the square
+    /// a pawn moves from and the square it moves to
+    fn antichess_player_offsets(&self) -> (usize, i8) {
+        return match self.current_player {
+            Color::White => (0, 8),
+            Color::Black => (6, -8)
+        };
+    }
+
+    fn antichess_ep_square(&self) -> u64 {
+        return match (self.ep, self.current_player) {
+            (0, _) => 0,
+            (ep, Color::White) => 1u64 << (ep - 1 + 16),
+            (ep, Color::Black) => 1u64 << (ep - 1 + 40)
+        };
+    }
+
+    /// Captures if `captures` is set, otherwise the moves to empty squares
+    fn extract_antichess_moves(&self, moves: &mut MoveList, captures: bool) {
+        let occupancy = !self.get_piece_set(Piece::Empty);
+        let enemy_occupancy = self.player_occupancy(self.current_player.next_player());
+        let (offset, forward) = self.antichess_player_offsets();
+        let pawns = self.piece_sets[offset];
+        let targets = match captures {
+            true => enemy_occupancy,
+            false => !occupancy
+        };
+
+        if captures {
+            // Captures towards the a-file and towards the h-file
+            let pawn_targets = targets | self.antichess_ep_square();
+            self.extract_antichess_pawn_moves(moves, shift(pawns & !COLUMNS[0], forward + 1) & pawn_targets, forward + 1);
+            self.extract_antichess_pawn_moves(moves, shift(pawns & !COLUMNS[7], forward - 1) & pawn_targets, forward - 1);
+        }
+        else {
+            let double_move_row = match self.current_player {
+                Color::White => ROWS[5],
+                Color::Black => ROWS[2]
+            };
+            let single_moves = shift(pawns, forward) & targets;
+            let double_moves = shift(single_moves & double_move_row, forward) & targets;
+            self.extract_antichess_pawn_moves(moves, single_moves, forward);
+            self.extract_antichess_pawn_moves(moves, double_moves, 2 * forward);
+        }
+
+        for piece in 1..6 {
+            let mut pieces = self.piece_sets[offset + piece];
+            while pieces > 0 {
+                let pos = pieces.trailing_zeros() as usize;
+                pieces &= pieces - 1;
+                self.extract_moves_from_mask(moves, self.piece_attacks(piece, pos, occupancy) & targets, pos as u8);
+            }
+        }
+    }
+
+    /// Pawn moves to the squares of `move_mask`, from the square `from_offset` away
+    fn extract_antichess_pawn_moves(&self, moves: &mut MoveList, mut move_mask: u64, from_offset: i8) {
+        let promotion_pieces = match self.current_player {
+            Color::White => PROMOTION_PIECES_WHITE,
+            Color::Black => PROMOTION_PIECES_BLACK
+        };
+        while move_mask > 0 {
+            let to = move_mask.trailing_zeros() as u8;
+            move_mask &= move_mask - 1;
+            let from = (to as i8 + from_offset) as u8;
+            let captured = self.get_piece(to);
+            if (1u64 << to) & (ROWS[0] | ROWS[7]) != 0 {
+                for promotion in promotion_pieces {
+                    moves.push(Move::new(self, from, to, promotion, captured));
+                }
+            }
+            else {
+                moves.push(Move::new(self, from, to, Piece::Empty, captured));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::tests::assert_moves_eq_algebraic;
+    use crate::core::variant::Variant;
+
+    use super::bitboard::*;
+
+    #[test]
+    fn test_antichess_move_gen() {
+        let mut moves = MoveList::empty();
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let board_of = |fen: &str| Board::from_fen_variant(fen, Variant::Antichess, Arc::clone(&runtime_constants)).unwrap();
+
+        // Without captures every piece moves freely, the king may move next to the enemy king
+        let board = board_of("8/8/8/8/8/3k4/8/1N2K3 w - - 0 1");
+        board.get_moves(&mut moves, false);
+        assert_moves_eq_algebraic(&moves, &vec!["b1a3", "b1c3", "b1d2", "e1d1", "e1d2", "e1e2", "e1f1", "e1f2"]);
+        assert!(!board.has_forced_capture());
+        board.get_moves(&mut moves, true);
+        assert_eq!(moves.len(), 0);
+
+        // Any capture has to be made, including en passant and captures by several kings
+        let board = board_of("8/8/8/3pP3/8/8/1k6/K1K5 w - d6 0 1");
+        board.get_moves(&mut moves, false);
+        assert_moves_eq_algebraic(&moves, &vec!["e5d6", "a1b2", "c1b2"]);
+        assert!(board.has_forced_capture());
+        board.get_moves(&mut moves, true);
+        assert_eq!(moves.len(), 3);
+    }
+}
@@ -4,78 +4,47 @@ use super::{Board, MovegenState};
 use crate::core::*;
 use crate::core::bitboard::constants::*;
 
-const BLACK_QUEENSIDE_THREATENED_CASTLING_MASK: u64 = 0b1100;
-const BLACK_KINGSIDE_THREATENED_CASTLING_MASK: u64 = 0b1100000;
-const WHITE_QUEENSIDE_THREATENED_CASTLING_MASK: u64 = BLACK_QUEENSIDE_THREATENED_CASTLING_MASK << 56;
-const WHITE_KINGSIDE_THREATENED_CASTLING_MASK: u64 = BLACK_KINGSIDE_FREE_CASTLING_MASK << 56;
-
-const WHITE_QUEENSIDE_FREE_CASTLING_MASK: u64 = 0b01110 << 56;
-const WHITE_KINGSIDE_FREE_CASTLING_MASK: u64 = 0b0110 << 60;
-const BLACK_QUEENSIDE_FREE_CASTLING_MASK: u64 = 0b01110;
-const BLACK_KINGSIDE_FREE_CASTLING_MASK: u64 = 0b0110 << 4;
-
-const WHITE_KING_SQUARE: u8 = 60;
-const BLACK_KING_SQUARE: u8 = 4;
+/// Squares from `a` to `b` on the same rank, both included
+fn rank_span(a: u8, b: u8) -> u64 {
+    let (low, high) = (a.min(b), a.max(b));
+    return (!0u64 >> (63 - high)) & (!0u64 << low);
+}
 
 impl Board {
+    /// Castling is legal if the king is not in check, the squares the king and the rook pass through and land
+    /// on are empty apart from the king and the rook themselves, and the king does not pass through or land
+    /// on an attacked square. This covers Chess960, where the king and the rooks can start on any file.
     fn extract_castling_moves<const COLOR: bool>(&self, moves : &mut MoveList, state: &MovegenState) {
-        if state.only_captures {
-            return;
-        }
-        // Get some constants based on color
-        let (
-            queenside_castling_offset, 
-            kingside_castling_offset, 
-            queenside_castling_king_pos, 
-            kingside_castling_king_pos,
-            queenside_mask,
-            queenside_threaten_mask,
-            kingside_mask,
-            kingside_threaten_mask,
-            king_square
-        );
-        if COLOR == WHITE {
-            queenside_castling_offset = 1;
-            kingside_castling_offset = 0;
-            queenside_castling_king_pos = 58;
-            kingside_castling_king_pos = 62;
-            queenside_mask = WHITE_QUEENSIDE_FREE_CASTLING_MASK;
-            queenside_threaten_mask = WHITE_QUEENSIDE_THREATENED_CASTLING_MASK;
-            kingside_mask = WHITE_KINGSIDE_FREE_CASTLING_MASK;
-            kingside_threaten_mask = WHITE_KINGSIDE_THREATENED_CASTLING_MASK;
-            king_square = WHITE_KING_SQUARE;
-        } else {
-            queenside_castling_offset = 3;
-            kingside_castling_offset = 2;
-            queenside_castling_king_pos = 2;
-            kingside_castling_king_pos = 6;
-            queenside_mask = BLACK_QUEENSIDE_FREE_CASTLING_MASK;
-            queenside_threaten_mask = BLACK_QUEENSIDE_THREATENED_CASTLING_MASK;
-            kingside_mask = BLACK_KINGSIDE_FREE_CASTLING_MASK;
-            kingside_threaten_mask = BLACK_KINGSIDE_THREATENED_CASTLING_MASK;
-            king_square = BLACK_KING_SQUARE;
-        }
-
-        if state.checks > 0 {
+        let (first_index, king, enemy_rooks) = match COLOR {
+            WHITE => (0, Piece::WhiteKing, self.get_piece_set(Piece::BlackRook) | self.get_piece_set(Piece::BlackQueen)),
+            BLACK => (2, Piece::BlackKing, self.get_piece_set(Piece::WhiteRook) | self.get_piece_set(Piece::WhiteQueen))
+        };
+        if state.only_captures || state.checks > 0 || self.get_piece_set(king) == 0 {
             return;
         }
-        let can_castle_queenside: u64 = ((self.castling >> queenside_castling_offset) & 1) as u64;
-        let can_castle_kingside: u64 = ((self.castling >> kingside_castling_offset) & 1) as u64;
-        let queenside_squares_legal = (((state.occupancy & queenside_mask) | (state.threatened_squares & queenside_threaten_mask)) == 0) as u64;
-        let kingside_squares_legal = (((state.occupancy & kingside_mask) | (state.threatened_squares & kingside_threaten_mask)) == 0) as u64;
-        let mut move_mask: u64 = ((queenside_squares_legal & can_castle_queenside) << queenside_castling_king_pos) | 
-                                 ((kingside_squares_legal & can_castle_kingside) << kingside_castling_king_pos);
-
-        while move_mask > 0 {
-            let index = move_mask.trailing_zeros() as u8;
-            move_mask &= move_mask - 1;
-            moves.push(Move::new(
-                self,
-                king_square,
-                index,
-                Piece::Empty,
-                Piece::Empty
-            ));
+        let king_square = self.get_piece_set(king).trailing_zeros() as u8;
+        for index in first_index..first_index + 2 {
+            if self.castling & (1 << index) == 0 {
+                continue;
+            }
+            let rook_square = self.castling_rooks[index];
+            let (king_to, rook_to) = Board::castling_destinations(index);
+            let castling_pieces = (1u64 << king_square) | (1u64 << rook_square);
+            let king_path = rank_span(king_square, king_to);
+            let blockers = (king_path | rank_span(rook_square, rook_to)) & state.occupancy & !castling_pieces;
+            if blockers != 0 || king_path & state.threatened_squares != 0 {
+                continue;
+            }
+            // The rook may shield the destination of the king from a rook or queen on the back rank
+            let occupancy_after = (state.occupancy & !castling_pieces) | (1u64 << rook_to);
+            if self.runtime_constants.rook_magic(king_to as usize, occupancy_after) & enemy_rooks != 0 {
+                continue;
+            }
+            let to = match self.chess960 {
+                true => rook_square,
+                false => king_to
+            };
+            moves.push(Move::new(self, king_square, to, Piece::Empty, Piece::Empty));
         }
     }
 
@@ -100,9 +69,9 @@ mod tests {
     #[test]
     fn test_castling_move_gen() {
         let mut moves = MoveList::empty();
-        let runtime_constants = Rc::new(BOARD_CONSTANT_STATE.clone());
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
         // Check that the castling moves are not generated if blocked in the starting position
-        let board = Board::new(Rc::clone(&runtime_constants));
+        let board = Board::new(Arc::clone(&runtime_constants));
         let movegen_state = MovegenState::new(&board, false);
         board.generate_white_castling_moves(&mut moves, &movegen_state);
         assert_eq!(moves.len(), 0);
@@ -110,7 +79,7 @@ mod tests {
         assert_eq!(moves.len(), 0);
 
         // Check that the castling moves generate when not blocked
-        let mut board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1 ", Rc::clone(&runtime_constants));
+        let mut board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1 ", Arc::clone(&runtime_constants)).unwrap();
         let movegen_state = MovegenState::new(&board, false);
         board.generate_white_castling_moves(&mut moves, &movegen_state);
         assert_moves_eq_algebraic(&moves, &vec!["e1c1", "e1g1"]);
@@ -148,4 +117,44 @@ mod tests {
         moves.clear();
         board.validate();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chess960_castling_move_gen() {
+        let mut moves = MoveList::empty();
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        // The rook leaving b1 would expose the king on c1 to the rook on a1
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/rRK4R w HB - 0 1", Arc::clone(&runtime_constants)).unwrap();
+        let movegen_state = MovegenState::new(&board, false);
+        board.generate_white_castling_moves(&mut moves, &movegen_state);
+        assert_moves_eq_algebraic(&moves, &vec!["c1h1"]);
+        moves.clear();
+
+        // The king stays on g1 when castling kingside
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R5KR w HA - 0 1", Arc::clone(&runtime_constants)).unwrap();
+        let movegen_state = MovegenState::new(&board, false);
+        board.generate_white_castling_moves(&mut moves, &movegen_state);
+        assert_moves_eq_algebraic(&moves, &vec!["g1a1", "g1h1"]);
+        let fen = board.to_fen();
+        for (to, expected) in [(56, "4k3/8/8/8/8/8/8/2KR3R b - - 1 2"), (63, "4k3/8/8/8/8/8/8/R4RK1 b - - 1 2")] {
+            let mv = *moves.iter().find(|mv| mv.to == to).unwrap();
+            board.make_move(&mv);
+            board.validate();
+            assert_eq!(board.to_fen(), expected);
+            board.unmake_move(&mv);
+            assert_eq!(board.to_fen(), fen);
+            board.validate();
+        }
+        moves.clear();
+
+        // Standard positions use the king-captures-rook encoding in Chess960 mode
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", Arc::clone(&runtime_constants)).unwrap();
+        board.set_chess960(true);
+        let movegen_state = MovegenState::new(&board, false);
+        board.generate_white_castling_moves(&mut moves, &movegen_state);
+        assert_moves_eq_algebraic(&moves, &vec!["e1a1", "e1h1"]);
+        let kingside = *moves.iter().find(|mv| mv.to == 63).unwrap();
+        assert_eq!(kingside.to_san(&board), "O-O");
+        board.make_move(&kingside);
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 2");
+    }
+}
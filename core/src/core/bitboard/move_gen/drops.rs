@@ -0,0 +1,74 @@
+use move_list::MoveList;
+use variant::Variant;
+
+use super::{Board, MovegenState};
+use super::bitboard::constants::*;
+use super::bitboard::crazyhouse::piece_of_color;
+use crate::core::*;
+
+/// Pieces which can be in a pocket, in the order of `Piece`
+const POCKET_PIECES: [Piece; 5] = [Piece::WhitePawn, Piece::WhiteBishop, Piece::WhiteKnight, Piece::WhiteRook, Piece::WhiteQueen];
+
+impl Board {
+    /// Drops of the pieces in the pocket of the player to move on empty squares, in Crazyhouse. Pawns can not
+    /// be dropped on the first or last rank. In check, a drop has to block the checking piece, which is
+    /// only possible against a single check by a bishop, rook or queen.
+    pub(in crate::core) fn generate_drop_moves(&self, moves: &mut MoveList, state: &MovegenState) {
+        if self.variant != Variant::Crazyhouse || state.only_captures || state.checks > 1 {
+            return;
+        }
+        let targets = !state.occupancy & state.legal_targets;
+        for piece in POCKET_PIECES {
+            let piece = piece_of_color(piece, self.current_player);
+            if self.get_pocket_count(piece) == 0 {
+                continue;
+            }
+            let mut squares = match piece {
+                Piece::WhitePawn | Piece::BlackPawn => targets & !(ROWS[0] | ROWS[7]),
+                _ => targets
+            };
+            while squares > 0 {
+                let to = squares.trailing_zeros() as u8;
+                squares &= squares - 1;
+                moves.push(Move::new_drop(self, piece, to));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::tests::assert_moves_eq_algebraic;
+    use crate::core::variant::Variant;
+
+    use super::bitboard::*;
+
+    #[test]
+    fn test_drop_move_gen() {
+        let mut moves = MoveList::empty();
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let board_of = |fen: &str| Board::from_fen_variant(fen, Variant::Crazyhouse, Arc::clone(&runtime_constants)).unwrap();
+
+        // Pawns are not dropped on the back ranks and drops are never captures
+        let board = board_of("k7/8/8/8/8/8/8/K7[Pn] w - - 0 1");
+        board.get_moves(&mut moves, false);
+        assert_eq!(moves.len(), 3 + 48);
+        assert!(moves.iter().all(|mv| !mv.is_drop() || mv.promotion == Piece::WhitePawn));
+        board.get_moves(&mut moves, true);
+        assert_eq!(moves.len(), 0);
+
+        // A check by a rook can be blocked by a drop, but not by a pawn on the first rank
+        let board = board_of("4k3/8/8/8/8/8/8/r3K3[NP] w - - 0 1");
+        board.get_moves(&mut moves, false);
+        assert_moves_eq_algebraic(&moves, &vec!["e1d2", "e1e2", "e1f2", "N@b1", "N@c1", "N@d1"]);
+
+        // Checks by a knight and double checks can not be blocked
+        let board = board_of("4k3/8/8/8/8/3n4/8/4K3[Q] w - - 0 1");
+        board.get_moves(&mut moves, false);
+        assert!(moves.iter().all(|mv| !mv.is_drop()));
+        let board = board_of("4k3/8/8/8/8/3n4/8/r3K3[Q] w - - 0 1");
+        board.get_moves(&mut moves, false);
+        assert!(moves.iter().all(|mv| !mv.is_drop()));
+    }
+}
@@ -81,8 +81,8 @@ mod tests {
     #[test]
     fn test_king_move_gen() {
         let mut moves = MoveList::empty();
-        let runtime_constants = Rc::new(BOARD_CONSTANT_STATE.clone());
-        let mut board = Board::empty(Rc::clone(&runtime_constants));
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let mut board = Board::empty(Arc::clone(&runtime_constants));
         board.set_piece_pos(3, 3, &Piece::WhiteKing);
         board.set_piece_pos(3, 6, &Piece::BlackKing);
         let movegen_state = MovegenState::new(&board, false);
@@ -39,9 +39,9 @@ mod tests {
     #[test]
     fn test_knight_move_gen() {
         let mut moves = MoveList::empty();
-        let runtime_constants = Rc::new(BOARD_CONSTANT_STATE.clone());
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
         // Check that knight moves are generated correctly in the starting position
-        let board = Board::new(Rc::clone(&runtime_constants));
+        let board = Board::new(Arc::clone(&runtime_constants));
         let movegen_state = MovegenState::new(&board, false);
         board.generate_white_knight_moves(&mut moves, &movegen_state);
         assert_moves_eq_algebraic(&moves, &vec!["b1a3", "b1c3","g1f3", "g1h3"]);
@@ -52,7 +52,7 @@ mod tests {
 
         moves.clear();
         // Check that all knight moves are generated correctly in an open position
-        let mut board = Board::empty(Rc::clone(&runtime_constants));
+        let mut board = Board::empty(Arc::clone(&runtime_constants));
         board.generate_white_knight_moves(&mut moves, &movegen_state);
         board.generate_black_knight_moves(&mut moves, &movegen_state);
         assert_eq!(moves.len(), 0);
@@ -183,8 +183,8 @@ mod tests {
 
     #[test]
     fn test_pawn_move_gen() {
-        let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-        let board = Board::new(Rc::clone(&constant_state));
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let board = Board::new(Arc::clone(&constant_state));
         let mut moves = MoveList::empty();
         let movegen_state = MovegenState::new(&board, false);
         board.generate_white_pawn_moves(&mut moves, &movegen_state);
@@ -196,7 +196,7 @@ mod tests {
         assert_moves_eq_algebraic(&moves, &vec!["a7a6","b7b6","c7c6","d7d6","e7e6","f7f6","g7g6","h7h6",
                                                "a7a5","b7b5","c7c5","d7d5","e7e5","f7f5","g7g5","h7h5"]);
 
-        let board = Board::from_fen("r1bqkbnr/1P2pppp/5P2/2p3P1/1p5P/p7/PPPP2p1/RNBQKB1R", Rc::clone(&constant_state));
+        let board = Board::from_fen("r1bqkbnr/1P2pppp/5P2/2p3P1/1p5P/p7/PPPP2p1/RNBQKB1R", Arc::clone(&constant_state)).unwrap();
         let movegen_state = MovegenState::new(&board, false);
         moves.clear();
         board.generate_white_pawn_moves(&mut moves, &movegen_state);
@@ -217,7 +217,7 @@ mod tests {
         ]);   
 
 
-        let board = Board::from_fen("K7/8/1p6/p1p5/8/P1P5/1P6/k7 b - - 0 1", Rc::clone(&constant_state));
+        let board = Board::from_fen("K7/8/1p6/p1p5/8/P1P5/1P6/k7 b - - 0 1", Arc::clone(&constant_state)).unwrap();
         let movegen_state = MovegenState::new(&board, false);
         moves.clear();
         board.generate_black_pawn_moves(&mut moves, &movegen_state);
@@ -226,7 +226,7 @@ mod tests {
         ]);
 
         // En passant
-        let board = Board::from_fen("K7/8/8/1pP5/5Pp1/8/8/k7 w - b6", Rc::clone(&constant_state));
+        let board = Board::from_fen("K7/8/8/1pP5/5Pp1/8/8/k7 w - b6", Arc::clone(&constant_state)).unwrap();
         let movegen_state = MovegenState::new(&board, false);
         moves.clear();
         board.generate_white_pawn_moves(&mut moves, &movegen_state);
@@ -234,7 +234,7 @@ mod tests {
             "c5c6", "c5b6", "f4f5",
         ]);
 
-        let board = Board::from_fen("K7/8/8/1pP5/5Pp1/8/8/k7 b - f3", Rc::clone(&constant_state));
+        let board = Board::from_fen("K7/8/8/1pP5/5Pp1/8/8/k7 b - f3", Arc::clone(&constant_state)).unwrap();
         let movegen_state = MovegenState::new(&board, false);
         moves.clear();
         board.generate_black_pawn_moves(&mut moves, &movegen_state);
@@ -48,9 +48,9 @@ mod tests {
     #[test]
     fn test_knight_move_gen() {
         let mut moves = MoveList::empty();
-        let runtime_constants = Rc::new(BOARD_CONSTANT_STATE.clone());
+        let runtime_constants = Arc::new(BOARD_CONSTANT_STATE.clone());
         // Check that knight moves are generated correctly in the starting position
-        let board = Board::new(Rc::clone(&runtime_constants));
+        let board = Board::new(Arc::clone(&runtime_constants));
         let movegen_state = MovegenState::new(&board, false);
         board.generate_white_bishop_like_moves(&mut moves, &movegen_state);
         //assert_moves_eq_algebraic(&moves, &vec!["b1a3", "b1c3","g1f3", "g1h3"]);
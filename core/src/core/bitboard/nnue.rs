@@ -0,0 +1,270 @@
+//! Efficiently updatable neural network (NNUE) evaluation.
+//!
+//! The network is a quantized `(768 -> N)x2 -> 1` perspective network. Each side has an accumulator with the
+//! hidden layer of its own view of the board, which `Board::set_piece` keeps up to date when pieces are
+//! added and removed. The output layer applies a clipped ReLU to the accumulator of the side to move and the
+//! accumulator of the other side, in that order.
+//!
+//! Network files contain little-endian `i16` values: the feature weights `[768][N]`, the feature biases `[N]`,
+//! the output weights `[2][N]` and the output bias, optionally padded with zeros to a multiple of 64 bytes.
+//! Features are indexed by `384 * side + 64 * piece + square`, where `side` is 0 for the pieces of the
+//! perspective and 1 for the pieces of the opponent, pieces are ordered pawn, knight, bishop, rook, queen, king
+//! and squares start at A1 from the view of white. Black sees the board mirrored vertically.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::core::{Color, Piece};
+
+/// Quantization of the feature weights and biases, which is also the upper bound of the clipped ReLU
+pub const QA: i32 = 255;
+/// Quantization of the output weights
+pub const QB: i32 = 64;
+/// Scale from the network output to centipawns
+pub const OUTPUT_SCALE: i32 = 400;
+
+const FEATURE_COUNT: usize = 768;
+/// Index of each piece type of `Piece` in the feature order: pawn, knight, bishop, rook, queen, king
+const FEATURE_PIECE_INDEX: [usize; 6] = [0, 2, 1, 3, 4, 5];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NetworkError {
+    /// The file size does not match any hidden layer size
+    WrongSize(usize)
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::WrongSize(size) => write!(f, "{} bytes is not the size of a (768 -> N)x2 -> 1 network", size)
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden_size: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Network {{ hidden_size: {} }}", self.hidden_size)
+    }
+}
+
+impl Network {
+    /// Read a network file, the hidden layer size follows from the size of the file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NetworkError> {
+        let values_per_neuron = FEATURE_COUNT + 1 + 2;
+        let hidden_size = (bytes.len() / 2).saturating_sub(1) / values_per_neuron;
+        let unpadded_size = 2 * (hidden_size * values_per_neuron + 1);
+        if hidden_size == 0 || bytes.len() % 2 != 0 || bytes.len() - unpadded_size >= 64 {
+            return Err(NetworkError::WrongSize(bytes.len()));
+        }
+        let mut values = bytes.chunks_exact(2).map(|value| i16::from_le_bytes([value[0], value[1]]));
+        let mut take = |count: usize| values.by_ref().take(count).collect::<Vec<i16>>();
+        let feature_weights = take(FEATURE_COUNT * hidden_size);
+        let feature_biases = take(hidden_size);
+        let output_weights = take(2 * hidden_size);
+        let output_bias = take(1)[0];
+        return Ok(Network { hidden_size, feature_weights, feature_biases, output_weights, output_bias });
+    }
+
+    /// Network file of this network, which can be read with `from_bytes`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let values = self.feature_weights.iter().chain(self.feature_biases.iter()).chain(self.output_weights.iter())
+            .chain(std::iter::once(&self.output_bias));
+        let mut bytes: Vec<u8> = values.flat_map(|value| value.to_le_bytes()).collect();
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        return bytes;
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        return self.hidden_size;
+    }
+
+    /// Network with small random weights, used to test the incremental updates
+    #[cfg(test)]
+    pub(crate) fn random(hidden_size: usize, seed: u64) -> Network {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(seed);
+        let mut random = |count: usize, bound: i16| (0..count).map(|_| rng.gen_range(-bound..=bound)).collect::<Vec<i16>>();
+        return Network {
+            hidden_size,
+            feature_weights: random(FEATURE_COUNT * hidden_size, 40),
+            feature_biases: random(hidden_size, 100),
+            output_weights: random(2 * hidden_size, 64),
+            output_bias: random(1, 1000)[0]
+        };
+    }
+}
+
+/// Feature of `piece` on `pos` from the view of `perspective`
+fn feature_index(perspective: Color, piece: Piece, pos: u8) -> usize {
+    let piece_index = piece.to_u8() as usize;
+    let (piece_color, piece_type) = (piece_index / 6, FEATURE_PIECE_INDEX[piece_index % 6]);
+    // Squares of the board start at A8, features start at A1 for white and at A8 for black
+    return match perspective {
+        Color::White => 384 * piece_color + 64 * piece_type + (pos ^ 56) as usize,
+        Color::Black => 384 * (1 - piece_color) + 64 * piece_type + pos as usize
+    };
+}
+
+/// Hidden layer of the network from the view of each side, updated incrementally as pieces move
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    /// Indexed by the color of the perspective
+    values: [Vec<i16>; 2]
+}
+
+impl Accumulator {
+    /// Accumulator of an empty board
+    pub fn new(network: Arc<Network>) -> Accumulator {
+        let values = [network.feature_biases.clone(), network.feature_biases.clone()];
+        return Accumulator { network, values };
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        return &self.network;
+    }
+
+    pub fn add_piece(&mut self, piece: Piece, pos: u8) {
+        self.update(piece, pos, |value, weight| value.wrapping_add(weight));
+    }
+
+    pub fn remove_piece(&mut self, piece: Piece, pos: u8) {
+        self.update(piece, pos, |value, weight| value.wrapping_sub(weight));
+    }
+
+    fn update(&mut self, piece: Piece, pos: u8, operation: impl Fn(i16, i16) -> i16) {
+        if piece == Piece::Empty {
+            return;
+        }
+        let hidden_size = self.network.hidden_size;
+        for perspective in [Color::White, Color::Black] {
+            let feature = feature_index(perspective, piece, pos);
+            let weights = &self.network.feature_weights[feature * hidden_size..(feature + 1) * hidden_size];
+            // Simple enough for the compiler to vectorize
+            for (value, weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value = operation(*value, *weight);
+            }
+        }
+    }
+
+    /// Evaluation in centipawns from the view of `side_to_move`
+    pub fn evaluate(&self, side_to_move: Color) -> i32 {
+        let hidden_size = self.network.hidden_size;
+        let (us, them) = (&self.values[side_to_move as usize], &self.values[side_to_move.next_player() as usize]);
+        let output = crelu_dot(us, &self.network.output_weights[..hidden_size])
+            + crelu_dot(them, &self.network.output_weights[hidden_size..])
+            + self.network.output_bias as i32;
+        return output * OUTPUT_SCALE / (QA * QB);
+    }
+}
+
+/// Sum of the clipped ReLU of `values` times `weights`
+fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    {
+        if values.len() % 16 == 0 {
+            return unsafe { avx2::crelu_dot(values, weights) };
+        }
+    }
+    return crelu_dot_scalar(values, weights);
+}
+
+fn crelu_dot_scalar(values: &[i16], weights: &[i16]) -> i32 {
+    return values.iter().zip(weights)
+        .map(|(value, weight)| (*value as i32).clamp(0, QA) * *weight as i32)
+        .sum();
+}
+
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(target_feature = "avx2"), allow(dead_code))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::QA;
+
+    /// Same as `crelu_dot_scalar`, for slices with a length which is a multiple of 16
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi16(QA as i16));
+        let mut sum = _mm256_setzero_si256();
+        for (values, weights) in values.chunks_exact(16).zip(weights.chunks_exact(16)) {
+            let values = _mm256_loadu_si256(values.as_ptr() as *const __m256i);
+            let weights = _mm256_loadu_si256(weights.as_ptr() as *const __m256i);
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(values, zero), max);
+            // Products of clipped values and weights fit in i16 pairs summed to i32
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weights));
+        }
+        let sum = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256(sum, 1));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        return _mm_cvtsi128_si32(sum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::{Color, Piece};
+    use super::{crelu_dot_scalar, Accumulator, Network, NetworkError, OUTPUT_SCALE, QA, QB};
+
+    #[test]
+    fn test_network_file() {
+        let network = Network::random(32, 1);
+        let bytes = network.to_bytes();
+        assert_eq!(bytes.len() % 64, 0);
+        assert_eq!(Network::from_bytes(&bytes).unwrap(), network);
+        // Unpadded files are also accepted
+        assert_eq!(Network::from_bytes(&bytes[..2 * (771 * 32 + 1)]).unwrap(), network);
+        assert_eq!(Network::from_bytes(&bytes[..1000]), Err(NetworkError::WrongSize(1000)));
+        assert_eq!(Network::from_bytes(&[]), Err(NetworkError::WrongSize(0)));
+    }
+
+    #[test]
+    fn test_accumulator() {
+        let network = Arc::new(Network::random(32, 2));
+        let mut accumulator = Accumulator::new(Arc::clone(&network));
+        let empty = accumulator.clone();
+        let bias_only = (crelu_dot_scalar(&network.feature_biases, &network.output_weights[..32])
+            + crelu_dot_scalar(&network.feature_biases, &network.output_weights[32..])
+            + network.output_bias as i32) * OUTPUT_SCALE / (QA * QB);
+        assert_eq!(accumulator.evaluate(Color::White), bias_only);
+
+        accumulator.add_piece(Piece::WhiteKnight, 10);
+        accumulator.add_piece(Piece::Empty, 11);
+        assert_ne!(accumulator, empty);
+        accumulator.remove_piece(Piece::WhiteKnight, 10);
+        assert_eq!(accumulator, empty);
+
+        // A white piece seen by white is the same feature as the mirrored black piece seen by black
+        let mut white = Accumulator::new(Arc::clone(&network));
+        white.add_piece(Piece::WhiteQueen, 52);
+        let mut black = Accumulator::new(Arc::clone(&network));
+        black.add_piece(Piece::BlackQueen, 52 ^ 56);
+        assert_eq!(white.values[0], black.values[1]);
+        assert_eq!(white.evaluate(Color::White), black.evaluate(Color::Black));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        let network = Network::random(64, 3);
+        let values: Vec<i16> = network.feature_weights[..64].iter().map(|weight| weight * 20).collect();
+        let weights = &network.output_weights[..64];
+        assert_eq!(unsafe { super::avx2::crelu_dot(&values, weights) }, crelu_dot_scalar(&values, weights));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::core::*;
+use crate::core::variant::Variant;
+use bitboard::constants::*;
+use move_list::SearchResult;
+
+use super::Board;
+
+impl Board {
+    pub fn get_variant(&self) -> Variant {
+        return self.variant;
+    }
+
+    /// Play the position with the rules of `variant`. Antichess does not have castling, so the castling
+    /// rights are removed, the checks given are only kept in Three-check and the pockets only in Crazyhouse.
+    /// Set before making moves.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        if variant == Variant::Antichess {
+            self.castling = 0;
+        }
+        if variant != Variant::ThreeCheck {
+            self.checks_given = [0; 2];
+        }
+        if variant != Variant::Crazyhouse {
+            self.pockets = [0; 12];
+            self.promoted = 0;
+        }
+        self.hash_key = self.calculate_hash();
+    }
+
+    /// Number of checks `color` has given, only counted in Three-check
+    pub fn get_checks_given(&self, color: Color) -> u8 {
+        return self.checks_given[color as usize];
+    }
+
+    /// Set the number of checks `color` has given and update the hash key
+    pub(super) fn set_checks_given(&mut self, color: Color, checks: u8) {
+        self.hash_key ^= self.checks_hash(color, self.checks_given[color as usize]) ^ self.checks_hash(color, checks);
+        self.checks_given[color as usize] = checks;
+    }
+
+    fn checks_hash(&self, color: Color, checks: u8) -> u64 {
+        return self.runtime_constants.zoobrist_keys[CHECKS_INDEX + 4 * color as usize + checks.min(3) as usize];
+    }
+
+    /// Hash key of the variant, the checks given and the pockets, part of `calculate_hash`
+    pub(super) fn variant_hash(&self) -> u64 {
+        return self.runtime_constants.zoobrist_keys[VARIANT_INDEX + self.variant as usize]
+            ^ self.checks_hash(Color::White, self.checks_given[0])
+            ^ self.checks_hash(Color::Black, self.checks_given[1])
+            ^ self.pocket_hash();
+    }
+
+    /// Result for the player to move if the game has ended by a rule of the variant, before the player
+    /// has run out of moves: the king of the opponent reached the hill, the opponent gave the third check,
+    /// or the player to move has lost all pieces in Antichess.
+    pub fn variant_result(&self) -> Option<SearchResult> {
+        let opponent = self.current_player.next_player();
+        return match self.variant {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::KingOfTheHill => {
+                let king = match opponent {
+                    Color::White => Piece::WhiteKing,
+                    Color::Black => Piece::BlackKing
+                };
+                (self.get_piece_set(king) & CENTER_SQUARES != 0).then_some(SearchResult::Loss)
+            }
+            Variant::ThreeCheck => (self.checks_given[opponent as usize] >= 3).then_some(SearchResult::Loss),
+            Variant::Antichess => (self.player_occupancy(self.current_player) == 0).then_some(SearchResult::Win)
+        };
+    }
+
+    /// Squares occupied by the pieces of `color`
+    pub(super) fn player_occupancy(&self, color: Color) -> u64 {
+        let offset = match color {
+            Color::White => 0,
+            Color::Black => 6
+        };
+        return self.piece_sets[offset..offset + 6].iter().fold(0, |occupancy, set| occupancy | set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::move_list::MoveList;
+    use crate::core::tests::{assert_moves_eq_algebraic, BOARD_CONSTANT_STATE};
+    use crate::core::variant::Variant;
+    use crate::core::{Color, GameStatus, Move};
+    use super::Board;
+
+    #[test]
+    fn test_variant_game_end() {
+        let constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let board_of = |fen: &str, variant: Variant| Board::from_fen_variant(fen, variant, Arc::clone(&constants)).unwrap();
+
+        // The king reaching the hill wins, but only in King of the Hill
+        for (variant, status) in [(Variant::Standard, GameStatus::InProgress), (Variant::KingOfTheHill, GameStatus::WhiteWon)] {
+            let mut board = board_of("4k3/7p/8/8/8/4K3/8/8 w - - 0 1", variant);
+            board.make_move(&Move::from_algebraic(&board, "e3d4"));
+            assert_eq!(board.get_game_status(), status);
+        }
+
+        // The third check wins in Three-check
+        let mut board = board_of("4k3/8/8/8/8/8/8/R3K3 w - - 2+3 0 1", Variant::ThreeCheck);
+        assert_eq!(board.get_checks_given(Color::White), 1);
+        for mv in ["a1a8", "e8e7", "a8a7"] {
+            board.make_move(&Move::from_algebraic(&board, mv));
+        }
+        assert_eq!(board.get_checks_given(Color::White), 3);
+        assert_eq!(board.get_game_status(), GameStatus::WhiteWon);
+        assert_eq!(board.to_fen(), "8/R3k3/8/8/8/8/8/4K3 b - - 0+3 3 4");
+
+        // Captures are compulsory in Antichess, and losing all pieces or having no moves wins
+        let mut board = board_of("8/8/8/8/8/8/1p6/2R5 b - - 0 1", Variant::Antichess);
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        assert_moves_eq_algebraic(&moves, &vec!["b2c1q", "b2c1r", "b2c1b", "b2c1n", "b2c1k"]);
+        board.make_move(&Move::from_algebraic(&board, "b2c1k"));
+        assert_eq!(board.get_game_status(), GameStatus::WhiteWon);
+        let mut board = board_of("8/8/8/8/p7/P7/8/8 w - - 0 1", Variant::Antichess);
+        assert_eq!(board.get_game_status(), GameStatus::WhiteWon);
+    }
+}
@@ -1,18 +1,21 @@
 use super::Move;
 
 
-const MAX_MOVE_COUNT: usize = 210;
+/// At most 218 moves on the board and 5 * 62 drops in Crazyhouse
+pub const MAX_MOVE_COUNT: usize = 600;
 
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug)]
 pub enum SearchResult {
     InProgress,
     Stalemate,
-    Loss
+    Loss,
+    /// The player to move has won, in variants where running out of moves wins
+    Win
 }
 
 /// MoveList which unsafely wraps a Vector, to avoid runtime checks.
-/// This is valid as we can 'garantuee' no chess position has more than MAX_MOVE_COUNT=210 valid moves
+/// This is valid as we can 'garantuee' no chess position has more than MAX_MOVE_COUNT=600 valid moves
 pub struct MoveList {
     moves: Vec<Move>,
     result: SearchResult
@@ -26,7 +29,7 @@ impl MoveList {
         }
     }
 
-    /// Push a move to the move list. No more than MAX_MOVE_COUNT=210 valid moves can be pushed at once.
+    /// Push a move to the move list. No more than MAX_MOVE_COUNT=600 valid moves can be pushed at once.
     pub fn push(&mut self, mv: Move) {
         unsafe {
             let len = self.moves.len();
@@ -0,0 +1,181 @@
+//! Standard Algebraic Notation (SAN), the move format used in PGN files, for example `Nbd7`,
+//! `exd6`, `O-O-O`, `e8=Q+`, `Qxf7#` or the Crazyhouse drop `N@f3`.
+
+use super::bitboard::Board;
+use super::move_list::MoveList;
+use super::{pos_to_algebraic_pos, Move, MoveParseError, Piece};
+
+/// Letter of the piece in SAN, which is the same for both colors. Pawns use `P`.
+fn piece_letter(piece: Piece) -> char {
+    return piece.as_char().to_ascii_uppercase();
+}
+
+impl Move {
+    /// Format the move in Standard Algebraic Notation. `board` is the position before the move,
+    /// and the move has to be legal in it.
+    pub fn to_san(&self, board: &Board) -> String {
+        let piece = board.get_piece(self.from);
+        let is_pawn = piece == Piece::WhitePawn || piece == Piece::BlackPawn;
+        // En passant captures have an empty target square
+        let is_capture = self.captured != Piece::Empty || (is_pawn && self.from % 8 != self.to % 8);
+        let to = pos_to_algebraic_pos(self.to % 8, self.to / 8);
+
+        let mut san = String::with_capacity(8);
+        if self.is_drop() {
+            san.push(piece_letter(self.promotion));
+            san.push('@');
+            san.push_str(&to);
+        }
+        else if let Some(index) = board.castling_index(self) {
+            san.push_str(if index % 2 == 0 { "O-O" } else { "O-O-O" });
+        }
+        else if is_pawn {
+            if is_capture {
+                san.push((b'a' + self.from % 8) as char);
+                san.push('x');
+            }
+            san.push_str(&to);
+            if self.promotion != Piece::Empty {
+                san.push('=');
+                san.push(piece_letter(self.promotion));
+            }
+        }
+        else {
+            san.push(piece_letter(piece));
+            san.push_str(&self.san_disambiguation(board));
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&to);
+        }
+
+        // Check and mate suffixes
+        let mut board_after = board.clone();
+        board_after.make_move(self);
+        if board_after.is_in_check() {
+            let mut moves = MoveList::empty();
+            board_after.get_moves(&mut moves, false);
+            san.push(if moves.len() == 0 { '#' } else { '+' });
+        }
+        return san;
+    }
+
+    /// The shortest prefix of the origin square which separates this move from other moves
+    /// of the same piece type to the same square
+    fn san_disambiguation(&self, board: &Board) -> String {
+        let piece = board.get_piece(self.from);
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let others: Vec<&Move> = moves.iter()
+            .filter(|mv| mv.to == self.to && mv.from != self.from && board.get_piece(mv.from) == piece)
+            .collect();
+        let from = pos_to_algebraic_pos(self.from % 8, self.from / 8);
+        if others.is_empty() {
+            return "".to_string();
+        }
+        if others.iter().all(|mv| mv.from % 8 != self.from % 8) {
+            return from[0..1].to_string();
+        }
+        if others.iter().all(|mv| mv.from / 8 != self.from / 8) {
+            return from[1..2].to_string();
+        }
+        return from;
+    }
+
+    /// Resolve a move in Standard Algebraic Notation to one of the legal moves in `board`.
+    ///
+    /// Check, mate and annotation suffixes (`+`, `#`, `!`, `?`) are ignored, as is the `e.p.`
+    /// suffix of en passant captures. Castling can be written with either `O` or `0`.
+    pub fn try_from_san(board: &Board, san: &str) -> Result<Move, MoveParseError> {
+        let invalid = || MoveParseError::InvalidSan(san.to_string());
+        let text = san.trim();
+        let text = text.strip_suffix("e.p.").unwrap_or(text).trim_end();
+        let text = text.trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'));
+
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+
+        if matches!(text, "O-O" | "0-0" | "O-O-O" | "0-0-0") {
+            let queenside = text.len() == 5;
+            let castling_move = moves.iter()
+                .find(|mv| board.castling_index(mv).is_some_and(|index| (index % 2 == 1) == queenside));
+            return castling_move.copied().ok_or_else(|| MoveParseError::IllegalMove(san.to_string()));
+        }
+        // Drops, pawn drops can also be written without the piece as in `@e4`
+        if let Some((piece, to)) = text.split_once('@') {
+            let piece = match piece {
+                "" => 'P',
+                "P" | "N" | "B" | "R" | "Q" => piece.chars().next().unwrap(),
+                _ => return Err(invalid())
+            };
+            let to: Vec<char> = to.chars().collect();
+            if to.len() != 2 || !('a'..='h').contains(&to[0]) || !('1'..='8').contains(&to[1]) {
+                return Err(invalid());
+            }
+            let to = (b'8' - to[1] as u8) * 8 + (to[0] as u8 - b'a');
+            let drop = moves.iter().find(|mv| mv.is_drop() && mv.to == to && piece_letter(mv.promotion) == piece);
+            return drop.copied().ok_or_else(|| MoveParseError::IllegalMove(san.to_string()));
+        }
+
+        let mut chars: Vec<char> = text.chars().collect();
+        let piece = match chars.first() {
+            Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => {
+                let piece = *c;
+                chars.remove(0);
+                piece
+            },
+            Some('a'..='h') => 'P',
+            _ => return Err(invalid())
+        };
+        // Promotion, written as `e8=Q` or `e8Q`. Pawns promote to a king in Antichess.
+        let mut promotion = None;
+        if let Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) = chars.last().copied() {
+            if piece != 'P' {
+                return Err(invalid());
+            }
+            promotion = Some(c);
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+        // Target square
+        if chars.len() < 2 {
+            return Err(invalid());
+        }
+        let to_rank = chars.pop().unwrap();
+        let to_file = chars.pop().unwrap();
+        if !('a'..='h').contains(&to_file) || !('1'..='8').contains(&to_rank) {
+            return Err(invalid());
+        }
+        let to = (b'8' - to_rank as u8) * 8 + (to_file as u8 - b'a');
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+        // Optional origin file and rank used for disambiguation
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c as u8 - b'a'),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(b'8' - c as u8),
+                _ => return Err(invalid())
+            }
+        }
+
+        let candidates: Vec<Move> = moves.iter().filter(|mv| {
+            let mv_promotion = match mv.promotion {
+                Piece::Empty => None,
+                promotion => Some(piece_letter(promotion))
+            };
+            return !mv.is_drop() && piece_letter(board.get_piece(mv.from)) == piece && mv.to == to && mv_promotion == promotion
+                && from_file.map_or(true, |file| mv.from % 8 == file)
+                && from_rank.map_or(true, |rank| mv.from / 8 == rank);
+        }).copied().collect();
+        return match candidates.len() {
+            0 => Err(MoveParseError::IllegalMove(san.to_string())),
+            1 => Ok(candidates[0]),
+            _ => Err(MoveParseError::AmbiguousMove(san.to_string()))
+        };
+    }
+}
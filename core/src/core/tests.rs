@@ -1,7 +1,7 @@
 #![cfg(test)]
 
 use super::Move;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::core::bitboard::*;
 use crate::core::bitboard::constants::*;
@@ -13,8 +13,8 @@ use strum::IntoEnumIterator;
 
 #[test]
 fn test_set_piece() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let mut board = Board::empty(Rc::clone(&constant_state));
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let mut board = Board::empty(Arc::clone(&constant_state));
 
     // Ensure that there are no out of bounds problems with edges
     board.set_piece_pos(0, 0, &Piece::WhiteQueen);
@@ -59,8 +59,8 @@ fn assert_board_equal_to_array_board(board: &Board, array_board: &[Piece; 64]) {
 #[test]
 fn test_fen() {
     // Starting position
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let board1 = Board::from_fen(STARTING_POS_FEN, Rc::clone(&constant_state));
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let board1 = Board::from_fen(STARTING_POS_FEN, Arc::clone(&constant_state)).unwrap();
     let expected_pieces1 = [
         Piece::BlackRook, Piece::BlackKnight, Piece::BlackBishop, Piece::BlackQueen, Piece::BlackKing, Piece::BlackBishop, Piece::BlackKnight, Piece::BlackRook,
         Piece::BlackPawn, Piece::BlackPawn,   Piece::BlackPawn,   Piece::BlackPawn,  Piece::BlackPawn, Piece::BlackPawn,   Piece::BlackPawn,   Piece::BlackPawn,
@@ -78,7 +78,7 @@ fn test_fen() {
     board1.validate();
 
     // Kiwipete
-    let board2 = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq c6 2 3", Rc::clone(&constant_state));
+    let board2 = Board::from_fen_unchecked("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq c6 2 3", Arc::clone(&constant_state)).unwrap();
     let expected_pieces2 = [
         Piece::BlackRook,   Piece::Empty,      Piece::Empty,      Piece::Empty,      Piece::BlackKing, Piece::Empty,      Piece::Empty,      Piece::BlackRook,
         Piece::BlackPawn,   Piece::Empty,      Piece::BlackPawn,  Piece::BlackPawn,  Piece::BlackQueen,Piece::BlackPawn,  Piece::BlackBishop,Piece::Empty,
@@ -97,16 +97,75 @@ fn test_fen() {
     assert_eq!(board2.get_half_moves(), 3);
     board2.validate();
 
-    let board3 = Board::from_fen("8/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/8 b - d3 23 26", Rc::clone(&constant_state));
+    let board3 = Board::from_fen_unchecked("8/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/8 b - d3 23 26", Arc::clone(&constant_state)).unwrap();
     assert_eq!(board3.get_current_player(), Color::Black);
     assert_eq!(board3.get_ep(), 4);
     assert_eq!("8/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/8 b - d3 23 26", &board3.to_fen());
+    assert!(!board1.is_chess960() && !board2.is_chess960());
+    assert_eq!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1", &board1.to_shredder_fen());
+
+    // Chess960 castling rights in Shredder-FEN and X-FEN
+    let board4 = Board::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", Arc::clone(&constant_state)).unwrap();
+    assert!(board4.is_chess960());
+    assert_eq!((board4.get_castling_rook(0), board4.get_castling_rook(1), board4.get_castling_rook(2), board4.get_castling_rook(3)), (63, 61, 7, 5));
+    assert_eq!("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9", &board4.to_fen());
+    assert_eq!("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &board4.to_shredder_fen());
+    assert_eq!(Board::from_fen(&board4.to_fen(), Arc::clone(&constant_state)).unwrap(), board4);
+    let board5 = Board::from_fen("1r2k1r1/8/8/8/8/8/8/RR2K2R w KBq - 0 1", Arc::clone(&constant_state)).unwrap();
+    assert!(board5.is_chess960());
+    assert_eq!((board5.get_castling_rook(1), board5.get_castling_rook(3)), (57, 1));
+    assert_eq!("1r2k1r1/8/8/8/8/8/8/RR2K2R w KBq - 0 1", &board5.to_fen());
+    assert_eq!("1r2k1r1/8/8/8/8/8/8/RR2K2R w HBb - 0 1", &board5.to_shredder_fen());
+    assert_eq!(Board::from_fen(&board5.to_shredder_fen(), Arc::clone(&constant_state)).unwrap(), board5);
+}
+
+#[test]
+fn test_fen_errors() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let fen_error = |fen: &str| Board::from_fen(fen, Arc::clone(&constant_state)).unwrap_err();
+
+    // Malformed FEN strings
+    assert_eq!(fen_error(""), FenError::Empty);
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/RNBQKBNR w KQkq - 0 1"), FenError::WrongRowCount(7));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/9/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::InvalidPiece('9'));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/7/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::WrongRowLength(3));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1"), FenError::WrongRowLength(1));
+    assert_eq!(fen_error("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::InvalidPiece('x'));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR white KQkq - 0 1"), FenError::InvalidSideToMove("white".to_string()));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1"), FenError::InvalidCastling("KQkx".to_string()));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1"), FenError::InvalidCastling("KKkq".to_string()));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1"), FenError::InvalidEnPassant("e3".to_string()));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1"), FenError::InvalidHalfmoveClock("-1".to_string()));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 one"), FenError::InvalidFullmoveNumber("one".to_string()));
+    assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra"), FenError::TooManyFields(7));
+
+    // Illegal positions
+    assert_eq!(fen_error("8/8/8/8/8/8/8/4K3 w - - 0 1"), FenError::WrongKingCount(Color::Black, 0));
+    assert_eq!(fen_error("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"), FenError::WrongKingCount(Color::White, 2));
+    assert_eq!(fen_error("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"), FenError::PawnOnBackRank("a1".to_string()));
+    assert_eq!(fen_error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), FenError::OpponentInCheck);
+    assert_eq!(fen_error("r3k2r/8/8/8/8/8/8/R3K3 w KQkq - 0 1"), FenError::CastlingWithoutKingOrRook('K'));
+    assert_eq!(fen_error("r3k2r/8/8/8/8/8/8/4K2R w Qkq - 0 1"), FenError::CastlingWithoutKingOrRook('Q'));
+    assert_eq!(fen_error("r3k2r/8/8/8/8/8/4K3/R6R w Qkq - 0 1"), FenError::CastlingWithoutKingOrRook('Q'));
+    assert_eq!(fen_error("r3k2r/8/8/8/8/8/8/R3K2R w KQkb - 0 1"), FenError::CastlingWithoutKingOrRook('q'));
+    assert_eq!(fen_error("r3k2r/8/8/8/8/8/8/R3K2R w HAhh - 0 1"), FenError::InvalidCastling("HAhh".to_string()));
+    assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"), FenError::ImplausibleEnPassant("e6".to_string()));
+
+    // Fields after the piece placement are optional
+    let board = Board::from_fen("4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 300", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(board.get_ep(), 4);
+    assert_eq!(board.get_half_moves(), 300);
+    let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 300 200", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(board.get_quiet_moves(), u8::MAX);
+    assert_eq!(board.get_game_status(), GameStatus::FiftyMoveRule);
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(board.get_current_player(), Color::White);
 }
 
 #[test]
 fn test_algebraic_notation() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let board = Board::from_fen(STARTING_POS_FEN, Rc::clone(&constant_state));
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let board = Board::from_fen(STARTING_POS_FEN, Arc::clone(&constant_state)).unwrap();
     assert_eq!("a2a3", Move::to_algebraic(&Move::from_algebraic(&board, "a2a3")));
     assert_eq!("d4d5", Move::to_algebraic(&Move::from_algebraic(&board, "d4d5")));
     assert_eq!("d8d1", Move::to_algebraic(&Move::from_algebraic(&board, "d8d1")));
@@ -115,10 +174,118 @@ fn test_algebraic_notation() {
     assert_eq!("a2a1b", Move::to_algebraic(&Move::from_algebraic(&board, "a2a1b")));
 }
 
+#[test]
+fn test_checked_algebraic_notation() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let board = Board::new(Arc::clone(&constant_state));
+    let mv = Move::try_from_algebraic(&board, "g1f3").unwrap();
+    assert_eq!((mv.from, mv.to, mv.promotion, mv.captured), (62, 45, Piece::Empty, Piece::Empty));
+    assert_eq!(Move::try_from_algebraic(&board, "e2e5"), Err(MoveParseError::IllegalMove("e2e5".to_string())));
+    assert_eq!(Move::try_from_algebraic(&board, "e7e5"), Err(MoveParseError::IllegalMove("e7e5".to_string())));
+    for invalid in ["", "e2", "e2e4qq", "i2e4", "e9e4", "e2e4x", "e2-e4"] {
+        assert_eq!(Move::try_from_algebraic(&board, invalid), Err(MoveParseError::InvalidFormat(invalid.to_string())));
+    }
+
+    // Castling, en passant and promotions resolve to the generated moves
+    let board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1", Arc::clone(&constant_state)).unwrap();
+    let mut legal_moves = MoveList::empty();
+    board.get_moves(&mut legal_moves, false);
+    for algebraic in ["e1g1", "e1c1", "e5d6", "b7a8q", "b7a8n", "b7b8r", "b7b8b"] {
+        let mv = Move::try_from_algebraic(&board, algebraic).unwrap();
+        assert!(legal_moves.iter().any(|legal_move| *legal_move == mv));
+        assert_eq!(mv.to_algebraic(), algebraic);
+    }
+    let capture_promotion = Move::try_from_algebraic(&board, "b7a8Q").unwrap();
+    assert_eq!((capture_promotion.promotion, capture_promotion.captured), (Piece::WhiteQueen, Piece::BlackRook));
+    assert_eq!(Move::try_from_algebraic(&board, "b7b8"), Err(MoveParseError::IllegalMove("b7b8".to_string())));
+    assert_eq!(Move::try_from_algebraic(&board, "b7b8x"), Err(MoveParseError::InvalidFormat("b7b8x".to_string())));
+    // Promoting to a king is only legal in Antichess
+    assert_eq!(Move::try_from_algebraic(&board, "b7b8k"), Err(MoveParseError::IllegalMove("b7b8k".to_string())));
+
+    // Playing a list of moves stops at the first illegal move
+    let board = Board::new(Arc::clone(&constant_state));
+    let moves: Vec<String> = ["e2e4", "e7e5", "e1e3"].iter().map(|mv| mv.to_string()).collect();
+    assert_eq!(commands::board_from_moves(&board, &moves), Err(MoveParseError::IllegalMove("e1e3".to_string())));
+    let played = commands::board_from_moves(&board, &moves[..2].to_vec()).unwrap();
+    assert!(played.to_fen().starts_with("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0"));
+}
+
+#[test]
+fn test_san() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let san_of = |board: &Board, algebraic: &str| Move::try_from_algebraic(board, algebraic).unwrap().to_san(board);
+
+    // Kiwipete: castling, captures and pawn pushes
+    let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(san_of(&board, "e1g1"), "O-O");
+    assert_eq!(san_of(&board, "e1c1"), "O-O-O");
+    assert_eq!(san_of(&board, "e5f7"), "Nxf7");
+    assert_eq!(san_of(&board, "d5e6"), "dxe6");
+    assert_eq!(san_of(&board, "g2h3"), "gxh3");
+    assert_eq!(san_of(&board, "a2a4"), "a4");
+    assert_eq!(san_of(&board, "f3f6"), "Qxf6");
+    assert_eq!(Move::try_from_san(&board, "0-0").unwrap(), Move::try_from_algebraic(&board, "e1g1").unwrap());
+    assert_eq!(Move::try_from_san(&board, "O-O-O").unwrap(), Move::try_from_algebraic(&board, "e1c1").unwrap());
+
+    // Disambiguation by file, by rank, and by both
+    let board = Board::from_fen("k7/8/8/2N3N1/8/2N5/8/K7 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(san_of(&board, "g5e4"), "Nge4");
+    assert_eq!(san_of(&board, "c3e4"), "N3e4");
+    assert_eq!(san_of(&board, "c5e4"), "Nc5e4");
+    assert_eq!(san_of(&board, "c5e6"), "Nce6");
+    assert_eq!(san_of(&board, "c3d5"), "Nd5");
+    assert_eq!(Move::try_from_san(&board, "Nc5e4").unwrap(), Move::try_from_algebraic(&board, "c5e4").unwrap());
+    assert_eq!(Move::try_from_san(&board, "N3e4").unwrap(), Move::try_from_algebraic(&board, "c3e4").unwrap());
+    assert_eq!(Move::try_from_san(&board, "Ne4"), Err(MoveParseError::AmbiguousMove("Ne4".to_string())));
+    assert_eq!(Move::try_from_san(&board, "Nce4"), Err(MoveParseError::AmbiguousMove("Nce4".to_string())));
+    assert_eq!(Move::try_from_san(&board, "Nd6"), Err(MoveParseError::IllegalMove("Nd6".to_string())));
+
+    // En passant, with or without the e.p. suffix
+    let board = Board::from_fen("K7/8/8/1pP5/5Pp1/8/8/k7 w - b6", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(san_of(&board, "c5b6"), "cxb6");
+    let ep_move = Move::try_from_algebraic(&board, "c5b6").unwrap();
+    assert_eq!(Move::try_from_san(&board, "cxb6 e.p.").unwrap(), ep_move);
+    assert_eq!(Move::try_from_san(&board, "cxb6").unwrap(), ep_move);
+
+    // Promotions with check and mate suffixes
+    let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(san_of(&board, "b7b8q"), "b8=Q+");
+    assert_eq!(san_of(&board, "b7b8n"), "b8=N");
+    assert_eq!(Move::try_from_san(&board, "b8=R+").unwrap(), Move::try_from_algebraic(&board, "b7b8r").unwrap());
+    assert_eq!(Move::try_from_san(&board, "b8Q").unwrap(), Move::try_from_algebraic(&board, "b7b8q").unwrap());
+    assert_eq!(Move::try_from_san(&board, "b8"), Err(MoveParseError::IllegalMove("b8".to_string())));
+    let board = Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(san_of(&board, "h5f7"), "Qxf7#");
+    assert_eq!(Move::try_from_san(&board, "Qxf7#").unwrap(), Move::try_from_algebraic(&board, "h5f7").unwrap());
+
+    // Malformed input
+    for invalid in ["", "Xe4", "e9", "Nz4", "Qe8=Q", "N", "Nbb1d2"] {
+        assert_eq!(Move::try_from_san(&board, invalid), Err(MoveParseError::InvalidSan(invalid.to_string())));
+    }
+
+    // Every legal move survives a round trip through SAN
+    let fens = [
+        STARTING_POS_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P2P/2N2Q2/PPPBBPp1/1R2K2R b Kkq h3 0 4",
+        "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -",
+    ];
+    for fen in fens {
+        let board = Board::from_fen(fen, Arc::clone(&constant_state)).unwrap();
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        for mv in moves.iter() {
+            let san = mv.to_san(&board);
+            assert_eq!(Move::try_from_san(&board, &san), Ok(*mv), "{} in {}", san, fen);
+        }
+    }
+}
+
 #[test]
 fn test_make_unmake_moves() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let mut board = Board::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", Rc::clone(&constant_state));
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let mut board = Board::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", Arc::clone(&constant_state)).unwrap();
 
     assert_eq!(board.get_piece_pos(10 % 8, 10 / 8), Piece::BlackPawn);
     assert_eq!(board.get_piece_pos(2 % 8, 2 / 8), Piece::Empty);
@@ -145,9 +312,9 @@ fn test_make_unmake_moves() {
 
 #[test]
 fn test_make_unmake_moves_special() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
     // Castling
-    let mut board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq", Rc::clone(&constant_state));
+    let mut board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq", Arc::clone(&constant_state)).unwrap();
     // Black left side
     let mv = Move::new(&board, 4, 2, Piece::Empty, Piece::Empty);
     board.make_move(&mv);
@@ -160,7 +327,7 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.to_fen().split(" ").nth(0).unwrap(), "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R");
 
     // Black right side
-    board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq", Rc::clone(&constant_state));
+    board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq", Arc::clone(&constant_state)).unwrap();
     let mv = Move::new(&board, 4, 6, Piece::Empty, Piece::Empty);
     board.make_move(&mv);
     assert_eq!(board.get_piece_pos(4, 0), Piece::Empty);
@@ -172,7 +339,7 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.to_fen().split(" ").nth(0).unwrap(), "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R");
 
     // White left side
-    board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq", Rc::clone(&constant_state));
+    board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq", Arc::clone(&constant_state)).unwrap();
     let mv = Move::new(&board, 60, 62, Piece::Empty, Piece::Empty);
     board.make_move(&mv);
     assert_eq!(board.get_piece_pos(4, 7), Piece::Empty);
@@ -184,7 +351,7 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.to_fen().split(" ").nth(0).unwrap(), "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R");
 
     // White right side
-    board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq", Rc::clone(&constant_state));
+    board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq", Arc::clone(&constant_state)).unwrap();
     let mv = Move::new(&board, 60, 58, Piece::Empty, Piece::Empty);
     board.make_move(&mv);
     assert_eq!(board.get_piece_pos(4, 7), Piece::Empty);
@@ -196,7 +363,7 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.to_fen().split(" ").nth(0).unwrap(), "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R");
 
     // Promotions
-    board = Board::from_fen("1r6/P7/8/8/8/8/p7/1R6 b", Rc::clone(&constant_state));
+    board = Board::from_fen_unchecked("1r6/P7/8/8/8/8/p7/1R6 b", Arc::clone(&constant_state)).unwrap();
     let mv = Move::new(&board, 48, 56, Piece::BlackQueen, Piece::Empty);
     board.make_move(&mv);
     assert_eq!(board.get_piece_pos(0, 6), Piece::Empty);
@@ -205,7 +372,7 @@ fn test_make_unmake_moves_special() {
     board.unmake_move(&mv);
     assert_eq!(board.to_fen().split(" ").nth(0).unwrap(), "1r6/P7/8/8/8/8/p7/1R6");
 
-    board = Board::from_fen("1r6/P7/8/8/8/8/p7/1R6", Rc::clone(&constant_state));
+    board = Board::from_fen_unchecked("1r6/P7/8/8/8/8/p7/1R6", Arc::clone(&constant_state)).unwrap();
     let mv = Move::new(&board, 8, 0, Piece::WhiteBishop, Piece::Empty);
     board.make_move(&mv);
     assert_eq!(board.get_piece_pos(0, 1), Piece::Empty);
@@ -215,7 +382,7 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.to_fen().split(" ").nth(0).unwrap(), "1r6/P7/8/8/8/8/p7/1R6");
 
     // En passant
-    board = Board::from_fen("8/6p1/8/7P/1p6/8/P7/8 w - - 0 1", Rc::clone(&constant_state));
+    board = Board::from_fen_unchecked("8/6p1/8/7P/1p6/8/P7/8 w - - 0 1", Arc::clone(&constant_state)).unwrap();
     let move1 = Move::from_algebraic(&board, "a2a4");
     board.make_move(&move1);
     assert_eq!(board.get_ep(), 1);
@@ -229,7 +396,7 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.get_ep(), 0);
     assert_eq!(board.to_fen(), "8/6p1/8/7P/1p6/8/P7/8 w - - 0 1");
 
-    board = Board::from_fen("8/6p1/8/7P/1p6/8/P7/8 b - - 0 1", Rc::clone(&constant_state));
+    board = Board::from_fen_unchecked("8/6p1/8/7P/1p6/8/P7/8 b - - 0 1", Arc::clone(&constant_state)).unwrap();
     let move1 = Move::from_algebraic(&board, "g7g5");
     board.make_move(&move1);
     assert_eq!(board.get_ep(), 7);
@@ -244,6 +411,217 @@ fn test_make_unmake_moves_special() {
     assert_eq!(board.to_fen(), "8/6p1/8/7P/1p6/8/P7/8 b - - 0 1");
 }
 
+#[test]
+fn test_draw_detection() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+
+    // Threefold repetition by moving the knights back and forth
+    let mut board = Board::new(Arc::clone(&constant_state));
+    let knight_moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    for mv in knight_moves {
+        board.make_move(&Move::from_algebraic(&board, mv));
+    }
+    assert_eq!(board.repetition_count(), 1);
+    assert!(board.is_repetition());
+    assert_eq!(board.get_game_status(), GameStatus::InProgress);
+    let mut made_moves = Vec::new();
+    for mv in knight_moves {
+        let mv = Move::from_algebraic(&board, mv);
+        board.make_move(&mv);
+        made_moves.push(mv);
+    }
+    assert!(board.is_threefold_repetition());
+    assert_eq!(board.get_game_status(), GameStatus::ThreefoldRepetition);
+    assert!(board.get_game_status().is_draw());
+    // Undoing a move removes it from the history
+    board.unmake_move(&made_moves.pop().unwrap());
+    assert!(!board.is_threefold_repetition());
+
+    // Captures and pawn moves make earlier positions unreachable
+    let mut board = Board::new(Arc::clone(&constant_state));
+    for mv in ["g1f3", "g8f6", "f3g1", "f6g8", "e2e3", "e7e6"] {
+        board.make_move(&Move::from_algebraic(&board, mv));
+    }
+    assert_eq!(board.repetition_count(), 0);
+
+    // Fifty-move rule
+    let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(board.get_game_status(), GameStatus::InProgress);
+    board.make_move(&Move::from_algebraic(&board, "a1a2"));
+    assert!(board.is_fifty_move_rule_draw());
+    assert_eq!(board.get_game_status(), GameStatus::FiftyMoveRule);
+
+    // Insufficient material
+    let insufficient_positions = [
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+        "4kb2/8/8/8/8/8/8/4K3 w - - 0 1",
+        "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+    ];
+    for fen in insufficient_positions {
+        let mut board = Board::from_fen(fen, Arc::clone(&constant_state)).unwrap();
+        assert!(board.has_insufficient_material(), "Expected insufficient material in {}", fen);
+        assert_eq!(board.get_game_status(), GameStatus::InsufficientMaterial);
+    }
+    let sufficient_positions = [
+        "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+        "4k3/8/8/8/8/8/8/3NKB2 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K2R w - - 0 1",
+    ];
+    for fen in sufficient_positions {
+        let board = Board::from_fen(fen, Arc::clone(&constant_state)).unwrap();
+        assert!(!board.has_insufficient_material(), "Expected sufficient material in {}", fen);
+    }
+}
+
+#[test]
+fn test_null_move() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let mut board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 3 10", Arc::clone(&constant_state)).unwrap();
+    let (fen, hash_key) = (board.to_fen(), board.get_hashkey());
+    let state = board.make_null_move();
+    assert_eq!(board.get_current_player(), Color::Black);
+    assert_eq!(board.get_ep(), 0);
+    assert_eq!(board.get_quiet_moves(), 0);
+    assert_ne!(board.get_hashkey(), hash_key);
+    board.unmake_null_move(state);
+    assert_eq!((board.to_fen(), board.get_hashkey()), (fen, hash_key));
+
+    assert!(!board.has_non_pawn_material(Color::White));
+    let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4KN2 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    assert!(board.has_non_pawn_material(Color::White));
+    assert!(!board.has_non_pawn_material(Color::Black));
+}
+
+#[test]
+fn test_evaluation() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let board = Board::new(Arc::clone(&constant_state));
+    assert_eq!(board.eval(), 0);
+    assert_eq!(board.phase(), eval_params::TOTAL_PHASE);
+
+    // The evaluation is symmetric between the colors
+    let board = Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", Arc::clone(&constant_state)).unwrap();
+    let mirrored = Board::from_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(board.eval(), mirrored.eval());
+    assert_eq!(board.piece_square_score(), -mirrored.piece_square_score());
+
+    // A passed pawn is worth more than a blocked one, and more the further it is advanced
+    let passed = Board::from_fen("4k3/8/8/8/4P3/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    let blocked = Board::from_fen("4k3/8/4p3/8/4P3/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    let advanced = Board::from_fen("4k3/8/4P3/8/8/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(passed.phase(), 0);
+    assert!(passed.eval() > blocked.eval() + 82);
+    assert!(advanced.eval() > passed.eval());
+
+    // The bishop pair is worth more than a bishop and a knight
+    let bishop_pair = Board::from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    let bishop_knight = Board::from_fen("4k3/8/8/8/8/8/8/2B1KN2 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    assert!(bishop_pair.eval() > bishop_knight.eval() + 30);
+
+    // The incremental piece-square score follows moves
+    let mut board = Board::new(Arc::clone(&constant_state));
+    for algebraic in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3"] {
+        let mv = Move::from_algebraic(&board, algebraic);
+        board.make_move(&mv);
+        assert_eq!(board.piece_square_score(), board.calculate_piece_square_score());
+    }
+}
+
+#[test]
+fn test_eval_trace() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let fens = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 4 4",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+    for fen in fens {
+        let board = Board::from_fen(fen, Arc::clone(&constant_state)).unwrap();
+        let trace = board.eval_trace();
+        let eval = match board.get_current_player() {
+            Color::White => board.eval(),
+            Color::Black => -board.eval()
+        };
+        assert_eq!(trace.tapered(), eval, "Trace does not match the evaluation of {}", fen);
+        assert_eq!(trace.phase, board.phase());
+        assert_eq!(trace.terms[0].total() + trace.terms[1].total(), board.piece_square_score());
+    }
+
+    // Each side is scored from its own view
+    let trace = Board::new(Arc::clone(&constant_state)).eval_trace();
+    assert!(trace.terms.iter().all(|term| term.white == term.black));
+    assert_eq!(trace.terms[0].white, evaluation::s(8 * 82 + 2 * 365 + 2 * 337 + 2 * 477 + 1025, 8 * 94 + 2 * 297 + 2 * 281 + 2 * 512 + 936));
+}
+
+#[test]
+fn test_eval_params() {
+    use eval_params::{EvalParams, EvalParamsErrorKind};
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let params = EvalParams::default();
+    assert_eq!(EvalParams::parse(&params.to_param_file()).unwrap(), params);
+    assert_eq!(params.with_values(&params.values()), params);
+    assert_eq!(params.names().len(), params.values().len());
+    assert!(params.to_rust_constants().contains("pub const MATERIAL: [TaperedScore; 6] = [s(  82,   94), s( 365,  297)"));
+
+    // Parameters missing from the file keep their default value
+    let knight_params = EvalParams::parse("# Knights are worth more\nmaterial.knight.mg 400\n\nmaterial.knight.eg 350").unwrap();
+    let changed: Vec<(String, i32)> = knight_params.names().into_iter().zip(knight_params.values())
+        .zip(params.values())
+        .filter(|((_, value), default)| value != default)
+        .map(|(param, _)| param)
+        .collect();
+    assert_eq!(changed, [("material.knight.mg".to_string(), 400), ("material.knight.eg".to_string(), 350)]);
+
+    let error = |text| EvalParams::parse(text).unwrap_err();
+    assert_eq!(error("material.knight.mg 400\nmaterial.knight 3").kind, EvalParamsErrorKind::UnknownParameter("material.knight".to_string()));
+    assert_eq!(error("material.knight.mg 400\nmaterial.knight 3").line, 2);
+    assert_eq!(error("material.knight.mg four").kind, EvalParamsErrorKind::InvalidValue("four".to_string()));
+    assert_eq!(error("material.knight.mg").kind, EvalParamsErrorKind::WrongFieldCount);
+
+    // Boards use the parameters of the runtime constants, or the ones set on the board
+    let mut board = Board::from_fen("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+    let eval = board.eval();
+    board.set_eval_params(Arc::new(knight_params.clone()));
+    assert!(board.eval() > eval);
+    board.validate();
+    let mut knight_constants = BOARD_CONSTANT_STATE.clone();
+    knight_constants.eval_params = Arc::new(knight_params);
+    let knight_board = Board::from_fen("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1", Arc::new(knight_constants)).unwrap();
+    assert_eq!(knight_board.eval(), board.eval());
+}
+
+#[test]
+fn test_nnue_evaluation() {
+    let network = Arc::new(nnue::Network::random(32, 7));
+    let mut network_constants = BOARD_CONSTANT_STATE.clone();
+    network_constants.network = Some(Arc::clone(&network));
+    let constant_state = Arc::new(network_constants);
+
+    // The accumulators are checked by `validate` after every make and unmake
+    let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", Arc::clone(&constant_state)).unwrap();
+    let mut reserved_moves = MoveListCollection::new();
+    validation_perft(3, &mut board, &mut reserved_moves);
+    let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P2P/2N2Q2/PPPBBPp1/1R2K2R b Kkq h3 0 4", Arc::clone(&constant_state)).unwrap();
+    validation_perft(3, &mut board, &mut reserved_moves);
+
+    // The network sees both sides the same way
+    let board = Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", Arc::clone(&constant_state)).unwrap();
+    let mirrored = Board::from_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4", Arc::clone(&constant_state)).unwrap();
+    assert_eq!(board.eval(), mirrored.eval());
+
+    // The evaluation backend can be switched on a board
+    let mut classical = board.clone();
+    classical.set_network(None);
+    assert_eq!(classical.network(), None);
+    assert_eq!(classical.eval(), board.classical_eval());
+    classical.set_network(Some(Arc::clone(&network)));
+    assert_eq!(classical, board);
+    assert_eq!(board.network(), Some(&network));
+}
+
 #[test]
 fn test_bit_twiddling() {
     // Test all possible bits
@@ -339,15 +717,15 @@ pub fn attacking_perft_test(depth: usize, board: &mut Board, reserved_moves: &mu
 
 #[test]
 fn board_validation_with_perft() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let mut board = Board::new(Rc::clone(&constant_state));
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let mut board = Board::new(Arc::clone(&constant_state));
     let mut reserved_moves = MoveListCollection::new();
     validation_perft(4, &mut board, &mut reserved_moves);
 
-    let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", Rc::clone(&constant_state));
+    let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", Arc::clone(&constant_state)).unwrap();
     validation_perft(4, &mut board, &mut reserved_moves);
 
-    let mut board = Board::from_fen("rnbqkbnr/pppppp2/8/6pp/7P/P7/1PPPPPP1/RNBQKBNR b KQkq - 0 6", Rc::clone(&constant_state));
+    let mut board = Board::from_fen("rnbqkbnr/pppppp2/8/6pp/7P/P7/1PPPPPP1/RNBQKBNR b KQkq - 0 6", Arc::clone(&constant_state)).unwrap();
     validation_perft(3, &mut board, &mut reserved_moves);
 
     // rnbqkbnr/pppppp2/8/6pp/7P/P7/1PPPPPP1/RNBQKBNR b KQkq - 0 6
@@ -355,15 +733,15 @@ fn board_validation_with_perft() {
 
 #[test]
 fn attack_move_gen_test() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let mut board = Board::new(Rc::clone(&constant_state));
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let mut board = Board::new(Arc::clone(&constant_state));
     let mut reserved_moves = MoveListCollection::new();
     attacking_perft_test(4, &mut board, &mut reserved_moves);
 
-    let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", Rc::clone(&constant_state));
+    let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", Arc::clone(&constant_state)).unwrap();
     attacking_perft_test(4, &mut board, &mut reserved_moves);
 
-    let mut board = Board::from_fen("rnbqkbnr/pppppp2/8/6pp/7P/P7/1PPPPPP1/RNBQKBNR b KQkq - 0 6", Rc::clone(&constant_state));
+    let mut board = Board::from_fen("rnbqkbnr/pppppp2/8/6pp/7P/P7/1PPPPPP1/RNBQKBNR b KQkq - 0 6", Arc::clone(&constant_state)).unwrap();
     attacking_perft_test(3, &mut board, &mut reserved_moves);
 
     // rnbqkbnr/pppppp2/8/6pp/7P/P7/1PPPPPP1/RNBQKBNR b KQkq - 0 6
@@ -371,20 +749,64 @@ fn attack_move_gen_test() {
 
 #[test]
 fn perft_tests() {
-    let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-    assert!(commands::perft_tests(Rc::clone(&constant_state), 2_000_000), "Perft tests failed, see print output for more details.");
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    assert!(commands::perft_tests(Arc::clone(&constant_state), 2_000_000), "Perft tests failed, see print output for more details.");
 }            
 
+#[test]
+fn perft_bisection_tests() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let positions = crate::epd::parse_epd(commands::DEFAULT_PERFT_SUITE, Arc::clone(&constant_state)).unwrap();
+    assert_eq!(positions.len(), 7);
+    // The reference move generator should agree with the move generator everywhere
+    for position in positions.iter() {
+        assert_eq!(commands::find_perft_error(&position.board, 3), None, "Reference move generator disagrees on {}", position.board.to_fen());
+    }
+    // A wrong expected count fails, but bisection finds no fault in the move generator
+    let wrong_counts = crate::epd::parse_epd("4k3/8/8/8/8/8/4P3/4K3 w - - ;D1 6 ;D2 30", Arc::clone(&constant_state)).unwrap();
+    assert!(commands::perft_suite(&wrong_counts[..], 1_000));
+    let wrong_counts = crate::epd::parse_epd("4k3/8/8/8/8/8/4P3/4K3 w - - ;D1 6 ;D2 31", Arc::clone(&constant_state)).unwrap();
+    assert!(!commands::perft_suite(&wrong_counts[..], 1_000));
+    assert_eq!(commands::find_perft_error(&wrong_counts[0].board, 2), None);
+}
+
+#[test]
+fn chess960_perft_tests() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let positions = crate::epd::parse_epd(commands::CHESS960_PERFT_SUITE, Arc::clone(&constant_state)).unwrap();
+    assert_eq!(positions.len(), 10);
+    assert!(commands::perft_suite(&positions, 2_000_000), "Chess960 perft tests failed, see print output for more details.");
+    for position in positions.iter() {
+        assert!(position.board.is_chess960());
+        assert_eq!(commands::find_perft_error(&position.board, 3), None, "Reference move generator disagrees on {}", position.board.to_fen());
+        let mut board = position.board.clone();
+        validation_perft(3, &mut board, &mut MoveListCollection::new());
+    }
+}
+
+#[test]
+fn variant_perft_tests() {
+    let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let positions = crate::epd::parse_epd(commands::VARIANT_PERFT_SUITE, Arc::clone(&constant_state)).unwrap();
+    assert_eq!(positions.len(), 12);
+    assert!(commands::perft_suite(&positions, 2_000_000), "Variant perft tests failed, see print output for more details.");
+    for position in positions.iter() {
+        assert_eq!(commands::find_perft_error(&position.board, 3), None, "Reference move generator disagrees on {}", position.board.to_fen());
+        let mut board = position.board.clone();
+        validation_perft(3, &mut board, &mut MoveListCollection::new());
+    }
+}
+
 #[test]
 fn see_test() {
-    let constants = Rc::new(BOARD_CONSTANT_STATE.clone());
-    let mut board = Board::from_fen("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - -", Rc::clone(&constants));
+    let constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+    let mut board = Board::from_fen("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - -", Arc::clone(&constants)).unwrap();
     let mut val = board.static_exchange_evaluation(60, 28);
     assert!(val==100, "see failed for 1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - on e1e5");
-    board = Board::from_fen("1k1r4/1pp4p/p7/3rp3/8/P5P1/1PP4P/2K1R3 w - -", Rc::clone(&constants));
+    board = Board::from_fen("1k1r4/1pp4p/p7/3rp3/8/P5P1/1PP4P/2K1R3 w - -", Arc::clone(&constants)).unwrap();
     val = board.static_exchange_evaluation(60, 28);
     assert!(val==-400, "see failed for 1k1r4/1pp4p/p7/3rp3/8/P5P1/1PP4P/2K1R3 w - - on e1e5");
-    board = Board::from_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -", Rc::clone(&constants));
+    board = Board::from_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -", Arc::clone(&constants)).unwrap();
     val = board.static_exchange_evaluation(43, 28);
     assert!(-200==val, "see failed for 1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - on d3e5");
 }
@@ -420,4 +842,4 @@ lazy_static! {
     pub static ref BOARD_CONSTANT_STATE: BitboardRuntimeConstants = {
         BitboardRuntimeConstants::create()
     };
-}
\ No newline at end of file
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Rules the board is played with. The variants change how the game ends, which moves are legal
+/// and how positions are evaluated, the board and the pieces are the same as in standard chess.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// The player whose king reaches one of the four center squares wins
+    KingOfTheHill,
+    /// The player who gives check three times wins
+    ThreeCheck,
+    /// Captures are compulsory and the king is an ordinary piece. The player who loses all pieces,
+    /// or has no legal move, wins.
+    Antichess,
+    /// Captured pieces go to the pocket of the capturing player, who can drop them on an empty square
+    /// instead of making a move
+    Crazyhouse
+}
+
+impl Variant {
+    /// All variants, in the order they are listed in the `UCI_Variant` option
+    pub const ALL: [Variant; 5] = [Variant::Standard, Variant::KingOfTheHill, Variant::ThreeCheck, Variant::Antichess, Variant::Crazyhouse];
+
+    /// Name of the variant in the `UCI_Variant` option, the same as used by lichess and Fairy-Stockfish
+    pub fn uci_name(&self) -> &'static str {
+        return match self {
+            Variant::Standard => "chess",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse"
+        };
+    }
+
+    /// Variant with the given `UCI_Variant` name, ignoring case
+    pub fn from_uci_name(name: &str) -> Option<Variant> {
+        return Variant::ALL.into_iter().find(|variant| variant.uci_name().eq_ignore_ascii_case(name));
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.uci_name())
+    }
+}
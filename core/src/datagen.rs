@@ -0,0 +1,253 @@
+//! Generation of labeled training positions from self-play games, for tuning the evaluation or training a network.
+//!
+//! Every game starts with a few random moves and is then played by `StandardAlphaBetaEngine` with fixed search
+//! limits. Quiet positions are written as `<fen> | <score> | <result>`, with the search score in centipawns and
+//! the result (1.0, 0.5 or 0.0) from the view of white. Each game only depends on the seed and the game number,
+//! so a generation run can be resumed and gives the same positions as an uninterrupted one.
+
+use std::sync::{Arc, Mutex};
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use crate::core::bitboard::{constants::BitboardRuntimeConstants, Board};
+use crate::core::move_list::MoveList;
+use crate::core::{Color, GameStatus, Move};
+use crate::engine::ab_engine::StandardAlphaBetaEngine;
+use crate::engine::move_sorting::is_noisy_move;
+use crate::engine::{Engine, Score, SearchLimits};
+use crate::pgn::GameResult;
+
+/// Games still running after this many plies are adjudicated as draws
+pub const DEFAULT_MAX_PLIES: usize = 400;
+/// Random moves played before the engine takes over
+pub const DEFAULT_RANDOM_PLIES: usize = 8;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DatagenOptions {
+    /// Limits of every search, a node or depth limit keeps the games reproducible
+    pub limits: SearchLimits,
+    pub random_plies: usize,
+    pub max_plies: usize,
+    pub seed: u64
+}
+
+/// A quiet position from a self-play game with its label
+#[derive(Clone, PartialEq, Debug)]
+pub struct DataEntry {
+    pub fen: String,
+    /// Search score in centipawns from the view of white
+    pub score: i32,
+    /// 1 if white won, 0.5 for a draw and 0 if black won
+    pub result: f64
+}
+
+impl DataEntry {
+    pub fn to_line(&self) -> String {
+        return format!("{} | {} | {:.1}", self.fen, self.score, self.result);
+    }
+}
+
+/// Returns true if the position is quiet enough to be labeled with the static evaluation: the side to move is not
+/// in check, the best move is not a capture or promotion and no capture wins material by static exchange evaluation
+pub fn is_quiet_position(board: &Board, best_move: &Move) -> bool {
+    if board.is_in_check() || is_noisy_move(board, best_move) {
+        return false;
+    }
+    let mut captures = MoveList::empty();
+    board.get_moves(&mut captures, true);
+    return captures.iter().all(|mv| board.static_exchange_evaluation(mv.from, mv.to) <= 0);
+}
+
+/// Random number generator of a game, seeded from the run seed and the game number
+fn game_rng(seed: u64, game_index: u64) -> Pcg64 {
+    return Pcg64::seed_from_u64(seed ^ game_index.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+}
+
+/// Plays self-play games and collects the quiet positions of each game
+pub struct SelfPlay {
+    engine: StandardAlphaBetaEngine,
+    last_score: Arc<Mutex<Option<Score>>>,
+    runtime_constants: Arc<BitboardRuntimeConstants>,
+    options: DatagenOptions
+}
+
+impl SelfPlay {
+    pub fn new(runtime_constants: Arc<BitboardRuntimeConstants>, options: DatagenOptions) -> SelfPlay {
+        let last_score = Arc::new(Mutex::new(None));
+        let last_score_clone = Arc::clone(&last_score);
+        let engine = StandardAlphaBetaEngine::new(
+            &Board::new(Arc::clone(&runtime_constants)),
+            Box::new(move |metadata| *last_score_clone.lock().unwrap() = Some(metadata.score)),
+            Box::new(|_| ()),
+            Box::new(|| false),
+            Box::new(|| std::time::Duration::ZERO)
+        );
+        return SelfPlay { engine, last_score, runtime_constants, options };
+    }
+
+    /// Set an option of the engine, such as the transposition table size. Options which make
+    /// the search nondeterministic, like more than one thread, break resuming.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        return self.engine.set_option(name, value);
+    }
+
+    /// Play the random opening of game `game_index`. Openings which end the game are replaced by new ones.
+    pub fn random_opening(&self, game_index: u64) -> Board {
+        let mut rng = game_rng(self.options.seed, game_index);
+        let mut moves = MoveList::empty();
+        loop {
+            let mut board = Board::new(Arc::clone(&self.runtime_constants));
+            for _ in 0..self.options.random_plies {
+                board.get_moves(&mut moves, false);
+                if moves.len() == 0 {
+                    break;
+                }
+                let mv = moves.to_vec()[rng.gen_range(0..moves.len())];
+                board.make_move(&mv);
+            }
+            if board.get_game_status() == GameStatus::InProgress {
+                return board;
+            }
+        }
+    }
+
+    /// Play game `game_index`, returning its quiet positions labeled with the result and the result itself
+    pub fn play_game(&mut self, game_index: u64) -> (Vec<DataEntry>, GameResult) {
+        let mut board = self.random_opening(game_index);
+        self.engine.new_game();
+        let mut entries = Vec::new();
+        let mut plies = 0;
+        let result = loop {
+            match board.get_game_status() {
+                GameStatus::InProgress => (),
+                status => break GameResult::from_status(status)
+            }
+            if plies >= self.options.max_plies {
+                break GameResult::Draw;
+            }
+            *self.last_score.lock().unwrap() = None;
+            let pv = self.engine.search(&board, &self.options.limits);
+            let best_move = pv[0];
+            let to_white_view = |score: i32| if board.get_current_player() == Color::White { score } else { -score };
+            match *self.last_score.lock().unwrap() {
+                // The game is decided, the positions on the way to the mate are not useful for training
+                Some(Score::Mate(moves)) => break match to_white_view(moves) > 0 {
+                    true => GameResult::WhiteWon,
+                    false => GameResult::BlackWon
+                },
+                Some(Score::Centipawns(score)) if is_quiet_position(&board, &best_move) => {
+                    entries.push(DataEntry { fen: board.to_fen(), score: to_white_view(score), result: 0.0 });
+                }
+                _ => ()
+            }
+            board.make_move(&best_move);
+            plies += 1;
+        };
+
+        let result_value = match result {
+            GameResult::WhiteWon => 1.0,
+            GameResult::BlackWon => 0.0,
+            _ => 0.5
+        };
+        for entry in entries.iter_mut() {
+            entry.result = result_value;
+        }
+        return (entries, result);
+    }
+}
+
+/// State of a generation run, saved after every game so the run can be resumed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DatagenProgress {
+    pub seed: u64,
+    /// Number of games which are completely written
+    pub games: u64,
+    /// Length of the output file after the last complete game
+    pub bytes: u64
+}
+
+impl DatagenProgress {
+    pub fn to_text(&self) -> String {
+        return format!("seed {}\ngames {}\nbytes {}\n", self.seed, self.games, self.bytes);
+    }
+
+    pub fn parse(text: &str) -> Option<DatagenProgress> {
+        let mut progress = DatagenProgress { seed: 0, games: 0, bytes: 0 };
+        let mut fields_found = 0;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (name, value) = line.trim().split_once(' ')?;
+            let value = value.trim().parse::<u64>().ok()?;
+            match name {
+                "seed" => progress.seed = value,
+                "games" => progress.games = value,
+                "bytes" => progress.bytes = value,
+                _ => return None
+            }
+            fields_found += 1;
+        }
+        return match fields_found {
+            3 => Some(progress),
+            _ => None
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::Move;
+    use crate::engine::SearchLimits;
+    use super::{is_quiet_position, DataEntry, DatagenOptions, DatagenProgress, SelfPlay};
+
+    #[test]
+    fn test_quiet_positions() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let quiet = Board::from_fen("4k3/8/2p5/3p4/4P3/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+        assert!(is_quiet_position(&quiet, &Move::from_algebraic(&quiet, "e1d2")));
+        // The best move is a capture, even though it does not win material
+        assert!(!is_quiet_position(&quiet, &Move::from_algebraic(&quiet, "e4d5")));
+        // A capture wins material
+        let hanging = Board::from_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+        assert!(!is_quiet_position(&hanging, &Move::from_algebraic(&hanging, "e1d2")));
+        let check = Board::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1", constant_state).unwrap();
+        assert!(!is_quiet_position(&check, &Move::from_algebraic(&check, "e1e2")));
+    }
+
+    #[test]
+    fn test_self_play() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let options = DatagenOptions { limits: SearchLimits::from_depth(2), random_plies: 8, max_plies: 40, seed: 5 };
+        let mut self_play = SelfPlay::new(Arc::clone(&constant_state), options.clone());
+        let (entries, result) = self_play.play_game(3);
+        assert!(!entries.is_empty() && entries.len() <= 40);
+        let result_value = entries[0].result;
+        assert!(entries.iter().all(|entry| entry.result == result_value));
+        let fields: Vec<String> = entries[0].to_line().split(" | ").map(|field| field.to_string()).collect();
+        assert_eq!(fields.len(), 3);
+        assert!(Board::from_fen(&fields[0], Arc::clone(&constant_state)).is_ok());
+
+        // Games only depend on the seed and the game number
+        assert_eq!(self_play.play_game(3), (entries.clone(), result));
+        let mut other = SelfPlay::new(Arc::clone(&constant_state), options.clone());
+        other.play_game(1);
+        assert_eq!(other.play_game(3), (entries.clone(), result));
+        assert_ne!(self_play.random_opening(3).to_fen(), self_play.random_opening(4).to_fen());
+        let mut other_seed = SelfPlay::new(constant_state, DatagenOptions { seed: 6, ..options });
+        assert_ne!(other_seed.play_game(3).0, entries);
+
+        let entry = DataEntry { fen: "4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_string(), score: -12, result: 0.5 };
+        assert_eq!(entry.to_line(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1 | -12 | 0.5");
+    }
+
+    #[test]
+    fn test_datagen_progress() {
+        let progress = DatagenProgress { seed: 42, games: 10, bytes: 12345 };
+        assert_eq!(DatagenProgress::parse(&progress.to_text()), Some(progress));
+        assert_eq!(DatagenProgress::parse("seed 42\ngames 10\n"), None);
+        assert_eq!(DatagenProgress::parse("seed 42\ngames ten\nbytes 1"), None);
+    }
+}
@@ -1,7 +1,11 @@
 pub mod ab_engine;
 pub mod random_engine;
+pub mod history;
 pub mod move_sorting;
 pub mod pv;
+pub mod search_features;
+pub mod transposition_table;
+pub mod time_management;
 
 use std::time::Duration;
 
@@ -11,27 +15,89 @@ use random_engine::RandomEngine;
 use super::core::Move;
 use crate::core::bitboard::Board;
 
-pub type SearchMetadataCallback = Box<dyn Fn(SearchMetadata) -> ()>;
-pub type LogCallback = Box<dyn Fn(&str) -> ()>;
-pub type GetSystemTimeCallback = Box<dyn Fn() -> Duration>;
+// Callbacks are `Send` so engines can be moved to, and search from, other threads
+pub type SearchMetadataCallback = Box<dyn Fn(SearchMetadata) -> () + Send>;
+pub type LogCallback = Box<dyn Fn(&str) -> () + Send>;
+pub type GetSystemTimeCallback = Box<dyn Fn() -> Duration + Send>;
 
-pub type ShouldAbortSearchCallback = Box<dyn Fn() -> bool>;
+pub type ShouldAbortSearchCallback = Box<dyn Fn() -> bool + Send>;
 
 
+/// Score of a search from the view of the player to move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Score {
+    Centipawns(i32),
+    /// Moves until mate if a forced mate was found. Negative if the engine is getting mated.
+    Mate(i32)
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct SearchMetadata {
     pub depth: usize,
-    pub eval: f64,
+    /// Deepest ply reached, including quiescence search
+    pub selective_depth: usize,
+    pub score: Score,
+    pub nodes: u64,
+    pub nodes_per_second: u64,
+    pub time: Duration,
+    /// Transposition table usage in permille
+    pub hashfull: usize,
+    /// Positions found in the endgame tablebases
+    pub tb_hits: u64,
     pub pv: Vec<Move>
 }
 
+/// Limits for a single search, as given by the UCI `go` command.
+/// A search without any limits runs for `time_management::DEFAULT_MOVE_TIME`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SearchLimits {
+    /// Maximum depth to search to
+    pub depth: Option<usize>,
+    /// Maximum amount of nodes to search
+    pub nodes: Option<u64>,
+    /// Search for exactly this long
+    pub move_time: Option<Duration>,
+    /// Remaining clock time and increment for both players
+    pub white_time: Option<Duration>,
+    pub white_increment: Option<Duration>,
+    pub black_time: Option<Duration>,
+    pub black_increment: Option<Duration>,
+    /// Moves left until the next time control
+    pub moves_to_go: Option<usize>,
+    /// Search until stopped
+    pub infinite: bool
+}
+
+impl SearchLimits {
+    pub fn from_depth(depth: usize) -> SearchLimits {
+        return SearchLimits { depth: Some(depth), ..Default::default() };
+    }
+
+    pub fn from_move_time(move_time: Duration) -> SearchLimits {
+        return SearchLimits { move_time: Some(move_time), ..Default::default() };
+    }
+
+    pub fn infinite() -> SearchLimits {
+        return SearchLimits { infinite: true, ..Default::default() };
+    }
+}
+
 pub enum EngineType {
     Standard,
 }
 
-pub trait Engine {
-    fn search(&mut self, board: &Board) -> Vec<Move>;
+pub trait Engine: Send {
+    fn search(&mut self, board: &Board, limits: &SearchLimits) -> Vec<Move>;
     fn get_name(&self) -> &'static str;
+
+    /// Set an engine option by name, such as the transposition table size.
+    /// Returns an error if the engine does not support the option or the value is invalid.
+    fn set_option(&mut self, name: &str, _value: &str) -> Result<(), String> {
+        return Err(format!("Unsupported option: {}", name));
+    }
+
+    /// Reset any state which is kept between searches, such as the transposition table
+    fn new_game(&mut self) {}
 }
 
 pub fn from_name(
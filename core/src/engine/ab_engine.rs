@@ -2,20 +2,65 @@
 use core::panic;
 use std::i32;
 
-use super::{move_sorting, Engine, GetSystemTimeCallback, LogCallback, SearchMetadataCallback, ShouldAbortSearchCallback};
+use super::transposition_table::{NodeType, TranspositionTable, DEFAULT_HASH_SIZE_MB};
+use super::time_management::{calculate_time_budget, TimeBudget};
+use super::search_features::*;
+use super::{move_sorting, Engine, Score, SearchLimits, SearchMetadata, GetSystemTimeCallback, LogCallback, SearchMetadataCallback, ShouldAbortSearchCallback};
 use crate::core::bitboard::Board;
 use crate::core::*;
+use crate::core::variant::Variant;
+use crate::syzygy::{Tablebases, Wdl};
+use crate::tablebase::{kpk, Dtm, DtmTables};
 use bitboard::constants::KING_VALUE;
-use super::pv::PrincipalVariation;
+use super::pv::{legal_pv_prefix, PrincipalVariation};
+use super::history::{MoveHistory, PieceTo};
+use super::move_sorting::MovePicker;
 use move_list::{MoveList, MoveListCollection, SearchResult};
 use std::{thread, time::Duration};
 use std::time::Instant;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use crate::core::bitboard::constants;
 
 pub const ENGINE_NAME: &str = "magnificence";
 
-pub const CHECK_ABORT_NODE_INTERVAL: u64 = 1_000_000;
+pub const CHECK_ABORT_NODE_INTERVAL: u64 = 10_000;
+/// Maximum depth of the iterative deepening, the default of the `MaxDepth` option
+pub const MAX_SEARCH_DEPTH: usize = 64;
+/// Maximum distance from the root, check extensions stop before the search gets deeper
+pub const MAX_PLY: usize = 128;
+/// Score of giving mate at the root. Mates found at `ply` score `MATE_SCORE - ply`, so shorter mates score higher.
+const MATE_SCORE: i32 = KING_VALUE * 8;
+/// Scores at or beyond this value are mate scores. Quiescence search can go past `MAX_PLY`, but not twice as far.
+const MATE_THRESHOLD: i32 = MATE_SCORE - 2 * MAX_PLY as i32;
+/// Score of a tablebase win at the root. Wins found at `ply` score `TB_WIN_SCORE - ply`, below all mate scores.
+const TB_WIN_SCORE: i32 = MATE_THRESHOLD - 1;
+/// Scores at or beyond this value are tablebase wins or mates
+const TB_WIN_THRESHOLD: i32 = TB_WIN_SCORE - MAX_PLY as i32;
+/// Iterations from this depth on start with an aspiration window around the previous score
+const ASPIRATION_MIN_DEPTH: usize = 4;
+/// Initial half width of the aspiration window, which doubles after every failed search
+const ASPIRATION_WINDOW: i32 = 25;
+/// Half width from which a failed aspiration search is repeated with a full window
+const ASPIRATION_MAX_WINDOW: i32 = 1000;
+/// Maximum value of the `Threads` option
+pub const MAX_THREADS: usize = 256;
+
+/// State shared by the main search thread and its helper threads
+#[derive(Clone)]
+struct SharedSearchState {
+    transposition_table: Arc<TranspositionTable>,
+    /// Set by the main thread when it stops searching, helpers stop when they see it
+    stop_signal: Arc<AtomicBool>,
+    /// Nodes searched by all threads, each thread adds its nodes every `CHECK_ABORT_NODE_INTERVAL` nodes
+    nodes: Arc<AtomicU64>,
+    tablebases: Arc<Tablebases>,
+    /// Distance-to-mate tables loaded with the `DtmPath` option
+    dtm_tables: Arc<DtmTables>,
+    /// Tablebase probes of all threads which found the position
+    tb_hits: Arc<AtomicU64>
+}
 
 #[allow(unused)]
 pub struct StandardAlphaBetaEngine {
@@ -29,85 +74,491 @@ pub struct StandardAlphaBetaEngine {
     nodes_per_depth: Vec<u64>,
     qsearch_nodes: u64,
     pv_table: PrincipalVariation,
+    shared: SharedSearchState,
+    root_depth: usize,
+    selective_depth: usize,
     keep_searching: bool,
     total_nodes_searched_since_last_abort_check: u64,
-    alloted_time_for_search: Duration,
+    nodes_searched: u64,
+    node_limit: Option<u64>,
+    time_budget: TimeBudget,
     search_start_time: Duration,
+
+    // Lazy SMP: helper threads search the same position and share results through the transposition table
+    /// 0 for the main thread, which manages time and reports results
+    thread_index: usize,
+    helpers: Vec<StandardAlphaBetaEngine>,
+    /// Depth, score and principal variation of the last completed iteration
+    completed_depth: usize,
+    completed_eval: i32,
+    completed_pv: Vec<Move>,
+
+    features: SearchFeatures,
+    history: MoveHistory,
+    /// Principal variation of the previous iteration, searched first
+    previous_pv: Vec<Move>,
+    /// Moved piece and destination of the move made at each ply, `None` for null moves
+    move_stack: Vec<Option<PieceTo>>,
+    /// Quiet moves searched at each ply without causing a cutoff
+    quiets_tried: Vec<Vec<(Move, Piece)>>,
+    /// Depth limit of the iterative deepening, for searches without a lower depth limit
+    max_depth: usize,
+    /// Moves searched at the root, all legal moves if empty. Limited to the moves keeping the tablebase result.
+    root_moves: Vec<Move>
 }
 
 #[allow(unused)]
 impl Engine for StandardAlphaBetaEngine {
-    fn search(&mut self, board: &Board) -> Vec<Move> {        
+    fn search(&mut self, board: &Board, limits: &SearchLimits) -> Vec<Move> {
         self.search_start_time = (self.get_system_time)();
+        self.prepare_search(board);
+        self.node_limit = limits.nodes;
+        self.time_budget = calculate_time_budget(limits, board.get_current_player());
+        self.shared.transposition_table.new_search();
+        self.shared.stop_signal.store(false, Ordering::Relaxed);
+        self.shared.nodes.store(0, Ordering::Relaxed);
+        self.shared.tb_hits.store(0, Ordering::Relaxed);
+        self.root_moves = self.probe_root_moves(board);
 
-        self.board = board.clone();
-        let mut board_copy = board.clone();
+        let max_depth = limits.depth.unwrap_or(self.max_depth).clamp(1, self.max_depth);
+        if self.helpers.is_empty() {
+            self.iterative_deepening(board, max_depth);
+        }
+        else {
+            let mut helpers = std::mem::take(&mut self.helpers);
+            thread::scope(|scope| {
+                for helper in helpers.iter_mut() {
+                    helper.prepare_search(board);
+                    helper.root_moves = self.root_moves.clone();
+                    scope.spawn(move || helper.iterative_deepening(board, max_depth));
+                }
+                self.iterative_deepening(board, max_depth);
+                // The helpers stop when the main thread is done
+                self.shared.stop_signal.store(true, Ordering::Relaxed);
+            });
+            // Use the deepest completed iteration of any thread, preferring the main thread
+            let deepest_helper = helpers.iter().max_by_key(|helper| helper.completed_depth).unwrap();
+            if deepest_helper.completed_depth > self.completed_depth {
+                self.completed_depth = deepest_helper.completed_depth;
+                self.completed_eval = deepest_helper.completed_eval;
+                self.completed_pv = deepest_helper.completed_pv.clone();
+                (self.update_metadata)(self.create_search_metadata(self.completed_depth, self.completed_eval, &self.completed_pv));
+            }
+            self.helpers = helpers;
+        }
+
+        let elapsed = (self.get_system_time)() - self.search_start_time;
+        (self.info)(&format!("info string search took {:.2?} s", (elapsed.as_secs_f64())));
+        //self.report_node_counts(max_depth);
+
+        return self.completed_pv.clone();
+    }
+
+    fn get_name(&self) -> &'static str {
+        return ENGINE_NAME;
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                let size_mb = value.parse::<usize>().map_err(|_| format!("Invalid hash size: {}", value))?;
+                self.shared.transposition_table = Arc::new(TranspositionTable::new(size_mb));
+                for helper in self.helpers.iter_mut() {
+                    helper.shared.transposition_table = Arc::clone(&self.shared.transposition_table);
+                }
+                return Ok(());
+            }
+            "threads" => {
+                let threads = match value.parse::<usize>() {
+                    Ok(threads) if (1..=MAX_THREADS).contains(&threads) => threads,
+                    _ => return Err(format!("Invalid thread count: {}", value))
+                };
+                self.helpers = (1..threads).map(|thread_index| self.create_helper(thread_index)).collect();
+                return Ok(());
+            }
+            "maxdepth" => {
+                self.max_depth = match value.parse::<usize>() {
+                    Ok(depth) if (1..=MAX_SEARCH_DEPTH).contains(&depth) => depth,
+                    _ => return Err(format!("Invalid max depth: {}", value))
+                };
+                return Ok(());
+            }
+            feature if SearchFeatures::is_feature(feature) => {
+                self.features.set(name, value)?;
+                for helper in self.helpers.iter_mut() {
+                    helper.features = self.features;
+                }
+                return Ok(());
+            }
+            "clear hash" => {
+                self.shared.transposition_table.clear();
+                return Ok(());
+            }
+            "syzygypath" => {
+                let tablebases = match value.trim() {
+                    "" | "<empty>" => Tablebases::default(),
+                    path => Tablebases::load(path).map_err(|e| e.to_string())?
+                };
+                if !tablebases.is_empty() {
+                    (self.info)(&format!("info string found {} tablebases with up to {} pieces", tablebases.len(), tablebases.max_pieces()));
+                }
+                self.shared.tablebases = Arc::new(tablebases);
+                for helper in self.helpers.iter_mut() {
+                    helper.shared.tablebases = Arc::clone(&self.shared.tablebases);
+                }
+                return Ok(());
+            }
+            "dtmpath" => {
+                let dtm_tables = match value.trim() {
+                    "" | "<empty>" => DtmTables::default(),
+                    path => DtmTables::load(path).map_err(|e| e.to_string())?
+                };
+                if !dtm_tables.is_empty() {
+                    (self.info)(&format!("info string found {} distance-to-mate tables", dtm_tables.len()));
+                }
+                self.shared.dtm_tables = Arc::new(dtm_tables);
+                for helper in self.helpers.iter_mut() {
+                    helper.shared.dtm_tables = Arc::clone(&self.shared.dtm_tables);
+                }
+                return Ok(());
+            }
+            _ => return Err(format!("Unsupported option: {}", name))
+        }
+    }
 
-        // Reset various variables
+    fn new_game(&mut self) {
+        self.shared.transposition_table.clear();
+        self.history.clear();
+        for helper in self.helpers.iter_mut() {
+            helper.history.clear();
+        }
+    }
+}
+
+impl StandardAlphaBetaEngine {
+
+    /// Reset the per search state of this thread
+    fn prepare_search(&mut self, board: &Board) {
+        self.board = board.clone();
         for i in 0..self.nodes_per_depth.len() {
             self.nodes_per_depth[i] = 0;
         }
         self.total_nodes_searched_since_last_abort_check = 0;
-        self.alloted_time_for_search = Duration::from_secs(1);
+        self.nodes_searched = 0;
+        self.selective_depth = 0;
         self.keep_searching = true;
+        self.completed_depth = 0;
+        self.completed_eval = 0;
+        self.completed_pv.clear();
+        self.history.new_search();
+    }
 
-        // Iterative deepening
-        let max_depth = 12;
+    /// Search `board` with increasing depth until `max_depth` is reached or the search is stopped.
+    /// Helper threads start at different depths so the threads do not search the exact same tree.
+    fn iterative_deepening(&mut self, board: &Board, max_depth: usize) {
         let mut pv: Vec<Move> = Vec::new();
-        let mut depth = 1;
-        while depth < max_depth && self.keep_searching {
-            self.pv_table.set_max_depth(depth);
-            let eval = self.alpha_beta(depth, i32::MIN + 1, i32::MAX, &mut pv);
-            pv = self.pv_table.get_pv();
-            (self.info)(&format!("update metadata {depth} {eval}"));
-            (self.update_metadata)(super::SearchMetadata { depth: depth as usize, eval: eval as f64, pv: pv.clone() });
+        let mut depth = 1 + self.thread_index % 2;
+        while depth <= max_depth && self.keep_searching {
+            self.root_depth = depth;
+            self.previous_pv = pv.clone();
+            let eval = self.aspiration_search(depth);
+            // The first iteration of the main thread is always used, to always have a move
+            if !self.keep_searching && (depth > 1 || self.thread_index != 0) {
+                // The iteration was aborted, use the result of the previous iteration
+                break;
+            }
+            pv = legal_pv_prefix(board, &self.pv_table.get_pv());
+            self.completed_depth = depth;
+            self.completed_eval = eval;
+            self.completed_pv = pv.clone();
+            self.flush_node_count();
+            if self.thread_index == 0 {
+                (self.update_metadata)(self.create_search_metadata(depth, eval, &pv));
+            }
             depth += 1;
             self.update_should_abort();
+            if self.is_soft_time_limit_reached() {
+                break;
+            }
         }
+        self.flush_node_count();
+    }
 
-        let elapsed = (self.get_system_time)() - self.search_start_time;
-        //let elapsed = now.elapsed();
-        (self.info)(&format!("info search took {:.2?} s", (elapsed.as_secs_f64())));
-        //self.report_node_counts(max_depth);
+    /// Rank the root moves with the tablebases and keep the ones with the best rank. Returns an empty list, to
+    /// search all moves, if the root position is not in the tables.
+    fn probe_root_moves(&mut self, board: &Board) -> Vec<Move> {
+        let mut board = board.clone();
+        let Some(ranked_moves) = self.shared.tablebases.rank_root_moves(&mut board) else {
+            return Vec::new();
+        };
+        self.shared.tb_hits.fetch_add(ranked_moves.len() as u64, Ordering::Relaxed);
+        let best_rank = ranked_moves.iter().map(|&(_, rank)| rank).max().unwrap_or(0);
+        return ranked_moves.into_iter().filter(|&(_, rank)| rank == best_rank).map(|(mv, _)| mv).collect();
+    }
 
-        return pv;
+    /// Search the root with a narrow window around the score of the previous iteration. A search which
+    /// fails low or high is repeated with a wider window on that side, until the score is inside the window.
+    fn aspiration_search(&mut self, depth: usize) -> i32 {
+        let (mut lower_bound, mut upper_bound) = (i32::MIN + 1, i32::MAX);
+        let mut window = ASPIRATION_WINDOW;
+        let use_window = depth >= ASPIRATION_MIN_DEPTH && self.completed_depth > 0 && !is_mate_score(self.completed_eval);
+        if use_window {
+            lower_bound = self.completed_eval - window;
+            upper_bound = self.completed_eval + window;
+        }
+        loop {
+            let eval = self.alpha_beta(depth, 0, lower_bound, upper_bound, true, true);
+            if !self.keep_searching || (eval > lower_bound && eval < upper_bound) {
+                return eval;
+            }
+            window *= 2;
+            if eval <= lower_bound {
+                lower_bound = if window > ASPIRATION_MAX_WINDOW { i32::MIN + 1 } else { (eval - window).max(i32::MIN + 1) };
+            }
+            else {
+                upper_bound = if window > ASPIRATION_MAX_WINDOW { i32::MAX } else { eval.saturating_add(window) };
+            }
+        }
     }
 
-    fn get_name(&self) -> &'static str {
-        return ENGINE_NAME;
+    /// Add the nodes searched since the last flush to the node count shared by all threads
+    fn flush_node_count(&mut self) {
+        self.shared.nodes.fetch_add(self.total_nodes_searched_since_last_abort_check, Ordering::Relaxed);
+        self.total_nodes_searched_since_last_abort_check = 0;
     }
-}
 
-impl StandardAlphaBetaEngine {
+    /// Nodes searched by all threads
+    fn total_nodes(&self) -> u64 {
+        return self.shared.nodes.load(Ordering::Relaxed) + self.total_nodes_searched_since_last_abort_check;
+    }
+
+    /// Create a helper thread engine which shares the transposition table and stop signal with this engine.
+    /// Helpers do not report anything, do not manage time and stop when the main thread stops.
+    fn create_helper(&self, thread_index: usize) -> StandardAlphaBetaEngine {
+        let stop_signal = Arc::clone(&self.shared.stop_signal);
+        let mut helper = StandardAlphaBetaEngine::with_shared_state(
+            &self.board,
+            Box::new(|_| ()),
+            Box::new(|_| ()),
+            Box::new(move || stop_signal.load(Ordering::Relaxed)),
+            Box::new(|| Duration::ZERO),
+            self.shared.clone(),
+            thread_index
+        );
+        helper.features = self.features;
+        return helper;
+    }
 
     /// Evaluate the current position using an alpha beta search. Quiescence Search is ran for the leaf nodes.
-    fn alpha_beta(&mut self, depth: usize, mut lower_bound: i32, upper_bound: i32, previous_pv: &mut Vec<Move>) -> i32 {
-        self.nodes_per_depth[depth as usize] += 1;
+    /// `ply` is the distance from the root and `allow_null` is false directly after a null move.
+    /// `follow_pv` is true if all moves leading to this node are moves of the previous principal variation.
+    fn alpha_beta(&mut self, mut depth: usize, ply: usize, mut lower_bound: i32, upper_bound: i32, allow_null: bool, follow_pv: bool) -> i32 {
+        // Extend checks before dropping into quiescence search, which does not search evasions.
+        // Extensions stop when the maximum ply would be exceeded.
+        let in_check = self.board.is_in_check();
+        if in_check && self.features.check_extensions && ply + depth < MAX_PLY {
+            depth += 1;
+        }
+
+        self.nodes_per_depth[depth] += 1;
         self.total_nodes_searched_since_last_abort_check += 1;
+        self.nodes_searched += 1;
 
         if depth == 0 {
-            return self.qsearch(lower_bound, upper_bound);
+            return self.qsearch(lower_bound, upper_bound, ply);
+        }
+        self.pv_table.clear_ply(ply);
+
+        // Variant rules can end the game before the player to move runs out of moves
+        match self.board.variant_result() {
+            Some(SearchResult::Win) => return (MATE_SCORE - ply as i32).clamp(lower_bound, upper_bound),
+            Some(_) => return (-MATE_SCORE + ply as i32).clamp(lower_bound, upper_bound),
+            None => ()
         }
+
+        // Repeating a position or reaching the fifty-move limit can be claimed as a draw by the opponent
+        if ply != 0 && self.board.is_repetition() {
+            return 0.clamp(lower_bound, upper_bound);
+        }
+        // Checkmate on the move which reaches the fifty-move limit still wins, like in `Board::get_game_status`
+        if ply != 0 && self.board.is_fifty_move_rule_draw() {
+            let mut score = 0;
+            if in_check {
+                let mut moves = self.move_lists.get_move_list();
+                self.board.get_moves(&mut moves, false);
+                if matches!(moves.result(), SearchResult::Loss) {
+                    score = -MATE_SCORE + ply as i32;
+                }
+                self.move_lists.push_move_list(moves);
+            }
+            return score.clamp(lower_bound, upper_bound);
+        }
+
+        // Probe the transposition table. The root is always searched to get a principal variation.
+        let hash_key = self.board.get_hashkey();
+        let mut hash_move = None;
+        if let Some(entry) = self.shared.transposition_table.probe(hash_key) {
+            if entry.best_move != Move::empty() {
+                hash_move = Some(entry.best_move);
+            }
+            let score = score_from_table(entry.score, ply);
+            if ply != 0 && entry.depth as usize >= depth {
+                match entry.node_type {
+                    NodeType::Exact => return score.clamp(lower_bound, upper_bound),
+                    NodeType::LowerBound if score >= upper_bound => return upper_bound,
+                    NodeType::UpperBound if score <= lower_bound => return lower_bound,
+                    _ => ()
+                }
+            }
+        }
+
+        // Probe the tablebases after captures and pawn moves, when the fifty-move counter of the tables matches the
+        // board. Wins and losses are bounds, as a mate can score better than a tablebase win.
+        if ply != 0 && self.board.get_quiet_moves() == 0 && self.shared.tablebases.can_probe(&self.board) {
+            if let Some(wdl) = self.shared.tablebases.probe_wdl(&mut self.board) {
+                self.shared.tb_hits.fetch_add(1, Ordering::Relaxed);
+                let (score, node_type) = match wdl {
+                    Wdl::Win => (TB_WIN_SCORE - ply as i32, NodeType::LowerBound),
+                    Wdl::Loss => (-TB_WIN_SCORE + ply as i32, NodeType::UpperBound),
+                    // Cursed wins and blessed losses are draws, but slightly better or worse ones
+                    wdl => (wdl as i32, NodeType::Exact)
+                };
+                let cutoff = match node_type {
+                    NodeType::Exact => Some(score.clamp(lower_bound, upper_bound)),
+                    NodeType::LowerBound if score >= upper_bound => Some(upper_bound),
+                    NodeType::UpperBound if score <= lower_bound => Some(lower_bound),
+                    _ => None
+                };
+                if let Some(cutoff) = cutoff {
+                    self.shared.transposition_table.store(hash_key, Move::empty(), score_to_table(score, ply), MAX_PLY, node_type);
+                    return cutoff;
+                }
+            }
+        }
+
+        // Distance-to-mate tables give exact mate scores. Without them, the KPvK bitbase still finds the draws.
+        if ply != 0 {
+            let dtm = if self.shared.dtm_tables.is_empty() { None } else { self.shared.dtm_tables.probe(&self.board) };
+            let score = match dtm {
+                Some(Dtm::Win(plies)) if ply + plies < 2 * MAX_PLY => Some(MATE_SCORE - (ply + plies) as i32),
+                Some(Dtm::Loss(plies)) if ply + plies < 2 * MAX_PLY => Some(-MATE_SCORE + (ply + plies) as i32),
+                Some(Dtm::Draw) => Some(0),
+                None if kpk::probe(&self.board) == Some(false) => Some(0),
+                _ => None
+            };
+            if let Some(score) = score {
+                self.shared.tb_hits.fetch_add(1, Ordering::Relaxed);
+                self.shared.transposition_table.store(hash_key, Move::empty(), score_to_table(score, ply), MAX_PLY, NodeType::Exact);
+                return score.clamp(lower_bound, upper_bound);
+            }
+        }
+
+        // Nodes searched with a zero window only need to know if the score is above or below the bound
+        let is_pv_node = upper_bound > lower_bound + 1;
+        // Pruning is only safe when the node is not part of the principal variation, not in check and mate is not in sight
+        let can_prune = !is_pv_node && !in_check && ply != 0 && !is_mate_score(lower_bound) && !is_mate_score(upper_bound);
+        let static_eval = if can_prune { self.board.eval() } else { 0 };
+
+        // Reverse futility pruning: the position is so good that a shallow search will not change the outcome
+        if can_prune && self.features.reverse_futility_pruning && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= upper_bound {
+            return upper_bound;
+        }
+
+        // Null move pruning: if passing the turn still fails high with a reduced search, a real move will too.
+        // Passing can be an advantage in zugzwang, which is common when only pawns are left and in Antichess.
+        if can_prune && self.features.null_move_pruning && allow_null && depth >= NULL_MOVE_MIN_DEPTH
+                && static_eval >= upper_bound && self.board.has_non_pawn_material(self.board.get_current_player())
+                && self.board.get_variant() != Variant::Antichess {
+            let reduction = if depth >= 6 { 3 } else { 2 };
+            let null_depth = depth.saturating_sub(reduction + 1);
+            let state = self.board.make_null_move();
+            self.move_stack[ply] = None;
+            let result = -self.alpha_beta(null_depth, ply + 1, -upper_bound, -upper_bound + 1, false, false);
+            self.board.unmake_null_move(state);
+            if !self.keep_searching {
+                return upper_bound;
+            }
+            // Deep null move cutoffs are verified with a reduced normal search, to guard against zugzwang
+            if result >= upper_bound && (depth < NULL_MOVE_VERIFICATION_DEPTH
+                    || self.alpha_beta(null_depth, ply, upper_bound - 1, upper_bound, false, false) >= upper_bound) {
+                return upper_bound;
+            }
+        }
+
+        // Futility pruning: quiet moves can not raise the score above the lower bound at shallow depths
+        let futility_pruning = can_prune && self.features.futility_pruning && depth <= FUTILITY_MAX_DEPTH
+            && static_eval + FUTILITY_MARGINS[depth] <= lower_bound;
+
         let mut moves = self.move_lists.get_move_list();
 
         self.board.get_moves(&mut moves, false);
-        move_sorting::sort_moves_simple(&self.board, &mut moves, depth, previous_pv);
+        // The previous principal variation holds one move per ply
+        let pv_move = if follow_pv { self.previous_pv.get(ply).copied() } else { None };
+        let previous_moves = self.previous_moves(ply);
+        let search_result = moves.result();
+        let mut move_picker = MovePicker::new(&mut moves, pv_move, hash_move)
+            .with_quiet_moves(self.history.killers(ply), self.history.countermove(previous_moves[0]));
+        self.quiets_tried[ply].clear();
 
-        let returning = match moves.result() {
-            SearchResult::Loss => -KING_VALUE * 8 - depth as i32,
+        let mut best_move = Move::empty();
+        let mut node_type = NodeType::Exact;
+        let returning = match search_result {
+            SearchResult::Loss => -MATE_SCORE + ply as i32,
+            SearchResult::Win => MATE_SCORE - ply as i32,
             SearchResult::Stalemate => 0,
             SearchResult::InProgress => {
-                for mv in moves.iter() {
+                node_type = NodeType::UpperBound;
+                let mut index = 0;
+                while let Some(mv) = move_picker.next(&self.board, &self.history, previous_moves) {
+                    let mv = &mv;
+                    if ply == 0 && !self.root_moves.is_empty() && !self.root_moves.iter().any(|root_move| move_sorting::is_same_move(mv, root_move)) {
+                        continue;
+                    }
+                    let piece = self.board.moving_piece(mv);
+                    let is_quiet_move = !move_sorting::is_noisy_move(&self.board, mv);
                     self.board.make_move(mv);
-                    let result = -self.alpha_beta(depth - 1, -upper_bound, -lower_bound, previous_pv);
+                    // Checking moves are never pruned or reduced
+                    let gives_check = is_quiet_move && index > 0 && self.board.is_in_check();
+                    let is_late_quiet_move = is_quiet_move && index > 0 && !in_check && !gives_check;
+                    if futility_pruning && is_late_quiet_move {
+                        self.board.unmake_move(mv);
+                        continue;
+                    }
+
+                    self.move_stack[ply] = Some((piece, mv.to));
+                    let result = if index == 0 {
+                        let follow_pv = pv_move.is_some_and(|pv_move| move_sorting::is_same_move(mv, &pv_move));
+                        -self.alpha_beta(depth - 1, ply + 1, -upper_bound, -lower_bound, true, follow_pv)
+                    }
+                    else {
+                        let reduction = match self.features.late_move_reductions && is_late_quiet_move
+                                && depth >= LMR_MIN_DEPTH && index >= LMR_FULL_DEPTH_MOVES {
+                            true => late_move_reduction(depth, index),
+                            false => 0
+                        };
+                        self.search_late_move(depth, ply, lower_bound, upper_bound, reduction)
+                    };
                     self.board.unmake_move(mv);
+                    index += 1;
                     if result > lower_bound {
-                        self.pv_table.set_best_move(depth, *mv);
+                        self.pv_table.set_best_move(ply, *mv);
+                        best_move = *mv;
+                        node_type = NodeType::Exact;
                         lower_bound = result;
                         if lower_bound >= upper_bound {
+                            node_type = NodeType::LowerBound;
+                            if is_quiet_move && self.keep_searching {
+                                self.history.update_quiet_cutoff(
+                                    self.board.get_current_player(), ply, depth, (*mv, piece), &self.quiets_tried[ply], previous_moves
+                                );
+                            }
                             break;
                         }
                     }
+                    if is_quiet_move {
+                        self.quiets_tried[ply].push((*mv, piece));
+                    }
                     if self.should_abort() {
                         return lower_bound;
                     }
@@ -116,36 +567,92 @@ impl StandardAlphaBetaEngine {
             }
         };
         self.move_lists.push_move_list(moves);
+
+        // Results from aborted searches are incomplete and should not be stored
+        if self.keep_searching {
+            self.shared.transposition_table.store(hash_key, best_move, score_to_table(returning, ply), depth, node_type);
+        }
         return returning;
     }
 
-    // Evaluate the current position until it is quiet (no capturing moves).
-    pub fn qsearch(&mut self, mut lower_bound: i32, upper_bound: i32) -> i32 {
+    /// Search a move after the first move of a node, which has already been made on the board.
+    /// The move is searched with `reduction` less depth and with a zero window when principal variation
+    /// search is enabled, and is searched again with full depth and window if it raises the lower bound.
+    fn search_late_move(&mut self, depth: usize, ply: usize, lower_bound: i32, upper_bound: i32, reduction: usize) -> i32 {
+        let (window_lower, window_upper) = match self.features.principal_variation_search {
+            true => (-lower_bound - 1, -lower_bound),
+            false => (-upper_bound, -lower_bound)
+        };
+
+        let mut result = -self.alpha_beta(depth - 1 - reduction, ply + 1, window_lower, window_upper, true, false);
+        if reduction > 0 && result > lower_bound {
+            result = -self.alpha_beta(depth - 1, ply + 1, window_lower, window_upper, true, false);
+        }
+        if self.features.principal_variation_search && result > lower_bound && result < upper_bound {
+            result = -self.alpha_beta(depth - 1, ply + 1, -upper_bound, -lower_bound, true, false);
+        }
+        return result;
+    }
+
+    /// The moved piece and destination of the moves one and two plies before `ply`, `None` for null moves
+    fn previous_moves(&self, ply: usize) -> [Option<PieceTo>; 2] {
+        return [
+            ply.checked_sub(1).and_then(|ply| self.move_stack[ply]),
+            ply.checked_sub(2).and_then(|ply| self.move_stack[ply])
+        ];
+    }
+
+    // Evaluate the current position until it is quiet (no capturing moves) and the player to move is not in check.
+    // The line leading to the quiet position is kept in the pv table, up to `MAX_PLY`.
+    pub fn qsearch(&mut self, mut lower_bound: i32, upper_bound: i32, ply: usize) -> i32 {
+        if ply < MAX_PLY {
+            self.pv_table.clear_ply(ply);
+        }
         self.nodes_per_depth[0] += 1;
         self.total_nodes_searched_since_last_abort_check += 1;
+        self.nodes_searched += 1;
+        self.selective_depth = self.selective_depth.max(ply);
 
-        // Handle standing pat
-        let eval = self.board.eval();
-        if eval > lower_bound {
-            lower_bound = eval;
-            if eval >= upper_bound {
-                return upper_bound;
+        match self.board.variant_result() {
+            Some(SearchResult::Win) => return (MATE_SCORE - ply as i32).clamp(lower_bound, upper_bound),
+            Some(_) => return (-MATE_SCORE + ply as i32).clamp(lower_bound, upper_bound),
+            None => ()
+        }
+
+        // Handle standing pat. The player to move can not stand pat when in check, all evasions are searched instead.
+        // Neither when a capture is compulsory in Antichess.
+        let in_check = self.board.is_in_check();
+        if !in_check && !self.board.has_forced_capture() {
+            let eval = self.board.eval();
+            if eval > lower_bound {
+                lower_bound = eval;
+                if eval >= upper_bound {
+                    return upper_bound;
+                }
             }
         }
 
         let mut moves = self.move_lists.get_move_list();
-        self.board.get_moves(&mut moves, true); // Only generate captures
+        self.board.get_moves(&mut moves, !in_check); // Only generate captures, unless in check
+        let search_result = moves.result();
+        let mut move_picker = MovePicker::new(&mut moves, None, None);
 
-        let returning = match moves.result() {
-            SearchResult::Loss => -KING_VALUE * 8,
-            SearchResult::Stalemate => 0,
+        let returning = match search_result {
+            SearchResult::Loss => (-MATE_SCORE + ply as i32).clamp(lower_bound, upper_bound),
+            SearchResult::Win => (MATE_SCORE - ply as i32).clamp(lower_bound, upper_bound),
+            // Without captures the position is quiet, this is not a stalemate
+            SearchResult::Stalemate => lower_bound,
             SearchResult::InProgress => {
-                for mv in moves.iter() {
+                while let Some(mv) = move_picker.next(&self.board, &self.history, [None, None]) {
+                    let mv = &mv;
                     self.board.make_move(mv);
-                    let result = -self.qsearch(-upper_bound, -lower_bound);
+                    let result = -self.qsearch(-upper_bound, -lower_bound, ply + 1);
                     self.board.unmake_move(mv);
                     if result > lower_bound {
                         lower_bound = result;
+                        if ply < MAX_PLY {
+                            self.pv_table.set_best_move(ply, *mv);
+                        }
                         if lower_bound >= upper_bound {
                             break;
                         }
@@ -162,6 +669,31 @@ impl StandardAlphaBetaEngine {
         return returning;
     }
 
+    /// Quiescence search of `board` with a full window. Returns the score for the player to move and the line
+    /// of captures, and of evasions when in check, which leads to the quiet position the score is the evaluation of.
+    pub fn resolve(&mut self, board: &Board) -> (i32, Vec<Move>) {
+        self.prepare_search(board);
+        let eval = self.qsearch(i32::MIN + 1, i32::MAX, 0);
+        return (eval, legal_pv_prefix(board, &self.pv_table.get_pv()));
+    }
+
+    fn create_search_metadata(&self, depth: usize, eval: i32, pv: &[Move]) -> SearchMetadata {
+        let time = self.elapsed_time();
+        let nodes = self.total_nodes();
+        let nodes_per_second = (nodes as u128 * 1000 / time.as_millis().max(1)) as u64;
+        return SearchMetadata {
+            depth,
+            selective_depth: self.selective_depth.max(depth),
+            score: search_score(eval),
+            nodes,
+            nodes_per_second,
+            time,
+            hashfull: self.shared.transposition_table.hashfull(),
+            tb_hits: self.shared.tb_hits.load(Ordering::Relaxed),
+            pv: pv.to_vec()
+        };
+    }
+
     fn update_should_abort(&mut self) {
         // Only check the more expensive operations every CHECK_ABORT_NODE_INTERVAL nodes
         // Should we abort based on user abort?
@@ -170,22 +702,39 @@ impl StandardAlphaBetaEngine {
             return;
         }
         // Should we abort based on timekeeping?
-        let current_time = (self.get_system_time)();
-        let elapsed_time = current_time - self.search_start_time;
-        if elapsed_time > self.alloted_time_for_search {
-            self.keep_searching = false;
-            return;
+        if let Some(hard_limit) = self.time_budget.hard_limit {
+            if self.elapsed_time() > hard_limit {
+                self.keep_searching = false;
+                return;
+            }
         }
     }
 
+    /// Returns true if there is not enough time left to start another iteration
+    fn is_soft_time_limit_reached(&self) -> bool {
+        return match self.time_budget.soft_limit {
+            Some(soft_limit) => self.elapsed_time() > soft_limit,
+            None => false
+        };
+    }
+
+    fn elapsed_time(&self) -> Duration {
+        return (self.get_system_time)().saturating_sub(self.search_start_time);
+    }
+
     fn should_abort(&mut self) -> bool {
         // If we have decided to no longer search, abort
         if !self.keep_searching {
             return true;
         }
+        // Node limits are checked every node to stop at the exact count
+        if self.node_limit.is_some_and(|node_limit| self.total_nodes() >= node_limit) {
+            self.keep_searching = false;
+            return true;
+        }
         if self.total_nodes_searched_since_last_abort_check > CHECK_ABORT_NODE_INTERVAL {
+            self.flush_node_count();
             self.update_should_abort();
-            self.total_nodes_searched_since_last_abort_check = 0;
         } 
         
         return false;
@@ -215,6 +764,35 @@ impl StandardAlphaBetaEngine {
         should_abort_callback: ShouldAbortSearchCallback,
         get_system_time_callback: GetSystemTimeCallback
     ) -> StandardAlphaBetaEngine {
+        return StandardAlphaBetaEngine::with_shared_state(
+            board,
+            update_metadata_callback,
+            info_callback,
+            should_abort_callback,
+            get_system_time_callback,
+            SharedSearchState {
+                transposition_table: Arc::new(TranspositionTable::new(DEFAULT_HASH_SIZE_MB)),
+                stop_signal: Arc::new(AtomicBool::new(false)),
+                nodes: Arc::new(AtomicU64::new(0)),
+                tablebases: Arc::new(Tablebases::default()),
+                dtm_tables: Arc::new(DtmTables::default()),
+                tb_hits: Arc::new(AtomicU64::new(0))
+            },
+            0
+        );
+    }
+
+    fn with_shared_state(
+        board: &Board, 
+        update_metadata_callback: SearchMetadataCallback, 
+        info_callback: LogCallback, 
+        should_abort_callback: ShouldAbortSearchCallback,
+        get_system_time_callback: GetSystemTimeCallback,
+        shared: SharedSearchState,
+        thread_index: usize
+    ) -> StandardAlphaBetaEngine {
+        let mut pv_table = PrincipalVariation::new();
+        pv_table.set_max_ply(MAX_PLY);
         return StandardAlphaBetaEngine {
             update_metadata: update_metadata_callback,
             info: info_callback,
@@ -222,13 +800,245 @@ impl StandardAlphaBetaEngine {
             get_system_time: get_system_time_callback,
             board: board.clone(),
             move_lists: MoveListCollection::new(),
-            nodes_per_depth: vec![0; 100],
+            nodes_per_depth: vec![0; MAX_PLY + 1],
             qsearch_nodes: 0,
             keep_searching: true,
-            alloted_time_for_search: Duration::from_millis(0),
+            nodes_searched: 0,
+            node_limit: None,
+            time_budget: TimeBudget::unlimited(),
             search_start_time: Duration::from_millis(0),
             total_nodes_searched_since_last_abort_check: 0,
-            pv_table: PrincipalVariation::new()
+            pv_table,
+            shared,
+            root_depth: 0,
+            selective_depth: 0,
+            thread_index,
+            helpers: Vec::new(),
+            completed_depth: 0,
+            completed_eval: 0,
+            completed_pv: Vec::new(),
+            features: SearchFeatures::default(),
+            history: MoveHistory::new(),
+            previous_pv: Vec::new(),
+            move_stack: vec![None; MAX_PLY + 1],
+            quiets_tried: vec![Vec::new(); MAX_PLY + 1],
+            max_depth: MAX_SEARCH_DEPTH,
+            root_moves: Vec::new()
         };
     }
-}
\ No newline at end of file
+}
+fn is_mate_score(score: i32) -> bool {
+    return score.abs() >= MATE_THRESHOLD;
+}
+
+/// Mate and tablebase scores are relative to the root, but the transposition table stores them relative to the node,
+/// so a mate found through a transposition at a different ply keeps its distance
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    return match score {
+        score if score >= TB_WIN_THRESHOLD => score + ply as i32,
+        score if score <= -TB_WIN_THRESHOLD => score - ply as i32,
+        score => score
+    };
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    return match score {
+        score if score >= TB_WIN_THRESHOLD => score - ply as i32,
+        score if score <= -TB_WIN_THRESHOLD => score + ply as i32,
+        score => score
+    };
+}
+
+/// Convert a root score to centipawns or moves until mate
+fn search_score(eval: i32) -> Score {
+    if !is_mate_score(eval) {
+        return Score::Centipawns(eval);
+    }
+    let ply = MATE_SCORE - eval.abs();
+    return match eval > 0 {
+        true => Score::Mate((ply + 1) / 2),
+        false => Score::Mate(-ply / 2)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::Move;
+    use crate::engine::search_features::FEATURE_OPTION_NAMES;
+    use crate::engine::{Engine, Score, SearchLimits, SearchMetadata, ShouldAbortSearchCallback};
+    use crate::syzygy::tests::FIXTURE_DIRECTORY;
+    use crate::syzygy::{Tablebases, Wdl};
+    use super::{score_from_table, score_to_table, search_score, StandardAlphaBetaEngine, MATE_SCORE};
+
+    fn create_engine(board: &Board, metadata: &Arc<Mutex<Vec<SearchMetadata>>>, should_abort: ShouldAbortSearchCallback) -> StandardAlphaBetaEngine {
+        let metadata = Arc::clone(metadata);
+        return StandardAlphaBetaEngine::new(
+            board,
+            Box::new(move |data| metadata.lock().unwrap().push(data)),
+            Box::new(|_| ()),
+            should_abort,
+            Box::new(|| std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_lazy_smp() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        assert!(engine.set_option("Threads", "0").is_err());
+        assert!(engine.set_option("Threads", "4").is_ok());
+
+        let pv = engine.search(&board, &SearchLimits::from_depth(4));
+        assert_eq!(pv.first(), Some(&Move::from_algebraic(&board, "a1a8")));
+        let metadata = metadata.lock().unwrap();
+        assert_eq!(metadata.last().unwrap().depth, 4);
+        // Node counts include all threads and never decrease
+        assert!(metadata.windows(2).all(|pair| pair[0].nodes <= pair[1].nodes));
+    }
+
+    #[test]
+    fn test_lazy_smp_stop() {
+        // An infinite search stops in all threads when the search is aborted
+        let board = Board::new(Arc::new(BOARD_CONSTANT_STATE.clone()));
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| true));
+        engine.set_option("Threads", "3").unwrap();
+        let pv = engine.search(&board, &SearchLimits::infinite());
+        assert!(!pv.is_empty());
+
+        // The engine can search again after a stopped search
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        engine.set_option("Threads", "2").unwrap();
+        assert!(!engine.search(&board, &SearchLimits::from_move_time(Duration::from_millis(100))).is_empty());
+        assert!(!engine.search(&board, &SearchLimits::from_depth(3)).is_empty());
+    }
+
+    #[test]
+    fn test_kpk_bitbase() {
+        // The rook pawn is a draw, which the search sees through the bitbase right after the first move
+        let board = Board::from_fen("k7/8/K7/P7/8/8/8/8 w - - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        engine.search(&board, &SearchLimits::from_depth(4));
+        let last = metadata.lock().unwrap().last().unwrap().clone();
+        assert_eq!(last.score, Score::Centipawns(0));
+        assert!(last.tb_hits > 0);
+    }
+
+    #[test]
+    #[ignore = "needs the official Syzygy files of FIXTURE_TABLES in core/tests/syzygy"]
+    fn test_tablebase_root_moves() {
+        // The rook is attacked, so only the root moves which keep it are searched
+        let board = Board::from_fen("8/8/8/8/8/8/2k5/K2R4 w - - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        engine.set_option("SyzygyPath", FIXTURE_DIRECTORY).unwrap();
+        let pv = engine.search(&board, &SearchLimits::from_depth(4));
+
+        let tablebases = Tablebases::load(FIXTURE_DIRECTORY).unwrap();
+        let keeps_win = |mv: &Move| {
+            let mut board = board.clone();
+            board.make_move(mv);
+            return tablebases.probe_wdl(&mut board) == Some(Wdl::Loss);
+        };
+        assert!(!engine.root_moves.is_empty() && engine.root_moves.iter().all(keeps_win));
+        for losing_rook in ["a1a2", "d1d2", "d1c1"] {
+            assert!(!engine.root_moves.contains(&Move::from_algebraic(&board, losing_rook)), "{}", losing_rook);
+        }
+        assert!(keeps_win(&pv[0]));
+        assert!(metadata.lock().unwrap().last().unwrap().tb_hits > 0);
+    }
+
+    #[test]
+    fn test_search_features() {
+        // The back rank mate is found with every feature switched off on its own, and with all of them off
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        for disabled in FEATURE_OPTION_NAMES {
+            let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+            engine.set_option(disabled, "false").unwrap();
+            let pv = engine.search(&board, &SearchLimits::from_depth(5));
+            assert_eq!(pv.first(), Some(&Move::from_algebraic(&board, "a1a8")), "{} disabled", disabled);
+        }
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        for disabled in FEATURE_OPTION_NAMES {
+            engine.set_option(disabled, "false").unwrap();
+        }
+        assert!(engine.set_option("FutilityPruning", "off").is_err());
+        let pv = engine.search(&board, &SearchLimits::from_depth(3));
+        assert_eq!(pv.first(), Some(&Move::from_algebraic(&board, "a1a8")));
+        assert_eq!(metadata.lock().unwrap().last().unwrap().score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_mate_on_fifty_move_limit() {
+        // The mate reaches a halfmove clock of 100, every other move draws
+        let board = Board::from_fen("k7/8/1K6/8/8/8/8/7R w - - 99 80", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        let pv = engine.search(&board, &SearchLimits::from_depth(3));
+        assert_eq!(pv.first(), Some(&Move::from_algebraic(&board, "h1h8")));
+        assert_eq!(metadata.lock().unwrap().last().unwrap().score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let board = Board::new(Arc::new(BOARD_CONSTANT_STATE.clone()));
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        assert!(engine.set_option("MaxDepth", "0").is_err());
+        assert!(engine.set_option("MaxDepth", "65").is_err());
+        engine.set_option("MaxDepth", "3").unwrap();
+        // The depth of a search is capped by the option, even when a deeper search is requested
+        engine.search(&board, &SearchLimits::from_depth(6));
+        assert_eq!(metadata.lock().unwrap().last().unwrap().depth, 3);
+        engine.search(&board, &SearchLimits::default());
+        assert_eq!(metadata.lock().unwrap().last().unwrap().depth, 3);
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        // Mate scores in the transposition table are relative to the node they are stored at
+        let mate_at_ply_7 = MATE_SCORE - 7;
+        assert_eq!(score_to_table(mate_at_ply_7, 3), MATE_SCORE - 4);
+        assert_eq!(score_from_table(MATE_SCORE - 4, 5), MATE_SCORE - 9);
+        assert_eq!(score_from_table(score_to_table(-mate_at_ply_7, 3), 3), -mate_at_ply_7);
+        assert_eq!(score_to_table(150, 3), 150);
+
+        assert_eq!(search_score(-40), Score::Centipawns(-40));
+        assert_eq!(search_score(MATE_SCORE - 1), Score::Mate(1));
+        assert_eq!(search_score(MATE_SCORE - 3), Score::Mate(2));
+        assert_eq!(search_score(-MATE_SCORE + 2), Score::Mate(-1));
+
+        // The mate is reported in moves, and the shortest mate is found once the search is deep enough
+        let board = Board::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        engine.search(&board, &SearchLimits::from_depth(8));
+        let metadata = metadata.lock().unwrap();
+        assert!(matches!(metadata[0].score, Score::Centipawns(score) if score > 400));
+        assert!(metadata.iter().filter(|data| data.depth >= 5).all(|data| data.score == Score::Mate(2)));
+        assert_eq!(metadata.last().unwrap().depth, 8);
+    }
+
+    #[test]
+    fn test_aspiration_windows() {
+        // The scores of a search with aspiration windows match the scores of full window searches
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let metadata = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = create_engine(&board, &metadata, Box::new(|| false));
+        engine.search(&board, &SearchLimits::from_depth(5));
+        let aspiration_score = metadata.lock().unwrap().last().unwrap().score;
+        assert!(matches!(aspiration_score, Score::Centipawns(_)));
+        engine.prepare_search(&board);
+        engine.new_game();
+        let full_window_score = engine.alpha_beta(5, 0, i32::MIN + 1, i32::MAX, true, false);
+        assert_eq!(aspiration_score, search_score(full_window_score));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::core::{Color, Move, Piece};
+
+use super::ab_engine::MAX_PLY;
+
+/// The moved piece and destination square of a move, which is the context used by countermoves and
+/// continuation history. Promotions use the pawn as the moved piece.
+pub type PieceTo = (Piece, u8);
+
+/// History scores stay within this bound, so a few lucky cutoffs can not dominate the ordering
+pub const MAX_HISTORY: i32 = 16_384;
+/// Largest change of a history score from a single cutoff
+const MAX_HISTORY_BONUS: i32 = 1_200;
+
+const PIECE_TO_COUNT: usize = 12 * 64;
+
+fn piece_to_index((piece, to): PieceTo) -> usize {
+    return piece.to_u8() as usize * 64 + to as usize;
+}
+
+/// Move ordering statistics collected while searching, used to order quiet moves.
+/// Each search thread has its own history.
+pub struct MoveHistory {
+    /// Two quiet moves per ply which recently caused a cutoff at that ply
+    killers: Vec<[Move; 2]>,
+    /// Butterfly history indexed by color, from square and to square
+    butterfly: Vec<i16>,
+    /// Quiet move which refuted the previous move, indexed by the piece and square of the previous move
+    countermoves: Vec<Move>,
+    /// History of quiet moves indexed by the previous move and by the move itself
+    continuation: Vec<i16>
+}
+
+impl MoveHistory {
+    pub fn new() -> MoveHistory {
+        return MoveHistory {
+            killers: vec![[Move::empty(); 2]; MAX_PLY + 1],
+            butterfly: vec![0; 2 * 64 * 64],
+            countermoves: vec![Move::empty(); PIECE_TO_COUNT],
+            continuation: vec![0; PIECE_TO_COUNT * PIECE_TO_COUNT]
+        };
+    }
+
+    /// Forget everything, used when a new game starts
+    pub fn clear(&mut self) {
+        *self = MoveHistory::new();
+    }
+
+    /// Prepare for a new search. Killers are specific to the previous position and are cleared,
+    /// history scores are halved so the statistics of the current position weigh more.
+    pub fn new_search(&mut self) {
+        self.killers.fill([Move::empty(); 2]);
+        for score in self.butterfly.iter_mut().chain(self.continuation.iter_mut()) {
+            *score /= 2;
+        }
+    }
+
+    /// Killer moves of `ply`, `Move::empty()` for unused slots
+    pub fn killers(&self, ply: usize) -> [Move; 2] {
+        return self.killers[ply];
+    }
+
+    /// Countermove of the previous move, `Move::empty()` if there is none
+    pub fn countermove(&self, previous: Option<PieceTo>) -> Move {
+        return match previous {
+            Some(previous) => self.countermoves[piece_to_index(previous)],
+            None => Move::empty()
+        };
+    }
+
+    /// Ordering score of the quiet move `mv` of `piece`, given the moves one and two plies earlier
+    pub fn quiet_score(&self, color: Color, mv: &Move, piece: Piece, previous: [Option<PieceTo>; 2]) -> i32 {
+        let mut score = self.butterfly[butterfly_index(color, mv)] as i32;
+        for previous in previous.into_iter().flatten() {
+            score += self.continuation[continuation_index(previous, (piece, mv.to))] as i32;
+        }
+        return score;
+    }
+
+    /// Update the statistics after the quiet move `best_move` caused a cutoff at `ply`.
+    /// The quiet moves in `tried` were searched before it without causing a cutoff and are penalized.
+    pub fn update_quiet_cutoff(
+        &mut self, color: Color, ply: usize, depth: usize, best_move: (Move, Piece),
+        tried: &[(Move, Piece)], previous: [Option<PieceTo>; 2]
+    ) {
+        let (mv, _) = best_move;
+        if self.killers[ply][0] != mv {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = mv;
+        }
+        if let Some(previous) = previous[0] {
+            self.countermoves[piece_to_index(previous)] = mv;
+        }
+
+        let bonus = (depth * depth).min(MAX_HISTORY_BONUS as usize) as i32;
+        self.update_move(color, best_move, bonus, previous);
+        for tried_move in tried {
+            self.update_move(color, *tried_move, -bonus, previous);
+        }
+    }
+
+    fn update_move(&mut self, color: Color, (mv, piece): (Move, Piece), bonus: i32, previous: [Option<PieceTo>; 2]) {
+        update_score(&mut self.butterfly[butterfly_index(color, &mv)], bonus);
+        for previous in previous.into_iter().flatten() {
+            update_score(&mut self.continuation[continuation_index(previous, (piece, mv.to))], bonus);
+        }
+    }
+}
+
+fn butterfly_index(color: Color, mv: &Move) -> usize {
+    return (color as usize * 64 + mv.from as usize) * 64 + mv.to as usize;
+}
+
+fn continuation_index(previous: PieceTo, current: PieceTo) -> usize {
+    return piece_to_index(previous) * PIECE_TO_COUNT + piece_to_index(current);
+}
+
+/// Move the score towards the bonus, scores close to `MAX_HISTORY` change less
+fn update_score(score: &mut i16, bonus: i32) {
+    let current = *score as i32;
+    *score = (current + bonus - current * bonus.abs() / MAX_HISTORY) as i16;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::{Color, Move, Piece};
+    use super::{MoveHistory, MAX_HISTORY};
+
+    #[test]
+    fn test_move_history() {
+        let board = Board::new(Arc::new(BOARD_CONSTANT_STATE.clone()));
+        let knight_move = Move::from_algebraic(&board, "g1f3");
+        let pawn_move = Move::from_algebraic(&board, "e2e4");
+        let previous = [Some((Piece::BlackPawn, 28)), None];
+        let mut history = MoveHistory::new();
+
+        history.update_quiet_cutoff(Color::White, 3, 4, (knight_move, Piece::WhiteKnight), &[(pawn_move, Piece::WhitePawn)], previous);
+        assert_eq!(history.killers(3), [knight_move, Move::empty()]);
+        assert_eq!(history.killers(2), [Move::empty(); 2]);
+        assert_eq!(history.countermove(previous[0]), knight_move);
+        assert_eq!(history.countermove(None), Move::empty());
+        // Butterfly and continuation history both count
+        assert_eq!(history.quiet_score(Color::White, &knight_move, Piece::WhiteKnight, previous), 32);
+        assert_eq!(history.quiet_score(Color::White, &knight_move, Piece::WhiteKnight, [None, None]), 16);
+        assert_eq!(history.quiet_score(Color::White, &pawn_move, Piece::WhitePawn, previous), -32);
+        assert_eq!(history.quiet_score(Color::Black, &knight_move, Piece::WhiteKnight, [None, None]), 0);
+
+        // A repeated killer is not stored twice
+        history.update_quiet_cutoff(Color::White, 3, 4, (pawn_move, Piece::WhitePawn), &[], previous);
+        history.update_quiet_cutoff(Color::White, 3, 4, (pawn_move, Piece::WhitePawn), &[], previous);
+        assert_eq!(history.killers(3), [pawn_move, knight_move]);
+
+        // Scores are bounded, no matter how often a move causes a cutoff
+        for _ in 0..1000 {
+            history.update_quiet_cutoff(Color::White, 3, 40, (knight_move, Piece::WhiteKnight), &[], [None, None]);
+        }
+        let score = history.quiet_score(Color::White, &knight_move, Piece::WhiteKnight, [None, None]);
+        assert!(score > MAX_HISTORY / 2 && score <= MAX_HISTORY);
+
+        history.new_search();
+        assert_eq!(history.killers(3), [Move::empty(); 2]);
+        assert_eq!(history.quiet_score(Color::White, &knight_move, Piece::WhiteKnight, [None, None]), score / 2);
+    }
+}
@@ -1,124 +1,266 @@
-use crate::core::{bitboard::{constants::WHITE, Board}, move_list::MoveList, Color, Move};
+use crate::core::{bitboard::Board, move_list::{MoveList, MAX_MOVE_COUNT}, Move, Piece};
 
-use super::pv::PrincipalVariation;
+use super::history::{MoveHistory, PieceTo};
 
-fn eval_move(board: &Board, mv: &Move) -> i32 {
-    if mv.is_quiet() {
-        // If move is quiet, give -MAX
-        return i32::MIN;
-    }
-    else {
-        // If move is capture, give CAPTURED - TAKING
-        let from_piece = board.get_piece(mv.from);
-        return mv.captured.eval_score() - from_piece.eval_score();
-    }
+/// Returns true if `lhs` and `rhs` move the same piece in the same way. The stored board state
+/// of the moves (castling, ep, quiet) may differ, for example for moves from the transposition table.
+pub fn is_same_move(lhs: &Move, rhs: &Move) -> bool {
+    return lhs.from == rhs.from && lhs.to == rhs.to && lhs.promotion == rhs.promotion;
 }
 
-fn eval_move_with_pv(board: &Board, mv: &Move, pv_move: &Move) -> i32 {
-    if mv == pv_move {
-        return i32::MAX;
-    }
-    return eval_move(board, mv);
+/// Captures, en passant captures and promotions. Drops are quiet moves.
+pub fn is_noisy_move(board: &Board, mv: &Move) -> bool {
+    return !mv.is_quiet() || (mv.promotion != Piece::Empty && !mv.is_drop()) || is_en_passant(board, mv);
+}
+
+fn is_en_passant(board: &Board, mv: &Move) -> bool {
+    let piece = board.get_piece(mv.from);
+    return (piece == Piece::WhitePawn || piece == Piece::BlackPawn) && mv.captured == Piece::Empty && mv.from % 8 != mv.to % 8;
 }
 
-pub fn sort_moves_simple(board: &Board, move_list: &mut MoveList, depth: usize, previous_pv: &mut Vec<Move>) {
-    let mut moves = move_list.get_underlying_vec();
-    let mut move_scores = if previous_pv.len() >= depth as usize {
-        let pv_move = previous_pv.pop().unwrap();
-        moves.iter().map(|mv| eval_move_with_pv(board, mv, &pv_move)).collect()
+/// Ordering score of a capture or promotion. Captures which lose material according to the static
+/// exchange evaluation get a negative score, the others are ordered by victim and then by attacker.
+fn noisy_score(board: &Board, mv: &Move) -> i32 {
+    let attacker = board.get_piece(mv.from).eval_score();
+    let mvv_lva = mv.captured.eval_score() * 16 - attacker / 16 + mv.promotion.eval_score();
+    // En passant and quiet promotions never lose material on the target square itself
+    if mv.captured != Piece::Empty && board.static_exchange_evaluation(mv.from, mv.to) < 0 {
+        return mvv_lva - BAD_CAPTURE_OFFSET;
     }
-    else {
-        moves.iter().map(|mv| eval_move(board, mv)).collect()
-    };
-    insertion_sort(&mut move_scores, &mut moves);
+    return mvv_lva;
 }
 
-fn insertion_sort(list_keys: &mut Vec<i32>, list_values: &mut Vec<Move>) {
-    for i in 1..list_keys.len() {
-        let mut j = i;
-        let key = list_keys[i];
-        let value = list_values[i]; 
+/// Subtracted from the score of losing captures, so they sort below all other captures
+const BAD_CAPTURE_OFFSET: i32 = 1 << 24;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Stage {
+    PvMove,
+    HashMove,
+    ScoreCaptures,
+    GoodCaptures,
+    Killers,
+    Countermove,
+    Quiets,
+    BadCaptures,
+    Done
+}
+
+/// Returns the moves of a `MoveList` one by one, in order of their likelihood of being good:
+/// the previous principal variation move, the transposition table move, captures which do not lose
+/// material, killer moves, the countermove, other quiet moves ordered by history and finally losing captures.
+///
+/// Moves are only scored when their stage is reached and are picked by selection instead of sorting the
+/// whole list, so little work is wasted when one of the first moves causes a cutoff.
+pub struct MovePicker<'a> {
+    moves: &'a mut Vec<Move>,
+    scores: [i32; MAX_MOVE_COUNT],
+    stage: Stage,
+    pv_move: Option<Move>,
+    hash_move: Option<Move>,
+    killers: [Move; 2],
+    countermove: Move,
+    /// Moves before this index have been returned
+    next_index: usize,
+    /// Captures and promotions are kept in `capture_index..quiet_start`, quiet moves after them
+    capture_index: usize,
+    quiet_start: usize,
+    quiet_index: usize,
+    killer_index: usize
+}
 
-        while j > 0 && list_keys[j - 1] < key {
-            list_keys[j] = list_keys[j - 1];
-            list_values[j] = list_values[j - 1];
-            j -= 1;
+impl<'a> MovePicker<'a> {
+    pub fn new(moves: &'a mut MoveList, pv_move: Option<Move>, hash_move: Option<Move>) -> MovePicker<'a> {
+        return MovePicker {
+            moves: moves.get_underlying_vec(),
+            scores: [0; MAX_MOVE_COUNT],
+            stage: Stage::PvMove,
+            pv_move,
+            hash_move,
+            killers: [Move::empty(); 2],
+            countermove: Move::empty(),
+            next_index: 0,
+            capture_index: 0,
+            quiet_start: 0,
+            quiet_index: 0,
+            killer_index: 0
+        };
+    }
+
+    /// Search the killer moves and the countermove right after the good captures
+    pub fn with_quiet_moves(mut self, killers: [Move; 2], countermove: Move) -> MovePicker<'a> {
+        self.killers = killers;
+        self.countermove = countermove;
+        return self;
+    }
+
+    /// Returns the next move to search, or `None` if all moves were returned.
+    /// `previous` are the moves one and two plies earlier, used for continuation history.
+    pub fn next(&mut self, board: &Board, history: &MoveHistory, previous: [Option<PieceTo>; 2]) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::PvMove => {
+                    self.stage = Stage::HashMove;
+                    if let Some(mv) = self.pv_move.and_then(|pv_move| self.take_matching(pv_move)) {
+                        return Some(mv);
+                    }
+                }
+                Stage::HashMove => {
+                    self.stage = Stage::ScoreCaptures;
+                    if let Some(mv) = self.hash_move.and_then(|hash_move| self.take_matching(hash_move)) {
+                        return Some(mv);
+                    }
+                }
+                Stage::ScoreCaptures => {
+                    self.stage = Stage::GoodCaptures;
+                    self.partition_captures(board);
+                }
+                Stage::GoodCaptures => {
+                    match self.pick_best(self.capture_index, self.quiet_start) {
+                        Some(index) if self.scores[index] > -BAD_CAPTURE_OFFSET / 2 => {
+                            return Some(self.take_capture(index));
+                        }
+                        _ => self.stage = Stage::Killers
+                    }
+                }
+                Stage::Killers => {
+                    if self.killer_index == self.killers.len() {
+                        self.stage = Stage::Countermove;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_index];
+                    self.killer_index += 1;
+                    if let Some(mv) = self.take_matching_quiet(killer) {
+                        return Some(mv);
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::Quiets;
+                    if let Some(mv) = self.take_matching_quiet(self.countermove) {
+                        return Some(mv);
+                    }
+                    let color = board.get_current_player();
+                    for index in self.quiet_index..self.moves.len() {
+                        let mv = self.moves[index];
+                        self.scores[index] = history.quiet_score(color, &mv, board.moving_piece(&mv), previous);
+                    }
+                }
+                Stage::Quiets => {
+                    match self.pick_best(self.quiet_index, self.moves.len()) {
+                        Some(index) => {
+                            self.swap(index, self.quiet_index);
+                            self.quiet_index += 1;
+                            return Some(self.moves[self.quiet_index - 1]);
+                        }
+                        None => self.stage = Stage::BadCaptures
+                    }
+                }
+                Stage::BadCaptures => {
+                    match self.pick_best(self.capture_index, self.quiet_start) {
+                        Some(index) => return Some(self.take_capture(index)),
+                        None => self.stage = Stage::Done
+                    }
+                }
+                Stage::Done => return None
+            }
         }
-        list_keys[j] = key;
-        list_values[j] = value;
+    }
+
+    /// Returns the move matching `target` which has not been returned yet, moving it out of the remaining moves
+    fn take_matching(&mut self, target: Move) -> Option<Move> {
+        let index = (self.next_index..self.moves.len()).find(|index| is_same_move(&self.moves[*index], &target))?;
+        self.moves[self.next_index..=index].rotate_right(1);
+        self.next_index += 1;
+        return Some(self.moves[self.next_index - 1]);
+    }
+
+    fn take_matching_quiet(&mut self, target: Move) -> Option<Move> {
+        let index = (self.quiet_index..self.moves.len()).find(|index| is_same_move(&self.moves[*index], &target))?;
+        self.swap(index, self.quiet_index);
+        self.quiet_index += 1;
+        return Some(self.moves[self.quiet_index - 1]);
+    }
+
+    fn take_capture(&mut self, index: usize) -> Move {
+        self.swap(index, self.capture_index);
+        self.capture_index += 1;
+        return self.moves[self.capture_index - 1];
+    }
+
+    /// Move the captures and promotions in front of the quiet moves and score them
+    fn partition_captures(&mut self, board: &Board) {
+        let mut quiet_start = self.next_index;
+        for index in self.next_index..self.moves.len() {
+            if is_noisy_move(board, &self.moves[index]) {
+                self.moves.swap(index, quiet_start);
+                self.scores[quiet_start] = noisy_score(board, &self.moves[quiet_start]);
+                quiet_start += 1;
+            }
+        }
+        self.capture_index = self.next_index;
+        self.quiet_start = quiet_start;
+        self.quiet_index = quiet_start;
+    }
+
+    fn pick_best(&self, start: usize, end: usize) -> Option<usize> {
+        return (start..end).max_by_key(|index| (self.scores[*index], std::cmp::Reverse(*index)));
+    }
+
+    fn swap(&mut self, lhs: usize, rhs: usize) {
+        self.moves.swap(lhs, rhs);
+        self.scores.swap(lhs, rhs);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::rc::Rc;
+    use std::sync::Arc;
 
     use crate::core::bitboard::Board;
     use crate::core::move_list::MoveList;
-    use crate::core::{Move, Piece};
+    use crate::core::{Color, Move, Piece};
     use crate::core::tests::BOARD_CONSTANT_STATE;
-    use crate::engine::move_sorting::sort_moves_simple;
+    use crate::engine::history::MoveHistory;
+    use super::MovePicker;
 
-    use super::insertion_sort;
+    fn pick_all(board: &Board, picker: &mut MovePicker, history: &MoveHistory) -> Vec<String> {
+        let mut moves = Vec::new();
+        while let Some(mv) = picker.next(board, history, [None, None]) {
+            moves.push(mv.to_algebraic());
+        }
+        return moves;
+    }
 
     #[test]
-    fn test_move_sort() {
-        let constant_state = Rc::new(BOARD_CONSTANT_STATE.clone());
-        let board = Board::new(constant_state);
-        assert_eq!("a2a3", Move::to_algebraic(&Move::from_algebraic(&board, "a2a3")));
-
-        // Test insertion sort
-        let mut moves = vec![Move::new(&board, 0, 0, Piece::Empty, Piece::Empty); 5];
-        let mut scores = vec![5, 3, 1, 2, 4];
-        insertion_sort(&mut scores, &mut moves);
-        assert_eq!(scores, vec![5,4,3,2,1]);
-
-        // Test move sorting
-        let board = Board::from_fen("7k/8/8/8/8/P1Q5/1b6/2R4K b - - 0 1", Rc::new(BOARD_CONSTANT_STATE.clone()));
-        let moves = vec![
-            Move::from_algebraic(&board, "b2a3"), // Pawn capture
-            Move::from_algebraic(&board, "b2c3"), // Queen capture
-            Move::from_algebraic(&board, "b2c1"), // Rook capture
-            Move::from_algebraic(&board, "b2a1") // Quiet capture
-        ];
-        let mut move_list = MoveList::from_vec(moves);
-        sort_moves_simple(&board, &mut move_list, 3, &mut Vec::new());
-        let move_list_algebraic: Vec<String> = move_list.to_vec().iter().map(|mv| mv.to_algebraic()).collect();
-        assert_eq!(move_list_algebraic, ["b2c3", "b2c1", "b2a3", "b2a1"]);
-
-        // Test move sorting with principal variation
-        let board = Board::from_fen("7k/8/8/8/8/P1Q5/1b6/2R4K b - - 0 1", Rc::new(BOARD_CONSTANT_STATE.clone()));
-        let moves = vec![
-            Move::from_algebraic(&board, "b2a3"), // Pawn capture
-            Move::from_algebraic(&board, "b2c3"), // Queen capture
-            Move::from_algebraic(&board, "b2c1"), // Rook capture
-            Move::from_algebraic(&board, "b2a1") // Quiet capture
-        ];
-        let mut pv = vec![
-            Move::from_algebraic(&board, "b2a3"),
-            Move::from_algebraic(&board, "b2c3"), 
-            Move::from_algebraic(&board, "b2c1")
-        ];
-        let mut move_list = MoveList::from_vec(moves);
-        sort_moves_simple(&board, &mut move_list, 3, &mut pv);
-        
-        let move_list_algebraic: Vec<String> = move_list.to_vec().iter().map(|mv| mv.to_algebraic()).collect();
-        assert_eq!(move_list_algebraic, ["b2c1", "b2c3", "b2a3", "b2a1"]);
-        assert_eq!(pv.len(), 2);
-
-        sort_moves_simple(&board, &mut move_list, 2, &mut pv);
-        let move_list_algebraic: Vec<String> = move_list.to_vec().iter().map(|mv| mv.to_algebraic()).collect();
-        assert_eq!(move_list_algebraic, ["b2c3", "b2c1", "b2a3", "b2a1"]);
-        assert_eq!(pv.len(), 1);
-
-        sort_moves_simple(&board, &mut move_list, 1, &mut pv);
-        let move_list_algebraic: Vec<String> = move_list.to_vec().iter().map(|mv| mv.to_algebraic()).collect();
-        assert_eq!(move_list_algebraic, ["b2a3", "b2c3", "b2c1", "b2a1"]);
-        assert_eq!(pv.len(), 0);
-
-        // No moves left in PV, should just return regular movesort
-        sort_moves_simple(&board, &mut move_list, 3, &mut pv);
-        let move_list_algebraic: Vec<String> = move_list.to_vec().iter().map(|mv| mv.to_algebraic()).collect();
-        assert_eq!(move_list_algebraic, ["b2c3", "b2c1", "b2a3", "b2a1"]);
+    fn test_move_picker() {
+        let board = Board::from_fen("7k/8/8/8/8/P1Q5/1b6/2R4K b - - 0 1", Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+        let history = MoveHistory::new();
+        let algebraic = ["b2a3", "b2c3", "b2c1", "b2a1", "h8g8"];
+        let moves: Vec<Move> = algebraic.iter().map(|mv| Move::from_algebraic(&board, mv)).collect();
+
+        // Captures are ordered by victim, quiet moves follow and the pawn capture loses the bishop, so it comes last
+        let mut move_list = MoveList::from_vec(moves.clone());
+        let mut picker = MovePicker::new(&mut move_list, None, None);
+        assert_eq!(pick_all(&board, &mut picker, &history), ["b2c3", "b2c1", "b2a1", "h8g8", "b2a3"]);
+
+        // The previous principal variation move comes first, then the hash move, killers and the countermove
+        let mut move_list = MoveList::from_vec(moves.clone());
+        let mut picker = MovePicker::new(&mut move_list, Some(moves[0]), Some(moves[2]))
+            .with_quiet_moves([moves[4], Move::empty()], moves[2]);
+        assert_eq!(pick_all(&board, &mut picker, &history), ["b2a3", "b2c1", "b2c3", "h8g8", "b2a1"]);
+
+        // Quiet moves are ordered by history
+        let mut history = MoveHistory::new();
+        history.update_quiet_cutoff(Color::Black, 0, 3, (moves[4], Piece::BlackKing), &[], [None, None]);
+        let mut move_list = MoveList::from_vec(moves.clone());
+        let mut picker = MovePicker::new(&mut move_list, None, Some(Move::empty()));
+        assert_eq!(pick_all(&board, &mut picker, &history), ["b2c3", "b2c1", "h8g8", "b2a1", "b2a3"]);
+
+        // Every move is returned exactly once, even if the pv move, hash move and killers are the same
+        let mut move_list = MoveList::from_vec(moves.clone());
+        let mut picker = MovePicker::new(&mut move_list, Some(moves[3]), Some(moves[3]))
+            .with_quiet_moves([moves[3], moves[3]], moves[3]);
+        assert_eq!(pick_all(&board, &mut picker, &history), ["b2a1", "b2c3", "b2c1", "h8g8", "b2a3"]);
+        assert_eq!(picker.next(&board, &history, [None, None]), None);
     }
-}
\ No newline at end of file
+}
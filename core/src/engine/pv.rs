@@ -1,68 +1,124 @@
-use crate::core::Move;
+use crate::core::{bitboard::Board, move_list::MoveList, Move};
 
+/// Triangular principal variation table indexed by ply. Row `ply` holds the best line found from
+/// that ply, which is copied one row up when a move at the previous ply becomes the best move.
+/// The length of a row can differ from the remaining depth because of extensions and reductions.
 pub struct PrincipalVariation {
     pv_table: Vec<Move>,
-    max_depth: usize
+    pv_length: Vec<usize>,
+    max_ply: usize
 }
 
 impl PrincipalVariation {
     pub fn new() -> PrincipalVariation {
         return PrincipalVariation { 
             pv_table: Vec::new(), 
-            max_depth: 0 
+            pv_length: Vec::new(),
+            max_ply: 0 
         }
     }
 
-    pub fn set_max_depth(&mut self, depth: usize) {
-        self.pv_table.resize(depth * depth, Move::empty());
-        self.max_depth = depth;
+    pub fn set_max_ply(&mut self, max_ply: usize) {
+        self.pv_table.resize(max_ply * max_ply, Move::empty());
+        self.pv_length.resize(max_ply + 1, 0);
+        self.max_ply = max_ply;
     }
 
-    pub fn set_best_move(&mut self, depth: usize, mv: Move) {
-        let index = (self.max_depth - depth) * self.max_depth;
+    /// Clear the line of `ply`, called when a node at `ply` is entered
+    pub fn clear_ply(&mut self, ply: usize) {
+        self.pv_length[ply] = 0;
+    }
+
+    pub fn set_best_move(&mut self, ply: usize, mv: Move) {
+        let index = ply * self.max_ply;
 
-        // Update the move for the current depth
+        // Update the move for the current ply
         self.pv_table[index] = mv;
-        if depth == 1 {
-            return;
-        }
 
-        // Propagate from deeper depth to this depth
-        for next_index in 0..depth {
-            self.pv_table[index + next_index + 1] = self.pv_table[index + self.max_depth + next_index];
+        // Propagate the line of the next ply to this ply
+        let next_length = if ply + 1 < self.max_ply { self.pv_length[ply + 1] } else { 0 };
+        for next_index in 0..next_length {
+            self.pv_table[index + next_index + 1] = self.pv_table[index + self.max_ply + next_index];
         }
+        self.pv_length[ply] = next_length + 1;
     }
 
     pub fn get_pv(&self) -> Vec<Move> {
-        return self.pv_table[0..self.max_depth].to_vec();
+        return self.pv_table[0..self.pv_length[0]].to_vec();
+    }
+}
+
+/// Returns the longest prefix of `pv` which can be played from `board`. The pv table can contain
+/// stale moves from earlier branches or unfilled entries when the search was cut short.
+pub fn legal_pv_prefix(board: &Board, pv: &[Move]) -> Vec<Move> {
+    let mut board = board.clone();
+    let mut legal_moves = MoveList::empty();
+    let mut legal_pv = Vec::with_capacity(pv.len());
+    for pv_move in pv {
+        board.get_moves(&mut legal_moves, false);
+        let legal_move = legal_moves.iter().find(|mv| 
+            mv.from == pv_move.from && mv.to == pv_move.to && mv.promotion == pv_move.promotion
+        );
+        match legal_move {
+            Some(mv) => {
+                board.make_move(mv);
+                legal_pv.push(*mv);
+            }
+            None => break
+        }
     }
+    return legal_pv;
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
     use crate::core::{Move, Piece};
-    use super::PrincipalVariation;
+    use super::{legal_pv_prefix, PrincipalVariation};
 
     #[test]
     fn test_pv() {
         let mut principal_variation = PrincipalVariation::new();
-        principal_variation.set_max_depth(3);
+        principal_variation.set_max_ply(3);
         let mv1 = Move {from: 1, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
         let mv2 = Move {from: 2, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
         let mv3 = Move {from: 3, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
         let mv4 = Move {from: 4, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
         let mv5 = Move {from: 5, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
 
-        principal_variation.set_best_move(1, mv3);
-        principal_variation.set_best_move(2, mv2);
-        principal_variation.set_best_move(3, mv1);
+        principal_variation.clear_ply(2);
+        principal_variation.set_best_move(2, mv3);
+        principal_variation.set_best_move(1, mv2);
+        principal_variation.set_best_move(0, mv1);
         assert_eq!(vec![mv1, mv2, mv3], principal_variation.get_pv());
 
-        principal_variation.set_best_move(1, mv4);
-        principal_variation.set_best_move(1, mv1);
-        principal_variation.set_best_move(2, mv5);
-        principal_variation.set_best_move(2, mv2);
-        principal_variation.set_best_move(3, mv3);
+        principal_variation.set_best_move(2, mv4);
+        principal_variation.set_best_move(2, mv1);
+        principal_variation.set_best_move(1, mv5);
+        principal_variation.set_best_move(1, mv2);
+        principal_variation.set_best_move(0, mv3);
         assert_eq!(vec![mv3, mv2, mv1], principal_variation.get_pv());
+
+        // A line which ends early, for example in a reduced or drawn node, gives a shorter pv
+        principal_variation.clear_ply(1);
+        principal_variation.set_best_move(0, mv5);
+        assert_eq!(vec![mv5], principal_variation.get_pv());
+    }
+
+    #[test]
+    fn test_legal_pv_prefix() {
+        let board = Board::new(Arc::new(BOARD_CONSTANT_STATE.clone()));
+        let pv = vec![
+            Move::from_algebraic(&board, "e2e4"),
+            Move::from_algebraic(&board, "e7e5"),
+            Move::from_algebraic(&board, "e4e5"), // Illegal, pawn is blocked
+            Move::from_algebraic(&board, "g1f3"),
+        ];
+        let legal_pv: Vec<String> = legal_pv_prefix(&board, &pv).iter().map(|mv| mv.to_algebraic()).collect();
+        assert_eq!(legal_pv, ["e2e4", "e7e5"]);
+        assert_eq!(legal_pv_prefix(&board, &[Move::empty()]), vec![]);
     }
 }
\ No newline at end of file
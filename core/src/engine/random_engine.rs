@@ -1,5 +1,5 @@
 
-use super::{Engine, LogCallback, SearchMetadataCallback, ShouldAbortSearchCallback};
+use super::{Engine, SearchLimits, LogCallback, SearchMetadataCallback, ShouldAbortSearchCallback};
 use crate::core::bitboard::Board;
 use crate::core::*;
 use move_list::MoveList;
@@ -16,7 +16,7 @@ pub struct RandomEngine {
 
 #[allow(unused)]
 impl Engine for RandomEngine {
-    fn search(&mut self, board: &Board) -> Vec<Move> {
+    fn search(&mut self, board: &Board, _limits: &SearchLimits) -> Vec<Move> {
         let mut moves = MoveList::empty();
         board.get_moves(&mut moves, false);
         let pv = vec!(*moves.to_vec().choose(&mut rand::thread_rng()).unwrap());
@@ -0,0 +1,133 @@
+/// Selectivity techniques used by the alpha beta search. Each one can be switched off with an
+/// engine option, which makes it possible to test them in isolation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SearchFeatures {
+    /// Skip a turn and prune if the reduced search still fails high
+    pub null_move_pruning: bool,
+    /// Search quiet moves late in the move list with reduced depth
+    pub late_move_reductions: bool,
+    /// Search moves after the first with a zero window, re-search if they beat alpha
+    pub principal_variation_search: bool,
+    /// Search one ply deeper when in check
+    pub check_extensions: bool,
+    /// Return the static evaluation at shallow depths if it is far above beta
+    pub reverse_futility_pruning: bool,
+    /// Skip quiet moves at shallow depths if the static evaluation is far below alpha
+    pub futility_pruning: bool
+}
+
+/// Names of the engine options, in the order of the fields of `SearchFeatures`
+pub const FEATURE_OPTION_NAMES: [&str; 6] = [
+    "NullMovePruning",
+    "LateMoveReductions",
+    "PrincipalVariationSearch",
+    "CheckExtensions",
+    "ReverseFutilityPruning",
+    "FutilityPruning"
+];
+
+/// Null move pruning is only tried with at least this much depth left
+pub const NULL_MOVE_MIN_DEPTH: usize = 3;
+/// The null move search of nodes with at least this much depth left is verified with a normal search
+pub const NULL_MOVE_VERIFICATION_DEPTH: usize = 10;
+/// Late move reductions are only applied with at least this much depth left
+pub const LMR_MIN_DEPTH: usize = 3;
+/// Amount of moves searched at full depth before late move reductions start
+pub const LMR_FULL_DEPTH_MOVES: usize = 3;
+/// Maximum depth left for reverse futility pruning
+pub const REVERSE_FUTILITY_MAX_DEPTH: usize = 6;
+/// Margin per depth left for reverse futility pruning
+pub const REVERSE_FUTILITY_MARGIN: i32 = 120;
+/// Maximum depth left for futility pruning
+pub const FUTILITY_MAX_DEPTH: usize = 2;
+/// Margins for futility pruning, indexed by depth left
+pub const FUTILITY_MARGINS: [i32; FUTILITY_MAX_DEPTH + 1] = [0, 200, 500];
+
+impl Default for SearchFeatures {
+    fn default() -> SearchFeatures {
+        return SearchFeatures {
+            null_move_pruning: true,
+            late_move_reductions: true,
+            principal_variation_search: true,
+            check_extensions: true,
+            reverse_futility_pruning: true,
+            futility_pruning: true
+        };
+    }
+}
+
+impl SearchFeatures {
+    /// All features switched off, giving a plain alpha beta search
+    pub fn none() -> SearchFeatures {
+        return SearchFeatures {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            principal_variation_search: false,
+            check_extensions: false,
+            reverse_futility_pruning: false,
+            futility_pruning: false
+        };
+    }
+
+    fn feature_mut(&mut self, name: &str) -> Option<&mut bool> {
+        return match name.to_lowercase().as_str() {
+            "nullmovepruning" => Some(&mut self.null_move_pruning),
+            "latemovereductions" => Some(&mut self.late_move_reductions),
+            "principalvariationsearch" => Some(&mut self.principal_variation_search),
+            "checkextensions" => Some(&mut self.check_extensions),
+            "reversefutilitypruning" => Some(&mut self.reverse_futility_pruning),
+            "futilitypruning" => Some(&mut self.futility_pruning),
+            _ => None
+        };
+    }
+
+    /// Returns true if `name` is the option name of a feature
+    pub fn is_feature(name: &str) -> bool {
+        return SearchFeatures::default().feature_mut(name).is_some();
+    }
+
+    /// Switch a feature on or off from an engine option with the value `true` or `false`
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let enabled = match value.to_lowercase().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => return Err(format!("Invalid value for {}: {}", name, value))
+        };
+        let feature = self.feature_mut(name).ok_or_else(|| format!("Unsupported option: {}", name))?;
+        *feature = enabled;
+        return Ok(());
+    }
+}
+
+/// Depth reduction for the late move with index `move_index` in the move list
+pub fn late_move_reduction(depth: usize, move_index: usize) -> usize {
+    let reduction = 0.75 + (depth as f64).ln() * (move_index as f64).ln() / 2.25;
+    // Always leave at least one ply to search
+    return (reduction as usize).clamp(1, depth.saturating_sub(2).max(1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{late_move_reduction, SearchFeatures, FEATURE_OPTION_NAMES};
+
+    #[test]
+    fn test_search_features() {
+        let mut features = SearchFeatures::default();
+        for name in FEATURE_OPTION_NAMES {
+            assert!(SearchFeatures::is_feature(name));
+            features.set(name, "false").unwrap();
+        }
+        assert_eq!(features, SearchFeatures::none());
+        features.set("checkextensions", "TRUE").unwrap();
+        assert!(features.check_extensions);
+
+        assert!(features.set("NullMovePruning", "maybe").is_err());
+        assert!(features.set("Hash", "true").is_err());
+        assert!(!SearchFeatures::is_feature("Hash"));
+
+        // Reductions grow with depth and move index, but never reduce into quiescence search
+        assert_eq!(late_move_reduction(3, 3), 1);
+        assert!(late_move_reduction(20, 40) > late_move_reduction(6, 4));
+        assert!((3..64).all(|depth| late_move_reduction(depth, 200) < depth));
+    }
+}
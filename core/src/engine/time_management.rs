@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use crate::core::Color;
+use super::SearchLimits;
+
+/// Search time used when the search has no limits at all
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+/// Time reserved for communication with the GUI, to avoid losing on time
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+/// Assumed amount of moves left until the next time control if the GUI does not send `movestogo`
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeBudget {
+    /// No new iteration is started after this much time has passed
+    pub soft_limit: Option<Duration>,
+    /// The search is aborted after this much time has passed
+    pub hard_limit: Option<Duration>
+}
+
+impl TimeBudget {
+    pub fn unlimited() -> TimeBudget {
+        return TimeBudget { soft_limit: None, hard_limit: None };
+    }
+
+    pub fn fixed(time: Duration) -> TimeBudget {
+        return TimeBudget { soft_limit: Some(time), hard_limit: Some(time) };
+    }
+}
+
+/// Decide how much time to spend on a search for `player` given the search `limits`.
+pub fn calculate_time_budget(limits: &SearchLimits, player: Color) -> TimeBudget {
+    if limits.infinite {
+        return TimeBudget::unlimited();
+    }
+    if let Some(move_time) = limits.move_time {
+        return TimeBudget::fixed(move_time.saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1)));
+    }
+    let (time_left, increment) = match player {
+        Color::White => (limits.white_time, limits.white_increment),
+        Color::Black => (limits.black_time, limits.black_increment)
+    };
+    if let Some(time_left) = time_left {
+        let increment = increment.unwrap_or(Duration::ZERO);
+        let moves_to_go = limits.moves_to_go.map_or(DEFAULT_MOVES_TO_GO, |moves| moves.clamp(1, 1000) as u32);
+        let available = time_left.saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1));
+        // Never use more than three quarters of the remaining clock on one move
+        let max_time = available * 3 / 4;
+        let soft_limit = (available / moves_to_go + increment * 3 / 4).min(max_time);
+        let hard_limit = (soft_limit * 3).min(max_time);
+        return TimeBudget { soft_limit: Some(soft_limit), hard_limit: Some(hard_limit) };
+    }
+    if limits.depth.is_some() || limits.nodes.is_some() {
+        return TimeBudget::unlimited();
+    }
+    return TimeBudget::fixed(DEFAULT_MOVE_TIME);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::core::Color;
+    use crate::engine::SearchLimits;
+    use super::{calculate_time_budget, TimeBudget, DEFAULT_MOVE_TIME, MOVE_OVERHEAD};
+
+    #[test]
+    fn test_time_budget() {
+        // No limits uses the default move time, depth and node limits have no time limit
+        assert_eq!(calculate_time_budget(&SearchLimits::default(), Color::White), TimeBudget::fixed(DEFAULT_MOVE_TIME));
+        assert_eq!(calculate_time_budget(&SearchLimits::from_depth(5), Color::White), TimeBudget::unlimited());
+        assert_eq!(calculate_time_budget(&SearchLimits::infinite(), Color::White), TimeBudget::unlimited());
+
+        let move_time = SearchLimits::from_move_time(Duration::from_millis(500));
+        assert_eq!(calculate_time_budget(&move_time, Color::Black), TimeBudget::fixed(Duration::from_millis(500) - MOVE_OVERHEAD));
+
+        // Clock times use the clock of the player to move
+        let limits = SearchLimits {
+            white_time: Some(Duration::from_secs(60) + MOVE_OVERHEAD),
+            black_time: Some(Duration::from_secs(1) + MOVE_OVERHEAD),
+            white_increment: Some(Duration::from_secs(4)),
+            moves_to_go: Some(20),
+            ..Default::default()
+        };
+        let white_budget = calculate_time_budget(&limits, Color::White);
+        assert_eq!(white_budget.soft_limit, Some(Duration::from_secs(6)));
+        assert_eq!(white_budget.hard_limit, Some(Duration::from_secs(18)));
+        let black_budget = calculate_time_budget(&limits, Color::Black);
+        assert_eq!(black_budget.soft_limit, Some(Duration::from_millis(50)));
+        assert_eq!(black_budget.hard_limit, Some(Duration::from_millis(150)));
+
+        // Never use most of the clock, even on the last move before the time control
+        let limits = SearchLimits { white_time: Some(Duration::from_secs(4) + MOVE_OVERHEAD), moves_to_go: Some(1), ..Default::default() };
+        let budget = calculate_time_budget(&limits, Color::White);
+        assert_eq!(budget.hard_limit, Some(Duration::from_secs(3)));
+
+        // An empty clock still gives the minimum search time instead of the default move time
+        let limits = SearchLimits { black_time: Some(Duration::ZERO), ..Default::default() };
+        let budget = calculate_time_budget(&limits, Color::Black);
+        assert!(budget.hard_limit.unwrap() <= Duration::from_millis(1));
+    }
+}
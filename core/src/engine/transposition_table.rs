@@ -0,0 +1,230 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::core::{Move, Piece};
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+pub const MIN_HASH_SIZE_MB: usize = 1;
+pub const MAX_HASH_SIZE_MB: usize = 4096;
+
+/// Describes how the stored score relates to the true score of the position
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeType {
+    /// The score is exact, the search finished inside the window
+    Exact,
+    /// The search failed high, the true score is at least the stored score
+    LowerBound,
+    /// The search failed low, the true score is at most the stored score
+    UpperBound
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TranspositionEntry {
+    pub key: u64,
+    pub best_move: Move,
+    pub score: i32,
+    pub depth: u8,
+    pub node_type: NodeType,
+    generation: u8
+}
+
+/// An entry stored as three words which can be read and written by several threads without locking.
+///
+/// The first word is the key xored with the two data words. If another thread writes the entry at the
+/// same time, the words are mixed from two different writes and the key no longer matches on probing.
+struct AtomicEntry {
+    checksum: AtomicU64,
+    best_move: AtomicU64,
+    data: AtomicU64
+}
+
+impl AtomicEntry {
+    fn empty() -> AtomicEntry {
+        return AtomicEntry { checksum: AtomicU64::new(0), best_move: AtomicU64::new(0), data: AtomicU64::new(0) };
+    }
+
+    fn load(&self) -> TranspositionEntry {
+        let best_move = self.best_move.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.checksum.load(Ordering::Relaxed) ^ best_move ^ data;
+        return TranspositionEntry {
+            key,
+            best_move: unpack_move(best_move),
+            score: data as u32 as i32,
+            depth: (data >> 32) as u8,
+            node_type: match (data >> 40) as u8 {
+                0 => NodeType::Exact,
+                1 => NodeType::LowerBound,
+                _ => NodeType::UpperBound
+            },
+            generation: (data >> 48) as u8
+        };
+    }
+
+    fn save(&self, entry: &TranspositionEntry) {
+        let best_move = pack_move(&entry.best_move);
+        let node_type = match entry.node_type {
+            NodeType::Exact => 0u64,
+            NodeType::LowerBound => 1,
+            NodeType::UpperBound => 2
+        };
+        let data = (entry.score as u32 as u64) | (entry.depth as u64) << 32 | node_type << 40 | (entry.generation as u64) << 48;
+        self.checksum.store(entry.key ^ best_move ^ data, Ordering::Relaxed);
+        self.best_move.store(best_move, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+fn pack_move(mv: &Move) -> u64 {
+    return u64::from_le_bytes([mv.from, mv.to, mv.promotion.to_u8(), mv.captured.to_u8(), mv.ep, mv.castling, mv.quiet, 0]);
+}
+
+fn unpack_move(packed: u64) -> Move {
+    let [from, to, promotion, captured, ep, castling, quiet, _] = packed.to_le_bytes();
+    return Move { from, to, promotion: Piece::from_u8(promotion), captured: Piece::from_u8(captured), ep, castling, quiet };
+}
+
+/// Fixed size hash table indexed by the zoobrist key of a position.
+///
+/// The number of entries is always a power of two, so the index can be found by masking
+/// the lower bits of the key. The full key is stored in every entry to detect collisions.
+/// The table is shared between search threads, all operations except resizing take `&self`.
+pub struct TranspositionTable {
+    entries: Vec<AtomicEntry>,
+    index_mask: u64,
+    generation: AtomicU8
+}
+
+impl TranspositionTable {
+    /// Create a new table using at most `size_mb` megabytes of memory
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let entry_count = Self::entry_count_for_size(size_mb);
+        return TranspositionTable {
+            entries: (0..entry_count).map(|_| AtomicEntry::empty()).collect(),
+            index_mask: (entry_count - 1) as u64,
+            generation: AtomicU8::new(1)
+        };
+    }
+
+    /// Largest power of two amount of entries which fits in `size_mb` megabytes
+    fn entry_count_for_size(size_mb: usize) -> usize {
+        let size_mb = size_mb.clamp(MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB);
+        let max_entries = (size_mb * 1024 * 1024) / std::mem::size_of::<AtomicEntry>();
+        let mut entry_count = 1;
+        while entry_count * 2 <= max_entries {
+            entry_count *= 2;
+        }
+        return entry_count;
+    }
+
+    /// Reallocate the table with a new size. All stored entries are lost.
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = TranspositionTable::new(size_mb);
+    }
+
+    /// Remove all stored entries
+    pub fn clear(&self) {
+        for entry in self.entries.iter() {
+            entry.save(&TranspositionEntry {
+                key: 0, best_move: Move::empty(), score: 0, depth: 0, node_type: NodeType::Exact, generation: 0
+            });
+        }
+        self.generation.store(1, Ordering::Relaxed);
+    }
+
+    /// Mark the start of a new search. Entries from earlier searches are replaced before
+    /// entries from the current search.
+    pub fn new_search(&self) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation.store(generation.wrapping_add(1).max(1), Ordering::Relaxed);
+    }
+
+    fn entry(&self, key: u64) -> &AtomicEntry {
+        return unsafe { self.entries.get_unchecked((key & self.index_mask) as usize) };
+    }
+
+    /// Get the stored entry for `key`, if there is one
+    pub fn probe(&self, key: u64) -> Option<TranspositionEntry> {
+        let entry = self.entry(key).load();
+        if entry.generation != 0 && entry.key == key {
+            return Some(entry);
+        }
+        return None;
+    }
+
+    /// Store a search result for `key`. Deeper results from the current search are kept
+    /// over shallower ones.
+    pub fn store(&self, key: u64, best_move: Move, score: i32, depth: usize, node_type: NodeType) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let atomic_entry = self.entry(key);
+        let entry = atomic_entry.load();
+        let depth = depth.min(u8::MAX as usize) as u8;
+        if entry.generation == generation && entry.key != key && entry.depth > depth {
+            return;
+        }
+        // Keep the old best move if we did not find a new one for the same position
+        let best_move = if best_move == Move::empty() && entry.key == key {
+            entry.best_move
+        } else {
+            best_move
+        };
+        atomic_entry.save(&TranspositionEntry { key, best_move, score, depth, node_type, generation });
+    }
+
+    pub fn entry_count(&self) -> usize {
+        return self.entries.len();
+    }
+
+    /// How full the table is in permille, estimated from the first thousand entries
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample_size = self.entries.len().min(1000);
+        let used = self.entries[..sample_size].iter().filter(|entry| entry.load().generation == generation).count();
+        return used * 1000 / sample_size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{Move, Piece};
+    use super::{NodeType, TranspositionTable};
+
+    #[test]
+    fn test_transposition_table() {
+        let table = TranspositionTable::new(1);
+        assert!(table.entry_count().is_power_of_two());
+        let mv1 = Move {from: 1, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
+        let mv2 = Move {from: 2, to: 0, captured: Piece::Empty, promotion: Piece::Empty, ep: 0, castling: 0, quiet: 0};
+
+        let key = 0xdeadbeef;
+        assert_eq!(table.probe(key), None);
+        table.store(key, mv1, 50, 4, NodeType::Exact);
+        let entry = table.probe(key).unwrap();
+        assert_eq!((entry.best_move, entry.score, entry.depth, entry.node_type), (mv1, 50, 4, NodeType::Exact));
+
+        // A colliding key with lower depth should not replace an entry from the same search
+        let colliding_key = key + table.entry_count() as u64;
+        table.store(colliding_key, mv2, 10, 2, NodeType::LowerBound);
+        assert_eq!(table.probe(colliding_key), None);
+        assert_eq!(table.probe(key).unwrap().best_move, mv1);
+
+        // Entries from an older search are always replaced
+        table.new_search();
+        table.store(colliding_key, mv2, 10, 2, NodeType::LowerBound);
+        assert_eq!(table.probe(key), None);
+        assert_eq!(table.probe(colliding_key).unwrap().score, 10);
+
+        // Storing without a best move keeps the previous best move of the position
+        table.store(colliding_key, Move::empty(), -20, 3, NodeType::UpperBound);
+        let entry = table.probe(colliding_key).unwrap();
+        assert_eq!((entry.best_move, entry.score, entry.node_type), (mv2, -20, NodeType::UpperBound));
+
+        // An entry which was partially overwritten by another thread does not match any key
+        let index = (colliding_key & table.index_mask) as usize;
+        table.entries[index].data.fetch_xor(1, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(table.probe(colliding_key), None);
+
+        table.clear();
+        assert_eq!(table.probe(colliding_key), None);
+        assert_eq!(table.hashfull(), 0);
+    }
+}
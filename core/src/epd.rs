@@ -0,0 +1,310 @@
+//! Reading Extended Position Description (EPD) files, used for test suites such as WAC and STS.
+//!
+//! An EPD line is the first four fields of a FEN string followed by operations, for example
+//! `2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";`
+//!
+//! Positions of chess variants are marked with a `variant` operation, such as `variant 3check;`. The remaining
+//! checks of Three-check can follow the four FEN fields, like in `4k3/8/8/8/8/8/8/R3K3 w - - 2+3 variant 3check;`
+
+use std::{fmt, sync::Arc};
+
+use crate::core::bitboard::{constants::BitboardRuntimeConstants, Board, FenError};
+use crate::core::variant::Variant;
+use crate::core::{Move, MoveParseError};
+
+/// A position from an EPD file with its operations
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EpdPosition {
+    pub board: Board,
+    /// Value of the `id` operation, the name of the position
+    pub id: Option<String>,
+    /// Moves of the `bm` operation, one of which should be played
+    pub best_moves: Vec<Move>,
+    /// Moves of the `am` operation, none of which should be played
+    pub avoid_moves: Vec<Move>,
+    /// Value of the `acd` operation, the analysis depth
+    pub depth: Option<usize>,
+    /// Value of the `c0` operation, a comment
+    pub comment: Option<String>,
+    /// Expected perft node counts from the `D1`, `D2`, ... operations as (depth, nodes), sorted by depth
+    pub perft_counts: Vec<(usize, usize)>,
+    /// All operations in the order they appear, with quotes removed from string operands
+    pub operations: Vec<(String, Vec<String>)>
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EpdErrorKind {
+    /// The line has fewer than the four FEN fields
+    MissingFields,
+    InvalidFen(FenError),
+    UnterminatedString,
+    /// A move operand of the given opcode is not a legal move
+    InvalidMove(String, MoveParseError),
+    /// The operand of the given opcode has the wrong format
+    InvalidOperand(String, String),
+    /// An operation which is required, for example the result of a tuning position, is missing
+    MissingOperation(String)
+}
+
+/// Error while parsing an EPD file, `line` is the line number starting from 1
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EpdError {
+    pub line: usize,
+    pub kind: EpdErrorKind
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            EpdErrorKind::MissingFields => write!(f, "expected at least four FEN fields"),
+            EpdErrorKind::InvalidFen(e) => write!(f, "invalid position: {}", e),
+            EpdErrorKind::UnterminatedString => write!(f, "string operand is never closed"),
+            EpdErrorKind::InvalidMove(opcode, e) => write!(f, "invalid move in '{}': {}", opcode, e),
+            EpdErrorKind::InvalidOperand(opcode, operand) => write!(f, "invalid operand '{}' for '{}'", operand, opcode),
+            EpdErrorKind::MissingOperation(opcode) => write!(f, "missing operation '{}'", opcode)
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl EpdPosition {
+    /// Parse a single EPD line. A full FEN string, including the clocks, is also accepted.
+    pub fn from_line(line: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<EpdPosition, EpdErrorKind> {
+        let (fields, mut operations_text) = split_fields(line, 4);
+        if fields.len() < 4 {
+            return Err(EpdErrorKind::MissingFields);
+        }
+        let mut fen = fields.join(" ");
+        // Remaining checks of Three-check
+        let (checks, rest) = split_fields(operations_text, 1);
+        if checks.len() == 1 && checks[0].contains('+') && checks[0].chars().all(|c| c == '+' || c.is_ascii_digit()) {
+            fen = format!("{} {}", fen, checks[0]);
+            operations_text = rest;
+        }
+        // Clocks from a full FEN string
+        let (clocks, rest) = split_fields(operations_text, 2);
+        if clocks.len() == 2 && clocks.iter().all(|clock| clock.parse::<u32>().is_ok()) {
+            fen = format!("{} {}", fen, clocks.join(" "));
+            operations_text = rest;
+        }
+        let operations = parse_operations(operations_text)?;
+
+        // The clocks can also be given as operations
+        let operand = |opcode: &str| operations.iter().find(|(op, _)| op == opcode).and_then(|(_, operands)| operands.first());
+        if let (Some(halfmove_clock), Some(fullmove_number)) = (operand("hmvc"), operand("fmvn")) {
+            fen = format!("{} {} {}", fen, halfmove_clock, fullmove_number);
+        }
+        let variant = match operand("variant") {
+            Some(name) => Variant::from_uci_name(name)
+                .ok_or_else(|| EpdErrorKind::InvalidOperand("variant".to_string(), name.clone()))?,
+            None => Variant::Standard
+        };
+        let board = Board::from_fen_variant(&fen, variant, runtime_constants).map_err(EpdErrorKind::InvalidFen)?;
+
+        let mut position = EpdPosition {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            depth: None,
+            comment: None,
+            perft_counts: Vec::new(),
+            operations: Vec::new()
+        };
+        for (opcode, operands) in operations.iter() {
+            match opcode.as_str() {
+                "id" => position.id = operands.first().cloned(),
+                "c0" => position.comment = operands.first().cloned(),
+                "bm" => position.best_moves = position.parse_moves(opcode, operands)?,
+                "am" => position.avoid_moves = position.parse_moves(opcode, operands)?,
+                "acd" => {
+                    let depth = operands.first().map(|depth| depth.parse::<usize>());
+                    match depth {
+                        Some(Ok(depth)) => position.depth = Some(depth),
+                        _ => return Err(EpdErrorKind::InvalidOperand(opcode.clone(), operands.join(" ")))
+                    }
+                }
+                _ if opcode.starts_with('D') && opcode[1..].parse::<usize>().is_ok() => {
+                    let depth = opcode[1..].parse::<usize>().unwrap();
+                    match operands.first().map(|nodes| nodes.parse::<usize>()) {
+                        Some(Ok(nodes)) => position.perft_counts.push((depth, nodes)),
+                        _ => return Err(EpdErrorKind::InvalidOperand(opcode.clone(), operands.join(" ")))
+                    }
+                }
+                _ => ()
+            }
+        }
+        position.perft_counts.sort();
+        position.operations = operations;
+        return Ok(position);
+    }
+
+    /// Resolve move operands, written in SAN or in UCI notation
+    fn parse_moves(&self, opcode: &str, operands: &[String]) -> Result<Vec<Move>, EpdErrorKind> {
+        return operands.iter().map(|operand| {
+            let mv = match Move::try_from_san(&self.board, operand) {
+                Err(MoveParseError::InvalidSan(_)) => Move::try_from_algebraic(&self.board, operand),
+                result => result
+            };
+            return mv.map_err(|e| EpdErrorKind::InvalidMove(opcode.to_string(), e));
+        }).collect();
+    }
+
+    /// Get the operands of the first operation with `opcode`
+    pub fn get_operation(&self, opcode: &str) -> Option<&[String]> {
+        return self.operations.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice());
+    }
+
+    /// A move solves the position if it is one of the best moves and none of the moves to avoid.
+    /// Positions without best moves are solved by any move which is not avoided.
+    pub fn is_solved_by(&self, mv: &Move) -> bool {
+        return (self.best_moves.is_empty() || self.best_moves.contains(mv)) && !self.avoid_moves.contains(mv);
+    }
+}
+
+/// Split off the first `count` whitespace separated fields of `text`, returning them and the rest of the text.
+/// A semicolon ends the fields, since some files leave out the space before the first operation.
+fn split_fields(text: &str, count: usize) -> (Vec<&str>, &str) {
+    let mut fields = Vec::with_capacity(count);
+    let mut rest = text.trim_start();
+    while fields.len() < count && !rest.is_empty() && !rest.starts_with(';') {
+        let end = rest.find(|c: char| c.is_whitespace() || c == ';').unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    return (fields, rest);
+}
+
+/// Parse operations of the form `opcode operand operand;`. String operands are quoted and may contain semicolons.
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdErrorKind> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        match chars.next() {
+            Some(c) if c.is_whitespace() => (),
+            Some(';') | None => {
+                if !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, std::mem::take(&mut tokens)));
+                }
+                if chars.peek().is_none() {
+                    break;
+                }
+            }
+            Some('"') => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                        None => return Err(EpdErrorKind::UnterminatedString)
+                    }
+                }
+                tokens.push(string);
+            }
+            Some(c) => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    return Ok(operations);
+}
+
+/// Parse all positions of an EPD file. Empty lines and lines starting with `#` are skipped.
+pub fn parse_epd(text: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Vec<EpdPosition>, EpdError> {
+    let mut positions = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match EpdPosition::from_line(line, Arc::clone(&runtime_constants)) {
+            Ok(position) => positions.push(position),
+            Err(kind) => return Err(EpdError { line: index + 1, kind })
+        }
+    }
+    return Ok(positions);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::variant::Variant;
+    use crate::core::{Color, Move, MoveParseError};
+    use super::{parse_epd, EpdErrorKind, EpdPosition};
+
+    const TEST_EPD: &str = r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+# Comment lines and empty lines are skipped
+
+r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - am Nxc6; bm Nf5 e4e5; id "test \"2\""; acd 8; c0 "comment; with semicolon";
+4k3/8/8/8/8/8/4P3/4K3 w - - 5 20 bm e4; id "clocks";
+4k3/8/8/8/8/8/4P3/4K3 w - -;hmvc 7; fmvn 30; D1 5 ;D2 25
+4k3/8/8/8/8/8/8/R3K3 w - - 2+3 variant 3check; bm Ra8;
+"#;
+
+    #[test]
+    fn test_parse_epd() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let positions = parse_epd(TEST_EPD, Arc::clone(&constant_state)).unwrap();
+        assert_eq!(positions.len(), 5);
+
+        let position = &positions[0];
+        assert_eq!(position.id.as_deref(), Some("WAC.001"));
+        assert_eq!(position.best_moves, vec![Move::try_from_algebraic(&position.board, "g3g6").unwrap()]);
+        assert!(position.is_solved_by(&position.best_moves[0]));
+        assert!(!position.is_solved_by(&Move::try_from_algebraic(&position.board, "g3g4").unwrap()));
+
+        let position = &positions[1];
+        assert_eq!(position.id.as_deref(), Some("test \"2\""));
+        assert_eq!(position.depth, Some(8));
+        assert_eq!(position.comment.as_deref(), Some("comment; with semicolon"));
+        assert_eq!(position.best_moves.len(), 2);
+        let avoid_move = Move::try_from_algebraic(&position.board, "d4c6").unwrap();
+        assert_eq!(position.avoid_moves, vec![avoid_move]);
+        assert!(!position.is_solved_by(&avoid_move));
+
+        assert_eq!(positions[2].board.get_quiet_moves(), 5);
+        assert_eq!(positions[2].board.get_half_moves(), 20);
+        assert_eq!(positions[3].board.get_quiet_moves(), 7);
+        assert_eq!(positions[3].get_operation("D2"), Some(&["25".to_string()][..]));
+        assert_eq!(positions[3].perft_counts, vec![(1, 5), (2, 25)]);
+        // Positions without best moves are solved by anything that is not avoided
+        assert!(positions[3].is_solved_by(&Move::try_from_algebraic(&positions[3].board, "e2e4").unwrap()));
+
+        assert_eq!(positions[4].board.get_variant(), Variant::ThreeCheck);
+        assert_eq!(positions[4].board.get_checks_given(Color::White), 1);
+        assert_eq!(positions[4].best_moves, vec![Move::try_from_algebraic(&positions[4].board, "a1a8").unwrap()]);
+    }
+
+    #[test]
+    fn test_epd_errors() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let error = |line: &str| EpdPosition::from_line(line, Arc::clone(&constant_state)).unwrap_err();
+
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w -"), EpdErrorKind::MissingFields);
+        assert!(matches!(error("4k3/8/8/8/8/8/4P3/8 w - - bm e4;"), EpdErrorKind::InvalidFen(_)));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - id \"test;"), EpdErrorKind::UnterminatedString);
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - bm e5;"),
+            EpdErrorKind::InvalidMove("bm".to_string(), MoveParseError::IllegalMove("e5".to_string())));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - acd deep;"), EpdErrorKind::InvalidOperand("acd".to_string(), "deep".to_string()));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - ;D1 5 ;D2 many"), EpdErrorKind::InvalidOperand("D2".to_string(), "many".to_string()));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - variant suicide;"), EpdErrorKind::InvalidOperand("variant".to_string(), "suicide".to_string()));
+
+        let error = parse_epd("4k3/8/8/8/8/8/4P3/4K3 w - - bm e4;\n\n4k3/8/8/8/8/8/4P3/4K3 w", Arc::clone(&constant_state)).unwrap_err();
+        assert_eq!((error.line, error.kind), (3, EpdErrorKind::MissingFields));
+    }
+}
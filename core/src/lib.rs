@@ -1,3 +1,10 @@
 pub mod engine;
 pub mod core;
-pub mod commands;
\ No newline at end of file
+pub mod commands;
+pub mod pgn;
+pub mod epd;
+pub mod tuning;
+pub mod datagen;
+pub mod book;
+pub mod syzygy;
+pub mod tablebase;
@@ -0,0 +1,617 @@
+//! Reading and writing games in Portable Game Notation (PGN)
+
+use std::{fmt, sync::Arc};
+
+use crate::core::bitboard::{constants::BitboardRuntimeConstants, Board, FenError};
+use crate::core::{Color, GameStatus, Move, MoveParseError, STARTING_POS_FEN};
+
+/// Tags which every PGN game should have, in the order they are written
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+/// Movetext lines are wrapped at this many characters
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWon,
+    BlackWon,
+    Draw,
+    /// The game is still in progress or the result is unknown
+    Unknown
+}
+
+impl GameResult {
+    pub fn from_status(status: GameStatus) -> GameResult {
+        return match status {
+            GameStatus::WhiteWon => GameResult::WhiteWon,
+            GameStatus::BlackWon => GameResult::BlackWon,
+            GameStatus::InProgress => GameResult::Unknown,
+            _ => GameResult::Draw
+        };
+    }
+
+    pub fn from_str(result: &str) -> Option<GameResult> {
+        return match result {
+            "1-0" => Some(GameResult::WhiteWon),
+            "0-1" => Some(GameResult::BlackWon),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None
+        };
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            GameResult::WhiteWon => "1-0",
+            GameResult::BlackWon => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*"
+        };
+    }
+}
+
+/// A move with the annotations which follow it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PgnMove {
+    pub mv: Move,
+    /// Numeric Annotation Glyphs, `$1` is stored as 1
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    /// Comment before the move, only found before the first move of the game or of a variation
+    pub comment_before: Option<String>,
+    /// Alternatives to this move, played from the position before it
+    pub variations: Vec<Vec<PgnMove>>
+}
+
+impl PgnMove {
+    pub fn new(mv: Move) -> PgnMove {
+        return PgnMove { mv, nags: Vec::new(), comment: None, comment_before: None, variations: Vec::new() };
+    }
+}
+
+/// A single game with its variations and comments
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PgnGame {
+    /// Tag pairs in the order they were read
+    pub tags: Vec<(String, String)>,
+    /// Position before the first move, set by the `FEN` tag
+    pub start_board: Board,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult
+}
+
+impl PgnGame {
+    /// Create a game of `moves` played from `start_board`. A `FEN` tag is added if the game
+    /// does not start from the standard starting position.
+    pub fn from_moves(start_board: &Board, moves: &[Move], result: GameResult) -> PgnGame {
+        let mut game = PgnGame {
+            tags: Vec::new(),
+            start_board: start_board.clone(),
+            moves: moves.iter().map(|mv| PgnMove::new(*mv)).collect(),
+            result
+        };
+        let fen = start_board.to_fen();
+        if fen != STARTING_POS_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        return game;
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        return self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str());
+    }
+
+    /// Set the value of a tag, replacing the previous value if the tag already exists
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    /// The position after the first `ply` moves of the game
+    pub fn board_at(&self, ply: usize) -> Board {
+        let mut board = self.start_board.clone();
+        for pgn_move in self.moves.iter().take(ply) {
+            board.make_move(&pgn_move.mv);
+        }
+        return board;
+    }
+
+    /// Write the game as PGN, with the seven tag roster first
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                _ => self.get_tag(name).unwrap_or("?")
+            };
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+        }
+        for (name, value) in self.tags.iter().filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str())) {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+        }
+        pgn.push('\n');
+
+        // Movetext
+        let mut tokens: Vec<String> = Vec::new();
+        let move_number = self.start_board.get_half_moves().max(1) as usize;
+        write_line(&mut tokens, &mut self.start_board.clone(), &self.moves, move_number);
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push('\n');
+                line_length = 0;
+            }
+            if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            line_length += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push('\n');
+        return pgn;
+    }
+}
+
+/// Add the movetext tokens of `moves` played from `board`, with their variations in parentheses.
+/// `move_number` is the number of the first move.
+fn write_line(tokens: &mut Vec<String>, board: &mut Board, moves: &[PgnMove], mut move_number: usize) {
+    let mut needs_move_number = true;
+    for pgn_move in moves.iter() {
+        let white_to_move = board.get_current_player() == Color::White;
+        if let Some(comment) = &pgn_move.comment_before {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        if white_to_move {
+            tokens.push(format!("{}.", move_number));
+        }
+        else if needs_move_number {
+            tokens.push(format!("{}...", move_number));
+        }
+        tokens.push(pgn_move.mv.to_san(board));
+        for nag in pgn_move.nags.iter() {
+            tokens.push(format!("${}", nag));
+        }
+        needs_move_number = false;
+        if let Some(comment) = &pgn_move.comment {
+            tokens.push(format!("{{{}}}", comment));
+            needs_move_number = true;
+        }
+        for variation in pgn_move.variations.iter().filter(|variation| !variation.is_empty()) {
+            let start = tokens.len();
+            write_line(tokens, &mut board.clone(), variation, move_number);
+            tokens[start].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+            needs_move_number = true;
+        }
+        if !white_to_move {
+            move_number += 1;
+        }
+        board.make_move(&pgn_move.mv);
+    }
+}
+
+fn escape_tag_value(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('"', "\\\"");
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PgnErrorKind {
+    /// A tag pair is not of the form `[Name "Value"]`
+    InvalidTag(String),
+    UnterminatedComment,
+    /// A variation is closed without being opened, or is not closed before the game ends
+    UnbalancedVariation,
+    UnexpectedCharacter(char),
+    InvalidFen(FenError),
+    /// A move at the given ply, counted from the start of the game, could not be played
+    InvalidMove(usize, MoveParseError)
+}
+
+/// Error while parsing a PGN file, `game` is the number of the game in the file starting from 1
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PgnError {
+    pub game: usize,
+    pub kind: PgnErrorKind
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "game {}: ", self.game)?;
+        match &self.kind {
+            PgnErrorKind::InvalidTag(tag) => write!(f, "invalid tag pair '{}'", tag),
+            PgnErrorKind::UnterminatedComment => write!(f, "comment is never closed"),
+            PgnErrorKind::UnbalancedVariation => write!(f, "unbalanced variation parentheses"),
+            PgnErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            PgnErrorKind::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnErrorKind::InvalidMove(ply, e) => write!(f, "ply {}: {}", ply, e)
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token<'a> {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    MoveNumber,
+    San(&'a str)
+}
+
+fn is_symbol_char(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || "_+#=:-/!?".contains(c);
+}
+
+/// Splits PGN text into tokens
+#[derive(Clone, Copy)]
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        return &self.text[self.pos..];
+    }
+
+    fn peek(&self) -> Result<Option<Token<'a>>, PgnErrorKind> {
+        let mut lexer = *self;
+        return lexer.next_token();
+    }
+
+    /// Advance past the first character matching `predicate`, or to the end of the text.
+    /// Returns the skipped text, excluding the matching character.
+    fn take_until(&mut self, predicate: impl Fn(char) -> bool) -> Option<&'a str> {
+        let rest = self.rest();
+        return match rest.find(predicate) {
+            Some(index) => {
+                let end_char_len = rest[index..].chars().next().unwrap().len_utf8();
+                self.pos += index + end_char_len;
+                Some(&rest[..index])
+            }
+            None => {
+                self.pos = self.text.len();
+                None
+            }
+        };
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, PgnErrorKind> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let c = match trimmed.chars().next() {
+                Some(c) => c,
+                None => return Ok(None)
+            };
+            let at_line_start = self.pos == 0 || self.text[..self.pos].ends_with('\n');
+            match c {
+                // Escaped lines are ignored
+                '%' if at_line_start => { self.take_until(|c| c == '\n'); },
+                ';' => {
+                    self.pos += 1;
+                    let rest = self.rest();
+                    let comment = self.take_until(|c| c == '\n').unwrap_or(rest);
+                    return Ok(Some(Token::Comment(comment.trim().to_string())));
+                }
+                '{' => {
+                    self.pos += 1;
+                    return match self.take_until(|c| c == '}') {
+                        Some(comment) => Ok(Some(Token::Comment(comment.split_whitespace().collect::<Vec<&str>>().join(" ")))),
+                        None => Err(PgnErrorKind::UnterminatedComment)
+                    };
+                }
+                '[' => return self.read_tag().map(Some),
+                '(' => {
+                    self.pos += 1;
+                    return Ok(Some(Token::VariationStart));
+                }
+                ')' => {
+                    self.pos += 1;
+                    return Ok(Some(Token::VariationEnd));
+                }
+                '$' => {
+                    self.pos += 1;
+                    let digits = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+                    let nag = self.rest()[..digits].parse().map_err(|_| PgnErrorKind::UnexpectedCharacter('$'))?;
+                    self.pos += digits;
+                    return Ok(Some(Token::Nag(nag)));
+                }
+                // Periods after move numbers
+                '.' => self.pos += 1,
+                '*' => {
+                    self.pos += 1;
+                    return Ok(Some(Token::Result(GameResult::Unknown)));
+                }
+                c if is_symbol_char(c) => {
+                    let length = trimmed.find(|c| !is_symbol_char(c)).unwrap_or(trimmed.len());
+                    let symbol = &trimmed[..length];
+                    self.pos += length;
+                    if let Some(result) = GameResult::from_str(symbol) {
+                        return Ok(Some(Token::Result(result)));
+                    }
+                    if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        return Ok(Some(Token::MoveNumber));
+                    }
+                    // The optional en passant suffix is not part of the move
+                    let rest = self.rest();
+                    if let Some(after_suffix) = rest.trim_start().strip_prefix("e.p.") {
+                        self.pos += rest.len() - after_suffix.len();
+                    }
+                    return Ok(Some(Token::San(symbol)));
+                }
+                _ => return Err(PgnErrorKind::UnexpectedCharacter(c))
+            }
+        }
+    }
+
+    /// Read a tag pair of the form `[Name "Value"]`
+    fn read_tag(&mut self) -> Result<Token<'a>, PgnErrorKind> {
+        let start = self.pos;
+        let invalid = |lexer: &Lexer| {
+            let end = lexer.text[start..].find('\n').map_or(lexer.text.len(), |end| start + end);
+            PgnErrorKind::InvalidTag(lexer.text[start..end].trim().to_string())
+        };
+        self.pos += 1;
+        let rest = self.rest().trim_start();
+        let name_length = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let name = rest[..name_length].to_string();
+        let after_name = rest[name_length..].trim_start();
+        if name.is_empty() || !after_name.starts_with('"') {
+            return Err(invalid(self));
+        }
+        self.pos = self.text.len() - after_name.len() + 1;
+
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, escaped)) => value.push(escaped),
+                    None => return Err(invalid(self))
+                },
+                Some((index, '"')) => {
+                    self.pos += index + 1;
+                    break;
+                }
+                Some((_, c)) => value.push(c),
+                None => return Err(invalid(self))
+            }
+        }
+        let rest = self.rest().trim_start();
+        if !rest.starts_with(']') {
+            return Err(invalid(self));
+        }
+        self.pos = self.text.len() - rest.len() + 1;
+        return Ok(Token::Tag(name, value));
+    }
+}
+
+/// Parse all games in a PGN file. The moves of every game are checked to be legal.
+pub fn parse_pgn(pgn: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Vec<PgnGame>, PgnError> {
+    let mut lexer = Lexer { text: pgn, pos: 0 };
+    let mut games = Vec::new();
+    loop {
+        let game_number = games.len() + 1;
+        match parse_game(&mut lexer, Arc::clone(&runtime_constants)) {
+            Ok(Some(game)) => games.push(game),
+            Ok(None) => break,
+            Err(kind) => return Err(PgnError { game: game_number, kind })
+        }
+    }
+    return Ok(games);
+}
+
+/// Parse the next game, returns None if there are no more games
+fn parse_game(lexer: &mut Lexer, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Option<PgnGame>, PgnErrorKind> {
+    let mut tags = Vec::new();
+    while let Some(Token::Tag(_, _)) = lexer.peek()? {
+        if let Some(Token::Tag(name, value)) = lexer.next_token()? {
+            tags.push((name, value));
+        }
+    }
+    if tags.is_empty() && lexer.peek()?.is_none() {
+        return Ok(None);
+    }
+    let start_board = match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => Board::from_fen(fen, runtime_constants).map_err(PgnErrorKind::InvalidFen)?,
+        None => Board::new(runtime_constants)
+    };
+    let (moves, result) = parse_line(lexer, &mut start_board.clone(), 0, false)?;
+    let mut game = PgnGame { tags, start_board, moves, result: result.unwrap_or(GameResult::Unknown) };
+    // The result tag is used if the movetext does not end with a result
+    if game.result == GameResult::Unknown {
+        if let Some(result) = game.get_tag("Result").and_then(GameResult::from_str) {
+            game.result = result;
+        }
+    }
+    return Ok(Some(game));
+}
+
+/// Parse the moves of the main line, or of a variation up to its closing parenthesis, played from `board`
+/// after `ply` moves of the game. The SAN moves of variations are checked against the position before the
+/// move they are an alternative to. Returns the moves and the result ending the main line.
+fn parse_line(lexer: &mut Lexer, board: &mut Board, ply: usize, in_variation: bool) -> Result<(Vec<PgnMove>, Option<GameResult>), PgnErrorKind> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut comment_before: Option<String> = None;
+    loop {
+        // A new tag section starts the next game, even without a result
+        if !in_variation && matches!(lexer.peek()?, Some(Token::Tag(_, _))) {
+            return Ok((moves, None));
+        }
+        let token = match lexer.next_token()? {
+            Some(token) => token,
+            None if in_variation => return Err(PgnErrorKind::UnbalancedVariation),
+            None => return Ok((moves, None))
+        };
+        match token {
+            Token::VariationStart => {
+                // A variation is an alternative to the last move, so it can not come before the first move
+                let last_move = moves.last().ok_or(PgnErrorKind::UnexpectedCharacter('('))?;
+                let mut variation_board = board.clone();
+                variation_board.unmake_move(&last_move.mv);
+                let (variation, _) = parse_line(lexer, &mut variation_board, ply + moves.len() - 1, true)?;
+                if !variation.is_empty() {
+                    moves.last_mut().unwrap().variations.push(variation);
+                }
+            }
+            Token::VariationEnd if in_variation => return Ok((moves, None)),
+            Token::VariationEnd => return Err(PgnErrorKind::UnbalancedVariation),
+            Token::Result(_) if in_variation => return Err(PgnErrorKind::UnbalancedVariation),
+            Token::Result(result) => return Ok((moves, Some(result))),
+            Token::San(san) => {
+                let mv = Move::try_from_san(board, san)
+                    .map_err(|e| PgnErrorKind::InvalidMove(ply + moves.len() + 1, e))?;
+                board.make_move(&mv);
+                let mut pgn_move = PgnMove::new(mv);
+                pgn_move.comment_before = comment_before.take();
+                moves.push(pgn_move);
+            }
+            Token::Comment(comment) => {
+                let target = match moves.last_mut() {
+                    Some(last_move) => &mut last_move.comment,
+                    None => &mut comment_before
+                };
+                *target = match target.take() {
+                    Some(previous) => Some(format!("{} {}", previous, comment)),
+                    None => Some(comment)
+                };
+            }
+            Token::Nag(nag) => {
+                if let Some(last_move) = moves.last_mut() {
+                    last_move.nags.push(nag);
+                }
+            }
+            Token::MoveNumber | Token::Tag(_, _) => ()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::{bitboard::Board, Move, MoveParseError};
+    use super::{parse_pgn, GameResult, PgnErrorKind, PgnGame};
+
+    const TEST_PGN: &str = r#"[Event "Casual \"blitz\" game"]
+[Site "?"]
+[White "Magnificence"]
+[Black "Random"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {The most common move} Nc6 (2... d6 3. d4 {Philidor} (3. Bc4)) 3. Bc4 $1
+Nf6?! ; The two knights defense
+4. Ng5 d5 5. exd5 Na5 6. Bb5+ c6 7. dxc6 bxc6 8. Qf3 1-0
+
+[Event "Second game"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 40"]
+
+% This line is ignored
+40... exd3 e.p. 41. Kd2 Kd7 *
+
+1.e4 e5 2.Qh5 Nc6 3.Bc4 Nf6 4.Qxf7# 1-0
+"#;
+
+    #[test]
+    fn test_parse_pgn() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let games = parse_pgn(TEST_PGN, Arc::clone(&constant_state)).unwrap();
+        assert_eq!(games.len(), 3);
+
+        let game = &games[0];
+        assert_eq!(game.get_tag("Event"), Some("Casual \"blitz\" game"));
+        assert_eq!(game.get_tag("White"), Some("Magnificence"));
+        assert_eq!(game.result, GameResult::WhiteWon);
+        assert_eq!(game.moves.len(), 15);
+        assert_eq!(game.moves[2].comment.as_deref(), Some("The most common move"));
+        // The variation is an alternative to 2... Nc6, with a nested alternative to 3. d4
+        let variation = &game.moves[3].variations[0];
+        assert_eq!(game.moves[3].variations.len(), 1);
+        assert_eq!(variation.len(), 2);
+        assert_eq!(variation[0].mv, Move::from_algebraic(&game.board_at(3), "d7d6"));
+        assert_eq!(variation[1].comment.as_deref(), Some("Philidor"));
+        assert_eq!(variation[1].variations[0].len(), 1);
+        assert!(game.moves[4].variations.is_empty());
+        assert_eq!(game.moves[4].nags, vec![1]);
+        assert_eq!(game.moves[5].comment.as_deref(), Some("The two knights defense"));
+        assert_eq!(game.board_at(game.moves.len()).to_fen().split(' ').next(), Some("r1bqkb1r/p4ppp/2p2n2/nB2p1N1/8/5Q2/PPPP1PPP/RNB1K2R"));
+
+        let game = &games[1];
+        assert_eq!(game.start_board.to_fen(), "4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 40");
+        assert_eq!(game.moves[0].mv, Move::from_algebraic(&game.start_board, "e4d3"));
+        assert_eq!(game.moves.len(), 3);
+        assert_eq!(game.result, GameResult::Unknown);
+
+        let game = &games[2];
+        assert!(game.tags.is_empty());
+        assert_eq!(game.moves.len(), 7);
+        assert_eq!(game.result, GameResult::WhiteWon);
+    }
+
+    #[test]
+    fn test_pgn_errors() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let error = |pgn: &str| parse_pgn(pgn, Arc::clone(&constant_state)).unwrap_err();
+
+        assert_eq!(parse_pgn("", Arc::clone(&constant_state)).unwrap(), vec![]);
+        assert_eq!(error("[Event \"Test]\n1. e4 *").kind, PgnErrorKind::InvalidTag("[Event \"Test]".to_string()));
+        assert_eq!(error("[Event Test]\n1. e4 *").kind, PgnErrorKind::InvalidTag("[Event Test]".to_string()));
+        assert_eq!(error("1. e4 {Unterminated *").kind, PgnErrorKind::UnterminatedComment);
+        assert_eq!(error("1. e4 (1. d4 *").kind, PgnErrorKind::UnbalancedVariation);
+        assert_eq!(error("1. e4 ) *").kind, PgnErrorKind::UnbalancedVariation);
+        assert_eq!(error("1. e4 (1. d4 1-0) *").kind, PgnErrorKind::UnbalancedVariation);
+        assert_eq!(error("(1. e4) 1. d4 *").kind, PgnErrorKind::UnexpectedCharacter('('));
+        // Moves of variations are played from the position before the move they replace
+        assert_eq!(error("1. e4 e5 (1... Nf3) *").kind, PgnErrorKind::InvalidMove(2, MoveParseError::IllegalMove("Nf3".to_string())));
+        assert_eq!(error("1. e4 & *").kind, PgnErrorKind::UnexpectedCharacter('&'));
+        assert!(matches!(error("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n*").kind, PgnErrorKind::InvalidFen(_)));
+
+        let error = error("1. e4 e5 *\n\n1. e4 e5 2. Ke3 *");
+        assert_eq!(error.game, 2);
+        assert_eq!(error.kind, PgnErrorKind::InvalidMove(3, MoveParseError::IllegalMove("Ke3".to_string())));
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let games = parse_pgn(TEST_PGN, Arc::clone(&constant_state)).unwrap();
+        let written: String = games.iter().map(|game| game.to_pgn()).collect::<Vec<String>>().join("\n");
+        assert!(written.starts_with("[Event \"Casual \\\"blitz\\\" game\"]\n[Site \"?\"]\n[Date \"?\"]\n[Round \"?\"]\n"));
+        assert!(written.contains("\n\n1. e4 e5 2. Nf3 {The most common move} 2... Nc6 (2... d6 3. d4 {Philidor} (3.\nBc4)) 3. Bc4 $1 Nf6 {The two knights defense} 4. Ng5"));
+        assert!(written.lines().all(|line| line.len() <= 80));
+        assert!(written.contains("40... exd3 41. Kd2 Kd7 *"));
+        assert!(written.contains("4. Qxf7# 1-0"));
+        // Writing and reading again gives the same games
+        assert_eq!(parse_pgn(&written, Arc::clone(&constant_state)).unwrap().iter().map(|game| &game.moves).collect::<Vec<_>>(),
+            games.iter().map(|game| &game.moves).collect::<Vec<_>>());
+
+        // Nested variations and a comment before the first move are kept when writing and reading again
+        let pgn = "{Opening comment} 1. e4 (1. d4 {Queen pawn} 1... d5 (1... Nf6 2. c4 (2. Nf3)\n2... e6) 2. c4) 1... c5 *";
+        let games = parse_pgn(pgn, Arc::clone(&constant_state)).unwrap();
+        assert_eq!(games[0].moves[0].comment_before.as_deref(), Some("Opening comment"));
+        assert_eq!(games[0].moves[0].variations[0][1].variations[0][1].variations.len(), 1);
+        let written = games[0].to_pgn();
+        assert!(written.ends_with(&format!("\n\n{}\n", pgn)), "{}", written);
+        assert_eq!(parse_pgn(&written, Arc::clone(&constant_state)).unwrap()[0].moves, games[0].moves);
+
+        // Games which do not start from the starting position get a FEN tag
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 30", Arc::clone(&constant_state)).unwrap();
+        let mv = Move::try_from_algebraic(&board, "e2e4").unwrap();
+        let game = PgnGame::from_moves(&board, &[mv], GameResult::Unknown);
+        assert_eq!(game.get_tag("FEN"), Some("4k3/8/8/8/8/8/4P3/4K3 w - - 0 30"));
+        assert!(game.to_pgn().ends_with("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 30\"]\n\n30. e4 *\n"));
+    }
+}
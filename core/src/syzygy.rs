@@ -0,0 +1,650 @@
+//! Probing of Syzygy endgame tablebases.
+//!
+//! WDL tables (`.rtbw`) store whether a position is won, drawn or lost, taking the fifty-move rule into account:
+//! cursed wins and blessed losses are wins and losses which the fifty-move rule turns into draws. DTZ tables
+//! (`.rtbz`) store the distance to the next capture or pawn move on the way to that result, which is what is
+//! needed to convert a win. Tables are found by file name, such as `KRPvKR.rtbw`, and read into memory when they
+//! are first probed. Positions with castling rights are never probed.
+//!
+//! The tables do not store reliable values for positions where a capture is the best move, so probing first
+//! searches the captures of a position and only trusts the table if none of them is better.
+
+mod table;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Neg;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use crate::core::bitboard::Board;
+use crate::core::move_list::MoveList;
+use crate::core::{Color, Move, Piece};
+use crate::core::variant::Variant;
+use table::{TableData, TableInfo, TableValue, TB_PIECES};
+
+/// Separator of the directories in the `SyzygyPath` and `DtmPath` options
+#[cfg(windows)]
+pub(crate) const PATH_SEPARATOR: char = ';';
+#[cfg(not(windows))]
+pub(crate) const PATH_SEPARATOR: char = ':';
+
+/// Result of a position for the player to move
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    /// A loss which is a draw by the fifty-move rule
+    BlessedLoss = -1,
+    Draw = 0,
+    /// A win which is a draw by the fifty-move rule
+    CursedWin = 1,
+    Win = 2
+}
+
+impl Wdl {
+    fn from_i32(value: i32) -> Option<Wdl> {
+        return match value {
+            -2 => Some(Wdl::Loss),
+            -1 => Some(Wdl::BlessedLoss),
+            0 => Some(Wdl::Draw),
+            1 => Some(Wdl::CursedWin),
+            2 => Some(Wdl::Win),
+            _ => None
+        };
+    }
+
+    /// DTZ of a position whose best move is a capture or pawn move leading to this result
+    fn dtz_before_zeroing(self) -> i32 {
+        return match self {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::Draw => 0,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1
+        };
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        return Wdl::from_i32(-(self as i32)).unwrap();
+    }
+}
+
+#[derive(Debug)]
+pub enum SyzygyError {
+    /// A directory of the path could not be read
+    Io(String, std::io::Error)
+}
+
+impl fmt::Display for SyzygyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SyzygyError::Io(path, e) => write!(f, "Could not read tablebase directory '{}': {}", path, e)
+        };
+    }
+}
+
+impl std::error::Error for SyzygyError {}
+
+/// A WDL table and its DTZ table, if one was found
+struct TableEntry {
+    info: TableInfo,
+    /// Material key with the stronger side as white
+    key: u64,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<TableData>>,
+    dtz: OnceLock<Option<TableData>>
+}
+
+impl TableEntry {
+    fn data(&self, is_dtz: bool) -> Option<&TableData> {
+        let (cell, path) = match is_dtz {
+            true => (&self.dtz, self.dtz_path.as_ref()?),
+            false => (&self.wdl, &self.wdl_path)
+        };
+        return cell.get_or_init(|| TableData::parse(fs::read(path).ok()?, &self.info, is_dtz)).as_ref();
+    }
+}
+
+/// The tables found in the directories of a `SyzygyPath`
+#[derive(Default)]
+pub struct Tablebases {
+    /// Tables by the material key of both colorings of their material
+    tables: HashMap<u64, Arc<TableEntry>>,
+    table_count: usize,
+    max_pieces: usize
+}
+
+impl Tablebases {
+    /// Find the tables in one or more directories, separated by `:` (`;` on Windows)
+    pub fn load(path: &str) -> Result<Tablebases, SyzygyError> {
+        let mut wdl_paths = Vec::new();
+        let mut dtz_paths = HashMap::new();
+        for directory in path.split(PATH_SEPARATOR).map(str::trim).filter(|directory| !directory.is_empty()) {
+            let entries = fs::read_dir(directory).map_err(|e| SyzygyError::Io(directory.to_string(), e))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let (Some(name), Some(extension)) = (path.file_stem().and_then(|s| s.to_str()), path.extension()) else {
+                    continue;
+                };
+                match extension.to_str() {
+                    Some("rtbw") => wdl_paths.push((name.to_string(), path.clone())),
+                    Some("rtbz") => { dtz_paths.entry(name.to_string()).or_insert(path.clone()); }
+                    _ => ()
+                }
+            }
+        }
+
+        let mut tablebases = Tablebases::default();
+        for (name, wdl_path) in wdl_paths {
+            let Some(counts) = parse_table_name(&name) else {
+                continue;
+            };
+            let key = material_key(&counts);
+            if tablebases.tables.contains_key(&key) {
+                continue;
+            }
+            let entry = Arc::new(TableEntry {
+                info: table_info(&counts),
+                key,
+                wdl_path,
+                dtz_path: dtz_paths.get(&name).cloned(),
+                wdl: OnceLock::new(),
+                dtz: OnceLock::new()
+            });
+            tablebases.max_pieces = tablebases.max_pieces.max(entry.info.piece_count);
+            tablebases.table_count += 1;
+            tablebases.tables.insert(material_key(&swap_colors(&counts)), Arc::clone(&entry));
+            tablebases.tables.insert(key, entry);
+        }
+        return Ok(tablebases);
+    }
+
+    /// Number of WDL tables found
+    pub fn len(&self) -> usize {
+        return self.table_count;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.table_count == 0;
+    }
+
+    /// Largest number of pieces, including kings, of the tables found
+    pub fn max_pieces(&self) -> usize {
+        return self.max_pieces;
+    }
+
+    /// Returns true if the position may be in the tables: it has few enough pieces and no castling rights
+    pub fn can_probe(&self, board: &Board) -> bool {
+        return board.piece_count() <= self.max_pieces && board.get_castling_u8() == 0 && board.get_variant() == Variant::Standard;
+    }
+
+    /// Result of the position for the player to move. `None` if a table is missing.
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        return self.search(board, false).map(|(wdl, _)| wdl);
+    }
+
+    /// Plies until the next capture or pawn move when following the best line, positive if the player to move
+    /// wins and negative if they lose. Cursed wins and blessed losses are 100 plies further away, draws are 0.
+    /// The count can be one ply too high, but never changes a win into a draw by the fifty-move rule.
+    pub fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        // The table does not store a useful value if a capture or pawn move is best
+        if zeroing_best {
+            return Some(wdl.dtz_before_zeroing());
+        }
+        match self.probe_table(board, true, wdl)? {
+            TableValue::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                return Some((dtz + if cursed { 100 } else { 0 }) * (wdl as i32).signum());
+            }
+            TableValue::OtherSide => ()
+        }
+
+        // The table only stores the other side to move, take the best move found by probing after each move
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let mut best_dtz = None;
+        for mv in moves.iter() {
+            let zeroing = is_zeroing_move(board, mv);
+            board.make_move(mv);
+            // The DTZ of a zeroing move is the one before making it, which only depends on its result
+            let dtz = match zeroing {
+                true => self.search(board, false).map(|(wdl, _)| -wdl.dtz_before_zeroing()),
+                false => self.probe_dtz(board).map(|dtz| -dtz)
+            };
+            let is_mate = dtz == Some(1) && board.is_in_check() && has_no_moves(board);
+            board.unmake_move(mv);
+            let mut dtz = dtz?;
+            if is_mate {
+                best_dtz = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            // Winning moves minimize the distance, losing moves maximize it
+            if dtz.signum() == (wdl as i32).signum() && best_dtz.is_none_or(|best| dtz < best) {
+                best_dtz = Some(dtz);
+            }
+        }
+        return Some(best_dtz.unwrap_or(-1));
+    }
+
+    /// Rank the legal moves of a position by the result they lead to, higher is better. Wins which are safe
+    /// from the fifty-move rule rank 1000 and losses -1000, with moves approaching the fifty-move limit ranked
+    /// in between and draws at 0. Uses DTZ tables if they are available and otherwise only WDL tables.
+    /// Returns `None` if the position is not in the tables.
+    pub fn rank_root_moves(&self, board: &mut Board) -> Option<Vec<(Move, i32)>> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        return self.rank_root_moves_dtz(board).or_else(|| self.rank_root_moves_wdl(board));
+    }
+
+    fn rank_root_moves_dtz(&self, board: &mut Board) -> Option<Vec<(Move, i32)>> {
+        let quiet_moves = board.get_quiet_moves() as i32;
+        let repeated = board.is_repetition();
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let mut ranked_moves = Vec::new();
+        for mv in moves.iter() {
+            board.make_move(mv);
+            let mut dtz = match board.get_quiet_moves() {
+                0 => self.probe_wdl(board).map(|wdl| (-wdl).dtz_before_zeroing()),
+                _ => self.probe_dtz(board).map(|dtz| -dtz - dtz.signum())
+            };
+            if dtz == Some(2) && board.is_in_check() && has_no_moves(board) {
+                dtz = Some(1);
+            }
+            board.unmake_move(mv);
+            let dtz = dtz?;
+            let rank = match dtz {
+                dtz if dtz > 0 && dtz + quiet_moves <= 99 && !repeated => 1000,
+                dtz if dtz > 0 => 1000 - (dtz + quiet_moves),
+                dtz if dtz < 0 && -dtz * 2 + quiet_moves < 100 => -1000,
+                dtz if dtz < 0 => -1000 + (-dtz + quiet_moves),
+                _ => 0
+            };
+            ranked_moves.push((*mv, rank));
+        }
+        return Some(ranked_moves);
+    }
+
+    fn rank_root_moves_wdl(&self, board: &mut Board) -> Option<Vec<(Move, i32)>> {
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let mut ranked_moves = Vec::new();
+        for mv in moves.iter() {
+            board.make_move(mv);
+            let wdl = self.probe_wdl(board);
+            board.unmake_move(mv);
+            let rank = match -wdl? {
+                Wdl::Win => 1000,
+                Wdl::CursedWin => 899,
+                Wdl::Draw => 0,
+                Wdl::BlessedLoss => -899,
+                Wdl::Loss => -1000
+            };
+            ranked_moves.push((*mv, rank));
+        }
+        return Some(ranked_moves);
+    }
+
+    /// Best result of the captures of the position, and also pawn moves if `zeroing_moves` is true, or the table
+    /// value if that is better. Also returns whether a zeroing move gives the result, in which case the DTZ
+    /// table may not store a useful value.
+    fn search(&self, board: &mut Board, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let mut moves = MoveList::empty();
+        board.get_moves(&mut moves, false);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for mv in moves.iter() {
+            if !(is_capture(board, mv) || zeroing_moves && is_pawn_move(board, mv)) {
+                continue;
+            }
+            searched += 1;
+            board.make_move(mv);
+            let result = self.search(board, false);
+            board.unmake_move(mv);
+            let wdl = -result?.0;
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // Without any other moves the table value may be wrong, for example if the only legal move is en passant
+        let all_moves_searched = searched > 0 && searched == moves.len();
+        let value = match all_moves_searched {
+            true => best,
+            false => match self.probe_table(board, false, Wdl::Draw)? {
+                TableValue::Value(value) => Wdl::from_i32(value)?,
+                TableValue::OtherSide => return None
+            }
+        };
+        if best >= value {
+            return Some((best, best > Wdl::Draw || all_moves_searched));
+        }
+        return Some((value, false));
+    }
+
+    /// Look up the position in its WDL or DTZ table, `wdl` is the result of the position for DTZ tables
+    fn probe_table(&self, board: &Board, is_dtz: bool, wdl: Wdl) -> Option<TableValue> {
+        let mut counts = [0; 12];
+        let mut pieces = Vec::with_capacity(TB_PIECES);
+        for square in 0..64 {
+            let piece = board.get_piece(square);
+            if piece != Piece::Empty {
+                counts[piece.to_u8() as usize] += 1;
+                pieces.push((square as usize, piece));
+            }
+        }
+        // Only the kings are left
+        if pieces.len() == 2 {
+            return Some(TableValue::Value(0));
+        }
+        let key = material_key(&counts);
+        let entry = self.tables.get(&key)?;
+        let data = entry.data(is_dtz)?;
+
+        // Tables are stored with the stronger side as white, and tables of symmetric material only store white to
+        // move. Other positions are looked up with the colors swapped and the board flipped vertically.
+        let black_to_move = board.get_current_player() == Color::Black;
+        let flip = key != entry.key || (entry.info.symmetric && black_to_move);
+        let stm = (flip ^ black_to_move) as usize;
+        let position: Vec<(usize, u8)> = pieces.iter().map(|&(square, piece)| {
+            // Squares of the tables start at a1 instead of a8
+            let square = square ^ 56 ^ if flip { 56 } else { 0 };
+            return (square, piece_code(piece) ^ if flip { 8 } else { 0 });
+        }).collect();
+        return match is_dtz {
+            true => data.lookup_dtz(&entry.info, &position, stm, wdl as i32),
+            false => data.lookup_wdl(&entry.info, &position, stm)
+        };
+    }
+}
+
+fn is_pawn_move(board: &Board, mv: &Move) -> bool {
+    return matches!(board.get_piece(mv.from), Piece::WhitePawn | Piece::BlackPawn);
+}
+
+/// Captures, including en passant
+fn is_capture(board: &Board, mv: &Move) -> bool {
+    return mv.captured != Piece::Empty || (is_pawn_move(board, mv) && mv.from % 8 != mv.to % 8);
+}
+
+/// Captures and pawn moves, which reset the fifty-move counter
+fn is_zeroing_move(board: &Board, mv: &Move) -> bool {
+    return mv.captured != Piece::Empty || is_pawn_move(board, mv);
+}
+
+fn has_no_moves(board: &Board) -> bool {
+    let mut moves = MoveList::empty();
+    board.get_moves(&mut moves, false);
+    return moves.len() == 0;
+}
+
+/// Piece code of the table format
+fn piece_code(piece: Piece) -> u8 {
+    let code = match piece {
+        Piece::WhitePawn | Piece::BlackPawn => 1,
+        Piece::WhiteKnight | Piece::BlackKnight => 2,
+        Piece::WhiteBishop | Piece::BlackBishop => 3,
+        Piece::WhiteRook | Piece::BlackRook => 4,
+        Piece::WhiteQueen | Piece::BlackQueen => 5,
+        Piece::WhiteKing | Piece::BlackKing | Piece::Empty => 6
+    };
+    return if piece.is_black() { code + 8 } else { code };
+}
+
+/// Key of the material of a position, from the count of each piece
+fn material_key(counts: &[u8; 12]) -> u64 {
+    return counts.iter().enumerate().fold(0, |key, (piece, &count)| key | (count as u64) << (4 * piece));
+}
+
+fn swap_colors(counts: &[u8; 12]) -> [u8; 12] {
+    let mut swapped = [0; 12];
+    swapped[..6].copy_from_slice(&counts[6..]);
+    swapped[6..].copy_from_slice(&counts[..6]);
+    return swapped;
+}
+
+/// Piece counts of a table name such as `KRPvKR`, with the first side as white
+fn parse_table_name(name: &str) -> Option<[u8; 12]> {
+    let (white, black) = name.split_once('v')?;
+    let mut counts = [0; 12];
+    for (side, offset) in [(white, 0), (black, 6)] {
+        if !side.starts_with('K') || side[1..].contains('K') {
+            return None;
+        }
+        for c in side.chars() {
+            let piece = match c {
+                'P' | 'B' | 'N' | 'R' | 'Q' | 'K' => Piece::from_char(c),
+                _ => return None
+            };
+            counts[piece.to_u8() as usize + offset] += 1;
+        }
+    }
+    if counts.iter().map(|&count| count as usize).sum::<usize>() > TB_PIECES {
+        return None;
+    }
+    return Some(counts);
+}
+
+fn table_info(counts: &[u8; 12]) -> TableInfo {
+    let white_pawns = counts[Piece::WhitePawn.to_u8() as usize] as usize;
+    let black_pawns = counts[Piece::BlackPawn.to_u8() as usize] as usize;
+    // The leading color is the one with fewer pawns, as long as it has any
+    let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+    let is_king = |piece: usize| piece == Piece::WhiteKing.to_u8() as usize || piece == Piece::BlackKing.to_u8() as usize;
+    return TableInfo {
+        piece_count: counts.iter().map(|&count| count as usize).sum(),
+        has_pawns: white_pawns + black_pawns > 0,
+        has_unique_pieces: counts.iter().enumerate().any(|(piece, &count)| !is_king(piece) && count == 1),
+        pawn_counts: match white_leads {
+            true => [white_pawns, black_pawns],
+            false => [black_pawns, white_pawns]
+        },
+        symmetric: *counts == swap_colors(counts)
+    };
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::{Move, Piece};
+    use crate::tablebase::generator::tests::KPK_TABLES;
+    use crate::tablebase::{Dtm, Material};
+    use super::{material_key, parse_table_name, swap_colors, table_info, Tablebases, Wdl};
+
+    /// Directory of the official Syzygy files of `FIXTURE_TABLES`
+    pub(crate) const FIXTURE_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+    /// KBvK and KNvK are probed after the underpromotions of KPvK
+    const FIXTURE_TABLES: [&str; 7] = ["KQvK", "KRvK", "KBvK", "KNvK", "KPvK", "KBNvK", "KQvKR"];
+
+    fn board(fen: &str) -> Board {
+        return Board::from_fen(fen, Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+    }
+
+    /// Assert the DTZ of a position. Only tables which store DTZ in moves instead of plies may give one ply more.
+    fn assert_dtz(tablebases: &Tablebases, board: &mut Board, expected: i32) {
+        let fen = board.to_fen();
+        let probed = tablebases.probe_dtz(board).unwrap_or_else(|| panic!("{} is not in the tables", fen));
+        let mut counts = [0; 12];
+        for square in 0..64 {
+            let piece = board.get_piece(square);
+            if piece != Piece::Empty {
+                counts[piece.to_u8() as usize] += 1;
+            }
+        }
+        let entry = &tablebases.tables[&material_key(&counts)];
+        let in_plies = entry.data(true).unwrap().dtz_in_plies(&entry.info);
+        let rounded_up = !in_plies && probed == expected + expected.signum();
+        assert!(probed == expected || rounded_up, "{} has a DTZ of {} instead of {}", fen, probed, expected);
+    }
+
+    #[test]
+    fn test_table_names() {
+        let counts = parse_table_name("KRPvKR").unwrap();
+        let info = table_info(&counts);
+        assert_eq!((info.piece_count, info.has_pawns, info.has_unique_pieces, info.symmetric), (5, true, true, false));
+        assert_eq!(info.pawn_counts, [1, 0]);
+        // The side with fewer pawns leads
+        assert_eq!(table_info(&parse_table_name("KPPvKP").unwrap()).pawn_counts, [1, 2]);
+        let symmetric = table_info(&parse_table_name("KNNvKNN").unwrap());
+        assert!(symmetric.symmetric && !symmetric.has_unique_pieces);
+        assert_ne!(material_key(&counts), material_key(&swap_colors(&counts)));
+
+        assert_eq!(parse_table_name("KRvKK"), None);
+        assert_eq!(parse_table_name("RKvK"), None);
+        assert_eq!(parse_table_name("KQRBNPvKQ"), None);
+        assert_eq!(parse_table_name("KXvK"), None);
+    }
+
+    #[test]
+    fn test_without_tables() {
+        let tablebases = Tablebases::default();
+        let mut kings = board("8/8/4k3/8/8/3K4/8/8 w - - 0 1");
+        assert!(!tablebases.can_probe(&kings));
+        assert_eq!(tablebases.probe_wdl(&mut kings), None);
+        assert!(Tablebases::load("/nonexistent/syzygy").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs the official Syzygy files of FIXTURE_TABLES in core/tests/syzygy"]
+    fn test_probe_fixtures() {
+        let tablebases = Tablebases::load(FIXTURE_DIRECTORY).unwrap();
+        for name in FIXTURE_TABLES {
+            for extension in ["rtbw", "rtbz"] {
+                let path = Path::new(FIXTURE_DIRECTORY).join(format!("{}.{}", name, extension));
+                assert!(path.exists(), "{} is missing", path.display());
+            }
+        }
+        assert_eq!((tablebases.len(), tablebases.max_pieces()), (FIXTURE_TABLES.len(), 4));
+
+        let cases = [
+            ("8/8/8/4k3/8/8/8/KQ6 w - - 0 1", Wdl::Win),
+            ("8/8/8/4k3/8/8/8/KQ6 b - - 0 1", Wdl::Loss),
+            // The queen can be captured
+            ("8/8/8/8/8/8/2k5/K2Q4 b - - 0 1", Wdl::Draw),
+            ("8/8/8/8/8/3k4/8/K1R5 w - - 0 1", Wdl::Win),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+            ("8/8/8/8/8/8/k6P/7K w - - 0 1", Wdl::Win),
+            // Stalemate, and a rook pawn with the king in the corner
+            ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+            ("7k/8/8/8/8/8/7P/7K w - - 0 1", Wdl::Draw),
+            ("8/8/8/4k3/8/8/8/KBN5 w - - 0 1", Wdl::Win),
+            // The queen captures the rook
+            ("8/8/8/8/8/2k5/8/KQ5r w - - 0 1", Wdl::Win),
+            // The stronger side is black
+            ("7k/8/6q1/8/8/8/8/K7 b - - 0 1", Wdl::Win),
+            ("7k/8/8/8/8/4q3/8/7K w - - 0 1", Wdl::Loss)
+        ];
+        for (fen, expected) in cases {
+            assert_eq!(tablebases.probe_wdl(&mut board(fen)), Some(expected), "{}", fen);
+        }
+
+        // Mate in one, and a pawn move which wins. The pawn move is a zeroing move, so its DTZ is 1.
+        assert_dtz(&tablebases, &mut board("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), 1);
+        assert_dtz(&tablebases, &mut board("8/8/8/8/8/8/k6P/7K w - - 0 1"), 1);
+        // Every move of black is answered by the winning pawn move
+        assert_dtz(&tablebases, &mut board("8/8/8/8/8/8/KP5k/8 b - - 0 1"), -2);
+        assert_dtz(&tablebases, &mut board("7k/8/8/8/8/8/7P/7K w - - 0 1"), 0);
+        // Four piece tables, with the DTZ of the retrograde analysis. The rook can be won before mating.
+        let cases = [
+            ("8/8/8/4k3/8/8/8/KBN5 w - - 0 1", 59),
+            ("8/8/8/4k3/8/8/8/KBN5 b - - 0 1", -60),
+            ("7k/8/5KBN/8/8/8/8/8 w - - 0 1", 39),
+            ("3r4/8/8/8/5k2/8/8/KQ6 w - - 0 1", 15),
+            ("1r6/8/8/8/4k3/8/8/K4Q2 w - - 0 1", 13),
+            ("3r4/8/8/8/4k3/8/8/KQ6 b - - 0 1", -56)
+        ];
+        for (fen, expected) in cases {
+            assert_dtz(&tablebases, &mut board(fen), expected);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the official Syzygy files of FIXTURE_TABLES in core/tests/syzygy"]
+    fn test_probe_matches_retrograde_analysis() {
+        // A sample of the three piece positions has the result of the retrograde analysis. Without pawns the winning
+        // side never allows a capture, so the DTZ of a position is also its distance to mate.
+        let tablebases = Tablebases::load(FIXTURE_DIRECTORY).unwrap();
+        let (dtm_tables, _) = &*KPK_TABLES;
+        for name in ["KQvK", "KRvK", "KPvK"] {
+            let material = Material::from_name(name).unwrap();
+            let has_pawns = material.pieces().contains(&Piece::WhitePawn);
+            for (index, dtm) in dtm_tables.get(&material).unwrap().iter().enumerate().step_by(23) {
+                let Some(dtm) = dtm else {
+                    continue;
+                };
+                let (squares, player) = material.position(index);
+                let mut board = Board::empty(Arc::new(BOARD_CONSTANT_STATE.clone()));
+                for (&square, &piece) in squares.iter().zip(material.pieces()) {
+                    board.set_piece(square, piece);
+                }
+                if board.get_current_player() != player {
+                    board.switch_current_player();
+                }
+                let (wdl, dtz) = match dtm {
+                    Dtm::Win(plies) => (Wdl::Win, plies as i32),
+                    Dtm::Draw => (Wdl::Draw, 0),
+                    Dtm::Loss(plies) => (Wdl::Loss, -(plies as i32))
+                };
+                assert_eq!(tablebases.probe_wdl(&mut board), Some(wdl), "{}", board.to_fen());
+                // Checkmated positions have no DTZ to compare with
+                if !has_pawns && dtz != 0 {
+                    assert_dtz(&tablebases, &mut board, dtz);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the official Syzygy files of FIXTURE_TABLES in core/tests/syzygy"]
+    fn test_root_moves_fifty_move_counter() {
+        // Every move wins, but with the fifty-move counter at 98 only the pawn moves win before it runs out
+        let tablebases = Tablebases::load(FIXTURE_DIRECTORY).unwrap();
+        let mut board = board("8/8/8/8/8/8/1P5k/K7 w - - 98 60");
+        let ranked_moves = tablebases.rank_root_moves(&mut board).unwrap();
+        for (mv, rank) in ranked_moves {
+            if board.get_piece(mv.from) == Piece::WhitePawn {
+                assert_eq!(rank, 1000, "{:?}", mv);
+                continue;
+            }
+            // The king move is one ply further from zeroing the counter than the position after it
+            board.make_move(&mv);
+            let dtz = tablebases.probe_dtz(&mut board).unwrap();
+            board.unmake_move(&mv);
+            assert!(dtz < 0);
+            assert_eq!(rank, 1000 - (-dtz + 1 + 98), "{:?}", mv);
+        }
+
+        // With a fresh counter the king moves win as well
+        let mut board = self::board("8/8/8/8/8/8/1P5k/K7 w - - 0 1");
+        let ranked_moves = tablebases.rank_root_moves(&mut board).unwrap();
+        assert!(ranked_moves.iter().all(|&(_, rank)| rank == 1000));
+        assert_eq!(ranked_moves.len(), 4);
+        assert!(ranked_moves.iter().any(|&(mv, _)| mv == Move::from_algebraic(&board, "a1a2")));
+    }
+}
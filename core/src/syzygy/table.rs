@@ -0,0 +1,805 @@
+//! Decoding of a single Syzygy table file.
+//!
+//! A table file holds one subtable per side to move (WDL tables of asymmetric material only) and, with pawns,
+//! per file of the leading pawn. Every subtable stores its piece order, from which the position index is
+//! computed, and a canonical Huffman code of symbols which expand to runs of values by recursive pairing.
+//! Positions are given from the view of the table: square 0 is a1 and pieces use the codes of the file format,
+//! 1 to 6 for the white pawn, knight, bishop, rook, queen and king and 9 to 14 for the black ones.
+
+use lazy_static::lazy_static;
+
+/// Maximum number of pieces of a table
+pub const TB_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Flags of a subtable. All but the last one are only used by DTZ tables.
+/// The DTZ subtable stores positions with black to move
+const FLAG_STM: u8 = 1;
+/// DTZ values are stored as indices into a map per WDL result
+const FLAG_MAPPED: u8 = 2;
+/// DTZ values of wins are stored in plies instead of moves
+const FLAG_WIN_PLIES: u8 = 4;
+/// DTZ values of losses are stored in plies instead of moves
+const FLAG_LOSS_PLIES: u8 = 8;
+/// The DTZ map holds 16 bit values
+const FLAG_WIDE: u8 = 16;
+/// All positions of the subtable have the same value
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// Material of a table, the stronger side (the first in the file name) being white
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TableInfo {
+    pub piece_count: usize,
+    pub has_pawns: bool,
+    /// True if a side has a piece other than the king which it only has once
+    pub has_unique_pieces: bool,
+    /// Pawns of the leading color, the one with fewer pawns but at least one, and of the other color
+    pub pawn_counts: [usize; 2],
+    /// Both sides have the same material
+    pub symmetric: bool
+}
+
+/// Indexing tables shared by all table files
+struct Indices {
+    /// Squares a2 to h7 mapped to 0..47, higher for squares nearer to the edge and to the second rank
+    map_pawns: [usize; 64],
+    /// Squares below the a1-h8 diagonal mapped to 0..27
+    map_b1h1h7: [usize; 64],
+    /// The a1-d1-d4 triangle mapped to 0..9, with the diagonal squares last
+    map_a1d1d4: [usize; 64],
+    /// The 462 legal placements of two kings, the first one in the a1-d1-d4 triangle
+    map_kk: [[u64; 64]; 10],
+    /// `binomial[k][n]` ways to choose k of n elements
+    binomial: [[u64; 64]; TB_PIECES],
+    /// Index of the leading pawn square for every number of leading pawns
+    lead_pawn_idx: [[u64; 64]; 6],
+    /// Number of placements of the leading pawns for every number of them and file of the leading pawn
+    lead_pawns_size: [[u64; 4]; 6]
+}
+
+lazy_static! {
+    static ref INDICES: Indices = Indices::new();
+}
+
+/// Rank minus file, zero on the a1-h8 diagonal and negative below it
+fn off_diagonal(square: usize) -> i32 {
+    return (square / 8) as i32 - (square % 8) as i32;
+}
+
+fn king_distance(a: usize, b: usize) -> usize {
+    return (a / 8).abs_diff(b / 8).max((a % 8).abs_diff(b % 8));
+}
+
+impl Indices {
+    fn new() -> Indices {
+        let mut map_b1h1h7 = [0; 64];
+        for (code, square) in (0..64).filter(|&square| off_diagonal(square) < 0).enumerate() {
+            map_b1h1h7[square] = code;
+        }
+
+        let mut map_a1d1d4 = [0; 64];
+        let triangle = (0..=27).filter(|&square| square % 8 <= 3);
+        let below_diagonal = triangle.clone().filter(|&square| off_diagonal(square) < 0);
+        let on_diagonal = triangle.filter(|&square| off_diagonal(square) == 0);
+        for (code, square) in below_diagonal.chain(on_diagonal).enumerate() {
+            map_a1d1d4[square] = code;
+        }
+
+        // If the first king is on the diagonal, the second one is not above it. Placements with both
+        // kings on the diagonal come last.
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (idx, kk) in map_kk.iter_mut().enumerate() {
+            let first = (0..=27).find(|&square| square % 8 <= 3 && off_diagonal(square) <= 0 && map_a1d1d4[square] == idx).unwrap();
+            for second in 0..64 {
+                if king_distance(first, second) <= 1 || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((idx, second));
+                }
+                else {
+                    kk[second] = code;
+                    code += 1;
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        let mut binomial = [[0; 64]; TB_PIECES];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..TB_PIECES.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 } + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // The leading pawn is the one with the highest value, the other leading pawns can only be on squares with
+        // lower values: 47 squares are left with the leading pawn on a2, and 2 fewer for every rank further up
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_idx = [[0; 64]; 6];
+        let mut lead_pawns_size = [[0; 4]; 6];
+        let mut available_squares = 48;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..=6 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        available_squares -= 1;
+                        map_pawns[square] = available_squares;
+                        available_squares -= 1;
+                        map_pawns[square ^ 7] = available_squares;
+                    }
+                    lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += binomial[lead_pawns - 1][map_pawns[square]];
+                }
+                lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+
+        return Indices { map_pawns, map_b1h1h7, map_a1d1d4, map_kk, binomial, lead_pawn_idx, lead_pawns_size };
+    }
+}
+
+/// Indexing and compression data of a subtable, with offsets into the file
+#[derive(Clone, Default, Debug)]
+struct PairsData {
+    flags: u8,
+    /// Piece order of the index, the groups of pieces are encoded in this order
+    pieces: [u8; TB_PIECES],
+    /// Number of pieces in each group, zero terminated
+    group_len: [usize; TB_PIECES + 1],
+    /// Multiplier of each group in the index, the entry after the last group is the size of the subtable
+    group_idx: [u64; TB_PIECES + 1],
+    block_size: usize,
+    /// Every `span` values there is an entry in the sparse index
+    span: u64,
+    block_count: usize,
+    /// Minimum symbol length in bits, or the value of a single value subtable
+    min_sym_len: u8,
+    /// Lowest symbol of each length
+    lowest_sym: usize,
+    /// `base64[l]` is the lowest symbol of length `l + min_sym_len`, left aligned to 64 bits
+    base64: Vec<u64>,
+    /// Number of values represented by each symbol, minus one
+    symlen: Vec<u8>,
+    /// Left and right symbol of each symbol, 12 bits each
+    btree: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_lengths: usize,
+    block_lengths_size: usize,
+    data: usize,
+    /// Start of the DTZ map of wins, losses, cursed wins and blessed losses
+    map_idx: [usize; 4]
+}
+
+/// Result of a table lookup
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TableValue {
+    Value(i32),
+    /// The DTZ table only stores the position with the other side to move
+    OtherSide
+}
+
+/// A table file read into memory
+pub struct TableData {
+    bytes: Vec<u8>,
+    is_dtz: bool,
+    /// Subtables by side to move and file of the leading pawn
+    pairs: [[PairsData; 4]; 2],
+    sides: usize
+}
+
+impl TableData {
+    /// Parse a WDL or DTZ table file of the given material. Returns `None` if the file is corrupt.
+    pub fn parse(bytes: Vec<u8>, info: &TableInfo, is_dtz: bool) -> Option<TableData> {
+        let magic = if is_dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.get(0..4)? != magic || (bytes.get(4)? & 2 != 0) != info.has_pawns {
+            return None;
+        }
+        let mut table = TableData {
+            bytes,
+            is_dtz,
+            pairs: Default::default(),
+            sides: if !is_dtz && !info.symmetric { 2 } else { 1 }
+        };
+        let files = if info.has_pawns { 4 } else { 1 };
+        let both_pawns = info.has_pawns && info.pawn_counts[1] > 0;
+
+        let mut pos = 5;
+        for file in 0..files {
+            let order_byte = table.byte(pos)?;
+            let pawn_order_byte = if both_pawns { table.byte(pos + 1)? } else { 0xFF };
+            let orders = [
+                [order_byte & 0xF, pawn_order_byte & 0xF],
+                [order_byte >> 4, pawn_order_byte >> 4]
+            ];
+            pos += 1 + both_pawns as usize;
+            for k in 0..info.piece_count {
+                let piece_byte = table.byte(pos)?;
+                table.pairs[0][file].pieces[k] = piece_byte & 0xF;
+                table.pairs[1][file].pieces[k] = piece_byte >> 4;
+                pos += 1;
+            }
+            for side in 0..table.sides {
+                set_groups(info, &mut table.pairs[side][file], orders[side], file)?;
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..files {
+            for side in 0..table.sides {
+                let mut pairs = std::mem::take(&mut table.pairs[side][file]);
+                pos = table.set_sizes(&mut pairs, pos)?;
+                table.pairs[side][file] = pairs;
+            }
+        }
+
+        if is_dtz {
+            for file in 0..files {
+                let flags = table.pairs[0][file].flags;
+                if flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                for i in 0..4 {
+                    if flags & FLAG_WIDE != 0 {
+                        pos += pos & 1;
+                        table.pairs[0][file].map_idx[i] = pos + 2;
+                        pos += 2 + 2 * table.u16_le(pos)? as usize;
+                    }
+                    else {
+                        table.pairs[0][file].map_idx[i] = pos + 1;
+                        pos += 1 + table.byte(pos)? as usize;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..table.sides {
+                table.pairs[side][file].sparse_index = pos;
+                pos += table.pairs[side][file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side in 0..table.sides {
+                table.pairs[side][file].block_lengths = pos;
+                pos += table.pairs[side][file].block_lengths_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side in 0..table.sides {
+                pos = (pos + 0x3F) & !0x3F;
+                table.pairs[side][file].data = pos;
+                pos += table.pairs[side][file].block_count * table.pairs[side][file].block_size;
+            }
+        }
+        if pos > table.bytes.len() {
+            return None;
+        }
+        return Some(table);
+    }
+
+    /// Look up the WDL value of a position, from -2 for a loss to 2 for a win. `position` holds the square and
+    /// piece code of every piece, with colors and squares already flipped if the stronger side of the position
+    /// is black. Returns `None` if the table data is corrupt.
+    pub fn lookup_wdl(&self, info: &TableInfo, position: &[(usize, u8)], stm: usize) -> Option<TableValue> {
+        return match self.lookup(info, position, stm)? {
+            (TableValue::Value(value), _) => Some(TableValue::Value(value - 2)),
+            (other_side, _) => Some(other_side)
+        };
+    }
+
+    /// Look up the distance to zeroing the fifty-move counter of a position in plies, given its WDL value
+    pub fn lookup_dtz(&self, info: &TableInfo, position: &[(usize, u8)], stm: usize, wdl: i32) -> Option<TableValue> {
+        return match self.lookup(info, position, stm)? {
+            (TableValue::Value(value), file) => Some(TableValue::Value(self.map_dtz(value, wdl, file)?)),
+            (other_side, _) => Some(other_side)
+        };
+    }
+
+    /// Stored value of a position and the file of the subtable it is stored in
+    fn lookup(&self, info: &TableInfo, position: &[(usize, u8)], stm: usize) -> Option<(TableValue, usize)> {
+        let indices = &*INDICES;
+        let mut squares = [0; TB_PIECES];
+        let mut pieces = [0; TB_PIECES];
+        let mut size = 0;
+        let mut file = 0;
+
+        // The pawns of the leading color come first. The one with the highest `map_pawns` value is the leading
+        // pawn, its file selects the subtable.
+        let lead_pawn = if info.has_pawns { Some(self.pairs[0][0].pieces[0]) } else { None };
+        for &(square, piece) in position.iter().filter(|&&(_, piece)| Some(piece) == lead_pawn) {
+            squares[size] = square;
+            pieces[size] = piece;
+            size += 1;
+        }
+        let lead_pawn_count = size;
+        if lead_pawn_count > 0 {
+            let leading = (0..lead_pawn_count).max_by_key(|&i| indices.map_pawns[squares[i]]).unwrap();
+            squares.swap(0, leading);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+
+        if self.is_dtz {
+            let stored_stm = (self.pairs[0][file].flags & FLAG_STM) as usize;
+            if stored_stm != stm && (!info.symmetric || info.has_pawns) {
+                return Some((TableValue::OtherSide, file));
+            }
+        }
+
+        for &(square, piece) in position.iter().filter(|&&(_, piece)| Some(piece) != lead_pawn) {
+            if size == TB_PIECES {
+                return None;
+            }
+            squares[size] = square;
+            pieces[size] = piece;
+            size += 1;
+        }
+        if size != info.piece_count {
+            return None;
+        }
+
+        // Reorder the pieces to the order of the subtable
+        let pairs = &self.pairs[stm % self.sides][file];
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| pairs.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // Mirror the board so the leading piece is on files a to d
+        if squares[0] % 8 > 3 {
+            for square in squares[..size].iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx = if info.has_pawns {
+            let mut idx = indices.lead_pawn_idx[lead_pawn_count][squares[0]];
+            squares[1..lead_pawn_count].sort_by_key(|&square| indices.map_pawns[square]);
+            for i in 1..lead_pawn_count {
+                idx += indices.binomial[i][indices.map_pawns[squares[i]]];
+            }
+            idx
+        }
+        else {
+            // Without pawns the board is also mirrored so the leading piece is on ranks 1 to 4, and along the
+            // diagonal so the first piece of the leading group which is not on the diagonal is below it
+            if squares[0] / 8 > 3 {
+                for square in squares[..size].iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            if let Some(i) = (0..pairs.group_len[0]).find(|&i| off_diagonal(squares[i]) != 0) {
+                if off_diagonal(squares[i]) > 0 {
+                    for square in squares[i..size].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+            }
+            encode_leading_pieces(info, &squares)
+        };
+
+        // Encode the remaining groups, every piece of a group by its square among the ones left free by earlier groups
+        idx *= pairs.group_idx[0];
+        let mut start = pairs.group_len[0];
+        let mut remaining_pawns = info.has_pawns && info.pawn_counts[1] > 0;
+        let mut group = 1;
+        while pairs.group_len[group] != 0 {
+            let end = start + pairs.group_len[group];
+            if end > size {
+                return None;
+            }
+            squares[start..end].sort();
+            let mut n = 0;
+            for i in start..end {
+                let adjust = squares[..start].iter().filter(|&&square| squares[i] > square).count();
+                let free_square = (squares[i] - adjust).checked_sub(if remaining_pawns { 8 } else { 0 })?;
+                n += indices.binomial[i - start + 1][free_square];
+            }
+            remaining_pawns = false;
+            idx += n * pairs.group_idx[group];
+            start = end;
+            group += 1;
+        }
+
+        return Some((TableValue::Value(self.decompress(pairs, idx)?), file));
+    }
+
+    /// Returns true if every subtable of a DTZ table stores wins and losses in plies. Otherwise values are stored
+    /// in moves, and an even number of plies reads back as one ply more.
+    #[cfg(test)]
+    pub fn dtz_in_plies(&self, info: &TableInfo) -> bool {
+        let files = if info.has_pawns { 4 } else { 1 };
+        let plies = FLAG_WIN_PLIES | FLAG_LOSS_PLIES;
+        return self.pairs[0][..files].iter().all(|pairs| pairs.flags & plies == plies);
+    }
+
+    /// Convert a stored DTZ value to plies, given the WDL value of the position
+    fn map_dtz(&self, value: i32, wdl: i32, file: usize) -> Option<i32> {
+        // Index of the map of each WDL result: loss, blessed loss, draw, cursed win, win
+        const WDL_MAPS: [usize; 5] = [1, 3, 0, 2, 0];
+        let pairs = &self.pairs[0][file];
+        let flags = pairs.flags;
+        let mut value = value;
+        if flags & FLAG_MAPPED != 0 {
+            let map = pairs.map_idx[WDL_MAPS[(wdl + 2) as usize]];
+            value = match flags & FLAG_WIDE != 0 {
+                true => self.u16_le(map + 2 * value as usize)? as i32,
+                false => self.byte(map + value as usize)? as i32
+            };
+        }
+        let in_plies = match wdl {
+            2 => flags & FLAG_WIN_PLIES != 0,
+            -2 => flags & FLAG_LOSS_PLIES != 0,
+            _ => false
+        };
+        if !in_plies {
+            value *= 2;
+        }
+        return Some(value + 1);
+    }
+
+    /// Read the sizes and the Huffman code of a subtable starting at `pos`, returning the position after it
+    fn set_sizes(&self, pairs: &mut PairsData, mut pos: usize) -> Option<usize> {
+        pairs.flags = self.byte(pos)?;
+        pos += 1;
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            pairs.min_sym_len = self.byte(pos)?;
+            return Some(pos + 1);
+        }
+
+        let group_count = pairs.group_len.iter().position(|&len| len == 0)?;
+        let table_size = pairs.group_idx[group_count];
+        pairs.block_size = 1 << self.byte(pos)?.min(31);
+        pairs.span = 1 << self.byte(pos + 1)?.min(63);
+        pairs.sparse_index_size = table_size.div_ceil(pairs.span) as usize;
+        let padding = self.byte(pos + 2)? as usize;
+        pairs.block_count = self.u32_le(pos + 3)? as usize;
+        pairs.block_lengths_size = pairs.block_count + padding;
+        let max_sym_len = self.byte(pos + 7)? as usize;
+        pairs.min_sym_len = self.byte(pos + 8)?;
+        let min_sym_len = pairs.min_sym_len as usize;
+        pos += 9;
+        if min_sym_len == 0 || max_sym_len < min_sym_len || max_sym_len > 64 {
+            return None;
+        }
+
+        // Canonical Huffman code: longer symbols have lower values, all symbols of a length are consecutive
+        pairs.lowest_sym = pos;
+        let lengths = max_sym_len - min_sym_len + 1;
+        pairs.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = self.u16_le(pos + 2 * i)? as u64;
+            let next_lowest = self.u16_le(pos + 2 * i + 2)? as u64;
+            pairs.base64[i] = (pairs.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in pairs.base64.iter_mut().enumerate() {
+            *base = base.checked_shl((64 - i - min_sym_len) as u32).unwrap_or(0);
+        }
+        pos += lengths * 2;
+
+        let symbol_count = self.u16_le(pos)? as usize;
+        pos += 2;
+        pairs.btree = pos;
+        self.bytes.get(pos..pos + symbol_count * 3)?;
+        pairs.symlen = vec![0; symbol_count];
+        let mut visited = vec![false; symbol_count];
+        for symbol in 0..symbol_count {
+            if !visited[symbol] {
+                pairs.symlen[symbol] = self.set_symlen(pairs, symbol, &mut visited)?;
+            }
+        }
+        return Some(pos + symbol_count * 3 + (symbol_count & 1));
+    }
+
+    /// Number of values represented by `symbol` minus one, found by expanding it into its pair of symbols
+    fn set_symlen(&self, pairs: &mut PairsData, symbol: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[symbol] = true;
+        let right = self.btree_right(pairs, symbol)?;
+        if right == 0xFFF {
+            return Some(0);
+        }
+        let left = self.btree_left(pairs, symbol)?;
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                pairs.symlen[child] = self.set_symlen(pairs, child, visited)?;
+            }
+        }
+        return Some(pairs.symlen[left].wrapping_add(pairs.symlen[right]).wrapping_add(1));
+    }
+
+    /// Value at index `idx` of a subtable
+    fn decompress(&self, pairs: &PairsData, idx: u64) -> Option<i32> {
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(pairs.min_sym_len as i32);
+        }
+
+        // The sparse index gives the block and offset of every `span`th value, from which the block holding
+        // `idx` is found by walking the block lengths
+        let k = (idx / pairs.span) as usize;
+        if k >= pairs.sparse_index_size {
+            return None;
+        }
+        let entry = pairs.sparse_index + 6 * k;
+        let mut block = self.u32_le(entry)? as usize;
+        let mut offset = self.u16_le(entry + 4)? as i64 + (idx % pairs.span) as i64 - (pairs.span / 2) as i64;
+        let block_length = |block: usize| -> Option<i64> {
+            if block >= pairs.block_lengths_size {
+                return None;
+            }
+            return Some(self.u16_le(pairs.block_lengths + 2 * block)? as i64);
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        // Read symbols until the one holding the value at `offset` of the block
+        let min_sym_len = pairs.min_sym_len as usize;
+        let mut ptr = pairs.data + block * pairs.block_size;
+        let mut buffer = self.u64_be(ptr)?;
+        let mut buffer_size = 64;
+        ptr += 8;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while buffer < pairs.base64[len] {
+                len += 1;
+            }
+            symbol = ((buffer - pairs.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            symbol += self.u16_le(pairs.lowest_sym + 2 * len)? as usize;
+            let values = *pairs.symlen.get(symbol)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            len += min_sym_len;
+            buffer = buffer.checked_shl(len as u32).unwrap_or(0);
+            buffer_size -= len;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (self.u32_be(ptr).unwrap_or(0) as u64) << (64 - buffer_size);
+                ptr += 4;
+            }
+        }
+
+        // Expand the symbol into its pair of symbols until reaching a single value
+        while pairs.symlen[symbol] != 0 {
+            let left = self.btree_left(pairs, symbol)?;
+            let left_values = *pairs.symlen.get(left)? as i64 + 1;
+            if offset < left_values {
+                symbol = left;
+            }
+            else {
+                offset -= left_values;
+                symbol = self.btree_right(pairs, symbol)?;
+            }
+            pairs.symlen.get(symbol)?;
+        }
+        return Some(self.btree_left(pairs, symbol)? as i32);
+    }
+
+    fn btree_left(&self, pairs: &PairsData, symbol: usize) -> Option<usize> {
+        let pos = pairs.btree + 3 * symbol;
+        return Some(((self.byte(pos + 1)? as usize & 0xF) << 8) | self.byte(pos)? as usize);
+    }
+
+    fn btree_right(&self, pairs: &PairsData, symbol: usize) -> Option<usize> {
+        let pos = pairs.btree + 3 * symbol;
+        return Some(((self.byte(pos + 2)? as usize) << 4) | (self.byte(pos + 1)? as usize >> 4));
+    }
+
+    fn byte(&self, pos: usize) -> Option<u8> {
+        return self.bytes.get(pos).copied();
+    }
+
+    fn u16_le(&self, pos: usize) -> Option<u16> {
+        return Some(u16::from_le_bytes(self.bytes.get(pos..pos + 2)?.try_into().unwrap()));
+    }
+
+    fn u32_le(&self, pos: usize) -> Option<u32> {
+        return Some(u32::from_le_bytes(self.bytes.get(pos..pos + 4)?.try_into().unwrap()));
+    }
+
+    fn u32_be(&self, pos: usize) -> Option<u32> {
+        return Some(u32::from_be_bytes(self.bytes.get(pos..pos + 4)?.try_into().unwrap()));
+    }
+
+    fn u64_be(&self, pos: usize) -> Option<u64> {
+        return Some(u64::from_be_bytes(self.bytes.get(pos..pos + 8)?.try_into().unwrap()));
+    }
+}
+
+/// Group the pieces of a subtable and compute the multiplier of every group in the index. Pieces of the same type
+/// and color form a group, except for the leading group: the leading pawns, three unique pieces or the two kings.
+/// `order` gives the position of the leading group and of the other color's pawns in the index.
+fn set_groups(info: &TableInfo, pairs: &mut PairsData, order: [u8; 2], file: usize) -> Option<()> {
+    let indices = &*INDICES;
+    let mut first_len: i32 = if info.has_pawns { 0 } else if info.has_unique_pieces { 3 } else { 2 };
+    let mut n = 0;
+    pairs.group_len[0] = 1;
+    for i in 1..info.piece_count {
+        first_len -= 1;
+        if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+            pairs.group_len[n] += 1;
+        }
+        else {
+            n += 1;
+            pairs.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    pairs.group_len[n] = 0;
+
+    let both_pawns = info.has_pawns && info.pawn_counts[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free_squares = 64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+    let mut idx: u64 = 1;
+    let mut k = 0;
+    while next < n || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            pairs.group_idx[0] = idx;
+            idx *= match info.has_pawns {
+                true => *indices.lead_pawns_size.get(pairs.group_len[0])?.get(file)?,
+                false if info.has_unique_pieces => 31332,
+                false => 462
+            };
+        }
+        else if k == order[1] as usize {
+            pairs.group_idx[1] = idx;
+            idx *= indices.binomial.get(pairs.group_len[1])?[48 - pairs.group_len[0]];
+        }
+        else {
+            pairs.group_idx[next] = idx;
+            idx *= indices.binomial.get(pairs.group_len[next])?[free_squares];
+            free_squares = free_squares.checked_sub(pairs.group_len[next])?;
+            next += 1;
+        }
+        k += 1;
+    }
+    pairs.group_idx[n] = idx;
+    return Some(());
+}
+
+/// Index of the leading group of a table without pawns: three unique pieces, or the two kings. The leading
+/// piece is in the a1-d1-d4 triangle and the first piece off the diagonal is below it.
+fn encode_leading_pieces(info: &TableInfo, squares: &[usize; TB_PIECES]) -> u64 {
+    let indices = &*INDICES;
+    if !info.has_unique_pieces {
+        return indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]];
+    }
+    let [first, second, third] = [squares[0], squares[1], squares[2]];
+    let adjust1 = (second > first) as usize;
+    let adjust2 = (third > first) as usize + (third > second) as usize;
+    let rank = |square: usize| square / 8;
+    let idx = if off_diagonal(first) != 0 {
+        (indices.map_a1d1d4[first] * 63 + second - adjust1) * 62 + third - adjust2
+    }
+    else if off_diagonal(second) != 0 {
+        (6 * 63 + rank(first) * 28 + indices.map_b1h1h7[second]) * 62 + third - adjust2
+    }
+    else if off_diagonal(third) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62 + rank(first) * 7 * 28 + (rank(second) - adjust1) * 28 + indices.map_b1h1h7[third]
+    }
+    else {
+        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(first) * 7 * 6 + (rank(second) - adjust1) * 6 + rank(third) - adjust2
+    };
+    return idx as u64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{king_distance, off_diagonal, TableData, TableInfo, TableValue, INDICES, WDL_MAGIC};
+
+    /// Value stored at each index of the synthetic table
+    fn synthetic_value(idx: usize) -> u8 {
+        return if idx % 3 == 0 || idx % 7 == 0 { 4 } else { 0 };
+    }
+
+    /// A KQvK WDL table with two symbols of one bit, for a loss and a win, in blocks of 256 values
+    fn synthetic_table() -> Vec<u8> {
+        const SIZE: usize = 31332;
+        let blocks = SIZE.div_ceil(256);
+        let mut bytes = WDL_MAGIC.to_vec();
+        bytes.push(1);
+        bytes.push(0);
+        bytes.extend([6 | 6 << 4, 5 | 5 << 4, 14 | 14 << 4, 0]);
+        for _ in 0..2 {
+            bytes.extend([0, 5, 8, 0]);
+            bytes.extend((blocks as u32).to_le_bytes());
+            bytes.extend([1, 1, 0, 0, 2, 0]);
+            bytes.extend([0, 0xF0, 0xFF, 4, 0xF0, 0xFF]);
+        }
+        for _ in 0..2 {
+            for block in 0..blocks {
+                bytes.extend((block as u32).to_le_bytes());
+                bytes.extend(128u16.to_le_bytes());
+            }
+        }
+        for _ in 0..2 {
+            for block in 0..blocks {
+                bytes.extend(((256.min(SIZE - block * 256) - 1) as u16).to_le_bytes());
+            }
+        }
+        for _ in 0..2 {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            for block in 0..blocks {
+                let mut block_bytes = [0u8; 32];
+                for i in 0..256.min(SIZE - block * 256) {
+                    if synthetic_value(block * 256 + i) == 4 {
+                        block_bytes[i / 8] |= 0x80 >> (i % 8);
+                    }
+                }
+                bytes.extend(block_bytes);
+            }
+        }
+        return bytes;
+    }
+
+    #[test]
+    fn test_indices() {
+        let indices = &*INDICES;
+        // Two kings have 462 legal placements up to symmetry
+        let kk_codes: Vec<u64> = (0..10).flat_map(|idx| (0..64).map(move |square| indices.map_kk[idx][square])).collect();
+        assert_eq!(kk_codes.iter().max(), Some(&461));
+        assert_eq!(indices.map_a1d1d4[1], 0);
+        assert_eq!(indices.map_a1d1d4[0], 6);
+        assert_eq!(indices.map_a1d1d4[27], 9);
+        assert_eq!((0..64).filter(|&square| off_diagonal(square) < 0).map(|square| indices.map_b1h1h7[square]).max(), Some(27));
+        assert_eq!(indices.binomial[2][5], 10);
+        assert_eq!(indices.binomial[6][63], 67945521);
+
+        // The pawns on the a and h files of rank 2 have the highest values
+        assert_eq!((indices.map_pawns[8], indices.map_pawns[15]), (47, 46));
+        assert_eq!(indices.lead_pawn_idx[1][8], 0);
+        // With one leading pawn, there are 6 placements per file
+        assert_eq!(indices.lead_pawns_size[1], [6, 6, 6, 6]);
+        // Every placement of two pawns on a2 to h7 is counted exactly once, up to mirroring
+        let two_pawns: u64 = indices.lead_pawns_size[2].iter().sum();
+        assert_eq!(two_pawns, (48 * 47 / 2 + 24) / 2);
+    }
+
+    #[test]
+    fn test_synthetic_table() {
+        let info = TableInfo { piece_count: 3, has_pawns: false, has_unique_pieces: true, pawn_counts: [0, 0], symmetric: false };
+        let bytes = synthetic_table();
+        let table = TableData::parse(bytes.clone(), &info, false).unwrap();
+        let pairs = &table.pairs[1][0];
+        assert_eq!((pairs.group_len[0], pairs.group_idx[1], pairs.block_count), (3, 31332, 123));
+        for idx in [0, 1, 127, 128, 129, 255, 256, 5000, 31331] {
+            assert_eq!(table.decompress(pairs, idx), Some(synthetic_value(idx as usize) as i32), "{}", idx);
+        }
+        assert_eq!(table.decompress(pairs, 31332 + 256), None);
+        assert!(TableData::parse(bytes[..bytes.len() - 1].to_vec(), &info, false).is_none());
+        assert!(TableData::parse(bytes, &info, true).is_none());
+
+        // Every legal position has an index inside the table
+        for white_king in 0..64 {
+            for queen in (0..64).filter(|&queen| queen != white_king) {
+                for black_king in (0..64).filter(|&square| square != queen && king_distance(square, white_king) > 1) {
+                    let position = [(white_king, 6), (queen, 5), (black_king, 14)];
+                    for stm in 0..2 {
+                        let value = table.lookup_wdl(&info, &position, stm);
+                        assert!(matches!(value, Some(TableValue::Value(-2 | 2))), "{:?} {:?}", position, value);
+                    }
+                }
+            }
+        }
+    }
+}
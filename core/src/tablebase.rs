@@ -0,0 +1,487 @@
+//! Generation and probing of distance-to-mate endgame tables.
+//!
+//! Tables of up to four pieces are generated by retrograde analysis, see [`generator`]. Every position stores the
+//! plies to mate with best play: the fewest for the winning side and the most for the losing side. Captures and
+//! promotions lead out of a table and are looked up in the table of the resulting material. The fifty-move rule
+//! and en passant captures are not taken into account.
+//!
+//! Table files (`.dtm`) start with the bytes `MDTM`, a version byte, the piece count and the pieces, followed by one
+//! byte per position: 0 for draws, 255 for illegal positions and otherwise one more than the plies to mate, where
+//! odd plies are wins for the player to move and even plies losses. Positions are stored once per symmetry: the
+//! white king is mirrored into the a1-d1-d4 triangle, or onto the a-d files if there are pawns. Tables are stored
+//! with the stronger side as white, other positions are looked up with the colors swapped.
+
+pub mod generator;
+pub mod kpk;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+
+use crate::core::bitboard::constants::PIECE_VALUES;
+use crate::core::bitboard::Board;
+use crate::core::{Color, Piece};
+use crate::core::variant::Variant;
+use crate::syzygy::PATH_SEPARATOR;
+
+/// Maximum number of pieces of a table, including the kings
+pub const MAX_PIECES: usize = 4;
+/// File extension of the tables
+pub const EXTENSION: &str = "dtm";
+const MAGIC: &[u8; 4] = b"MDTM";
+const VERSION: u8 = 1;
+const DRAW: u8 = 0;
+const ILLEGAL: u8 = u8::MAX;
+/// Most plies to mate an entry can store
+const MAX_PLIES: usize = ILLEGAL as usize - 2;
+/// Piece letters of table names, in the order the pieces of a side are listed in
+const PIECE_LETTERS: [char; 6] = ['K', 'Q', 'R', 'B', 'N', 'P'];
+/// Squares the white king is mirrored onto, in tables without and with pawns
+const KING_SQUARES: [usize; 2] = [10, 32];
+
+/// Distance to mate of a position for the player to move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dtm {
+    /// The player to move mates in this many plies
+    Win(usize),
+    Draw,
+    /// The player to move is mated in this many plies, 0 if they already are
+    Loss(usize)
+}
+
+impl Dtm {
+    fn from_entry(entry: u8) -> Option<Dtm> {
+        return match entry {
+            DRAW => Some(Dtm::Draw),
+            ILLEGAL => None,
+            _ if entry % 2 == 0 => Some(Dtm::Win(entry as usize - 1)),
+            _ => Some(Dtm::Loss(entry as usize - 1))
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    /// A material name which can not be a table
+    InvalidMaterial(String),
+    /// A table needed for a capture or promotion has not been generated
+    MissingTable(Material),
+    /// A file which is not a table, with the reason
+    InvalidFile(String, String),
+    Io(String, std::io::Error)
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TablebaseError::InvalidMaterial(name) => write!(f, "Invalid table material '{}', expected for example KBNvK with at most {} pieces", name, MAX_PIECES),
+            TablebaseError::MissingTable(material) => write!(f, "The {} table has to be generated first", material),
+            TablebaseError::InvalidFile(path, reason) => write!(f, "Invalid table file '{}': {}", path, reason),
+            TablebaseError::Io(path, e) => write!(f, "Could not read '{}': {}", path, e)
+        };
+    }
+}
+
+impl std::error::Error for TablebaseError {}
+
+/// The pieces of a table: the white king, the other white pieces, the black king and the other black pieces
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Material {
+    pieces: Vec<Piece>
+}
+
+impl Material {
+    /// Material of a name such as `KBNvK`, with the first side as white
+    pub fn from_name(name: &str) -> Result<Material, TablebaseError> {
+        let invalid = || TablebaseError::InvalidMaterial(name.to_string());
+        let (white, black) = name.split_once('v').ok_or_else(invalid)?;
+        let mut pieces = Vec::new();
+        for (side, color) in [(white, Color::White), (black, Color::Black)] {
+            if !side.starts_with('K') || side[1..].contains('K') {
+                return Err(invalid());
+            }
+            for c in side.chars() {
+                if !PIECE_LETTERS.contains(&c) {
+                    return Err(invalid());
+                }
+                pieces.push(match color {
+                    Color::White => Piece::from_char(c),
+                    Color::Black => Piece::from_char(c.to_ascii_lowercase())
+                });
+            }
+        }
+        if pieces.len() > MAX_PIECES {
+            return Err(invalid());
+        }
+        return Ok(Material::from_pieces(pieces));
+    }
+
+    /// Material of the pieces on a board
+    pub fn from_board(board: &Board) -> Material {
+        return Material::from_pieces((0..64).map(|square| board.get_piece(square)).filter(|&piece| piece != Piece::Empty).collect());
+    }
+
+    fn from_pieces(mut pieces: Vec<Piece>) -> Material {
+        pieces.sort_by_key(|&piece| piece_order(piece));
+        return Material { pieces };
+    }
+
+    pub fn pieces(&self) -> &[Piece] {
+        return &self.pieces;
+    }
+
+    pub fn has_pawns(&self) -> bool {
+        return self.pieces.iter().any(|&piece| piece == Piece::WhitePawn || piece == Piece::BlackPawn);
+    }
+
+    /// The same material with the colors swapped
+    pub fn swap_colors(&self) -> Material {
+        return Material::from_pieces(self.pieces.iter().map(|&piece| swap_color(piece)).collect());
+    }
+
+    /// Returns true if white is the stronger side, which is how tables are stored
+    pub fn is_canonical(&self) -> bool {
+        let value = |white: bool| self.pieces.iter()
+            .filter(|piece| piece.is_white() == white)
+            .map(|piece| PIECE_VALUES[piece.to_u8() as usize % 6])
+            .sum::<i32>();
+        let (white, black) = (value(true), value(false));
+        return white > black || (white == black && self.to_string() >= self.swap_colors().to_string());
+    }
+
+    /// The material with the colors swapped if black is the stronger side
+    pub fn canonical(&self) -> Material {
+        return if self.is_canonical() { self.clone() } else { self.swap_colors() };
+    }
+
+    /// Returns true if neither side can mate, so no table is needed
+    pub fn is_drawn(&self) -> bool {
+        let minor = |piece: &&Piece| matches!(piece, Piece::WhiteBishop | Piece::WhiteKnight | Piece::BlackBishop | Piece::BlackKnight);
+        return self.pieces.iter().filter(minor).count() == self.pieces.len() - 2 && self.pieces.len() <= 3;
+    }
+
+    /// Canonical materials reached by captures and promotions, which have to be generated before this material
+    pub fn dependencies(&self) -> Vec<Material> {
+        let mut materials = Vec::new();
+        for (i, &piece) in self.pieces.iter().enumerate() {
+            // Captures of the piece
+            if piece != Piece::WhiteKing && piece != Piece::BlackKing {
+                let mut pieces = self.pieces.clone();
+                pieces.remove(i);
+                materials.push(pieces);
+            }
+            // Promotions, with and without capturing a piece of the other side
+            if piece == Piece::WhitePawn || piece == Piece::BlackPawn {
+                for promotion in ['Q', 'R', 'B', 'N'] {
+                    let promotion = if piece.is_white() { Piece::from_char(promotion) } else { Piece::from_char(promotion.to_ascii_lowercase()) };
+                    let mut pieces = self.pieces.clone();
+                    pieces[i] = promotion;
+                    materials.push(pieces.clone());
+                    for (j, &captured) in self.pieces.iter().enumerate() {
+                        if captured.is_white() != piece.is_white() && captured != Piece::WhiteKing && captured != Piece::BlackKing {
+                            let mut pieces = pieces.clone();
+                            pieces.remove(j);
+                            materials.push(pieces);
+                        }
+                    }
+                }
+            }
+        }
+        let mut dependencies: Vec<Material> = Vec::new();
+        for material in materials.into_iter().map(|pieces| Material::from_pieces(pieces).canonical()) {
+            if !material.is_drawn() && !dependencies.contains(&material) {
+                dependencies.push(material);
+            }
+        }
+        return dependencies;
+    }
+
+    /// Number of entries of the table
+    pub fn table_size(&self) -> usize {
+        return 2 * KING_SQUARES[self.has_pawns() as usize] * 64usize.pow(self.pieces.len() as u32 - 1);
+    }
+
+    /// Index of a position in the table, with the squares of the pieces in the order of the material
+    pub fn index(&self, squares: &[u8], player: Color) -> usize {
+        let has_pawns = self.has_pawns();
+        let (flip, transpose) = symmetry(squares[0], has_pawns);
+        let king = transform(squares[0], flip, transpose);
+        let king_index = match has_pawns {
+            true => (king / 8 * 4 + king % 8) as usize,
+            false => {
+                let (file, rank) = ((king % 8) as usize, (7 - king / 8) as usize);
+                file * (file + 1) / 2 + rank
+            }
+        };
+        let mut index = player as usize * KING_SQUARES[has_pawns as usize] + king_index;
+        for &square in &squares[1..] {
+            index = index * 64 + transform(square, flip, transpose) as usize;
+        }
+        return index;
+    }
+
+    /// Squares of the pieces and the player to move of an index, the inverse of `index`
+    pub fn position(&self, mut index: usize) -> (Vec<u8>, Color) {
+        let mut squares = vec![0; self.pieces.len()];
+        for square in squares[1..].iter_mut().rev() {
+            *square = (index % 64) as u8;
+            index /= 64;
+        }
+        let king_squares = KING_SQUARES[self.has_pawns() as usize];
+        let king_index = index % king_squares;
+        squares[0] = match self.has_pawns() {
+            true => (king_index / 4 * 8 + king_index % 4) as u8,
+            false => {
+                let file = (0..4).rev().find(|file| file * (file + 1) / 2 <= king_index).unwrap();
+                let rank = king_index - file * (file + 1) / 2;
+                ((7 - rank) * 8 + file) as u8
+            }
+        };
+        let player = if index / king_squares == 0 { Color::White } else { Color::Black };
+        return (squares, player);
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |white: bool| self.pieces.iter()
+            .filter(|piece| piece.is_white() == white)
+            .map(|piece| piece.as_char().to_ascii_uppercase())
+            .collect::<String>();
+        return write!(f, "{}v{}", side(true), side(false));
+    }
+}
+
+/// A generated table
+pub struct DtmTable {
+    material: Material,
+    entries: Vec<u8>
+}
+
+impl DtmTable {
+    pub(crate) fn new(material: Material, entries: Vec<u8>) -> DtmTable {
+        return DtmTable { material, entries };
+    }
+
+    pub fn material(&self) -> &Material {
+        return &self.material;
+    }
+
+    /// Distance to mate of a position, with the squares of the pieces in the order of the material.
+    /// `None` if the position is illegal.
+    pub fn probe(&self, squares: &[u8], player: Color) -> Option<Dtm> {
+        return Dtm::from_entry(self.entries[self.material.index(squares, player)]);
+    }
+
+    /// Distance to mate of every index, `None` for illegal positions
+    pub fn iter(&self) -> impl Iterator<Item = Option<Dtm>> + '_ {
+        return self.entries.iter().map(|&entry| Dtm::from_entry(entry));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.material.pieces.len() as u8);
+        bytes.extend(self.material.pieces.iter().map(|piece| piece.to_u8()));
+        bytes.extend(&self.entries);
+        return bytes;
+    }
+
+    /// Read a table written by `to_bytes`, `path` is only used for errors
+    pub fn from_bytes(bytes: &[u8], path: &str) -> Result<DtmTable, TablebaseError> {
+        let invalid = |reason: &str| TablebaseError::InvalidFile(path.to_string(), reason.to_string());
+        if bytes.len() < MAGIC.len() + 2 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a DTM table"));
+        }
+        if bytes[4] != VERSION {
+            return Err(invalid(&format!("unsupported version {}", bytes[4])));
+        }
+        let piece_count = bytes[5] as usize;
+        let Some(pieces) = bytes.get(6..6 + piece_count) else {
+            return Err(invalid("truncated header"));
+        };
+        if !(3..=MAX_PIECES).contains(&piece_count) || pieces.iter().any(|&piece| piece >= Piece::Empty.to_u8()) {
+            return Err(invalid("invalid pieces"));
+        }
+        let material = Material::from_pieces(pieces.iter().map(|&piece| Piece::from_u8(piece)).collect());
+        if material.pieces.iter().map(|&piece| piece.to_u8()).ne(pieces.iter().copied()) || Material::from_name(&material.to_string()).is_err() {
+            return Err(invalid("invalid pieces"));
+        }
+        let entries = &bytes[6 + piece_count..];
+        if entries.len() != material.table_size() {
+            return Err(invalid(&format!("expected {} entries, found {}", material.table_size(), entries.len())));
+        }
+        return Ok(DtmTable::new(material, entries.to_vec()));
+    }
+}
+
+/// Generated tables by their material
+#[derive(Default)]
+pub struct DtmTables {
+    tables: HashMap<Material, DtmTable>
+}
+
+impl DtmTables {
+    /// Read the `.dtm` tables of one or more directories, separated by `:` (`;` on Windows)
+    pub fn load(path: &str) -> Result<DtmTables, TablebaseError> {
+        let mut tables = DtmTables::default();
+        for directory in path.split(PATH_SEPARATOR).map(str::trim).filter(|directory| !directory.is_empty()) {
+            let entries = fs::read_dir(directory).map_err(|e| TablebaseError::Io(directory.to_string(), e))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|extension| extension.to_str()) != Some(EXTENSION) {
+                    continue;
+                }
+                let path_name = path.display().to_string();
+                let bytes = fs::read(&path).map_err(|e| TablebaseError::Io(path_name.clone(), e))?;
+                tables.insert(DtmTable::from_bytes(&bytes, &path_name)?);
+            }
+        }
+        return Ok(tables);
+    }
+
+    pub fn insert(&mut self, table: DtmTable) {
+        self.tables.insert(table.material.clone(), table);
+    }
+
+    /// Table of a canonical material
+    pub fn get(&self, material: &Material) -> Option<&DtmTable> {
+        return self.tables.get(material);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.tables.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.tables.is_empty();
+    }
+
+    /// Distance to mate of a position. Positions where neither side can mate are draws. `None` if the table is
+    /// missing, or if the position has castling rights or an en passant capture may be possible.
+    pub fn probe(&self, board: &Board) -> Option<Dtm> {
+        if board.piece_count() > MAX_PIECES || board.get_castling_u8() != 0 || board.get_variant() != Variant::Standard {
+            return None;
+        }
+        let (material, squares, player) = board_position(board);
+        if material.is_drawn() {
+            return Some(Dtm::Draw);
+        }
+        let pawn = match board.get_current_player() {
+            Color::White => Piece::WhitePawn,
+            Color::Black => Piece::BlackPawn
+        };
+        if board.get_ep() != 0 && (0..64).any(|square| board.get_piece(square) == pawn) {
+            return None;
+        }
+        return self.tables.get(&material)?.probe(&squares, player);
+    }
+}
+
+/// Canonical material of a board, the squares of its pieces in the order of the material and the player to move.
+/// The colors are swapped and the board is flipped vertically if black is the stronger side.
+pub(crate) fn board_position(board: &Board) -> (Material, Vec<u8>, Color) {
+    let mut pieces: Vec<(Piece, u8)> = (0..64)
+        .map(|square| (board.get_piece(square), square))
+        .filter(|&(piece, _)| piece != Piece::Empty)
+        .collect();
+    let mut player = board.get_current_player();
+    if !Material::from_pieces(pieces.iter().map(|&(piece, _)| piece).collect()).is_canonical() {
+        pieces = pieces.into_iter().map(|(piece, square)| (swap_color(piece), square ^ 56)).collect();
+        player = player.next_player();
+    }
+    pieces.sort_by_key(|&(piece, _)| piece_order(piece));
+    let material = Material { pieces: pieces.iter().map(|&(piece, _)| piece).collect() };
+    return (material, pieces.into_iter().map(|(_, square)| square).collect(), player);
+}
+
+/// Position of a piece in the pieces of a material
+fn piece_order(piece: Piece) -> usize {
+    let letter = piece.as_char().to_ascii_uppercase();
+    return (piece.is_black() as usize) * PIECE_LETTERS.len() + PIECE_LETTERS.iter().position(|&c| c == letter).unwrap();
+}
+
+fn swap_color(piece: Piece) -> Piece {
+    return Piece::from_u8((piece.to_u8() + 6) % 12);
+}
+
+/// Mirroring of the squares which moves the white king to the squares stored in the tables, as an xor of the
+/// square and whether the a1-h8 diagonal is mirrored afterwards
+fn symmetry(king: u8, has_pawns: bool) -> (u8, bool) {
+    let mut flip = if king % 8 > 3 { 7 } else { 0 };
+    if !has_pawns && king / 8 < 4 {
+        flip |= 56;
+    }
+    let king = king ^ flip;
+    return (flip, !has_pawns && 7 - king / 8 > king % 8);
+}
+
+fn transform(square: u8, flip: u8, transpose: bool) -> u8 {
+    let square = square ^ flip;
+    return if transpose { (7 - square % 8) * 8 + 7 - square / 8 } else { square };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::core::Color;
+    use super::{board_position, DtmTable, Material, TablebaseError};
+
+    fn board(fen: &str) -> Board {
+        return Board::from_fen(fen, Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+    }
+
+    #[test]
+    fn test_material() {
+        let material = Material::from_name("KNBvK").unwrap();
+        assert_eq!(material.to_string(), "KBNvK");
+        assert!(material.is_canonical() && !material.has_pawns());
+        assert_eq!(material.swap_colors().to_string(), "KvKBN");
+        assert_eq!(Material::from_name("KvKR").unwrap().canonical().to_string(), "KRvK");
+        assert!(Material::from_name("KBvK").unwrap().is_drawn() && !Material::from_name("KBvKN").unwrap().is_drawn());
+        for name in ["KQvKQK", "QKvK", "KQRvKR", "KXvK", "KQK"] {
+            assert!(matches!(Material::from_name(name), Err(TablebaseError::InvalidMaterial(_))), "{}", name);
+        }
+
+        let dependencies = |name: &str| Material::from_name(name).unwrap().dependencies().iter().map(|material| material.to_string()).collect::<Vec<String>>();
+        assert_eq!(dependencies("KBNvK"), Vec::<String>::new());
+        assert_eq!(dependencies("KPvK"), vec!["KQvK", "KRvK"]);
+        assert_eq!(dependencies("KQvKR"), vec!["KRvK", "KQvK"]);
+        assert_eq!(dependencies("KPvKN"), vec!["KQvKN", "KQvK", "KRvKN", "KRvK", "KNvKB", "KNvKN", "KPvK"]);
+    }
+
+    #[test]
+    fn test_index() {
+        for name in ["KQvK", "KPvK"] {
+            let material = Material::from_name(name).unwrap();
+            for index in (0..material.table_size()).step_by(97) {
+                let (squares, player) = material.position(index);
+                assert_eq!(material.index(&squares, player), index, "{} {:?}", name, squares);
+            }
+        }
+
+        // Mirrored positions have the same index, and positions with black as the stronger side are flipped
+        let krk = |fen: &str| {
+            let (material, squares, player) = board_position(&board(fen));
+            assert_eq!(material.to_string(), "KRvK");
+            return material.index(&squares, player);
+        };
+        let index = krk("8/8/8/3k4/8/8/1R6/K7 w - - 0 1");
+        assert_eq!(krk("7K/6R1/8/8/4k3/8/8/8 w - - 0 1"), index);
+        assert_eq!(krk("K7/1R6/8/8/3k4/8/8/8 w - - 0 1"), index);
+        assert_eq!(krk("8/8/8/4K3/8/8/6r1/7k b - - 0 1"), index);
+        assert_ne!(krk("8/8/8/3k4/8/8/1R6/K7 b - - 0 1"), index);
+        let (_, _, player) = board_position(&board("k7/1r6/8/8/3K4/8/8/8 w - - 0 1"));
+        assert_eq!(player, Color::Black);
+
+        let material = Material::from_name("KRvK").unwrap();
+        let table = DtmTable::new(material.clone(), vec![7; material.table_size()]);
+        let bytes = table.to_bytes();
+        assert_eq!(DtmTable::from_bytes(&bytes, "KRvK.dtm").unwrap().entries, table.entries);
+        assert!(DtmTable::from_bytes(&bytes[..bytes.len() - 1], "KRvK.dtm").is_err());
+        assert!(DtmTable::from_bytes(&bytes[1..], "KRvK.dtm").is_err());
+    }
+}
@@ -0,0 +1,404 @@
+//! Retrograde analysis of a material.
+//!
+//! Every position is first set up on a board and searched for its legal moves. Checkmates are lost in 0 plies,
+//! captures and promotions are looked up in the tables they lead to, and the other moves are counted. Positions
+//! are then resolved one ply at a time by taking back moves from the positions resolved at the previous ply: a
+//! position with a move to a loss is a win, and a position whose moves have all been found to lead to wins is a
+//! loss. Positions which are never resolved are draws.
+//!
+//! Generation indexes positions by the squares of all pieces without symmetry, so every move is counted exactly
+//! once, and only the tables written afterwards are reduced by symmetry.
+
+use std::sync::Arc;
+
+use crate::core::bitboard::constants::{BitboardRuntimeConstants, KING_MOVE_MASKS, KNIGHT_MOVE_MASKS};
+use crate::core::bitboard::Board;
+use crate::core::move_list::{MoveList, SearchResult};
+use crate::core::{Color, Move, Piece};
+use super::{Dtm, DtmTable, DtmTables, Material, TablebaseError, DRAW, ILLEGAL, MAX_PIECES, MAX_PLIES};
+
+/// Entry of a position which has not been resolved yet, draws are the positions left unresolved
+const UNRESOLVED: u8 = DRAW;
+/// Remaining move count of a position which can not be lost, as it has a move to a draw or a loss
+const CANNOT_LOSE: u8 = u8::MAX;
+/// Set on scheduled positions whose entry has already been set, so they are only resolved once
+const ALREADY_SET: u32 = 1 << 31;
+
+/// Generate the table of a material. The tables of its dependencies have to be in `tables`.
+pub fn generate(material: &Material, tables: &DtmTables, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<DtmTable, TablebaseError> {
+    let material = material.canonical();
+    if material.pieces().len() > MAX_PIECES {
+        return Err(TablebaseError::InvalidMaterial(material.to_string()));
+    }
+    if let Some(missing) = material.dependencies().into_iter().find(|dependency| tables.get(dependency).is_none()) {
+        return Err(TablebaseError::MissingTable(missing));
+    }
+    let mut generator = Generator::new(&material, tables, runtime_constants);
+    let mut moves = MoveList::empty();
+    generator.initialize(&mut moves)?;
+    generator.resolve(&mut moves)?;
+
+    let entries = (0..material.table_size()).map(|index| {
+        let (squares, player) = material.position(index);
+        return generator.entries[generator.full_index(&squares, player)];
+    }).collect();
+    return Ok(DtmTable::new(material, entries));
+}
+
+/// Generate the tables of `materials` and the tables they depend on which are not in `tables`, dependencies first.
+/// `on_generated` is called with every table before it is added to `tables`.
+pub fn generate_with_dependencies(
+    materials: &[Material],
+    tables: &mut DtmTables,
+    runtime_constants: Arc<BitboardRuntimeConstants>,
+    on_generated: &mut dyn FnMut(&DtmTable)
+) -> Result<(), TablebaseError> {
+    for material in materials.iter().map(Material::canonical) {
+        if tables.get(&material).is_some() {
+            continue;
+        }
+        generate_with_dependencies(&material.dependencies(), tables, Arc::clone(&runtime_constants), on_generated)?;
+        let table = generate(&material, tables, Arc::clone(&runtime_constants))?;
+        on_generated(&table);
+        tables.insert(table);
+    }
+    return Ok(());
+}
+
+struct Generator<'a> {
+    material: &'a Material,
+    tables: &'a DtmTables,
+    runtime_constants: Arc<BitboardRuntimeConstants>,
+    board: Board,
+    /// Squares of the pieces on `board`
+    board_squares: Vec<u8>,
+    /// Entry of every position, by `full_index`
+    entries: Vec<u8>,
+    /// Moves of every position which stay in the table and have not been found to lead to a win of the opponent
+    remaining: Vec<u8>,
+    /// Positions to resolve at each ply
+    schedule: Vec<Vec<u32>>
+}
+
+impl<'a> Generator<'a> {
+    fn new(material: &'a Material, tables: &'a DtmTables, runtime_constants: Arc<BitboardRuntimeConstants>) -> Generator<'a> {
+        let size = 2 * 64usize.pow(material.pieces().len() as u32);
+        return Generator {
+            material,
+            tables,
+            board: Board::empty(Arc::clone(&runtime_constants)),
+            runtime_constants,
+            board_squares: Vec::new(),
+            entries: vec![UNRESOLVED; size],
+            remaining: vec![0; size],
+            schedule: vec![Vec::new(); MAX_PLIES + 1]
+        };
+    }
+
+    fn full_index(&self, squares: &[u8], player: Color) -> usize {
+        return squares.iter().fold(player as usize, |index, &square| index * 64 + square as usize);
+    }
+
+    fn full_position(&self, mut index: usize) -> ([u8; MAX_PIECES], Color) {
+        let mut squares = [0; MAX_PIECES];
+        for square in squares[..self.material.pieces().len()].iter_mut().rev() {
+            *square = (index % 64) as u8;
+            index /= 64;
+        }
+        return (squares, if index == 0 { Color::White } else { Color::Black });
+    }
+
+    /// Returns false for positions with two pieces on a square, adjacent kings or pawns on the first or last rank
+    fn is_valid(&self, squares: &[u8]) -> bool {
+        let mut occupancy = 0u64;
+        for (&square, &piece) in squares.iter().zip(self.material.pieces()) {
+            if occupancy & (1 << square) != 0 {
+                return false;
+            }
+            if (piece == Piece::WhitePawn || piece == Piece::BlackPawn) && !(8..56).contains(&square) {
+                return false;
+            }
+            occupancy |= 1 << square;
+        }
+        let black_king = self.material.pieces().iter().position(|&piece| piece == Piece::BlackKing).unwrap();
+        return KING_MOVE_MASKS[squares[0] as usize] & (1 << squares[black_king]) == 0;
+    }
+
+    fn set_board(&mut self, squares: &[u8], player: Color) {
+        for &square in self.board_squares.iter() {
+            self.board.set_piece(square, Piece::Empty);
+        }
+        for (&square, &piece) in squares.iter().zip(self.material.pieces()) {
+            self.board.set_piece(square, piece);
+        }
+        self.board_squares = squares.to_vec();
+        if self.board.get_current_player() != player {
+            self.board.switch_current_player();
+        }
+    }
+
+    fn schedule(&mut self, entry: u32, plies: usize) -> Result<(), TablebaseError> {
+        if plies > MAX_PLIES {
+            return Err(TablebaseError::InvalidMaterial(self.material.to_string()));
+        }
+        self.schedule[plies].push(entry);
+        return Ok(());
+    }
+
+    /// Result of a capture or promotion, for the opponent
+    fn exit_value(&mut self, mv: &Move) -> Result<Dtm, TablebaseError> {
+        self.board.make_move(mv);
+        let dtm = self.tables.probe(&self.board).ok_or_else(|| TablebaseError::MissingTable(Material::from_board(&self.board).canonical()));
+        self.board.unmake_move(mv);
+        return dtm;
+    }
+
+    /// Mark illegal positions, count the moves of the legal ones and schedule the positions which are resolved by
+    /// their captures and promotions
+    fn initialize(&mut self, moves: &mut MoveList) -> Result<(), TablebaseError> {
+        let piece_count = self.material.pieces().len();
+        for index in 0..self.entries.len() {
+            let (squares, player) = self.full_position(index);
+            let squares = &squares[..piece_count];
+            if !self.is_valid(squares) {
+                self.entries[index] = ILLEGAL;
+                continue;
+            }
+            self.set_board(squares, player);
+            // The player who just moved can not be in check
+            self.board.switch_current_player();
+            let is_illegal = self.board.is_in_check();
+            self.board.switch_current_player();
+            if is_illegal {
+                self.entries[index] = ILLEGAL;
+                continue;
+            }
+
+            self.board.get_moves(moves, false);
+            if moves.len() == 0 {
+                if let SearchResult::Loss = moves.result() {
+                    self.schedule(index as u32, 0)?;
+                }
+                continue;
+            }
+            let mut table_moves = 0;
+            let mut shortest_win = None;
+            let mut longest_loss = 0;
+            let mut can_lose = true;
+            for mv in moves.iter() {
+                if !leaves_table(mv) {
+                    table_moves += 1;
+                    continue;
+                }
+                match self.exit_value(mv)? {
+                    Dtm::Loss(plies) => {
+                        shortest_win = Some(shortest_win.unwrap_or(usize::MAX).min(plies + 1));
+                        can_lose = false;
+                    }
+                    Dtm::Draw => can_lose = false,
+                    Dtm::Win(plies) => longest_loss = longest_loss.max(plies + 1)
+                }
+            }
+            self.remaining[index] = if can_lose { table_moves } else { CANNOT_LOSE };
+            if let Some(plies) = shortest_win {
+                self.schedule(index as u32, plies)?;
+            }
+            else if can_lose && table_moves == 0 {
+                self.schedule(index as u32, longest_loss)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Resolve the scheduled positions ply by ply and schedule the positions found by taking back moves
+    fn resolve(&mut self, moves: &mut MoveList) -> Result<(), TablebaseError> {
+        for plies in 0..=MAX_PLIES {
+            for entry in std::mem::take(&mut self.schedule[plies]) {
+                let index = (entry & !ALREADY_SET) as usize;
+                if entry & ALREADY_SET == 0 {
+                    // Positions with a winning capture or promotion can be resolved earlier by a shorter win
+                    if self.entries[index] != UNRESOLVED {
+                        continue;
+                    }
+                    self.entries[index] = plies as u8 + 1;
+                }
+                self.resolve_previous_positions(index, plies, moves)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Take back the moves leading to a position resolved at `plies`
+    fn resolve_previous_positions(&mut self, index: usize, plies: usize, moves: &mut MoveList) -> Result<(), TablebaseError> {
+        let piece_count = self.material.pieces().len();
+        let (squares, player) = self.full_position(index);
+        let previous_player = player.next_player();
+        let occupancy = squares[..piece_count].iter().fold(0u64, |occupancy, &square| occupancy | 1 << square);
+        for i in 0..piece_count {
+            let piece = self.material.pieces()[i];
+            if piece.is_white() != (previous_player == Color::White) {
+                continue;
+            }
+            let mut origins = self.origins(piece, squares[i], occupancy);
+            while origins != 0 {
+                let mut previous = squares;
+                previous[i] = origins.trailing_zeros() as u8;
+                origins &= origins - 1;
+                let previous_index = self.full_index(&previous[..piece_count], previous_player);
+                if self.entries[previous_index] != UNRESOLVED {
+                    continue;
+                }
+                if plies % 2 == 0 {
+                    // A move to a loss wins
+                    self.entries[previous_index] = plies as u8 + 2;
+                    self.schedule(previous_index as u32 | ALREADY_SET, plies + 1)?;
+                }
+                else if self.remaining[previous_index] != CANNOT_LOSE {
+                    // All moves lead to wins of the opponent, the loss takes as long as the longest of them
+                    self.remaining[previous_index] -= 1;
+                    if self.remaining[previous_index] == 0 {
+                        let longest_loss = self.longest_exit_loss(previous_index, moves)?;
+                        self.schedule(previous_index as u32, longest_loss.max(plies + 1))?;
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Plies until mate of the longest capture or promotion of a position whose moves all lose
+    fn longest_exit_loss(&mut self, index: usize, moves: &mut MoveList) -> Result<usize, TablebaseError> {
+        let (squares, player) = self.full_position(index);
+        self.set_board(&squares[..self.material.pieces().len()], player);
+        self.board.get_moves(moves, false);
+        let mut longest_loss = 0;
+        for mv in moves.iter().filter(|mv| leaves_table(mv)) {
+            if let Dtm::Win(plies) = self.exit_value(mv)? {
+                longest_loss = longest_loss.max(plies + 1);
+            }
+        }
+        return Ok(longest_loss);
+    }
+
+    /// Squares a piece can have moved to `square` from without capturing or promoting
+    fn origins(&self, piece: Piece, square: u8, occupancy: u64) -> u64 {
+        let square = square as usize;
+        let empty = !occupancy;
+        return match piece {
+            Piece::WhiteKing | Piece::BlackKing => KING_MOVE_MASKS[square] & empty,
+            Piece::WhiteKnight | Piece::BlackKnight => KNIGHT_MOVE_MASKS[square] & empty,
+            Piece::WhiteBishop | Piece::BlackBishop => self.runtime_constants.bishop_magic(square, occupancy) & empty,
+            Piece::WhiteRook | Piece::BlackRook => self.runtime_constants.rook_magic(square, occupancy) & empty,
+            Piece::WhiteQueen | Piece::BlackQueen => {
+                (self.runtime_constants.bishop_magic(square, occupancy) | self.runtime_constants.rook_magic(square, occupancy)) & empty
+            }
+            // White pawns move towards lower squares. Double moves end on the fourth rank.
+            Piece::WhitePawn if square < 48 && empty & (1 << (square + 8)) != 0 => {
+                let double = if square / 8 == 4 { empty & (1 << (square + 16)) } else { 0 };
+                (1 << (square + 8)) | double
+            }
+            Piece::BlackPawn if square >= 16 && empty & (1 << (square - 8)) != 0 => {
+                let double = if square / 8 == 3 { empty & (1 << (square - 16)) } else { 0 };
+                (1 << (square - 8)) | double
+            }
+            _ => 0
+        };
+    }
+}
+
+/// Captures and promotions change the material
+fn leaves_table(mv: &Move) -> bool {
+    return mv.captured != Piece::Empty || mv.promotion != Piece::Empty;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use lazy_static::lazy_static;
+
+    use crate::core::bitboard::Board;
+    use crate::core::move_list::MoveList;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use super::super::{Dtm, DtmTables, Material, TablebaseError};
+    use super::{generate, generate_with_dependencies};
+
+    lazy_static! {
+        /// The tables needed for KPvK, generated once for all tests, and the order they were generated in
+        pub(crate) static ref KPK_TABLES: (DtmTables, Vec<String>) = {
+            let mut tables = DtmTables::default();
+            let mut generated = Vec::new();
+            generate_with_dependencies(&[Material::from_name("KvKP").unwrap()], &mut tables, Arc::new(BOARD_CONSTANT_STATE.clone()), &mut |table| {
+                generated.push(table.material().to_string());
+            }).unwrap();
+            (tables, generated)
+        };
+    }
+
+    fn board(fen: &str) -> Board {
+        return Board::from_fen(fen, Arc::new(BOARD_CONSTANT_STATE.clone())).unwrap();
+    }
+
+    #[test]
+    fn test_generate() {
+        let kpk = Material::from_name("KPvK").unwrap();
+        let result = generate(&kpk, &DtmTables::default(), Arc::new(BOARD_CONSTANT_STATE.clone()));
+        assert!(matches!(result, Err(TablebaseError::MissingTable(material)) if material.to_string() == "KQvK"));
+        let (tables, generated) = &*KPK_TABLES;
+        assert_eq!(*generated, vec!["KQvK", "KRvK", "KPvK"]);
+
+        // Longest mates with the queen and the rook
+        let longest_win = |name: &str| tables.get(&Material::from_name(name).unwrap()).unwrap().iter()
+            .filter_map(|dtm| match dtm { Some(Dtm::Win(plies)) => Some(plies), _ => None })
+            .max();
+        assert_eq!(longest_win("KQvK"), Some(19));
+        assert_eq!(longest_win("KRvK"), Some(31));
+
+        let cases = [
+            ("7k/8/6K1/8/8/8/Q7/8 w - - 0 1", Some(Dtm::Win(1))),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Some(Dtm::Draw)),
+            ("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", Some(Dtm::Loss(0))),
+            ("8/8/8/8/8/6k1/6q1/7K w - - 0 1", Some(Dtm::Loss(0))),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", None),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", None),
+            ("k7/8/K7/P7/8/8/8/8 w - - 0 1", Some(Dtm::Draw)),
+            ("k7/P7/1K6/8/8/8/8/8 w - - 0 1", Some(Dtm::Draw)),
+            ("8/8/8/8/8/8/8/K6k w - - 0 1", Some(Dtm::Draw)),
+            ("8/8/8/8/8/8/1P6/K6k w - - 0 1", None)
+        ];
+        for (fen, expected) in cases {
+            let dtm = tables.probe(&board(fen));
+            match expected {
+                Some(expected) => assert_eq!(dtm, Some(expected), "{}", fen),
+                // Won by white, at an unknown distance
+                None => assert!(matches!((dtm, fen.contains(" w ")), (Some(Dtm::Win(_)), true) | (Some(Dtm::Loss(_)), false)), "{} {:?}", fen, dtm)
+            }
+        }
+
+        // Every move of a lost position leads to a win which is at most one ply shorter, and a won position has a
+        // move to a loss which is exactly one ply shorter
+        let mut moves = MoveList::empty();
+        for fen in ["8/8/8/3k4/8/8/1R6/K7 w - - 0 1", "8/8/8/3k4/8/8/1R6/K7 b - - 0 1", "8/8/8/8/8/8/1P5k/K7 b - - 0 1"] {
+            let mut board = board(fen);
+            let dtm = tables.probe(&board).unwrap();
+            board.get_moves(&mut moves, false);
+            let successors: Vec<Dtm> = moves.iter().map(|mv| {
+                board.make_move(mv);
+                let dtm = tables.probe(&board).unwrap();
+                board.unmake_move(mv);
+                return dtm;
+            }).collect();
+            match dtm {
+                Dtm::Win(plies) => {
+                    assert!(successors.contains(&Dtm::Loss(plies - 1)), "{}", fen);
+                    assert!(successors.iter().all(|&successor| !matches!(successor, Dtm::Loss(loss) if loss < plies - 1)), "{}", fen);
+                }
+                Dtm::Loss(plies) => {
+                    assert!(successors.contains(&Dtm::Win(plies - 1)), "{}", fen);
+                    assert!(successors.iter().all(|&successor| matches!(successor, Dtm::Win(win) if win < plies)), "{}", fen);
+                }
+                Dtm::Draw => panic!("{} is not a draw", fen)
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Bitbase of king and pawn versus king, compiled into the binary.
+//!
+//! The bitbase has one bit per position of the KPvK table, set if white wins. It is created from a generated table
+//! with `magnificence-oxidized tbgen KPvK --kpk-bitbase core/src/tablebase/kpk.bin`.
+
+use lazy_static::lazy_static;
+
+use crate::core::bitboard::Board;
+use crate::core::Color;
+use crate::core::variant::Variant;
+use super::{board_position, Dtm, DtmTable, Material};
+
+static BITBASE: &[u8] = include_bytes!("kpk.bin");
+
+lazy_static! {
+    static ref KPK: Material = Material::from_name("KPvK").unwrap();
+}
+
+/// Returns true if the side with the pawn wins and false if the position is a draw.
+/// `None` if the position is not king and pawn versus king.
+pub fn probe(board: &Board) -> Option<bool> {
+    if board.piece_count() != 3 || board.get_variant() != Variant::Standard {
+        return None;
+    }
+    let (material, squares, player) = board_position(board);
+    if material != *KPK {
+        return None;
+    }
+    let index = material.index(&squares, player);
+    return Some(BITBASE[index / 8] & (1 << (index % 8)) != 0);
+}
+
+/// Bitbase of a generated KPvK table
+pub fn create_bitbase(table: &DtmTable) -> Vec<u8> {
+    assert_eq!(*table.material(), *KPK, "The bitbase is created from the KPvK table");
+    let mut bitbase = vec![0; KPK.table_size() / 8];
+    for (index, dtm) in table.iter().enumerate() {
+        let (_, player) = KPK.position(index);
+        if matches!((dtm, player), (Some(Dtm::Win(_)), Color::White) | (Some(Dtm::Loss(_)), Color::Black)) {
+            bitbase[index / 8] |= 1 << (index % 8);
+        }
+    }
+    return bitbase;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::bitboard::Board;
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use super::super::generator::tests::KPK_TABLES;
+    use super::super::Material;
+    use super::{create_bitbase, probe, BITBASE, KPK};
+
+    #[test]
+    fn test_kpk_bitbase() {
+        let constants = Arc::new(BOARD_CONSTANT_STATE.clone());
+        assert!(create_bitbase(KPK_TABLES.0.get(&KPK).unwrap()) == BITBASE, "kpk.bin does not match the generated KPvK table");
+
+        let cases = [
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Some(true)),
+            ("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1", Some(true)),
+            ("k7/8/K7/P7/8/8/8/8 w - - 0 1", Some(false)),
+            ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Some(false)),
+            ("8/8/8/8/8/8/1P5k/K7 b - - 0 1", Some(true)),
+            ("8/8/8/8/8/4k3/4N3/4K3 b - - 0 1", None)
+        ];
+        for (fen, expected) in cases {
+            let board = Board::from_fen(fen, Arc::clone(&constants)).unwrap();
+            assert_eq!(probe(&board), expected, "{}", fen);
+        }
+        assert_eq!(Material::from_name("KPvK").unwrap().table_size() / 8, BITBASE.len());
+    }
+}
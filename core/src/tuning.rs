@@ -0,0 +1,263 @@
+//! Texel tuning of the evaluation parameters.
+//!
+//! Positions with known game results are resolved with the quiescence search of the engine, so only quiet
+//! positions are evaluated. The tuner then changes the parameters one at a time and keeps every change which lowers the
+//! mean squared error between the game results and the sigmoid of the evaluation.
+//!
+//! A tuning position is a FEN or EPD line with the result from the view of white, either in brackets
+//! after the FEN, `... w - - 0 1 [0.5]`, or as a `c9` operation, `... w - - c9 "1/2-1/2";`. Lines written by
+//! `datagen`, `<fen> | <score> | <result>`, are also accepted and the score is ignored.
+
+use std::sync::Arc;
+
+use crate::core::bitboard::{constants::BitboardRuntimeConstants, eval_params::EvalParams, Board};
+use crate::core::Color;
+use crate::engine::ab_engine::StandardAlphaBetaEngine;
+use crate::epd::{EpdError, EpdErrorKind, EpdPosition};
+use crate::pgn::GameResult;
+
+/// A position with the result of the game it was played in
+#[derive(Clone, PartialEq, Debug)]
+pub struct TuningPosition {
+    pub board: Board,
+    /// 1 if white won, 0.5 for a draw and 0 if black won
+    pub result: f64
+}
+
+impl TuningPosition {
+    pub fn from_line(line: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<TuningPosition, EpdErrorKind> {
+        let result_field = line.find('|').map(|start| (start, line.rfind('|').unwrap() + 1))
+            .or_else(|| line.rfind('[').map(|start| (start, start + 1)));
+        if let Some((fen_end, result_start)) = result_field {
+            let result_text = line[result_start..].trim_end().trim_end_matches(']').trim();
+            let result = match result_text.parse::<f64>() {
+                Ok(result) if result == 0.0 || result == 0.5 || result == 1.0 => result,
+                _ => return Err(EpdErrorKind::InvalidOperand("result".to_string(), result_text.to_string()))
+            };
+            let position = EpdPosition::from_line(&line[..fen_end], runtime_constants)?;
+            return Ok(TuningPosition { board: position.board, result });
+        }
+
+        let position = EpdPosition::from_line(line, runtime_constants)?;
+        let result_text = position.get_operation("c9").and_then(|operands| operands.first())
+            .ok_or_else(|| EpdErrorKind::MissingOperation("c9".to_string()))?;
+        let result = match GameResult::from_str(result_text) {
+            Some(GameResult::WhiteWon) => 1.0,
+            Some(GameResult::Draw) => 0.5,
+            Some(GameResult::BlackWon) => 0.0,
+            _ => return Err(EpdErrorKind::InvalidOperand("c9".to_string(), result_text.clone()))
+        };
+        return Ok(TuningPosition { board: position.board, result });
+    }
+}
+
+/// Parse all positions of a tuning file. Empty lines and lines starting with `#` are skipped.
+pub fn parse_tuning_positions(text: &str, runtime_constants: Arc<BitboardRuntimeConstants>) -> Result<Vec<TuningPosition>, EpdError> {
+    let mut positions = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match TuningPosition::from_line(line, Arc::clone(&runtime_constants)) {
+            Ok(position) => positions.push(position),
+            Err(kind) => return Err(EpdError { line: index + 1, kind })
+        }
+    }
+    return Ok(positions);
+}
+
+/// Engine whose quiescence search resolves tuning positions
+pub fn resolving_engine(board: &Board) -> StandardAlphaBetaEngine {
+    return StandardAlphaBetaEngine::new(
+        board,
+        Box::new(|_| ()),
+        Box::new(|_| ()),
+        Box::new(|| false),
+        Box::new(|| std::time::Duration::ZERO)
+    );
+}
+
+/// Play the principal variation of the quiescence search of `engine`, which gives the quiet position
+/// whose evaluation the quiescence search returns
+pub fn resolve_position(engine: &mut StandardAlphaBetaEngine, board: &Board) -> Board {
+    let (_, pv) = engine.resolve(board);
+    let mut board = board.clone();
+    for mv in pv.iter() {
+        board.make_move(mv);
+    }
+    return board;
+}
+
+/// Expected result from the view of white for an evaluation in centipawns from the view of white
+fn sigmoid(eval: i32, k: f64) -> f64 {
+    return 1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0));
+}
+
+/// Tunes evaluation parameters on a set of resolved positions
+pub struct Tuner {
+    positions: Vec<TuningPosition>
+}
+
+impl Tuner {
+    /// Create a tuner for `positions`, which are resolved with a quiescence search first
+    pub fn new(positions: Vec<TuningPosition>) -> Tuner {
+        let mut engine: Option<StandardAlphaBetaEngine> = None;
+        let positions = positions.into_iter()
+            .map(|position| {
+                let engine = engine.get_or_insert_with(|| resolving_engine(&position.board));
+                TuningPosition { board: resolve_position(engine, &position.board), result: position.result }
+            })
+            .collect();
+        return Tuner { positions };
+    }
+
+    pub fn position_count(&self) -> usize {
+        return self.positions.len();
+    }
+
+    /// Mean squared error between the game results and the expected results from the evaluation with `params`
+    pub fn error(&mut self, params: &EvalParams, k: f64) -> f64 {
+        let params = Arc::new(params.clone());
+        let total: f64 = self.positions.iter_mut().map(|position| {
+            position.board.set_eval_params(Arc::clone(&params));
+            let eval = match position.board.get_current_player() {
+                Color::White => position.board.classical_eval(),
+                Color::Black => -position.board.classical_eval()
+            };
+            (position.result - sigmoid(eval, k)).powi(2)
+        }).sum();
+        return total / self.positions.len().max(1) as f64;
+    }
+
+    /// Scaling constant of the sigmoid which gives the lowest error with `params`
+    pub fn find_scaling_constant(&mut self, params: &EvalParams) -> f64 {
+        let (mut best_k, mut best_error) = (1.0, self.error(params, 1.0));
+        let mut step = 0.5;
+        // Refine the best constant with smaller and smaller steps
+        while step > 0.0005 {
+            let mut improved = true;
+            while improved {
+                improved = false;
+                for k in [best_k - step, best_k + step] {
+                    let error = self.error(params, k);
+                    if k > 0.0 && error < best_error {
+                        (best_k, best_error, improved) = (k, error, true);
+                    }
+                }
+            }
+            step /= 2.0;
+        }
+        return best_k;
+    }
+
+    /// Local search over the parameters whose names start with one of `prefixes`, all parameters if it is
+    /// empty. Each parameter is changed by `step` in both directions and the change is kept if it lowers the
+    /// error. Stops after `max_iterations` passes over the parameters, or when a pass improves nothing.
+    /// `progress` is called with the iteration, the error and the parameters after each pass.
+    pub fn tune(
+        &mut self, params: &EvalParams, k: f64, step: i32, max_iterations: usize, prefixes: &[String],
+        progress: &mut dyn FnMut(usize, f64, &EvalParams)
+    ) -> EvalParams {
+        let mut values = params.values();
+        let tuned_indices: Vec<usize> = params.names().iter().enumerate()
+            .filter(|(_, name)| prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())))
+            .map(|(index, _)| index)
+            .collect();
+        let mut best_params = params.clone();
+        let mut best_error = self.error(&best_params, k);
+        for iteration in 1..=max_iterations {
+            let mut improved = false;
+            for index in tuned_indices.iter() {
+                for delta in [step, -step] {
+                    values[*index] += delta;
+                    let candidate = params.with_values(&values);
+                    let error = self.error(&candidate, k);
+                    if error < best_error {
+                        (best_params, best_error, improved) = (candidate, error, true);
+                        break;
+                    }
+                    values[*index] -= delta;
+                }
+            }
+            progress(iteration, best_error, &best_params);
+            if !improved {
+                break;
+            }
+        }
+        return best_params;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::bitboard::{eval_params::EvalParams, Board};
+    use crate::core::tests::BOARD_CONSTANT_STATE;
+    use crate::epd::EpdErrorKind;
+    use crate::core::Color;
+    use super::{parse_tuning_positions, resolve_position, resolving_engine, Tuner, TuningPosition};
+
+    #[test]
+    fn test_tuning_positions() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        let text = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 [1.0]\n\n# Comment\n4k3/8/8/8/8/8/4P3/4K3 b - - c9 \"1/2-1/2\";\n4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 | -35 | 0.0\n";
+        let positions = parse_tuning_positions(text, Arc::clone(&constant_state)).unwrap();
+        assert_eq!(positions.iter().map(|position| position.result).collect::<Vec<f64>>(), [1.0, 0.5, 0.0]);
+
+        let error = |line| TuningPosition::from_line(line, Arc::clone(&constant_state)).unwrap_err();
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - [0.7]"), EpdErrorKind::InvalidOperand("result".to_string(), "0.7".to_string()));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 | 20 | 2.0"), EpdErrorKind::InvalidOperand("result".to_string(), "2.0".to_string()));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - - c9 \"*\";"), EpdErrorKind::InvalidOperand("c9".to_string(), "*".to_string()));
+        assert_eq!(error("4k3/8/8/8/8/8/4P3/4K3 w - -"), EpdErrorKind::MissingOperation("c9".to_string()));
+        assert_eq!(parse_tuning_positions("\n4k3/8/8/8/8/8/4P3/4K3 w - -", constant_state).unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_resolve_position() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        // The queen is taken and the pawn is recaptured
+        let board = Board::from_fen("4k3/8/2p5/1n1q4/4P3/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+        let mut engine = resolving_engine(&board);
+        assert_eq!(resolve_position(&mut engine, &board).to_fen(), "4k3/8/8/1n1p4/8/8/8/4K3 w - - 0 3");
+        // Quiet positions stay the same
+        let board = Board::from_fen("4k3/8/2p5/1n6/4P3/8/8/4K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+        assert_eq!(resolve_position(&mut engine, &board), board);
+
+        // In check the player to move can not stand pat, the king escapes to e2 or f2 and the knight stays
+        let board = Board::from_fen("4k3/8/8/8/8/1n6/8/r3K3 w - - 0 1", Arc::clone(&constant_state)).unwrap();
+        let resolved = resolve_position(&mut engine, &board);
+        assert!(!resolved.is_in_check());
+        assert_eq!(resolved.get_current_player(), Color::Black);
+        // A checkmated position is resolved as a loss and not by its evaluation
+        let board = Board::from_fen("6k1/8/8/8/8/8/5PPP/r5K1 w - - 0 1", constant_state).unwrap();
+        let (score, pv) = engine.resolve(&board);
+        assert!(pv.is_empty());
+        assert!(score < -10_000, "{}", score);
+    }
+
+    #[test]
+    fn test_tuner() {
+        let constant_state = Arc::new(BOARD_CONSTANT_STATE.clone());
+        // White is a knight up in every position but never wins, so knights should lose value
+        let text = "4k3/pppp4/8/8/8/8/PPPP4/2N1K3 w - - [0.5]\n4k3/1ppp4/8/8/8/3N4/PPP5/4K3 b - - [0.0]\n";
+        let mut tuner = Tuner::new(parse_tuning_positions(text, constant_state).unwrap());
+        assert_eq!(tuner.position_count(), 2);
+        let params = EvalParams::default();
+        assert!(tuner.find_scaling_constant(&params) > 0.0);
+        let k = 1.0;
+
+        let mut iterations = Vec::new();
+        let tuned = tuner.tune(&params, k, 5, 3, &["material.knight".to_string()], &mut |iteration, error, _| iterations.push((iteration, error)));
+        assert_eq!(iterations.len(), 3);
+        assert!(iterations.windows(2).all(|errors| errors[1].1 < errors[0].1));
+        assert!(tuner.error(&tuned, k) < tuner.error(&params, k));
+        // Only the selected parameters change
+        let changed: Vec<String> = params.names().into_iter().zip(params.values().into_iter().zip(tuned.values()))
+            .filter(|(_, (default, tuned))| default != tuned)
+            .map(|(name, _)| name)
+            .collect();
+        assert!(!changed.is_empty() && changed.iter().all(|name| name.starts_with("material.knight")));
+    }
+}
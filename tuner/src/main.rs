@@ -0,0 +1,124 @@
+/// Texel tuner for the evaluation parameters of Magnificence Oxidized
+///
+/// Reads a file of quiet positions with game results, see `engine_core::tuning` for the format,
+/// and writes a parameter file which can be loaded with the `ParamFile` UCI option.
+use std::fs;
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+
+use clap::Arg;
+use engine_core::core::bitboard::constants::BitboardRuntimeConstants;
+use engine_core::core::bitboard::eval_params::EvalParams;
+use engine_core::tuning::{self, Tuner};
+
+fn main() {
+    let matches = clap::App::new("Magnificence Oxidized Evaluation Tuner")
+    .version("0.1")
+    .about("Tunes the evaluation parameters on positions with known game results by minimizing the sigmoid error.")
+    .arg(Arg::new("positions")
+        .help("File with one position and game result per line.")
+        .value_name("POSITIONS")
+        .required(true))
+    .arg(Arg::new("output")
+        .help("Parameter file to write, updated after every iteration.")
+        .short('o')
+        .long("output")
+        .value_name("FILE")
+        .default_value("tuned_params.txt")
+        .takes_value(true))
+    .arg(Arg::new("rust")
+        .help("Also write the tuned parameters as Rust constants for eval_params.rs.")
+        .short('r')
+        .long("rust")
+        .value_name("FILE")
+        .takes_value(true))
+    .arg(Arg::new("params")
+        .help("Parameter file to start from instead of the default parameters.")
+        .short('p')
+        .long("params")
+        .value_name("FILE")
+        .takes_value(true))
+    .arg(Arg::new("iterations")
+        .help("Maximum number of passes over the parameters.")
+        .short('i')
+        .long("iterations")
+        .value_name("COUNT")
+        .default_value("100")
+        .takes_value(true))
+    .arg(Arg::new("step")
+        .help("Amount each parameter is changed by in a step.")
+        .short('s')
+        .long("step")
+        .value_name("STEP")
+        .default_value("1")
+        .takes_value(true))
+    .arg(Arg::new("only")
+        .help("Only tune the parameters starting with one of these prefixes, for example 'material' or 'psqt.knight'.")
+        .long("only")
+        .value_name("PREFIX")
+        .takes_value(true)
+        .multiple_values(true))
+    .arg(Arg::new("k")
+        .help("Scaling constant of the sigmoid, found from the positions if not given.")
+        .short('k')
+        .value_name("K")
+        .takes_value(true))
+    .get_matches();
+
+    let iterations = parse_number::<usize>(matches.value_of("iterations").unwrap(), "iteration count");
+    let step = parse_number::<i32>(matches.value_of("step").unwrap(), "step");
+    let prefixes: Vec<String> = matches.values_of("only").map(|values| values.map(|prefix| prefix.to_string()).collect()).unwrap_or_default();
+    let output_path = matches.value_of("output").unwrap();
+
+    let params = match matches.value_of("params") {
+        Some(path) => EvalParams::parse(&read_file(path)).unwrap_or_else(|e| exit_with_error(&format!("Invalid parameter file '{}': {}", path, e))),
+        None => EvalParams::default()
+    };
+    if !prefixes.is_empty() && !params.names().iter().any(|name| prefixes.iter().any(|prefix| name.starts_with(prefix.as_str()))) {
+        exit_with_error("No parameters match the given prefixes");
+    }
+
+    let runtime_constants = Arc::new(BitboardRuntimeConstants::create());
+    let positions_path = matches.value_of("positions").unwrap();
+    let positions = tuning::parse_tuning_positions(&read_file(positions_path), runtime_constants)
+        .unwrap_or_else(|e| exit_with_error(&format!("Invalid position file '{}': {}", positions_path, e)));
+    println!("Resolving {} positions with quiescence search", positions.len());
+    let mut tuner = Tuner::new(positions);
+
+    let k = match matches.value_of("k") {
+        Some(k) => parse_number::<f64>(k, "scaling constant"),
+        None => tuner.find_scaling_constant(&params)
+    };
+    println!("Scaling constant K = {:.4}, initial error {:.6}", k, tuner.error(&params, k));
+
+    let start = Instant::now();
+    let tuned = tuner.tune(&params, k, step, iterations, &prefixes, &mut |iteration, error, params| {
+        println!("Iteration {}: error {:.6} ({:.1} s)", iteration, error, start.elapsed().as_secs_f64());
+        write_file(output_path, &params.to_param_file());
+    });
+    println!("Wrote tuned parameters to {}", output_path);
+    if let Some(path) = matches.value_of("rust") {
+        write_file(path, &tuned.to_rust_constants());
+        println!("Wrote Rust constants to {}", path);
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str, name: &str) -> T {
+    return text.parse().unwrap_or_else(|_| exit_with_error(&format!("Invalid {}: {}", name, text)));
+}
+
+fn read_file(path: &str) -> String {
+    return fs::read_to_string(path).unwrap_or_else(|e| exit_with_error(&format!("Could not read '{}': {}", path, e)));
+}
+
+fn write_file(path: &str, contents: &str) {
+    if let Err(e) = fs::write(path, contents) {
+        exit_with_error(&format!("Could not write '{}': {}", path, e));
+    }
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    process::exit(1);
+}
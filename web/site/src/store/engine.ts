@@ -135,6 +135,13 @@ export const useChessEngineStore = defineStore('chess_engine', {
             this.currentBoardFenString = data;
             localStorage.setItem("current_board_fen", data);
           }
+          else if (messageType == "set_board_fen") {
+            if (data) {
+              // The FEN was rejected and the board left unchanged
+              this.logHistory.push(data);
+              this.syncBoardState();
+            }
+          }
           else if (messageType == "get_game_status") {
             this.setGameStatus(data);
           }
@@ -312,7 +319,7 @@ export const useChessEngineStore = defineStore('chess_engine', {
       this.gamePaused = false;
       this.startSearchIfNecessary();
     },
-    setGameStatus(status: "white_won" | "black_won" | "stalemate" | "running") {
+    setGameStatus(status: "white_won" | "black_won" | "stalemate" | "threefold_repetition" | "fifty_move_rule" | "insufficient_material" | "running") {
       this.gameStatus = status;
       if (status != "running") {
         worker.postMessage(["abort"]);
@@ -1,26 +1,33 @@
-use std::rc::Rc;
+use std::sync::Arc;
 use std::time::Duration;
 
 use constants::BitboardRuntimeConstants;
 use engine_core::{commands, engine};
+use engine_core::book::Book;
 use engine_core::core::move_list::{MoveList, MoveListCollection};
 use engine_core::engine::ab_engine::StandardAlphaBetaEngine;
-use engine_core::engine::{Engine, GetSystemTimeCallback, LogCallback, SearchMetadata, SearchMetadataCallback, ShouldAbortSearchCallback};
+use engine_core::engine::{Engine, Score, SearchLimits, GetSystemTimeCallback, LogCallback, SearchMetadata, SearchMetadataCallback, ShouldAbortSearchCallback};
 /// This file contains a wasm_bindgen interface to the chess engine core
 use wasm_bindgen::prelude::*;
-use engine_core::core::{Color, GameStatus, Move, Piece, STARTING_POS_FEN};
+use engine_core::core::{Color, GameStatus, Move, Piece};
+use engine_core::core::variant::Variant;
 use engine_core::core::bitboard::*;
+use engine_core::core::bitboard::nnue::Network;
 use serde::{Serialize, Deserialize};
 
 extern crate console_error_panic_hook;
 
 #[wasm_bindgen]
 pub struct ChessEngine {
-    board_constant_state: Rc<BitboardRuntimeConstants>,
+    board_constant_state: Arc<BitboardRuntimeConstants>,
     board: Board,
     white_player: Option<Box<dyn Engine>>,
     black_player: Option<Box<dyn Engine>>,
-    game_moves: Vec<Move>
+    game_moves: Vec<Move>,
+    /// Opening book the engine players move from while the game is in it
+    book: Option<Book>,
+    /// Rules the board is played with, kept when the board is reset or set from a FEN string
+    variant: Variant
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -57,10 +64,35 @@ impl MoveWrapper {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SearchMetadataWrapper {
     depth: usize,
+    selective_depth: usize,
     eval: f64,
+    mate_in: Option<i32>,
+    nodes: u64,
+    nodes_per_second: u64,
     pv: String
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EvalTermWrapper {
+    name: String,
+    white_mg: i32,
+    white_eg: i32,
+    black_mg: i32,
+    black_eg: i32,
+    total_mg: i32,
+    total_eg: i32
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EvalTraceWrapper {
+    terms: Vec<EvalTermWrapper>,
+    phase: i32,
+    total_mg: i32,
+    total_eg: i32,
+    /// Tapered evaluation in centipawns from the view of white
+    eval: i32
+}
+
 ///
 /// When to search and when not to?
 /// Make a move => get current turn => if 
@@ -74,13 +106,15 @@ impl ChessEngine {
         console_error_panic_hook::set_once();
         wasm_logger::init(wasm_logger::Config::new(log::Level::Debug));
 
-        let board_constant_state = Rc::new(BitboardRuntimeConstants::create());
+        let board_constant_state = Arc::new(BitboardRuntimeConstants::create());
         ChessEngine { 
-            board: Board::from_fen(STARTING_POS_FEN, Rc::clone(&board_constant_state)),
+            board: Board::new(Arc::clone(&board_constant_state)),
             board_constant_state, 
             white_player: None, 
             black_player: None,
-            game_moves: Vec::new()
+            game_moves: Vec::new(),
+            book: None,
+            variant: Variant::Standard
         }
     }
 
@@ -132,11 +166,52 @@ impl ChessEngine {
         self.game_moves.push(mv);
     }
 
+    /// Number of `piece` in the pocket of its color, only nonzero in Crazyhouse
+    pub fn get_pocket_count(&self, piece: usize) -> usize {
+        return self.board.get_pocket_count(Piece::from_u8(piece as u8)) as usize;
+    }
+
+    /// Drop `piece` from the pocket of the player to move on the square (x, y), in Crazyhouse.
+    /// Returns false if the drop is not legal.
+    pub fn make_drop(&mut self, piece: usize, x: usize, y: usize) -> bool {
+        let mut legal_moves = MoveList::empty();
+        self.board.get_moves(&mut legal_moves, false);
+        let to = (y * 8 + x) as u8;
+        match legal_moves.iter().find(|mv| mv.is_drop() && mv.to == to && mv.promotion.to_u8() as usize == piece) {
+            Some(mv) => {
+                let mv = *mv;
+                self.board.make_move(&mv);
+                self.game_moves.push(mv);
+                return true;
+            }
+            None => return false
+        }
+    }
+
     pub fn reset_board(&mut self) {
-        self.board = Board::new(Rc::clone(&self.board_constant_state));
+        self.board = Board::new(Arc::clone(&self.board_constant_state));
+        self.board.set_variant(self.variant);
         self.game_moves = Vec::new();
     }
 
+    /// Names of the variants accepted by `set_variant`
+    pub fn get_variants() -> Vec<String> {
+        return Variant::ALL.iter().map(|variant| variant.to_string()).collect();
+    }
+
+    /// Play with the rules of the variant `name` and reset the board to the starting position.
+    /// Returns an error message if the variant is unknown, in which case the board is left unchanged.
+    pub fn set_variant(&mut self, name: String) -> Option<String> {
+        match Variant::from_uci_name(&name) {
+            Some(variant) => {
+                self.variant = variant;
+                self.reset_board();
+                return None;
+            }
+            None => return Some(format!("Unknown variant '{}'", name))
+        }
+    }
+
     pub fn undo_move(&mut self) {
         let prev_move = self.game_moves.pop();
         if let Some(mv) = prev_move {
@@ -153,8 +228,68 @@ impl ChessEngine {
         return self.board.to_fen();
     }
 
-    pub fn set_board_fen(&mut self, fen: String) {
-        self.board = Board::from_fen(&fen, Rc::clone(&self.board_constant_state));
+    /// Set the board from a FEN string. Returns an error message if the FEN string is invalid,
+    /// in which case the board is left unchanged.
+    pub fn set_board_fen(&mut self, fen: String) -> Option<String> {
+        match Board::from_fen_variant(&fen, self.variant, Arc::clone(&self.board_constant_state)) {
+            Ok(board) => {
+                self.board = board;
+                return None;
+            }
+            Err(e) => return Some(format!("Invalid FEN '{}': {}", fen, e))
+        }
+    }
+
+    /// Evaluate with the network file in `bytes`, or with the classical evaluation if it is empty.
+    /// Returns an error message if the network is invalid, in which case the evaluation is unchanged.
+    pub fn load_network(&mut self, bytes: Vec<u8>) -> Option<String> {
+        let network = match bytes.is_empty() {
+            true => None,
+            false => match Network::from_bytes(&bytes) {
+                Ok(network) => Some(Arc::new(network)),
+                Err(e) => return Some(format!("Invalid network: {}", e))
+            }
+        };
+        let mut board_constant_state = (*self.board_constant_state).clone();
+        board_constant_state.network = network;
+        self.board_constant_state = Arc::new(board_constant_state);
+        self.board.set_network(self.board_constant_state.network.clone());
+        return None;
+    }
+
+    /// Play the engine moves from the Polyglot book in `bytes` while the game is in it, or stop using a book
+    /// if it is empty. Returns an error message if the book is invalid, in which case the book is unchanged.
+    pub fn load_book(&mut self, bytes: Vec<u8>) -> Option<String> {
+        self.book = match bytes.is_empty() {
+            true => None,
+            false => match Book::from_bytes(&bytes) {
+                Ok(book) => Some(book),
+                Err(e) => return Some(format!("Invalid book: {}", e))
+            }
+        };
+        return None;
+    }
+
+    /// Evaluation of the current position split into its terms, see `EvalTraceWrapper`
+    pub fn get_eval_trace(&self) -> JsValue {
+        let trace = self.board.eval_trace();
+        let terms = trace.terms.iter().map(|term| EvalTermWrapper {
+            name: term.name.to_string(),
+            white_mg: term.white.mg,
+            white_eg: term.white.eg,
+            black_mg: term.black.mg,
+            black_eg: term.black.eg,
+            total_mg: term.total().mg,
+            total_eg: term.total().eg
+        }).collect();
+        let wrapped_trace = EvalTraceWrapper {
+            terms,
+            phase: trace.phase,
+            total_mg: trace.total().mg,
+            total_eg: trace.total().eg,
+            eval: trace.tapered()
+        };
+        return serde_wasm_bindgen::to_value(&wrapped_trace).unwrap();
     }
 
     pub fn set_white_player(&mut self, engine_name: String) {
@@ -203,13 +338,24 @@ impl ChessEngine {
             GameStatus::InProgress => "running".to_string(),
             GameStatus::WhiteWon => "white_won".to_string(),
             GameStatus::BlackWon => "black_won".to_string(),
-            GameStatus::Stalemate => "stalemate".to_string()
+            GameStatus::Stalemate => "stalemate".to_string(),
+            GameStatus::ThreefoldRepetition => "threefold_repetition".to_string(),
+            GameStatus::FiftyMoveRule => "fifty_move_rule".to_string(),
+            GameStatus::InsufficientMaterial => "insufficient_material".to_string()
         }
     }
 
     fn handle_search_metadata(metadata: SearchMetadata) {
         let pv = metadata.pv.iter().map(|&mv| mv.to_algebraic()).collect::<Vec<String>>().join(" ");
-        let wrapped_metadata = SearchMetadataWrapper { depth: metadata.depth, eval: metadata.eval, pv};
+        let wrapped_metadata = SearchMetadataWrapper { 
+            depth: metadata.depth, 
+            selective_depth: metadata.selective_depth,
+            eval: match metadata.score { Score::Centipawns(centipawns) => centipawns as f64, Score::Mate(_) => 0.0 },
+            mate_in: match metadata.score { Score::Mate(moves) => Some(moves), Score::Centipawns(_) => None },
+            nodes: metadata.nodes,
+            nodes_per_second: metadata.nodes_per_second,
+            pv
+        };
         js_search_metadata_update(serde_wasm_bindgen::to_value(&wrapped_metadata).unwrap());
     }
 
@@ -239,17 +385,27 @@ impl ChessEngine {
     }
 
     pub async fn search(&mut self) -> JsValue {
+        let limits = SearchLimits::from_move_time(Duration::from_secs(1));
+        let player = match self.board.get_current_player() {
+            Color::White => &self.white_player,
+            Color::Black => &self.black_player
+        };
+        if player.is_some() {
+            if let Some(mv) = self.book.as_ref().and_then(|book| book.pick_move(&self.board)) {
+                return serde_wasm_bindgen::to_value(&ChessEngine::moves_to_return_moves(&vec![mv])).unwrap();
+            }
+        }
         if self.board.get_current_player() == Color::Black && self.black_player.is_some() {
             let black_player = self.black_player.as_mut().unwrap();
             let moves = ChessEngine::moves_to_return_moves(
-                &black_player.search(&self.board)
+                &black_player.search(&self.board, &limits)
             );
             return serde_wasm_bindgen::to_value(&moves).unwrap();
         }
         else if self.white_player.is_some() {
             let white_player = self.white_player.as_mut().unwrap();
             let moves = ChessEngine::moves_to_return_moves(
-                &white_player.search(&self.board)
+                &white_player.search(&self.board, &limits)
             );
             return serde_wasm_bindgen::to_value(&moves).unwrap();
         }